@@ -0,0 +1,309 @@
+//! Command line tool for pushing images and text to an Inky display
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use inky::{
+    core::{
+        colors::Color,
+        geometry::Rect,
+        image::{Adjustments, Dither, Fit, ImportOptions},
+        text::Text,
+    },
+    config::Config,
+    inky::Inky,
+    slideshow::{parse_interval, SlideshowOptions},
+};
+use image::{DynamicImage, RgbImage};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Push images and text to an Inky e-ink display")]
+struct Cli {
+    /// Settings file, see inky::config. Defaults to /etc/inky.toml if it exists
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show an image, fitted to the display
+    Show {
+        /// Image file, or - to read the image from standard input
+        path: PathBuf,
+        /// Read raw 8-bit RGB pixels of the given size, e.g. 800x480, instead of an image file
+        #[arg(long, value_name = "WxH", value_parser = parse_size)]
+        raw: Option<(u32, u32)>,
+        /// How to fit the image: cover, contain[:color], stretch or tile [default: stretch]
+        #[arg(long)]
+        fit: Option<Fit>,
+        /// Dithering algorithm: fs, atkinson or none [default: fs]
+        #[arg(long)]
+        dither: Option<Dither>,
+        /// Color saturation applied before quantising to the display palette [default: 0.5]
+        #[arg(long)]
+        saturation: Option<f32>,
+        /// Amount added to every color channel, from -1.0 to 1.0
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        brightness: f32,
+        /// Contrast around mid grey, where 1.0 leaves the image unchanged
+        #[arg(long, default_value_t = 1.0)]
+        contrast: f32,
+        /// Gamma correction, where values above 1.0 lighten the midtones
+        #[arg(long, default_value_t = 1.0)]
+        gamma: f32,
+        /// Stretch each color channel to the full range before the other adjustments
+        #[arg(long)]
+        auto_levels: bool,
+    },
+    /// Clear the display to white
+    Clear,
+    /// Flash the panel through its colors to remove ghosting, leaving it white
+    Clean {
+        #[arg(long, default_value_t = 2)]
+        cycles: usize,
+    },
+    /// Show text centered on the display
+    Text {
+        text: String,
+        /// Size of each font pixel on the display
+        #[arg(long, default_value_t = 4)]
+        scale: usize,
+        #[arg(long, default_value = "black")]
+        color: Color,
+    },
+    /// Cycle through the images in a directory, showing new ones as they are added
+    Slideshow {
+        dir: PathBuf,
+        /// How long each image is shown, e.g. 30s, 10m or 1h
+        #[arg(long, default_value = "10m", value_parser = parse_interval)]
+        interval: Duration,
+        /// Show the images in a random order
+        #[arg(long)]
+        shuffle: bool,
+        /// How to fit the images: cover, contain[:color], stretch or tile [default: cover]
+        #[arg(long)]
+        fit: Option<Fit>,
+        /// Dithering algorithm: fs, atkinson or none [default: fs]
+        #[arg(long)]
+        dither: Option<Dither>,
+    },
+    /// Run an HTTP server that draws images and text posted to it
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on [default: 0.0.0.0:8080]
+        #[arg(long)]
+        bind: Option<String>,
+    },
+    /// Run a daemon that owns the display and takes frames from other programs over a Unix
+    /// socket, or over TCP for clients on other machines
+    #[cfg(feature = "daemon")]
+    Daemon {
+        /// Path of the socket [default: /run/inky.sock]
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        /// Listen on this TCP address instead of the socket, e.g. 0.0.0.0:7800
+        #[arg(long)]
+        tcp: Option<String>,
+        /// Serve Prometheus metrics over HTTP on this address, e.g. 0.0.0.0:9090
+        #[arg(long)]
+        metrics: Option<String>,
+    },
+    /// Drive the display from an MQTT broker
+    #[cfg(feature = "mqtt")]
+    Mqtt {
+        /// Hostname of the broker
+        host: String,
+        #[arg(long, default_value_t = 1883)]
+        port: u16,
+        /// Topic that the display's topics live under
+        #[arg(long, default_value = "inky")]
+        topic: String,
+        /// Publish Home Assistant discovery messages under this prefix
+        #[arg(long)]
+        discovery_prefix: Option<String>,
+    },
+}
+
+/// Parse a size given as WIDTHxHEIGHT
+fn parse_size(text: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid size {:?}, expected e.g. 800x480", text);
+    let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Read an image from `path`, or from standard input if it is `-`, either encoded in any format
+/// `image` supports or as raw RGB pixels of the size given
+fn read_image(path: &Path, raw: Option<(u32, u32)>) -> Result<DynamicImage> {
+    let data = if path == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .context("Failed to read standard input")?;
+        data
+    } else {
+        fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?
+    };
+    match raw {
+        Some((width, height)) => {
+            let expected = width as usize * height as usize * 3;
+            if data.len() != expected {
+                bail!("Expected {} bytes of RGB pixels, got {}", expected, data.len());
+            }
+            let image = RgbImage::from_raw(width, height, data).expect("length was checked");
+            Ok(DynamicImage::ImageRgb8(image))
+        }
+        None => image::load_from_memory(&data).context("Failed to decode the image"),
+    }
+}
+
+/// Stop on SIGTERM and SIGINT, leaving the configured offline screen on the display
+#[cfg(feature = "shutdown")]
+fn shutdown(config: &Config) -> Result<inky::shutdown::Shutdown> {
+    use inky::shutdown::{OfflineScreen, Shutdown};
+
+    let settings = &config.shutdown;
+    let mut shutdown = Shutdown::new();
+    if let Some(path) = &settings.offline_image {
+        shutdown = shutdown.with_offline(OfflineScreen::Image(path.clone()));
+    } else if let Some(text) = &settings.offline_text {
+        shutdown = shutdown.with_offline(OfflineScreen::Text(text.clone()));
+    }
+    shutdown
+        .register_signals()
+        .context("Failed to handle signals")?;
+    Ok(shutdown)
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let config = Config::load_or_default(cli.config.as_deref())?;
+    let mut builder = Inky::builder();
+    builder
+        .connection(config.connection_config()?)
+        .rotation(config.rotation()?)
+        .import_options(config.import_options());
+    if let Some(border) = config.display.border {
+        builder.border(border);
+    }
+    if let Some(celsius) = config.display.ambient_temperature {
+        builder.ambient_temperature(celsius);
+    }
+    let mut inky = builder.build().context("Failed to connect to the display")?;
+    let palette = inky.canvas().palette();
+    let defaults = inky.import_options().clone();
+
+    match cli.command {
+        Command::Show {
+            path,
+            raw,
+            fit,
+            dither,
+            saturation,
+            brightness,
+            contrast,
+            gamma,
+            auto_levels,
+        } => {
+            let image = read_image(&path, raw)?;
+            let options = ImportOptions {
+                dither: dither.unwrap_or(defaults.dither),
+                saturation: saturation.unwrap_or(defaults.saturation),
+                fit: fit.unwrap_or(defaults.fit),
+                adjustments: Adjustments {
+                    brightness,
+                    contrast,
+                    gamma,
+                    auto_levels,
+                },
+            };
+            inky.canvas_mut().import_image(&image, palette, &options);
+        }
+        Command::Clear => {}
+        Command::Slideshow {
+            dir,
+            interval,
+            shuffle,
+            fit,
+            dither,
+        } => {
+            let options = SlideshowOptions {
+                interval,
+                shuffle,
+                import: ImportOptions {
+                    fit: fit.or(config.image.fit).unwrap_or(Fit::Cover),
+                    dither: dither.unwrap_or(defaults.dither),
+                    ..defaults
+                },
+            };
+            return Ok(inky::slideshow::run(inky, palette, &dir, &options)?);
+        }
+        Command::Clean { cycles } => {
+            return Ok(inky.clean(cycles, &Color::White)?);
+        }
+        #[cfg(feature = "server")]
+        Command::Serve { bind } => {
+            let bind = bind
+                .or(config.server.bind)
+                .unwrap_or_else(|| "0.0.0.0:8080".into());
+            let shutdown = shutdown(&config)?;
+            return Ok(inky::server::serve(inky, palette, &bind, &shutdown)?);
+        }
+        #[cfg(feature = "daemon")]
+        Command::Daemon {
+            socket,
+            tcp,
+            metrics: metrics_addr,
+        } => {
+            use inky::metrics::Metrics;
+            use std::sync::Arc;
+
+            let shutdown = shutdown(&config)?;
+            let settings = config.daemon;
+            let socket = socket
+                .or(settings.socket)
+                .unwrap_or_else(|| PathBuf::from("/run/inky.sock"));
+            let metrics = Arc::new(Metrics::new());
+            if let Some(addr) = metrics_addr.or(settings.metrics) {
+                inky::metrics::serve(Arc::clone(&metrics), &addr)?;
+            }
+            return Ok(match tcp.or(settings.tcp) {
+                Some(addr) => inky::daemon::serve_tcp(inky, palette, metrics, &addr, &shutdown)?,
+                None => inky::daemon::serve(inky, palette, metrics, &socket, &shutdown)?,
+            });
+        }
+        #[cfg(feature = "mqtt")]
+        Command::Mqtt {
+            host,
+            port,
+            topic,
+            discovery_prefix,
+        } => {
+            let mut config = inky::mqtt::MqttConfigBuilder::default();
+            config.host(host).port(port).base_topic(topic);
+            if let Some(prefix) = discovery_prefix {
+                config.discovery_prefix(prefix);
+            }
+            return Ok(inky::mqtt::run(inky, palette, &config.build()?)?);
+        }
+        Command::Text { text, scale, color } => {
+            let area = Rect::new(0, 0, inky.canvas().width(), inky.canvas().height());
+            inky.canvas_mut()
+                .draw(Text::centered(&text, scale, area), &color);
+        }
+    }
+
+    inky.force_update()?;
+    Ok(())
+}
@@ -0,0 +1,342 @@
+//! Settings for the command line tool and its long running modes, read from a TOML file
+//!
+//! The file is `/etc/inky.toml` unless another is given with `--config`. Every section and key is
+//! optional:
+//!
+//! ```toml
+//! [display]
+//! rotation = 90        # degrees clockwise, for a panel mounted on its side
+//! border = "black"
+//! ambient_temperature = 18.0  # for the waveform, when the panel can't be measured
+//!
+//! [connection]
+//! spi_bus = 0
+//! chip_select = 0
+//! software_cs_pin = 8  # drive chip select from a GPIO instead of the SPI device
+//! dc_pin = 22
+//! reset_pin = 27
+//! busy_pin = 17
+//! spi_speed = 488000
+//! spi_chunk_size = 65536  # largest transfer, read from the spidev module when unset
+//! busy_poll_ms = 10    # poll the busy line, if its interrupt is unreliable
+//! backend = "cdev"     # GPIO character device and spidev, for boards other than the Pi
+//! gpio_chip = "/dev/gpiochip1"  # cdev only
+//!
+//! [image]
+//! dither = "atkinson"
+//! fit = "contain:black"
+//! saturation = 0.6
+//!
+//! [server]
+//! bind = "0.0.0.0:8080"
+//!
+//! [daemon]
+//! socket = "/run/inky.sock"
+//! tcp = "0.0.0.0:7800"
+//! metrics = "0.0.0.0:9090"
+//!
+//! [shutdown]
+//! offline_text = "Offline"  # or offline_image, drawn when the server or daemon stops
+//! ```
+
+use crate::{
+    core::{
+        colors::Color,
+        image::{Dither, Fit, ImportOptions},
+        transform::Rotation,
+    },
+    error::{InkyError, Result},
+    hardware::display::{
+        Backend, BusyWait, ChipSelect, ConnectionConfig, ConnectionConfigBuilder, Retry,
+    },
+};
+
+use rppal::spi::{Bus, SlaveSelect};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Where the configuration is read from when no path is given
+pub const DEFAULT_PATH: &str = "/etc/inky.toml";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub display: DisplaySettings,
+    pub connection: ConnectionSettings,
+    pub image: ImageSettings,
+    pub server: ServerSettings,
+    pub daemon: DaemonSettings,
+    pub shutdown: ShutdownSettings,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplaySettings {
+    /// Turn everything the command line tool draws clockwise by this many degrees: 0, 90, 180
+    /// or 270
+    pub rotation: u16,
+    pub border: Option<Color>,
+    /// Degrees Celsius, see [`crate::inky::Inky::set_ambient_temperature`]
+    pub ambient_temperature: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionSettings {
+    pub spi_bus: Option<u8>,
+    pub chip_select: Option<u8>,
+    /// BCM pin to drive chip select from in software
+    pub software_cs_pin: Option<u8>,
+    pub dc_pin: Option<u8>,
+    pub reset_pin: Option<u8>,
+    pub busy_pin: Option<u8>,
+    /// SPI clock in Hz
+    pub spi_speed: Option<u32>,
+    /// Largest SPI transfer in bytes, read from the spidev module when unset
+    pub spi_chunk_size: Option<usize>,
+    /// Times to retry a failed SPI write
+    pub spi_retries: Option<u32>,
+    /// Read the busy line every this many milliseconds instead of waiting for its interrupt
+    pub busy_poll_ms: Option<u64>,
+    /// `rppal`, or `cdev` for boards other than the Pi, see [`crate::hardware::display::Backend`]
+    pub backend: Option<String>,
+    pub gpio_chip: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageSettings {
+    #[serde(deserialize_with = "parsed")]
+    pub dither: Option<Dither>,
+    #[serde(deserialize_with = "parsed")]
+    pub fit: Option<Fit>,
+    pub saturation: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonSettings {
+    pub socket: Option<PathBuf>,
+    pub tcp: Option<String>,
+    pub metrics: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownSettings {
+    /// Image to leave on the display when the server or daemon stops
+    pub offline_image: Option<PathBuf>,
+    /// Message to leave on the display instead of an image
+    pub offline_text: Option<String>,
+}
+
+// Deserialize a value from a string with its `FromStr` implementation, as the command line does
+fn parsed<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(de::Error::custom)
+}
+
+fn invalid(message: String) -> InkyError {
+    InkyError::InvalidArgument(format!("Invalid configuration: {}", message))
+}
+
+impl Config {
+    /// Parse a configuration from TOML
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let config: Self = toml::from_str(toml).map_err(|e| invalid(e.to_string()))?;
+        // Check the settings that are only interpreted later
+        config.rotation()?;
+        config.connection_config()?;
+        if config.shutdown.offline_image.is_some() && config.shutdown.offline_text.is_some() {
+            return Err(invalid(
+                "only one of offline_image and offline_text can be set".into(),
+            ));
+        }
+        Ok(config)
+    }
+
+    /// Read the configuration at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Read the configuration at `path`, or [`DEFAULT_PATH`] if it is `None`. A missing default
+    /// file gives the default configuration, but a missing file that was asked for is an error
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => match fs::read_to_string(DEFAULT_PATH) {
+                Ok(toml) => Self::from_toml(&toml),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    pub fn rotation(&self) -> Result<Rotation> {
+        match self.display.rotation {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Deg90),
+            180 => Ok(Rotation::Deg180),
+            270 => Ok(Rotation::Deg270),
+            degrees => Err(invalid(format!(
+                "rotation must be 0, 90, 180 or 270, not {}",
+                degrees
+            ))),
+        }
+    }
+
+    /// The pins and SPI device to drive the display through
+    pub fn connection_config(&self) -> Result<ConnectionConfig> {
+        let settings = &self.connection;
+        let mut config = ConnectionConfigBuilder::default();
+        if let Some(bus) = settings.spi_bus {
+            config.bus(match bus {
+                0 => Bus::Spi0,
+                1 => Bus::Spi1,
+                2 => Bus::Spi2,
+                3 => Bus::Spi3,
+                4 => Bus::Spi4,
+                5 => Bus::Spi5,
+                6 => Bus::Spi6,
+                _ => return Err(invalid(format!("there is no SPI bus {}", bus))),
+            });
+        }
+        if let Some(select) = settings.chip_select {
+            config.slave_select(match select {
+                0 => SlaveSelect::Ss0,
+                1 => SlaveSelect::Ss1,
+                2 => SlaveSelect::Ss2,
+                _ => return Err(invalid(format!("there is no chip select {}", select))),
+            });
+        }
+        if let Some(pin) = settings.software_cs_pin {
+            config.chip_select(ChipSelect::Software(pin));
+        }
+        if let Some(pin) = settings.dc_pin {
+            config.dc_pin(pin);
+        }
+        if let Some(pin) = settings.reset_pin {
+            config.reset_pin(pin);
+        }
+        if let Some(pin) = settings.busy_pin {
+            config.busy_pin(pin);
+        }
+        if let Some(speed) = settings.spi_speed {
+            config.spi_speed(speed);
+        }
+        if let Some(size) = settings.spi_chunk_size {
+            config.spi_chunk_size(size);
+        }
+        if let Some(retries) = settings.spi_retries {
+            config.retry(Retry {
+                retries,
+                ..Retry::default()
+            });
+        }
+        if let Some(interval) = settings.busy_poll_ms {
+            config.busy_wait(BusyWait::Poll(Duration::from_millis(interval)));
+        }
+        if let Some(backend) = &settings.backend {
+            config.backend(match backend.as_str() {
+                "rppal" => Backend::Rppal,
+                #[cfg(feature = "cdev")]
+                "cdev" => Backend::Cdev,
+                _ => return Err(invalid(format!("there is no {} backend", backend))),
+            });
+        }
+        if let Some(chip) = &settings.gpio_chip {
+            if settings.backend.as_deref() != Some("cdev") {
+                return Err(invalid("gpio_chip needs the cdev backend".to_string()));
+            }
+            config.gpio_chip(chip.clone());
+        }
+        config.build().map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Import options with the configured defaults in place of the library's
+    pub fn import_options(&self) -> ImportOptions {
+        let defaults = ImportOptions::default();
+        ImportOptions {
+            dither: self.image.dither.unwrap_or(defaults.dither),
+            fit: self.image.fit.unwrap_or(defaults.fit),
+            saturation: self.image.saturation.unwrap_or(defaults.saturation),
+            ..defaults
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::core::{
+        colors::Color,
+        image::{Dither, Fit},
+        transform::Rotation,
+    };
+    use crate::hardware::display::ChipSelect;
+
+    #[test]
+    fn parse() {
+        let config = Config::from_toml(
+            r#"
+            [display]
+            rotation = 270
+            border = "black"
+
+            [connection]
+            software_cs_pin = 8
+            busy_pin = 25
+
+            [image]
+            dither = "atkinson"
+            fit = "contain:black"
+
+            [daemon]
+            tcp = "0.0.0.0:7800"
+
+            [shutdown]
+            offline_text = "Back soon"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rotation().unwrap(), Rotation::Deg270);
+        assert_eq!(config.display.border, Some(Color::Black));
+        let connection = config.connection_config().unwrap();
+        assert_eq!(connection.chip_select, Some(ChipSelect::Software(8)));
+        assert_eq!((connection.busy_pin, connection.dc_pin), (25, 22));
+        let options = config.import_options();
+        assert_eq!(options.dither, Dither::Atkinson);
+        assert_eq!(options.fit, Fit::Contain(Color::Black));
+        assert_eq!(options.saturation, 0.5);
+        assert_eq!(config.daemon.tcp.as_deref(), Some("0.0.0.0:7800"));
+        assert_eq!(config.server.bind, None);
+        assert_eq!(config.shutdown.offline_text.as_deref(), Some("Back soon"));
+
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+        assert!(Config::from_toml("[display]\nrotation = 45").is_err());
+        assert!(Config::from_toml("[connection]\nspi_bus = 9").is_err());
+        assert!(Config::from_toml("[connection]\ngpio_chip = \"/dev/gpiochip1\"").is_err());
+        assert!(Config::from_toml("[image]\ndither = \"random\"").is_err());
+        assert!(Config::from_toml("[display]\nrotate = 90").is_err());
+        assert!(Config::from_toml(
+            "[shutdown]\noffline_text = \"Offline\"\noffline_image = \"offline.png\""
+        )
+        .is_err());
+    }
+}
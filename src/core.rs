@@ -1 +1,26 @@
-pub mod colors;
\ No newline at end of file
+pub mod colors;
+#[cfg(feature = "text")]
+pub mod font;
+pub mod geometry;
+pub mod layers;
+#[cfg(feature = "std")]
+pub mod layout;
+pub mod mask;
+pub mod packing;
+pub mod pattern;
+pub mod test_pattern;
+#[cfg(feature = "text")]
+pub mod text;
+pub mod transform;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded_graphics;
+#[cfg(feature = "plotters")]
+pub mod plotters;
+#[cfg(feature = "gif")]
+pub mod gif;
+#[cfg(feature = "image")]
+pub mod image;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(feature = "svg")]
+pub mod svg;
@@ -1,5 +1,7 @@
+use alloc::{format, string::{String, ToString}};
+use core::{fmt, str::FromStr};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Color {
     Black,
     White,
@@ -7,4 +9,153 @@ pub enum Color {
     Red,
     Blue,
     Green,
-}
\ No newline at end of file
+    /// Only shown by black and white panels in four level greyscale, see
+    /// [`crate::hardware::display::PixelMode`]
+    DarkGrey,
+    LightGrey,
+}
+
+impl Color {
+    // Every ink colour, in the order used for nearest-colour matching
+    pub(crate) const ALL: [Color; 6] = [
+        Color::Black,
+        Color::White,
+        Color::Yellow,
+        Color::Red,
+        Color::Blue,
+        Color::Green,
+    ];
+
+    /// The palette of four level greyscale, from dark to light
+    pub const GREYSCALE: [Color; 4] = [
+        Color::Black,
+        Color::DarkGrey,
+        Color::LightGrey,
+        Color::White,
+    ];
+
+    // Every colour: the inks, then the greys. Positions in this list are the colour indices used
+    // to serialize canvases
+    pub(crate) const EVERY: [Color; 8] = [
+        Color::Black,
+        Color::White,
+        Color::Yellow,
+        Color::Red,
+        Color::Blue,
+        Color::Green,
+        Color::DarkGrey,
+        Color::LightGrey,
+    ];
+
+    /// Canonical RGB value of the ink colour
+    pub fn to_rgb(&self) -> (u8, u8, u8) {
+        match self {
+            Color::Black => (0, 0, 0),
+            Color::White => (255, 255, 255),
+            Color::Yellow => (255, 255, 0),
+            Color::Red => (255, 0, 0),
+            Color::Blue => (0, 0, 255),
+            Color::Green => (0, 255, 0),
+            Color::DarkGrey => (85, 85, 85),
+            Color::LightGrey => (170, 170, 170),
+        }
+    }
+
+    /// The ink colour closest to an RGB value
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::nearest(&Color::ALL, r, g, b)
+    }
+
+    /// The opposite colour: black and white swap, and other inks become the colour out of
+    /// `palette` closest to their RGB complement
+    pub fn complement(&self, palette: &[Color]) -> Color {
+        match self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+            color => {
+                let (r, g, b) = color.to_rgb();
+                Color::nearest(palette, 255 - r, 255 - g, 255 - b)
+            }
+        }
+    }
+
+    /// The colour out of `palette` closest to an RGB value
+    pub fn nearest(palette: &[Color], r: u8, g: u8, b: u8) -> Color {
+        palette
+            .iter()
+            .copied()
+            .min_by_key(|color| {
+                let (pr, pg, pb) = color.to_rgb();
+                let dr = pr as i32 - r as i32;
+                let dg = pg as i32 - g as i32;
+                let db = pb as i32 - b as i32;
+                dr * dr + dg * dg + db * db
+            })
+            .unwrap_or(Color::White)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Color::Black => "black",
+            Color::White => "white",
+            Color::Yellow => "yellow",
+            Color::Red => "red",
+            Color::Blue => "blue",
+            Color::Green => "green",
+            Color::DarkGrey => "darkgrey",
+            Color::LightGrey => "lightgrey",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parse a color from its lowercase name, e.g. "black" or "lightgrey"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::EVERY
+            .iter()
+            .copied()
+            .find(|color| color.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown color {:?}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Color;
+
+    #[test]
+    fn rgb_round_trip() {
+        for color in Color::ALL {
+            let (r, g, b) = color.to_rgb();
+            assert_eq!(Color::from_rgb(r, g, b), color);
+        }
+    }
+
+    #[test]
+    fn complement() {
+        assert_eq!(Color::Black.complement(&Color::ALL), Color::White);
+        assert_eq!(Color::White.complement(&[Color::Red]), Color::Black);
+        assert_eq!(Color::Yellow.complement(&Color::ALL), Color::Blue);
+        assert_eq!(Color::Blue.complement(&Color::ALL), Color::Yellow);
+    }
+
+    #[test]
+    fn nearest_ink() {
+        assert_eq!(Color::from_rgb(250, 20, 10), Color::Red);
+        assert_eq!(Color::from_rgb(40, 40, 40), Color::Black);
+        assert_eq!(
+            Color::nearest(&Color::GREYSCALE, 160, 150, 180),
+            Color::LightGrey
+        );
+        assert_eq!("darkgrey".parse(), Ok(Color::DarkGrey));
+        assert_eq!(
+            Color::nearest(&[Color::Black, Color::White], 200, 200, 0),
+            Color::White
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! [embedded-graphics](https://docs.rs/embedded-graphics) support for the drawing canvas
+//!
+//! `Canvas` implements `DrawTarget` with the ink `Color` as its pixel color, so any
+//! embedded-graphics text, primitive or image can be drawn onto it. `Rgb888` and `BinaryColor`
+//! values can be converted into ink colors with `into()`; RGB values are mapped to the nearest
+//! ink color.
+
+use crate::{core::colors::Color, inky::Canvas};
+
+use embedded_graphics_core::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Size},
+    pixelcolor::{BinaryColor, PixelColor, Rgb888, RgbColor},
+    Pixel,
+};
+
+use core::convert::Infallible;
+
+impl PixelColor for Color {
+    type Raw = ();
+}
+
+impl From<Rgb888> for Color {
+    fn from(value: Rgb888) -> Self {
+        Color::from_rgb(value.r(), value.g(), value.b())
+    }
+}
+
+impl From<BinaryColor> for Color {
+    /// `BinaryColor::On` is drawn in black ink, `Off` is left white
+    fn from(value: BinaryColor) -> Self {
+        match value {
+            BinaryColor::On => Color::Black,
+            BinaryColor::Off => Color::White,
+        }
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.width() as u32, self.height() as u32)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Color;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x < 0 || point.y < 0 {
+                continue;
+            }
+            let (x, y) = (point.x as usize, point.y as usize);
+            if x < self.width() && y < self.height() {
+                self.set_pixel(x, y, &color);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{core::colors::Color, inky::Canvas};
+    use embedded_graphics_core::{
+        draw_target::DrawTarget,
+        geometry::Point,
+        pixelcolor::{BinaryColor, Rgb888},
+        Pixel,
+    };
+
+    #[test]
+    fn test_draw_iter() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas
+            .draw_iter([
+                Pixel(Point::new(1, 2), Color::from(BinaryColor::On)),
+                Pixel(Point::new(3, 4), Color::from(Rgb888::new(200, 10, 20))),
+                Pixel(Point::new(-1, 4), Color::Black),
+                Pixel(Point::new(3, 40), Color::Black),
+            ])
+            .unwrap();
+        assert_eq!(canvas.get_pixel(2, 1), Color::Black);
+        assert_eq!(canvas.get_pixel(4, 3), Color::Red);
+    }
+}
@@ -0,0 +1,579 @@
+//! Bitmap fonts loaded at runtime from BDF and PCF files, such as Terminus or Spleen, which are
+//! drawn pixel for pixel and suit low resolution displays far better than scaled outlines
+//!
+//! Glyphs are placed in cells as tall as the font's ascent plus descent, so a line of mixed
+//! glyphs shares a baseline. Encodings are taken to be Unicode code points, which holds for fonts
+//! in the ISO10646-1 encoding.
+
+use crate::{
+    core::text::{Font, Glyph},
+    error::{InkyError, Result},
+};
+
+use std::{collections::HashMap, fs, path::Path};
+
+/// A font of pre-rendered glyphs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitmapFont {
+    glyphs: HashMap<char, Glyph>,
+    ascent: usize,
+    descent: usize,
+}
+
+// A glyph as stored in a font file: a bitmap with its offset from the pen position
+struct RawGlyph {
+    advance: usize,
+    width: usize,
+    height: usize,
+    // Offset of the left edge from the pen position
+    left: isize,
+    // Height of the top row above the baseline
+    top: isize,
+    bitmap: Vec<bool>,
+}
+
+fn invalid(message: impl Into<String>) -> InkyError {
+    InkyError::Font(message.into())
+}
+
+impl BitmapFont {
+    /// Load a BDF or PCF font, telling them apart by their contents
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(PCF_MAGIC) {
+            Self::from_pcf(&data)
+        } else {
+            let text = std::str::from_utf8(&data).map_err(|_| invalid("BDF font is not text"))?;
+            Self::from_bdf(text)
+        }
+    }
+
+    /// Parse a font in the text Glyph Bitmap Distribution Format
+    pub fn from_bdf(source: &str) -> Result<Self> {
+        let mut lines = source.lines().map(str::trim);
+        let mut ascent = None;
+        let mut descent = None;
+        let mut bounding_box = None;
+        let mut glyphs = Vec::new();
+
+        while let Some(line) = lines.next() {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("FONTBOUNDINGBOX") => bounding_box = Some(numbers::<isize>(fields, 4)?),
+                Some("FONT_ASCENT") => ascent = Some(numbers::<isize>(fields, 1)?[0]),
+                Some("FONT_DESCENT") => descent = Some(numbers::<isize>(fields, 1)?[0]),
+                Some("STARTCHAR") => {
+                    let mut encoding = None;
+                    let mut advance = 0;
+                    let mut bbx = None;
+                    for line in lines.by_ref() {
+                        let mut fields = line.split_whitespace();
+                        match fields.next() {
+                            Some("ENCODING") => encoding = Some(numbers::<i64>(fields, 1)?[0]),
+                            Some("DWIDTH") => advance = numbers::<isize>(fields, 1)?[0],
+                            Some("BBX") => bbx = Some(numbers::<isize>(fields, 4)?),
+                            Some("BITMAP") => break,
+                            _ => {}
+                        }
+                    }
+                    let bbx = bbx.ok_or_else(|| invalid("BDF glyph without a BBX"))?;
+                    let (width, height) = (bbx[0].max(0) as usize, bbx[1].max(0) as usize);
+                    let mut bitmap = Vec::with_capacity(width * height);
+                    for line in lines.by_ref().take(height) {
+                        let row = (0..line.len())
+                            .step_by(2)
+                            .map(|i| {
+                                line.get(i..i + 2)
+                                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                            })
+                            .collect::<Option<Vec<u8>>>()
+                            .ok_or_else(|| invalid(format!("Invalid BDF bitmap row {:?}", line)))?;
+                        bitmap.extend(
+                            (0..width).map(|x| {
+                                row.get(x / 8).is_some_and(|b| b & (0x80 >> (x % 8)) != 0)
+                            }),
+                        );
+                    }
+                    if bitmap.len() != width * height {
+                        return Err(invalid("BDF glyph bitmap is too short"));
+                    }
+                    // Glyphs with negative or unknown encodings are not reachable by character
+                    let c = encoding
+                        .and_then(|code| u32::try_from(code).ok())
+                        .and_then(char::from_u32);
+                    if let Some(c) = c {
+                        glyphs.push((
+                            c,
+                            RawGlyph {
+                                advance: advance.max(0) as usize,
+                                width,
+                                height,
+                                left: bbx[2],
+                                top: bbx[3] + bbx[1],
+                                bitmap,
+                            },
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let bounding_box =
+            bounding_box.ok_or_else(|| invalid("BDF font without a FONTBOUNDINGBOX"))?;
+        let ascent = ascent.unwrap_or(bounding_box[1] + bounding_box[3]);
+        let descent = descent.unwrap_or(-bounding_box[3]);
+        Ok(Self::from_raw(glyphs, ascent, descent))
+    }
+
+    /// Parse a font in the binary Portable Compiled Format used by X11
+    pub fn from_pcf(data: &[u8]) -> Result<Self> {
+        Pcf::new(data)?.font()
+    }
+
+    // Place each glyph in a cell spanning the ascent and descent of the font
+    fn from_raw(raw: Vec<(char, RawGlyph)>, ascent: isize, descent: isize) -> Self {
+        let ascent = ascent.max(0) as usize;
+        let descent = descent.max(0) as usize;
+        let height = ascent + descent;
+        let glyphs = raw
+            .into_iter()
+            .map(|(c, raw)| {
+                // Ink left of the pen position is shifted right rather than lost
+                let left = raw.left.max(0) as usize;
+                let width = raw.advance.max(left + raw.width);
+                let mut bitmap = vec![false; width * height];
+                for y in 0..raw.height {
+                    let row = ascent as isize - raw.top + y as isize;
+                    if row < 0 || row >= height as isize {
+                        continue;
+                    }
+                    for x in 0..raw.width {
+                        if raw.bitmap[y * raw.width + x] {
+                            bitmap[row as usize * width + left + x] = true;
+                        }
+                    }
+                }
+                let glyph = Glyph {
+                    width,
+                    height,
+                    advance: raw.advance,
+                    bitmap,
+                };
+                (c, glyph)
+            })
+            .collect();
+        Self {
+            glyphs,
+            ascent,
+            descent,
+        }
+    }
+
+    /// Distance from the top of a line to the baseline
+    pub fn ascent(&self) -> usize {
+        self.ascent
+    }
+
+    /// Distance from the baseline to the bottom of a line
+    pub fn descent(&self) -> usize {
+        self.descent
+    }
+
+    /// Whether the font has a glyph for a character
+    pub fn contains(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+}
+
+impl Font for BitmapFont {
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        self.glyphs.get(&c).cloned()
+    }
+
+    fn line_height(&self) -> usize {
+        self.ascent + self.descent
+    }
+}
+
+// Parse exactly `count` whitespace separated numbers
+fn numbers<'a, T: std::str::FromStr>(
+    fields: impl Iterator<Item = &'a str>,
+    count: usize,
+) -> Result<Vec<T>> {
+    let values = fields
+        .take(count)
+        .map(|field| field.parse().ok())
+        .collect::<Option<Vec<T>>>()
+        .filter(|values| values.len() == count);
+    values.ok_or_else(|| invalid("Invalid number in BDF font"))
+}
+
+const PCF_MAGIC: &[u8] = b"\x01fcp";
+
+// Table types
+const PCF_ACCELERATORS: u32 = 1 << 1;
+const PCF_METRICS: u32 = 1 << 2;
+const PCF_BITMAPS: u32 = 1 << 3;
+const PCF_BDF_ENCODINGS: u32 = 1 << 5;
+const PCF_BDF_ACCELERATORS: u32 = 1 << 8;
+
+// Table format bits
+const PCF_GLYPH_PAD_MASK: u32 = 3;
+const PCF_BYTE_MSB_FIRST: u32 = 1 << 2;
+const PCF_BIT_MSB_FIRST: u32 = 1 << 3;
+const PCF_SCAN_UNIT_MASK: u32 = 3 << 4;
+const PCF_COMPRESSED_METRICS: u32 = 0x100;
+
+// A PCF file and its table of contents
+struct Pcf<'a> {
+    data: &'a [u8],
+    // (type, format, offset) of each table
+    tables: Vec<(u32, u32, usize)>,
+}
+
+// Reads values from a table in the byte order of its format
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or_else(|| invalid("PCF table runs past the end of the file"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = [self.u8()?, self.u8()?];
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes: [u8; 4] = self.bytes(4)?.try_into().unwrap_or_default();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+}
+
+// Glyph metrics from the metrics table
+struct Metrics {
+    left: isize,
+    right: isize,
+    advance: isize,
+    ascent: isize,
+    descent: isize,
+}
+
+impl<'a> Pcf<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        if !data.starts_with(PCF_MAGIC) {
+            return Err(invalid("Not a PCF font"));
+        }
+        let mut reader = Reader {
+            data,
+            position: 4,
+            big_endian: false,
+        };
+        let count = reader.u32()?;
+        let tables = (0..count)
+            .map(|_| -> Result<(u32, u32, usize)> {
+                let kind = reader.u32()?;
+                let format = reader.u32()?;
+                let _size = reader.u32()?;
+                let offset = reader.u32()? as usize;
+                Ok((kind, format, offset))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { data, tables })
+    }
+
+    // A reader positioned after the format word of a table, and the table's format
+    fn table(&self, kind: u32) -> Option<(Reader<'a>, u32)> {
+        let &(_, format, offset) = self.tables.iter().find(|(k, _, _)| *k == kind)?;
+        Some((
+            Reader {
+                data: self.data,
+                position: offset + 4,
+                big_endian: format & PCF_BYTE_MSB_FIRST != 0,
+            },
+            format,
+        ))
+    }
+
+    fn metrics(&self) -> Result<Vec<Metrics>> {
+        let (mut reader, format) = self
+            .table(PCF_METRICS)
+            .ok_or_else(|| invalid("PCF font without metrics"))?;
+        if format & PCF_COMPRESSED_METRICS != 0 {
+            let count = reader.u16()?;
+            (0..count)
+                .map(|_| -> Result<Metrics> {
+                    let mut next = || Ok::<_, InkyError>(reader.u8()? as isize - 0x80);
+                    Ok(Metrics {
+                        left: next()?,
+                        right: next()?,
+                        advance: next()?,
+                        ascent: next()?,
+                        descent: next()?,
+                    })
+                })
+                .collect()
+        } else {
+            let count = reader.u32()?;
+            (0..count)
+                .map(|_| -> Result<Metrics> {
+                    let metrics = Metrics {
+                        left: reader.i16()? as isize,
+                        right: reader.i16()? as isize,
+                        advance: reader.i16()? as isize,
+                        ascent: reader.i16()? as isize,
+                        descent: reader.i16()? as isize,
+                    };
+                    let _attributes = reader.u16()?;
+                    Ok(metrics)
+                })
+                .collect()
+        }
+    }
+
+    // The bitmap of each glyph, most significant bit leftmost, with its row stride in bytes
+    fn bitmaps(&self, count: usize) -> Result<(Vec<Vec<u8>>, u32)> {
+        let (mut reader, format) = self
+            .table(PCF_BITMAPS)
+            .ok_or_else(|| invalid("PCF font without bitmaps"))?;
+        let glyphs = reader.u32()? as usize;
+        if glyphs != count {
+            return Err(invalid("PCF bitmap and metrics counts differ"));
+        }
+        let offsets = (0..glyphs)
+            .map(|_| reader.u32().map(|offset| offset as usize))
+            .collect::<Result<Vec<_>>>()?;
+        let sizes = (0..4).map(|_| reader.u32()).collect::<Result<Vec<_>>>()?;
+        let size = sizes[(format & PCF_GLYPH_PAD_MASK) as usize] as usize;
+        let mut data = reader.bytes(size)?.to_vec();
+
+        if format & PCF_BIT_MSB_FIRST == 0 {
+            for byte in data.iter_mut() {
+                *byte = byte.reverse_bits();
+            }
+        }
+        let unit = 1 << ((format & PCF_SCAN_UNIT_MASK) >> 4);
+        if (format & PCF_BYTE_MSB_FIRST != 0) != (format & PCF_BIT_MSB_FIRST != 0) && unit > 1 {
+            for chunk in data.chunks_mut(unit) {
+                chunk.reverse();
+            }
+        }
+
+        let bitmaps = offsets
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = offsets.get(i + 1).copied().unwrap_or(data.len());
+                data.get(start..end.max(start)).unwrap_or_default().to_vec()
+            })
+            .collect();
+        Ok((bitmaps, format))
+    }
+
+    // Map code points to glyph indices
+    fn encodings(&self) -> Result<Vec<(char, usize)>> {
+        let (mut reader, _) = self
+            .table(PCF_BDF_ENCODINGS)
+            .ok_or_else(|| invalid("PCF font without encodings"))?;
+        let (min2, max2) = (reader.u16()? as u32, reader.u16()? as u32);
+        let (min1, max1) = (reader.u16()? as u32, reader.u16()? as u32);
+        let _default = reader.u16()?;
+        let mut encodings = Vec::new();
+        for byte1 in min1..=max1 {
+            for byte2 in min2..=max2 {
+                let index = reader.u16()?;
+                if index == 0xffff {
+                    continue;
+                }
+                if let Some(c) = char::from_u32(byte1 << 8 | byte2) {
+                    encodings.push((c, index as usize));
+                }
+            }
+        }
+        Ok(encodings)
+    }
+
+    fn font(&self) -> Result<BitmapFont> {
+        let metrics = self.metrics()?;
+        let (bitmaps, format) = self.bitmaps(metrics.len())?;
+        let pad = 1 << (format & PCF_GLYPH_PAD_MASK);
+
+        let accelerators = self
+            .table(PCF_BDF_ACCELERATORS)
+            .or_else(|| self.table(PCF_ACCELERATORS));
+        let (ascent, descent) = match accelerators {
+            Some((mut reader, _)) => {
+                reader.bytes(8)?;
+                (reader.i32()? as isize, reader.i32()? as isize)
+            }
+            None => (
+                metrics.iter().map(|m| m.ascent).max().unwrap_or(0),
+                metrics.iter().map(|m| m.descent).max().unwrap_or(0),
+            ),
+        };
+
+        let mut glyphs = Vec::new();
+        for (c, index) in self.encodings()? {
+            let (Some(m), Some(data)) = (metrics.get(index), bitmaps.get(index)) else {
+                continue;
+            };
+            let width = (m.right - m.left).max(0) as usize;
+            let height = (m.ascent + m.descent).max(0) as usize;
+            let stride = width.div_ceil(8).div_ceil(pad) * pad;
+            let bitmap = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    data.get(y * stride + x / 8)
+                        .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+                })
+                .collect();
+            glyphs.push((
+                c,
+                RawGlyph {
+                    advance: m.advance.max(0) as usize,
+                    width,
+                    height,
+                    left: m.left,
+                    top: m.ascent,
+                    bitmap,
+                },
+            ));
+        }
+        Ok(BitmapFont::from_raw(glyphs, ascent, descent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitmapFont;
+    use crate::core::text::{Font, Text};
+    use crate::inky::Drawable;
+
+    const BDF: &str = "STARTFONT 2.1
+FONT -test-fixed-medium-r-normal--4-40-75-75-c-40-iso10646-1
+SIZE 4 75 75
+FONTBOUNDINGBOX 4 5 0 -1
+STARTPROPERTIES 2
+FONT_ASCENT 4
+FONT_DESCENT 1
+ENDPROPERTIES
+CHARS 2
+STARTCHAR A
+ENCODING 65
+SWIDTH 1000 0
+DWIDTH 4 0
+BBX 3 4 0 0
+BITMAP
+40
+A0
+E0
+A0
+ENDCHAR
+STARTCHAR g
+ENCODING 103
+SWIDTH 1000 0
+DWIDTH 4 0
+BBX 3 3 0 -1
+BITMAP
+E0
+60
+C0
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn parse_bdf() {
+        let font = BitmapFont::from_bdf(BDF).unwrap();
+        assert_eq!(font.line_height(), 5);
+        let a = font.glyph('A').unwrap();
+        assert_eq!((a.width, a.height, a.advance), (4, 5, 4));
+        assert!(a.is_set(1, 0) && !a.is_set(0, 0) && a.is_set(2, 3));
+        // The descender of g sits below the baseline
+        let g = font.glyph('g').unwrap();
+        assert!(g.is_set(1, 4) && !g.is_set(1, 1));
+        assert!(font.glyph('B').is_none());
+
+        let text = Text::new((0, 0), "Ag").with_font(&font);
+        assert_eq!(text.size(), (8, 5));
+        assert_eq!(text.coordinates().count(), 8 + 7);
+    }
+
+    #[test]
+    fn parse_pcf() {
+        // A single 2x2 glyph for 'A' with uncompressed metrics and bitmaps padded to a byte,
+        // both stored least significant byte and bit first
+        let mut pcf = b"\x01fcp".to_vec();
+        let le = |v: u32| v.to_le_bytes();
+        pcf.extend(le(3));
+        let header = 8 + 3 * 16;
+        let metrics_len = 4 + 4 + 12;
+        let bitmaps_len = 4 + 4 + 4 + 16 + 2;
+        for (kind, offset) in [
+            (1 << 2, header),
+            (1 << 3, header + metrics_len),
+            (1 << 5, header + metrics_len + bitmaps_len),
+        ] {
+            pcf.extend(le(kind));
+            pcf.extend(le(0));
+            pcf.extend(le(0));
+            pcf.extend(le(offset));
+        }
+        // Metrics: left 0, right 2, advance 3, ascent 2, descent 0
+        pcf.extend(le(0));
+        pcf.extend(le(1));
+        for value in [0u16, 2, 3, 2, 0, 0] {
+            pcf.extend(value.to_le_bytes());
+        }
+        // Bitmaps: a diagonal, with the leftmost pixel in the least significant bit
+        pcf.extend(le(0));
+        pcf.extend(le(1));
+        pcf.extend(le(0));
+        for size in [2, 4, 8, 16] {
+            pcf.extend(le(size));
+        }
+        pcf.extend([0b01, 0b10]);
+        // Encodings: 'A' only
+        pcf.extend(le(0));
+        for value in [65u16, 65, 0, 0, 0, 0] {
+            pcf.extend(value.to_le_bytes());
+        }
+
+        let font = BitmapFont::from_pcf(&pcf).unwrap();
+        assert_eq!(font.line_height(), 2);
+        let a = font.glyph('A').unwrap();
+        assert_eq!((a.width, a.advance), (3, 3));
+        assert!(a.is_set(0, 0) && a.is_set(1, 1));
+        assert!(!a.is_set(1, 0) && !a.is_set(0, 1));
+    }
+}
@@ -0,0 +1,115 @@
+//! Geometry primitives shared by the drawing layer and the display drivers
+
+/// An axis-aligned rectangular region of the display, in pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Create a rectangle from its top left corner and its size
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Create a rectangle from two inclusive corners
+    pub fn from_corners(top_left: (usize, usize), bottom_right: (usize, usize)) -> Self {
+        let (x0, x1) = (
+            top_left.0.min(bottom_right.0),
+            top_left.0.max(bottom_right.0),
+        );
+        let (y0, y1) = (
+            top_left.1.min(bottom_right.1),
+            top_left.1.max(bottom_right.1),
+        );
+        Self::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+    }
+
+    /// Create a rectangle from two inclusive corners that may lie left of or above the origin,
+    /// keeping only the part with non-negative coordinates
+    pub(crate) fn from_signed_corners(min: (isize, isize), max: (isize, isize)) -> Self {
+        if max.0 < 0 || max.1 < 0 || min.0 > max.0 || min.1 > max.1 {
+            return Self::default();
+        }
+        Self::from_corners(
+            (min.0.max(0) as usize, min.1.max(0) as usize),
+            (max.0 as usize, max.1 as usize),
+        )
+    }
+
+    /// One past the rightmost column of the rectangle
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    /// One past the bottom row of the rectangle
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    /// Whether the rectangle covers no pixels
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Whether the pixel at (x, y) lies inside the rectangle
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// The smallest rectangle covering both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    /// The region covered by both rectangles, if any
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (right > x && bottom > y).then(|| Rect::new(x, y, right - x, bottom - y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rect;
+
+    #[test]
+    fn test_from_corners() {
+        assert_eq!(Rect::from_corners((10, 5), (2, 7)), Rect::new(2, 5, 9, 3));
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        let a = Rect::new(0, 0, 10, 10);
+        let b = Rect::new(5, 5, 10, 10);
+        assert_eq!(a.union(&b), Rect::new(0, 0, 15, 15));
+        assert_eq!(a.intersection(&b), Some(Rect::new(5, 5, 5, 5)));
+        assert_eq!(a.intersection(&Rect::new(10, 0, 5, 5)), None);
+        assert_eq!(Rect::default().union(&b), b);
+    }
+}
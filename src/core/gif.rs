@@ -0,0 +1,155 @@
+//! Animated GIFs as a sequence of quantised frames, for simple multi-frame signage loops
+//!
+//! Each frame is resized to the canvas and quantised like any other imported image. E-ink panels
+//! take seconds to refresh, so [`Inky::play_frames`] holds every frame for at least a minimum
+//! delay rather than trying to keep up with the animation's own timing.
+
+use crate::{
+    core::{colors::Color, image::ImportOptions},
+    error::Result,
+    inky::Canvas,
+};
+#[cfg(feature = "hardware")]
+use crate::inky::Inky;
+
+use ::image::{codecs::gif::GifDecoder, AnimationDecoder, DynamicImage};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    time::Duration,
+};
+#[cfg(feature = "hardware")]
+use std::time::Instant;
+
+/// A single frame of an animation
+pub struct Frame {
+    pub canvas: Canvas,
+    /// How long the animation shows the frame for
+    pub delay: Duration,
+}
+
+/// Frames decoded from a GIF one at a time, see [`Canvas::load_gif`]
+pub struct Frames {
+    frames: ::image::Frames<'static>,
+    size: (usize, usize),
+    palette: Vec<Color>,
+    options: ImportOptions,
+}
+
+impl Iterator for Frames {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = match self.frames.next()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let delay = Duration::from(frame.delay());
+        let mut rgba = frame.into_buffer();
+        // Transparent areas show the white of the panel
+        for pixel in rgba.pixels_mut() {
+            let alpha = pixel.0[3] as u32;
+            for channel in &mut pixel.0[..3] {
+                *channel = ((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+            }
+            pixel.0[3] = 255;
+        }
+        let mut canvas = Canvas::new(self.size.0, self.size.1);
+        canvas.import_image(
+            &DynamicImage::ImageRgba8(rgba),
+            &self.palette,
+            &self.options,
+        );
+        Some(Ok(Frame { canvas, delay }))
+    }
+}
+
+impl Canvas {
+    /// Open an animated GIF, producing its frames resized to the size of this canvas and
+    /// quantised to `palette` as they are decoded
+    pub fn load_gif<P: AsRef<Path>>(
+        &self,
+        path: P,
+        palette: &[Color],
+        options: &ImportOptions,
+    ) -> Result<Frames> {
+        let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+        Ok(Frames {
+            frames: decoder.into_frames(),
+            size: (self.width(), self.height()),
+            palette: palette.to_vec(),
+            options: options.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl Inky {
+    /// Show each frame in turn, holding it for its own delay or `min_delay`, whichever is
+    /// longer. The hold includes the time taken to refresh the panel. Collect the frames from
+    /// [`Canvas::load_gif`] once and call this in a loop to repeat an animation
+    pub fn play_frames(&mut self, frames: &[Frame], min_delay: Duration) -> Result<()> {
+        for frame in frames {
+            let started = Instant::now();
+            self.canvas_mut().blit(&frame.canvas, (0, 0), None);
+            self.update()?;
+            if let Some(remaining) = frame.delay.max(min_delay).checked_sub(started.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{
+            colors::Color,
+            image::{Dither, ImportOptions},
+        },
+        error::Result,
+        inky::Canvas,
+    };
+
+    use ::image::{codecs::gif::GifEncoder, Delay, Frame, Rgba, RgbaImage};
+    use std::time::Duration;
+
+    #[test]
+    fn load_gif() {
+        let path = std::env::temp_dir().join("inky_test_load_gif.gif");
+        {
+            let file = std::fs::File::create(&path).expect("Failed to create gif");
+            let frames = [[0, 0, 0, 255], [255, 0, 0, 255]].map(|color| {
+                Frame::from_parts(
+                    RgbaImage::from_pixel(4, 4, Rgba(color)),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(500, 1),
+                )
+            });
+            GifEncoder::new(file)
+                .encode_frames(frames)
+                .expect("Failed to encode gif");
+        }
+
+        let options = ImportOptions {
+            dither: Dither::None,
+            saturation: 1.0,
+            ..Default::default()
+        };
+        let frames = Canvas::new(8, 8)
+            .load_gif(&path, &[Color::Black, Color::White, Color::Red], &options)
+            .expect("Failed to open gif")
+            .collect::<Result<Vec<_>>>()
+            .expect("Failed to decode gif");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].delay, Duration::from_millis(500));
+        assert_eq!(frames[0].canvas.width(), 8);
+        assert_eq!(frames[0].canvas.get_pixel(5, 5), Color::Black);
+        assert_eq!(frames[1].canvas.get_pixel(5, 5), Color::Red);
+    }
+}
@@ -0,0 +1,495 @@
+//! Conversion between the drawing canvas and [image](https://docs.rs/image) buffers
+
+use crate::{
+    core::colors::Color,
+    error::{InkyError, Result},
+    inky::Canvas,
+};
+#[cfg(feature = "hardware")]
+use crate::inky::Inky;
+
+use ::image::{
+    imageops::{self, FilterType},
+    DynamicImage, Rgb, RgbImage,
+};
+use std::{io::Cursor, path::Path, str::FromStr};
+
+/// How to approximate colors that are not in the display palette when importing an image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Map every pixel to the nearest palette color
+    None,
+    /// Diffuse the quantisation error over neighbouring pixels with Floyd-Steinberg
+    #[default]
+    FloydSteinberg,
+    /// Diffuse three quarters of the quantisation error over a wider neighbourhood with Atkinson
+    /// dithering, which keeps highlights and shadows cleaner on black and white panels
+    Atkinson,
+}
+
+impl Dither {
+    // Neighbours that receive a share of the quantisation error as (dx, dy, weight), and the
+    // divisor of the weights
+    fn kernel(&self) -> (&'static [(isize, usize, i32)], i32) {
+        match self {
+            Dither::None => (&[], 1),
+            Dither::FloydSteinberg => (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16),
+            Dither::Atkinson => (
+                &[
+                    (1, 0, 1),
+                    (2, 0, 1),
+                    (-1, 1, 1),
+                    (0, 1, 1),
+                    (1, 1, 1),
+                    (0, 2, 1),
+                ],
+                8,
+            ),
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "fs" | "floyd-steinberg" => Ok(Dither::FloydSteinberg),
+            "atkinson" => Ok(Dither::Atkinson),
+            _ => Err(format!("Unknown dithering algorithm {:?}", s)),
+        }
+    }
+}
+
+/// How to fit an image whose aspect ratio doesn't match the canvas
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fit {
+    /// Scale the image to cover the whole canvas, cropping whatever overflows
+    Cover,
+    /// Scale the image to fit inside the canvas, filling the borders with a color
+    Contain(Color),
+    /// Resize the image to the size of the canvas, distorting it if need be
+    #[default]
+    Stretch,
+    /// Repeat the image at its original size from the top left corner
+    Tile,
+}
+
+impl Fit {
+    /// Resize `image` to `width` by `height` pixels
+    pub fn apply(&self, image: &DynamicImage, width: u32, height: u32) -> RgbImage {
+        match *self {
+            Fit::Cover => image
+                .resize_to_fill(width, height, FilterType::Lanczos3)
+                .to_rgb8(),
+            Fit::Contain(color) => {
+                let (r, g, b) = color.to_rgb();
+                let mut fitted = RgbImage::from_pixel(width, height, Rgb([r, g, b]));
+                let scaled = image.resize(width, height, FilterType::Lanczos3).to_rgb8();
+                let x = (width - scaled.width()) / 2;
+                let y = (height - scaled.height()) / 2;
+                imageops::overlay(&mut fitted, &scaled, x as i64, y as i64);
+                fitted
+            }
+            Fit::Stretch => image
+                .resize_exact(width, height, FilterType::Lanczos3)
+                .to_rgb8(),
+            Fit::Tile => {
+                let tile = image.to_rgb8();
+                if tile.width() == 0 || tile.height() == 0 {
+                    return RgbImage::new(width, height);
+                }
+                RgbImage::from_fn(width, height, |x, y| {
+                    *tile.get_pixel(x % tile.width(), y % tile.height())
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for Fit {
+    type Err = String;
+
+    /// Parse `cover`, `stretch`, `tile`, or `contain` with an optional border color such as
+    /// `contain:black`. Borders are white by default
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        match lower.split_once(':') {
+            Some(("contain", color)) => Ok(Fit::Contain(color.parse()?)),
+            _ => match lower.as_str() {
+                "cover" => Ok(Fit::Cover),
+                "contain" => Ok(Fit::Contain(Color::White)),
+                "stretch" => Ok(Fit::Stretch),
+                "tile" => Ok(Fit::Tile),
+                _ => Err(format!("Unknown fit mode {:?}", s)),
+            },
+        }
+    }
+}
+
+/// Tone adjustments applied to each color channel before quantising, see [`adjust_levels`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adjustments {
+    /// Amount added to every channel, from -1.0 to 1.0
+    pub brightness: f32,
+    /// Scale applied to the distance of every channel from mid grey
+    pub contrast: f32,
+    /// Gamma correction. Values above 1.0 lift the midtones, which e-ink tends to render too dark
+    pub gamma: f32,
+    /// Stretch each channel so its darkest value becomes black and its brightest white before
+    /// the other adjustments, which brings back contrast to washed out photos
+    pub auto_levels: bool,
+}
+
+impl Default for Adjustments {
+    /// Adjustments that leave the image unchanged
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            auto_levels: false,
+        }
+    }
+}
+
+/// Settings for [`Canvas::import_image`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportOptions {
+    pub dither: Dither,
+    /// Saturation applied before quantising, see [`adjust_saturation`]
+    pub saturation: f32,
+    pub fit: Fit,
+    /// Brightness, contrast and gamma applied before the saturation
+    pub adjustments: Adjustments,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            dither: Dither::FloydSteinberg,
+            saturation: 0.5,
+            fit: Fit::Stretch,
+            adjustments: Adjustments::default(),
+        }
+    }
+}
+
+/// Apply tone adjustments to an image in place. Auto levels are applied first, then contrast
+/// around mid grey, brightness and finally gamma
+pub fn adjust_levels(image: &mut RgbImage, adjustments: &Adjustments) {
+    if *adjustments == Adjustments::default() {
+        return;
+    }
+    // Darkest and brightest value of each channel
+    let mut range = [(0_u8, 255_u8); 3];
+    if adjustments.auto_levels {
+        let mut found = [(255_u8, 0_u8); 3];
+        for pixel in image.pixels() {
+            for (channel, (lo, hi)) in found.iter_mut().enumerate() {
+                *lo = (*lo).min(pixel.0[channel]);
+                *hi = (*hi).max(pixel.0[channel]);
+            }
+        }
+        // A channel with a single value has no range to stretch
+        for (range, found) in range.iter_mut().zip(found) {
+            if found.0 < found.1 {
+                *range = found;
+            }
+        }
+    }
+    // A lookup table for each channel, as every pixel goes through the same curve
+    let tables = range.map(|(lo, hi)| {
+        let span = (hi.saturating_sub(lo) as f32).max(1.0);
+        let mut table = [0_u8; 256];
+        for (value, out) in table.iter_mut().enumerate() {
+            let mut v = (value as f32 - lo as f32) / span;
+            v = (v - 0.5) * adjustments.contrast + 0.5 + adjustments.brightness;
+            v = v
+                .clamp(0.0, 1.0)
+                .powf(1.0 / adjustments.gamma.max(f32::EPSILON));
+            *out = (v * 255.0).round() as u8;
+        }
+        table
+    });
+    for pixel in image.pixels_mut() {
+        for (channel, table) in tables.iter().enumerate() {
+            pixel.0[channel] = table[pixel.0[channel] as usize];
+        }
+    }
+}
+
+// Prepare an image for quantising with the adjustments and saturation in `options`
+pub(crate) fn adjust(image: &mut RgbImage, options: &ImportOptions) {
+    adjust_levels(image, &options.adjustments);
+    adjust_saturation(image, options.saturation);
+}
+
+/// Scale the color saturation of an image in place. 0.0 produces greyscale, 1.0 leaves the image
+/// unchanged and larger values exaggerate colors
+pub fn adjust_saturation(image: &mut RgbImage, saturation: f32) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b] = pixel.0.map(f32::from);
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        pixel.0 = [r, g, b].map(|c| (luma + (c - luma) * saturation).clamp(0.0, 255.0) as u8);
+    }
+}
+
+impl Canvas {
+    /// Render the canvas to an RGB image using the canonical RGB value of each ink color, as a
+    /// preview of what will be shown on the panel
+    pub fn to_rgb_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width() as u32, self.height() as u32, |x, y| {
+            let (r, g, b) = self.get_pixel(y as usize, x as usize).to_rgb();
+            Rgb([r, g, b])
+        })
+    }
+
+    /// Draw an image with its top left corner at (x, y), quantised to `palette`. Parts of the
+    /// image outside of the canvas are clipped
+    pub fn draw_image(
+        &mut self,
+        image: &RgbImage,
+        position: (usize, usize),
+        palette: &[Color],
+        dither: Dither,
+    ) {
+        let (x0, y0) = position;
+        let width = (image.width() as usize).min(self.width().saturating_sub(x0));
+        let height = (image.height() as usize).min(self.height().saturating_sub(y0));
+
+        // Working copy of the image in signed channels so diffused error can go out of range
+        let mut work: Vec<[i32; 3]> = image.pixels().map(|p| p.0.map(i32::from)).collect();
+        let stride = image.width() as usize;
+        let (kernel, divisor) = dither.kernel();
+
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = work[y * stride + x].map(|c| c.clamp(0, 255));
+                let color = Color::nearest(palette, r as u8, g as u8, b as u8);
+                self.set_pixel(x0 + x, y0 + y, &color);
+
+                let (pr, pg, pb) = color.to_rgb();
+                let error = [r - pr as i32, g - pg as i32, b - pb as i32];
+                for &(dx, dy, weight) in kernel {
+                    let nx = x as isize + dx;
+                    if nx < 0 || nx as usize >= width || y + dy >= height {
+                        continue;
+                    }
+                    let target = &mut work[(y + dy) * stride + nx as usize];
+                    for channel in 0..3 {
+                        target[channel] += error[channel] * weight / divisor;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Create a canvas from packed 8-bit RGB pixels, row by row from the top left, quantised to
+    /// `palette`. This takes frames from other renderers or a camera without going through an
+    /// image file
+    pub fn from_rgb_buffer(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        palette: &[Color],
+        dither: Dither,
+    ) -> Result<Canvas> {
+        let expected = width * height * 3;
+        if data.len() != expected {
+            return Err(InkyError::BufferSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        let image = RgbImage::from_raw(width as u32, height as u32, data.to_vec())
+            .expect("buffer length was checked");
+        let mut canvas = Canvas::new(width, height);
+        canvas.draw_image(&image, (0, 0), palette, dither);
+        Ok(canvas)
+    }
+
+    /// Replace the contents of the canvas with an image, fitted to the canvas with
+    /// `options.fit` and quantised to `palette`
+    pub fn import_image(
+        &mut self,
+        image: &DynamicImage,
+        palette: &[Color],
+        options: &ImportOptions,
+    ) {
+        let mut image = options
+            .fit
+            .apply(image, self.width() as u32, self.height() as u32);
+        adjust(&mut image, options);
+        self.draw_image(&image, (0, 0), palette, options.dither);
+    }
+
+    /// Encode a PNG preview of the canvas
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut png = Cursor::new(Vec::new());
+        self.to_rgb_image()
+            .write_to(&mut png, ::image::ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+
+    /// Save a PNG preview of the canvas to `path`
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.to_rgb_image()
+            .save_with_format(path, ::image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl Inky {
+    /// Replace the contents of the canvas with an image, fitted and quantised to the display
+    /// with the display's import options, see [`crate::inky::InkyBuilder::import_options`]
+    pub fn import_image(&mut self, image: &DynamicImage) {
+        let options = self.import_options().clone();
+        let palette = self.canvas().palette();
+        self.canvas_mut().import_image(image, palette, &options);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adjust_levels, adjust_saturation, Adjustments, Dither, Fit};
+    use crate::{
+        core::colors::Color,
+        inky::{Canvas, Rectangle},
+    };
+
+    #[test]
+    fn test_draw_image_dither() {
+        // A flat mid grey should dither to a mix of black and white
+        let image = ::image::RgbImage::from_pixel(8, 8, ::image::Rgb([128, 128, 128]));
+        let palette = [Color::Black, Color::White];
+
+        let mut canvas = Canvas::new(8, 8);
+        canvas.draw_image(&image, (0, 0), &palette, Dither::FloydSteinberg);
+        let blacks = (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|(x, y)| canvas.get_pixel(*y, *x) == Color::Black)
+            .count();
+        assert!((24..=40).contains(&blacks), "{} black pixels", blacks);
+
+        canvas.draw_image(&image, (0, 0), &palette, Dither::Atkinson);
+        let blacks = (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|(x, y)| canvas.get_pixel(*y, *x) == Color::Black)
+            .count();
+        assert!((16..=40).contains(&blacks), "{} black pixels", blacks);
+
+        canvas.draw_image(&image, (0, 0), &palette, Dither::None);
+        assert_eq!(canvas.get_pixel(3, 3), Color::White);
+    }
+
+    #[test]
+    fn test_from_rgb_buffer() {
+        let data = [255, 0, 0, 250, 250, 250, 10, 10, 10, 0, 0, 240];
+        let palette = [Color::Black, Color::White, Color::Red];
+        let canvas = Canvas::from_rgb_buffer(&data, 2, 2, &palette, Dither::None).unwrap();
+        assert_eq!(canvas.get_pixel(0, 0), Color::Red);
+        assert_eq!(canvas.get_pixel(0, 1), Color::White);
+        assert_eq!(canvas.get_pixel(1, 0), Color::Black);
+        assert_eq!(canvas.get_pixel(1, 1), Color::Black);
+        assert!(Canvas::from_rgb_buffer(&data[1..], 2, 2, &palette, Dither::None).is_err());
+    }
+
+    #[test]
+    fn test_fit() {
+        // A wide image with a red left half and blue right half
+        let image = ::image::RgbImage::from_fn(4, 2, |x, _| {
+            ::image::Rgb(if x < 2 { [255, 0, 0] } else { [0, 0, 255] })
+        });
+        let image = ::image::DynamicImage::ImageRgb8(image);
+
+        let stretched = Fit::Stretch.apply(&image, 4, 4);
+        assert_eq!(stretched.dimensions(), (4, 4));
+        assert_eq!(stretched.get_pixel(0, 3).0, [255, 0, 0]);
+
+        let contained = Fit::Contain(Color::Black).apply(&image, 4, 4);
+        assert_eq!(contained.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(contained.get_pixel(3, 3).0, [0, 0, 0]);
+        assert_eq!(contained.get_pixel(3, 1).0, [0, 0, 255]);
+
+        // Covering a square crops the sides, leaving the middle where the halves meet
+        let covered = Fit::Cover.apply(&image, 2, 2);
+        assert_eq!(covered.dimensions(), (2, 2));
+        assert!(covered.get_pixel(0, 0).0[0] > 128);
+        assert!(covered.get_pixel(1, 1).0[2] > 128);
+
+        let tiled = Fit::Tile.apply(&image, 6, 3);
+        assert_eq!(tiled.get_pixel(4, 2).0, [255, 0, 0]);
+
+        assert_eq!(
+            "contain:black".parse::<Fit>(),
+            Ok(Fit::Contain(Color::Black))
+        );
+        assert_eq!("Contain".parse::<Fit>(), Ok(Fit::Contain(Color::White)));
+        assert!("squash".parse::<Fit>().is_err());
+    }
+
+    #[test]
+    fn test_adjust_saturation() {
+        let mut image = ::image::RgbImage::from_pixel(1, 1, ::image::Rgb([200, 50, 50]));
+        adjust_saturation(&mut image, 0.0);
+        let [r, g, b] = image.get_pixel(0, 0).0;
+        assert!(r == g && g == b);
+    }
+
+    #[test]
+    fn test_adjust_levels() {
+        let mut image = ::image::RgbImage::from_fn(2, 1, |x, _| {
+            ::image::Rgb(if x == 0 {
+                [64, 100, 128]
+            } else {
+                [192, 150, 128]
+            })
+        });
+        let unchanged = image.clone();
+        adjust_levels(&mut image, &Adjustments::default());
+        assert_eq!(image, unchanged);
+
+        let auto = Adjustments {
+            auto_levels: true,
+            ..Default::default()
+        };
+        adjust_levels(&mut image, &auto);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 128]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 255, 128]);
+
+        let mut grey = ::image::RgbImage::from_pixel(1, 1, ::image::Rgb([128, 128, 128]));
+        let lift = Adjustments {
+            gamma: 2.0,
+            brightness: 0.2,
+            ..Default::default()
+        };
+        adjust_levels(&mut grey, &lift);
+        assert!(grey.get_pixel(0, 0).0[0] > 200);
+
+        let mut grey = ::image::RgbImage::from_pixel(1, 1, ::image::Rgb([160, 160, 160]));
+        let flat = Adjustments {
+            contrast: 0.0,
+            ..Default::default()
+        };
+        adjust_levels(&mut grey, &flat);
+        assert_eq!(grey.get_pixel(0, 0).0, [128, 128, 128]);
+    }
+
+    #[test]
+    fn test_save_png() {
+        let mut canvas = Canvas::new(16, 8);
+        canvas.draw(Rectangle::new((2, 1), (5, 3)), &Color::Red);
+
+        let path = std::env::temp_dir().join("inky_test_save_png.png");
+        canvas.save_png(&path).expect("Failed to save png");
+        let image = ::image::open(&path).expect("Failed to open png").to_rgb8();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(image.dimensions(), (16, 8));
+        assert_eq!(image.get_pixel(2, 1).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(6, 1).0, [255, 255, 255]);
+    }
+}
@@ -0,0 +1,128 @@
+//! Stacked canvases that are composited into the display canvas, so a static background can be
+//! kept while the layers above it are redrawn
+
+use crate::{core::colors::Color, core::geometry::Rect, inky::Canvas};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// Bottom layer. Every pixel is opaque
+    Background,
+    Content,
+    /// Top layer
+    Overlay,
+}
+
+/// A background, content and overlay canvas. Pixels of the transparent color in the upper layers
+/// show the layer beneath
+pub struct Layers {
+    background: Canvas,
+    content: Canvas,
+    overlay: Canvas,
+    transparent: Color,
+}
+
+impl Layers {
+    /// Create a white background with empty content and overlay layers
+    pub fn new(width: usize, height: usize, transparent: Color) -> Self {
+        let mut layers = Self {
+            background: Canvas::new(width, height),
+            content: Canvas::filled(width, height, transparent),
+            overlay: Canvas::filled(width, height, transparent),
+            transparent,
+        };
+        // Nothing has been composited yet
+        layers.background.mark_dirty(Rect::new(0, 0, width, height));
+        layers
+    }
+
+    pub fn layer(&self, layer: Layer) -> &Canvas {
+        match layer {
+            Layer::Background => &self.background,
+            Layer::Content => &self.content,
+            Layer::Overlay => &self.overlay,
+        }
+    }
+
+    pub fn layer_mut(&mut self, layer: Layer) -> &mut Canvas {
+        match layer {
+            Layer::Background => &mut self.background,
+            Layer::Content => &mut self.content,
+            Layer::Overlay => &mut self.overlay,
+        }
+    }
+
+    /// Color treated as transparent in the content and overlay layers
+    pub fn transparent(&self) -> Color {
+        self.transparent
+    }
+
+    /// Redraw the parts of `target` covered by anything drawn on the layers since the last
+    /// composite
+    pub fn composite(&mut self, target: &mut Canvas) {
+        let dirty = [&self.background, &self.content, &self.overlay]
+            .iter()
+            .filter_map(|layer| layer.dirty_region())
+            .reduce(|a, b| a.union(&b));
+        let bounds = Rect::new(0, 0, target.width(), target.height());
+        if let Some(region) = dirty.and_then(|dirty| dirty.intersection(&bounds)) {
+            for y in region.y..region.bottom() {
+                for x in region.x..region.right() {
+                    let color = [&self.overlay, &self.content]
+                        .iter()
+                        .map(|layer| layer.get_pixel(y, x))
+                        .find(|color| *color != self.transparent)
+                        .unwrap_or_else(|| self.background.get_pixel(y, x));
+                    target.set_pixel(x, y, &color);
+                }
+            }
+        }
+        self.background.clear_dirty();
+        self.content.clear_dirty();
+        self.overlay.clear_dirty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layer, Layers};
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::{Canvas, Rectangle};
+
+    #[test]
+    fn composite_order() {
+        let mut layers = Layers::new(4, 2, Color::Green);
+        let mut target = Canvas::new(4, 2);
+        layers
+            .layer_mut(Layer::Background)
+            .draw(Rectangle::new((0, 0), (3, 1)), &Color::Yellow);
+        layers
+            .layer_mut(Layer::Content)
+            .draw(Rectangle::new((0, 0), (1, 0)), &Color::Red);
+        layers
+            .layer_mut(Layer::Overlay)
+            .draw(Rectangle::new((1, 0), (1, 0)), &Color::Black);
+        layers.composite(&mut target);
+
+        assert_eq!(target.get_pixel(0, 0), Color::Red);
+        assert_eq!(target.get_pixel(0, 1), Color::Black);
+        assert_eq!(target.get_pixel(0, 2), Color::Yellow);
+        assert_eq!(target.get_pixel(1, 0), Color::Yellow);
+    }
+
+    #[test]
+    fn composite_only_dirty_region() {
+        let mut layers = Layers::new(8, 8, Color::Green);
+        let mut target = Canvas::new(8, 8);
+        layers.composite(&mut target);
+        target.clear_dirty();
+
+        layers
+            .layer_mut(Layer::Overlay)
+            .draw(Rectangle::new((2, 3), (4, 3)), &Color::Black);
+        layers.composite(&mut target);
+        assert_eq!(target.dirty_region(), Some(Rect::new(2, 3, 3, 1)));
+
+        layers.composite(&mut target);
+        assert_eq!(target.dirty_region(), Some(Rect::new(2, 3, 3, 1)));
+    }
+}
@@ -0,0 +1,273 @@
+//! Box layout of dashboard panels, so one description of a dashboard adapts to the resolution of
+//! each display
+//!
+//! A [`Container`] splits its area horizontally or vertically between its children, each of
+//! which is either a panel or a nested container. Children are a fixed number of pixels wide or
+//! share the space the fixed children leave in proportion to their weights. Arranging a container
+//! within an area gives the [`Rect`] of every panel for widgets to draw into, and draws the
+//! container borders.
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    /// Exactly this many pixels along the split
+    Fixed(usize),
+    /// A share of the space left over by fixed children, weighted against the other flexible
+    /// children
+    Flex(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Children are placed left to right
+    Horizontal,
+    /// Children are placed top to bottom
+    Vertical,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Child {
+    Panel,
+    Container(Container),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Container {
+    direction: Direction,
+    children: Vec<(Size, Child)>,
+    border: usize,
+    padding: usize,
+    gap: usize,
+}
+
+impl Container {
+    fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            children: Vec::new(),
+            border: 0,
+            padding: 0,
+            gap: 0,
+        }
+    }
+
+    /// A container placing its children left to right
+    pub fn horizontal() -> Self {
+        Self::new(Direction::Horizontal)
+    }
+
+    /// A container placing its children top to bottom
+    pub fn vertical() -> Self {
+        Self::new(Direction::Vertical)
+    }
+
+    /// Add a panel
+    pub fn panel(mut self, size: Size) -> Self {
+        self.children.push((size, Child::Panel));
+        self
+    }
+
+    /// Add a nested container
+    pub fn container(mut self, size: Size, container: Container) -> Self {
+        self.children.push((size, Child::Container(container)));
+        self
+    }
+
+    /// Draw a border this many pixels wide around the container
+    pub fn with_border(mut self, border: usize) -> Self {
+        self.border = border;
+        self
+    }
+
+    /// Leave this many pixels between the border and the children
+    pub fn with_padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Leave this many pixels between neighbouring children
+    pub fn with_gap(mut self, gap: usize) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Lay the container out within `area`
+    pub fn arrange(&self, area: Rect) -> Arrangement {
+        let mut arrangement = Arrangement {
+            panels: Vec::new(),
+            borders: Vec::new(),
+        };
+        self.arrange_into(area, &mut arrangement);
+        arrangement
+    }
+
+    fn arrange_into(&self, area: Rect, arrangement: &mut Arrangement) {
+        if self.border > 0 && !area.is_empty() {
+            arrangement.borders.push(Rectangle::outline(
+                (area.x, area.y),
+                (area.right() - 1, area.bottom() - 1),
+                self.border,
+            ));
+        }
+        let inset = self.border + self.padding;
+        let inner = Rect::new(
+            area.x + inset,
+            area.y + inset,
+            area.width.saturating_sub(2 * inset),
+            area.height.saturating_sub(2 * inset),
+        );
+        let (start, length) = match self.direction {
+            Direction::Horizontal => (inner.x, inner.width),
+            Direction::Vertical => (inner.y, inner.height),
+        };
+        let mut offset = start;
+        for (extent, (_, child)) in self.extents(length).into_iter().zip(&self.children) {
+            let rect = match self.direction {
+                Direction::Horizontal => Rect::new(offset, inner.y, extent, inner.height),
+                Direction::Vertical => Rect::new(inner.x, offset, inner.width, extent),
+            };
+            match child {
+                Child::Panel => arrangement.panels.push(rect),
+                Child::Container(container) => container.arrange_into(rect, arrangement),
+            }
+            offset += extent + self.gap;
+        }
+    }
+
+    // The length of each child along the split. Fixed children that don't fit are cut short
+    fn extents(&self, length: usize) -> Vec<usize> {
+        let gaps = self.gap * self.children.len().saturating_sub(1);
+        let mut remaining = length.saturating_sub(gaps);
+        let fixed: Vec<usize> = self
+            .children
+            .iter()
+            .map(|(size, _)| match size {
+                Size::Fixed(pixels) => {
+                    let pixels = (*pixels).min(remaining);
+                    remaining -= pixels;
+                    pixels
+                }
+                Size::Flex(_) => 0,
+            })
+            .collect();
+        let weights: usize = self
+            .children
+            .iter()
+            .map(|(size, _)| match size {
+                Size::Fixed(_) => 0,
+                Size::Flex(weight) => *weight,
+            })
+            .sum();
+
+        let mut shared = 0;
+        let mut weight_so_far = 0;
+        self.children
+            .iter()
+            .zip(fixed)
+            .map(|((size, _), fixed)| match size {
+                Size::Fixed(_) => fixed,
+                Size::Flex(weight) => {
+                    // Hand out the rounding remainder so flexible children fill the container
+                    weight_so_far += weight;
+                    let end = (remaining * weight_so_far)
+                        .checked_div(weights)
+                        .unwrap_or(0);
+                    let extent = end - shared;
+                    shared = end;
+                    extent
+                }
+            })
+            .collect()
+    }
+}
+
+/// The result of arranging a [`Container`]. Drawing it draws the container borders
+#[derive(Clone, Debug)]
+pub struct Arrangement {
+    panels: Vec<Rect>,
+    borders: Vec<Rectangle>,
+}
+
+impl Arrangement {
+    /// The area of every panel, in the order the panels were added with nested containers
+    /// expanded in place
+    pub fn panels(&self) -> &[Rect] {
+        &self.panels
+    }
+
+    /// The area of a single panel, or an empty rectangle if there is no such panel
+    pub fn panel(&self, index: usize) -> Rect {
+        self.panels.get(index).copied().unwrap_or_default()
+    }
+}
+
+impl Drawable for Arrangement {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.borders
+                .iter()
+                .flat_map(|border| border.rectangle_coordinates()),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.borders
+            .iter()
+            .map(Drawable::bounds)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Container, Size};
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::Canvas;
+
+    #[test]
+    fn nested_splits() {
+        let dashboard = Container::vertical().panel(Size::Fixed(20)).container(
+            Size::Flex(1),
+            Container::horizontal()
+                .with_gap(2)
+                .panel(Size::Flex(1))
+                .panel(Size::Flex(2)),
+        );
+        let arrangement = dashboard.arrange(Rect::new(0, 0, 101, 100));
+        assert_eq!(
+            arrangement.panels(),
+            &[
+                Rect::new(0, 0, 101, 20),
+                Rect::new(0, 20, 33, 80),
+                Rect::new(35, 20, 66, 80),
+            ]
+        );
+
+        // Fixed children are cut short when they don't fit
+        let small = dashboard.arrange(Rect::new(0, 0, 40, 10));
+        assert_eq!(small.panel(0), Rect::new(0, 0, 40, 10));
+        assert_eq!(small.panel(1).height, 0);
+        assert!(small.panel(5).is_empty());
+    }
+
+    #[test]
+    fn borders_and_padding() {
+        let container = Container::horizontal()
+            .with_border(1)
+            .with_padding(2)
+            .panel(Size::Flex(1));
+        let arrangement = container.arrange(Rect::new(0, 0, 20, 10));
+        assert_eq!(arrangement.panel(0), Rect::new(3, 3, 14, 4));
+
+        let mut canvas = Canvas::new(20, 10);
+        canvas.draw(arrangement, &Color::Black);
+        assert_eq!(canvas.get_pixel(0, 0), Color::Black);
+        assert_eq!(canvas.get_pixel(9, 19), Color::Black);
+        assert_eq!(canvas.get_pixel(1, 1), Color::White);
+    }
+}
@@ -0,0 +1,107 @@
+//! Stencils that limit drawing to part of the canvas, for non-rectangular widgets and for
+//! protecting an area such as a logo from being drawn over
+
+use crate::inky::Drawable;
+
+use alloc::{vec, vec::Vec};
+
+/// A 1-bit mask the size of a canvas. While it is set with [`crate::inky::Canvas::set_mask`],
+/// drawing only changes pixels where the mask is set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mask {
+    width: usize,
+    height: usize,
+    bits: Vec<bool>,
+}
+
+impl Mask {
+    /// Create a mask with no pixels set, which blocks all drawing until shapes are added to it
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            bits: vec![false; width * height],
+        }
+    }
+
+    /// Create a mask with every pixel set, to cut protected areas out of
+    pub fn filled(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            bits: vec![true; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether drawing may change the pixel at (x, y). Pixels outside of the mask are never set
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.bits[y * self.width + x]
+    }
+
+    /// Set or clear a single pixel. Pixels outside of the mask are ignored
+    pub fn set(&mut self, x: usize, y: usize, value: bool) {
+        if x < self.width && y < self.height {
+            self.bits[y * self.width + x] = value;
+        }
+    }
+
+    /// Set or clear every pixel covered by a shape
+    pub fn draw<D: Drawable>(&mut self, drawable: D, value: bool) {
+        for (x, y) in drawable.coordinates() {
+            self.set(x, y, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mask;
+    use crate::core::colors::Color;
+    use crate::inky::{Canvas, Rectangle};
+
+    #[test]
+    fn mask_shapes() {
+        let mut mask = Mask::new(4, 4);
+        mask.draw(Rectangle::new((1, 1), (2, 2)), true);
+        assert!(mask.is_set(1, 2));
+        assert!(!mask.is_set(0, 0));
+        assert!(!mask.is_set(5, 5));
+
+        let mut mask = Mask::filled(4, 4);
+        mask.draw(Rectangle::new((0, 0), (1, 3)), false);
+        assert!(!mask.is_set(1, 3));
+        assert!(mask.is_set(2, 3));
+    }
+
+    #[test]
+    fn masked_drawing() {
+        let mut mask = Mask::filled(6, 6);
+        mask.draw(Rectangle::new((0, 0), (2, 5)), false);
+        let mut canvas = Canvas::new(6, 6);
+        canvas.set_mask(Some(mask));
+
+        canvas.draw(Rectangle::new((0, 0), (5, 0)), &Color::Black);
+        assert_eq!(canvas.get_pixel(0, 2), Color::White);
+        assert_eq!(canvas.get_pixel(0, 3), Color::Black);
+
+        canvas.fill(&Color::Red);
+        assert_eq!(canvas.get_pixel(4, 1), Color::White);
+        assert_eq!(canvas.get_pixel(4, 4), Color::Red);
+
+        // Flood fills stop at the edge of the mask
+        canvas.set_mask(None);
+        canvas.draw(Rectangle::new((0, 0), (5, 5)), &Color::White);
+        canvas.set_mask(Some(Mask::filled(3, 6)));
+        canvas.flood_fill(0, 0, &Color::Blue);
+        assert_eq!(canvas.get_pixel(5, 2), Color::Blue);
+        assert_eq!(canvas.get_pixel(5, 3), Color::White);
+    }
+}
@@ -0,0 +1,140 @@
+//! Packing canvas rows into the framebuffer layouts used by the display controllers
+
+use crate::{
+    core::colors::Color,
+    error::{InkyError, Result},
+};
+
+use alloc::vec::Vec;
+
+/// Which end of a byte the first pixel goes in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+impl BitOrder {
+    fn shift(&self, bit: usize) -> usize {
+        match self {
+            BitOrder::MsbFirst => 7 - bit,
+            BitOrder::LsbFirst => bit,
+        }
+    }
+}
+
+/// Pack one bit per pixel as a continuous stream across rows, setting the bit for pixels where
+/// `bit` returns true. A partial final byte is padded with zeros
+pub fn pack_1bpp(rows: &[Vec<Color>], order: BitOrder, bit: impl Fn(&Color) -> bool) -> Vec<u8> {
+    let mut result = Vec::with_capacity(rows.iter().map(Vec::len).sum::<usize>().div_ceil(8));
+    let mut cur_byte: u8 = 0;
+    let mut bit_pos = 0;
+    for color in rows.iter().flatten() {
+        if bit(color) {
+            cur_byte |= 1 << order.shift(bit_pos);
+        }
+        bit_pos += 1;
+        if bit_pos == 8 {
+            result.push(cur_byte);
+            cur_byte = 0;
+            bit_pos = 0;
+        }
+    }
+    if bit_pos != 0 {
+        result.push(cur_byte);
+    }
+    result
+}
+
+/// Pack two pixels per byte, the first pixel of each pair in the high nibble. `nibble` gives the
+/// controller's 4-bit value for a color. Rows must have an even length
+pub fn pack_4bpp(rows: &[Vec<Color>], nibble: impl Fn(&Color) -> u8) -> Result<Vec<u8>> {
+    let mut result = Vec::with_capacity(rows.iter().map(Vec::len).sum::<usize>() / 2);
+    for row in rows {
+        if row.len() % 2 != 0 {
+            return Err(InkyError::InvalidArgument(
+                "Row length must be even!".into(),
+            ));
+        }
+        for pair in row.chunks(2) {
+            result.push((nibble(&pair[0]) << 4) | (nibble(&pair[1]) & 0x0F));
+        }
+    }
+    Ok(result)
+}
+
+/// Pack the two 1-bit planes used by black/white/colour panels: a black/white plane where the
+/// bit is set for `white` pixels, and a colour plane where it is set for `accent` pixels
+pub fn pack_dual_plane(
+    rows: &[Vec<Color>],
+    order: BitOrder,
+    white: impl Fn(&Color) -> bool,
+    accent: impl Fn(&Color) -> bool,
+) -> (Vec<u8>, Vec<u8>) {
+    (
+        pack_1bpp(rows, order, white),
+        pack_1bpp(rows, order, accent),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack_1bpp, pack_4bpp, pack_dual_plane, BitOrder};
+    use crate::core::colors::Color;
+
+    fn rows() -> Vec<Vec<Color>> {
+        use Color::*;
+        vec![
+            vec![Black, White, White, Black, Black, Black, Black, White],
+            vec![White, Red, Black, Black, Black, Black, Black, Black],
+        ]
+    }
+
+    #[test]
+    fn one_bit_orders() {
+        let white = |c: &Color| *c == Color::White;
+        assert_eq!(
+            pack_1bpp(&rows(), BitOrder::MsbFirst, white),
+            vec![0b0110_0001, 0b1000_0000]
+        );
+        assert_eq!(
+            pack_1bpp(&rows(), BitOrder::LsbFirst, white),
+            vec![0b1000_0110, 0b0000_0001]
+        );
+    }
+
+    #[test]
+    fn one_bit_pads_final_byte() {
+        let rows = vec![vec![Color::White; 3], vec![Color::White; 2]];
+        assert_eq!(
+            pack_1bpp(&rows, BitOrder::MsbFirst, |c| *c == Color::White),
+            vec![0b1111_1000]
+        );
+        assert_eq!(
+            pack_1bpp(&[], BitOrder::MsbFirst, |_| true),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn four_bit_pairs() {
+        let nibble = |c: &Color| Color::ALL.iter().position(|a| a == c).unwrap() as u8;
+        assert_eq!(
+            pack_4bpp(&rows(), nibble).unwrap(),
+            vec![0x01, 0x10, 0x00, 0x01, 0x13, 0x00, 0x00, 0x00]
+        );
+        assert!(pack_4bpp(&[vec![Color::Black; 3]], nibble).is_err());
+    }
+
+    #[test]
+    fn dual_plane() {
+        let (bw, accent) = pack_dual_plane(
+            &rows(),
+            BitOrder::MsbFirst,
+            |c| *c != Color::Black,
+            |c| *c == Color::Red,
+        );
+        assert_eq!(bw, vec![0b0110_0001, 0b1100_0000]);
+        assert_eq!(accent, vec![0b0000_0000, 0b0100_0000]);
+    }
+}
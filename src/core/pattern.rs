@@ -0,0 +1,136 @@
+//! Patterned fills that simulate grey levels and tell chart series apart on panels with only a
+//! few ink colors
+//!
+//! Patterns are anchored to the canvas rather than to each shape, so neighbouring shapes with
+//! the same pattern line up seamlessly.
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Coordinates, Drawable},
+};
+
+use alloc::boxed::Box;
+
+/// A repeating pattern of inked pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    /// Diagonal lines running up to the right, `spacing` pixels apart
+    DiagonalHatch { spacing: usize },
+    /// Diagonal lines in both directions, `spacing` pixels apart
+    Crosshatch { spacing: usize },
+    /// Alternating inked and blank squares `size` pixels wide
+    Checkerboard { size: usize },
+    /// Every other pixel, which reads as a 50% tint of the color
+    Dither50,
+}
+
+impl Pattern {
+    /// Whether the pixel at (x, y) on the canvas is inked
+    pub fn covers(&self, x: usize, y: usize) -> bool {
+        match *self {
+            Pattern::DiagonalHatch { spacing } => (x + y).is_multiple_of(spacing.max(1)),
+            Pattern::Crosshatch { spacing } => {
+                let spacing = spacing.max(1);
+                (x + y).is_multiple_of(spacing)
+                    || (x + spacing - y % spacing).is_multiple_of(spacing)
+            }
+            Pattern::Checkerboard { size } => {
+                let size = size.max(1);
+                (x / size + y / size).is_multiple_of(2)
+            }
+            Pattern::Dither50 => (x + y).is_multiple_of(2),
+        }
+    }
+}
+
+/// How the inside of a shape is filled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FillStyle {
+    /// Every pixel of the shape is inked
+    #[default]
+    Solid,
+    /// Only the pixels of the shape that the pattern covers are inked
+    Pattern(Pattern),
+}
+
+impl FillStyle {
+    /// Whether the pixel at (x, y) on the canvas is inked
+    pub fn covers(&self, x: usize, y: usize) -> bool {
+        match self {
+            FillStyle::Solid => true,
+            FillStyle::Pattern(pattern) => pattern.covers(x, y),
+        }
+    }
+}
+
+/// A drawable filled with a [`FillStyle`]
+pub struct Styled<D> {
+    drawable: D,
+    style: FillStyle,
+}
+
+impl<D: Drawable> Styled<D> {
+    pub fn new(drawable: D, style: FillStyle) -> Self {
+        Self { drawable, style }
+    }
+}
+
+impl<D: Drawable> Drawable for Styled<D> {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.drawable
+                .coordinates()
+                .filter(|&(x, y)| self.style.covers(x, y)),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.drawable.bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FillStyle, Pattern, Styled};
+    use crate::core::colors::Color;
+    use crate::inky::{Canvas, Drawable, Rectangle};
+
+    #[test]
+    fn patterns() {
+        let hatch = Pattern::DiagonalHatch { spacing: 4 };
+        assert!(hatch.covers(0, 0) && hatch.covers(1, 3) && hatch.covers(4, 4));
+        assert!(!hatch.covers(1, 1));
+
+        let cross = Pattern::Crosshatch { spacing: 4 };
+        assert!(cross.covers(1, 3) && cross.covers(1, 1) && cross.covers(5, 1));
+        assert!(!cross.covers(1, 0));
+
+        let checks = Pattern::Checkerboard { size: 2 };
+        assert!(checks.covers(1, 1) && checks.covers(2, 2));
+        assert!(!checks.covers(2, 1));
+
+        assert!(Pattern::Dither50.covers(1, 1));
+        assert!(!Pattern::Dither50.covers(0, 1));
+    }
+
+    #[test]
+    fn styled_fill() {
+        let square = Rectangle::new((0, 0), (3, 3));
+        let dithered = Styled::new(square, FillStyle::Pattern(Pattern::Dither50));
+        assert_eq!(dithered.coordinates().count(), 8);
+        assert_eq!(dithered.bounds(), square.bounds());
+        assert_eq!(
+            Styled::new(square, FillStyle::Solid).coordinates().count(),
+            16
+        );
+
+        let mut canvas = Canvas::new(4, 4);
+        canvas.draw_styled(
+            square,
+            &FillStyle::Pattern(Pattern::Dither50),
+            &Color::Black,
+        );
+        assert_eq!(canvas.get_pixel(0, 0), Color::Black);
+        assert_eq!(canvas.get_pixel(0, 1), Color::White);
+    }
+}
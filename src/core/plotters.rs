@@ -0,0 +1,151 @@
+//! [plotters](https://docs.rs/plotters) support for the drawing canvas
+//!
+//! [`CanvasBackend`] is a plotters `DrawingBackend` that draws onto a `Canvas`, so charts with
+//! axes, labels and legends can be rendered straight to the display. Colors plotters asks for
+//! are ordered dithered against the canvas palette, so shaded fills come out as patterns of
+//! ink rather than all snapping to the same color, and anti-aliased edges are blended with the
+//! pixels already on the canvas.
+
+use crate::{core::colors::Color, inky::Canvas};
+
+use plotters_backend::{BackendColor, BackendCoord, DrawingBackend, DrawingErrorKind};
+
+use std::convert::Infallible;
+
+/// 4x4 Bayer matrix, used to spread dithering evenly across small areas
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How far dithering may push a color towards its neighbours. Half the range of a channel is
+/// enough for a mid grey to become an even mix of black and white, while colors the display
+/// can show exactly stay solid
+const DITHER_SPREAD: i32 = 128;
+
+/// A plotters drawing backend for a [`Canvas`]
+///
+/// ```ignore
+/// let root = CanvasBackend::new(inky.canvas_mut()).into_drawing_area();
+/// root.fill(&WHITE)?;
+/// ```
+pub struct CanvasBackend<'a> {
+    canvas: &'a mut Canvas,
+}
+
+impl<'a> CanvasBackend<'a> {
+    pub fn new(canvas: &'a mut Canvas) -> Self {
+        Self { canvas }
+    }
+
+    // The ink color for an RGB value at a position on the canvas
+    fn dither(&self, (x, y): (usize, usize), (r, g, b): (u8, u8, u8)) -> Color {
+        let threshold = BAYER[y % 4][x % 4] as i32;
+        let offset = (threshold * 2 + 1 - 16) * DITHER_SPREAD / 32;
+        let channel = |value: u8| (value as i32 + offset).clamp(0, 255) as u8;
+        Color::nearest(self.canvas.palette(), channel(r), channel(g), channel(b))
+    }
+}
+
+impl<'a> DrawingBackend for CanvasBackend<'a> {
+    type ErrorType = Infallible;
+
+    fn get_size(&self) -> (u32, u32) {
+        (self.canvas.width() as u32, self.canvas.height() as u32)
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<Infallible>> {
+        Ok(())
+    }
+
+    /// The canvas is sent to the display separately, with [`crate::inky::Inky::update`]
+    fn present(&mut self) -> Result<(), DrawingErrorKind<Infallible>> {
+        Ok(())
+    }
+
+    fn draw_pixel(
+        &mut self,
+        (x, y): BackendCoord,
+        color: BackendColor,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        if x < 0 || y < 0 || color.alpha <= 0.0 {
+            return Ok(());
+        }
+        let (x, y) = (x as usize, y as usize);
+        if x >= self.canvas.width() || y >= self.canvas.height() {
+            return Ok(());
+        }
+        let rgb = if color.alpha >= 1.0 {
+            color.rgb
+        } else {
+            let under = self.canvas.get_pixel(y, x).to_rgb();
+            let blend = |over: u8, under: u8| {
+                (over as f64 * color.alpha + under as f64 * (1.0 - color.alpha)).round() as u8
+            };
+            (
+                blend(color.rgb.0, under.0),
+                blend(color.rgb.1, under.1),
+                blend(color.rgb.2, under.2),
+            )
+        };
+        let ink = self.dither((x, y), rgb);
+        self.canvas.set_pixel(x, y, &ink);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CanvasBackend;
+    use crate::{core::colors::Color, inky::Canvas};
+    use plotters_backend::{BackendColor, DrawingBackend};
+
+    fn color(rgb: (u8, u8, u8), alpha: f64) -> BackendColor {
+        BackendColor { alpha, rgb }
+    }
+
+    #[test]
+    fn solid_colors() {
+        let mut canvas = Canvas::new(10, 10);
+        let mut backend = CanvasBackend::new(&mut canvas);
+        assert_eq!(backend.get_size(), (10, 10));
+        backend
+            .draw_rect((0, 0), (3, 3), &color((255, 0, 0), 1.0), true)
+            .unwrap();
+        backend
+            .draw_line((0, 5), (9, 5), &color((0, 0, 0), 1.0))
+            .unwrap();
+        backend.draw_pixel((-1, 20), color((0, 0, 0), 1.0)).unwrap();
+        for (x, y) in [(0, 0), (1, 2), (3, 3)] {
+            assert_eq!(canvas.get_pixel(y, x), Color::Red);
+        }
+        assert!((0..10).all(|x| canvas.get_pixel(5, x) == Color::Black));
+        assert_eq!(canvas.get_pixel(9, 9), Color::White);
+    }
+
+    #[test]
+    fn dithered_fill() {
+        let mut canvas = Canvas::new(8, 8);
+        let mut backend = CanvasBackend::new(&mut canvas);
+        backend
+            .draw_rect((0, 0), (7, 7), &color((128, 128, 128), 1.0), true)
+            .unwrap();
+        let black = (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.get_pixel(y, x) == Color::Black)
+            .count();
+        assert_eq!(black, 32);
+    }
+
+    #[test]
+    fn alpha_blending() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill(&Color::Black);
+        let mut backend = CanvasBackend::new(&mut canvas);
+        // Mostly transparent white over black stays black
+        backend
+            .draw_rect((0, 0), (3, 3), &color((255, 255, 255), 0.1), true)
+            .unwrap();
+        backend
+            .draw_pixel((0, 0), color((255, 255, 255), 0.0))
+            .unwrap();
+        assert!((0..4).all(|y| (0..4).all(|x| canvas.get_pixel(y, x) == Color::Black)));
+    }
+}
@@ -0,0 +1,113 @@
+//! [serde](https://docs.rs/serde) support for colors and canvases, to cache rendered frames,
+//! send them to a display daemon or snapshot them in tests
+//!
+//! Colors are serialized by their lowercase name. A canvas is serialized compactly as its size
+//! and one digit per pixel, row by row from the top left, giving the index of the pixel's color
+//! in the order black, white, yellow, red, blue, green. The palette and mask are not included,
+//! and a deserialized canvas is marked dirty all over.
+
+use crate::{core::colors::Color, inky::Canvas};
+
+use ::serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
+
+// The serialized form of a canvas
+#[derive(Serialize, Deserialize)]
+struct CanvasData {
+    width: usize,
+    height: usize,
+    pixels: String,
+}
+
+impl Serialize for Canvas {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pixels = self
+            .pixels()
+            .map(|(_, _, color)| {
+                let index = Color::EVERY.iter().position(|c| *c == color).unwrap_or(0);
+                char::from(b'0' + index as u8)
+            })
+            .collect();
+        CanvasData {
+            width: self.width(),
+            height: self.height(),
+            pixels,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Canvas {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = CanvasData::deserialize(deserializer)?;
+        if data.pixels.len() != data.width * data.height {
+            return Err(de::Error::custom(format!(
+                "expected {} pixels for a {}x{} canvas, found {}",
+                data.width * data.height,
+                data.width,
+                data.height,
+                data.pixels.len()
+            )));
+        }
+        // Keep greys, which the default palette would map to black and white
+        let mut canvas = Canvas::new(data.width, data.height).with_palette(&Color::EVERY);
+        for (i, digit) in data.pixels.bytes().enumerate() {
+            let color = digit
+                .checked_sub(b'0')
+                .and_then(|index| Color::EVERY.get(index as usize))
+                .ok_or_else(|| {
+                    de::Error::custom(format!("invalid pixel {:?}", char::from(digit)))
+                })?;
+            canvas.set_pixel(i % data.width, i / data.width, color);
+        }
+        Ok(canvas.with_palette(&Color::ALL))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{colors::Color, geometry::Rect},
+        inky::{Canvas, Rectangle},
+    };
+
+    #[test]
+    fn color() {
+        assert_eq!(serde_json::to_string(&Color::Red).unwrap(), r#""red""#);
+        assert_eq!(
+            serde_json::from_str::<Color>(r#""Blue""#).unwrap(),
+            Color::Blue
+        );
+        assert!(serde_json::from_str::<Color>(r#""purple""#).is_err());
+    }
+
+    #[test]
+    fn canvas_round_trip() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.draw(Rectangle::new((1, 0), (2, 0)), &Color::Red);
+        canvas.draw(Rectangle::new((0, 1), (0, 1)), &Color::Black);
+        let json = serde_json::to_string(&canvas).unwrap();
+        assert_eq!(json, r#"{"width":3,"height":2,"pixels":"133011"}"#);
+
+        let restored: Canvas = serde_json::from_str(&json).unwrap();
+        assert!(restored.pixels().eq(canvas.pixels()));
+        assert_eq!(restored.dirty_region(), Some(Rect::new(0, 0, 3, 2)));
+
+        assert!(
+            serde_json::from_str::<Canvas>(r#"{"width":3,"height":2,"pixels":"13301"}"#).is_err()
+        );
+        assert!(serde_json::from_str::<Canvas>(r#"{"width":1,"height":1,"pixels":"9"}"#).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Rasterising SVG icons and logos with [resvg](https://docs.rs/resvg)
+//!
+//! SVGs are scaled to fit the target size keeping their aspect ratio, centred, and drawn over a
+//! white background before being quantised to the display palette like any other image. Text
+//! elements are not rendered, so convert text to paths before exporting.
+
+use crate::{
+    core::{
+        colors::Color,
+        image::{adjust, ImportOptions},
+    },
+    error::{InkyError, Result},
+    inky::Canvas,
+};
+
+use ::image::RgbImage;
+use resvg::{tiny_skia, usvg};
+use std::path::Path;
+
+/// Render an SVG document to an RGB image `width` by `height` pixels
+pub fn rasterise_svg(data: &[u8], width: u32, height: u32) -> Result<RgbImage> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())?;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| {
+        InkyError::InvalidArgument(format!("Cannot rasterise SVG to {}x{}", width, height))
+    })?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+
+    let size = tree.size();
+    let scale = (width as f32 / size.width()).min(height as f32 / size.height());
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(
+        (width as f32 - size.width() * scale) / 2.0,
+        (height as f32 - size.height() * scale) / 2.0,
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // Every pixel is opaque over the white background, so the premultiplied channels can be
+    // used as they are
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| [pixel.red(), pixel.green(), pixel.blue()])
+        .collect();
+    Ok(RgbImage::from_raw(width, height, pixels).expect("pixmap matches the image size"))
+}
+
+impl Canvas {
+    /// Replace the contents of the canvas with an SVG document, scaled to fit the canvas and
+    /// quantised to `palette`
+    pub fn import_svg(
+        &mut self,
+        data: &[u8],
+        palette: &[Color],
+        options: &ImportOptions,
+    ) -> Result<()> {
+        let mut image = rasterise_svg(data, self.width() as u32, self.height() as u32)?;
+        adjust(&mut image, options);
+        self.draw_image(&image, (0, 0), palette, options.dither);
+        Ok(())
+    }
+
+    /// Load an SVG file with [`Canvas::import_svg`]
+    pub fn load_svg<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        palette: &[Color],
+        options: &ImportOptions,
+    ) -> Result<()> {
+        let data = std::fs::read(path)?;
+        self.import_svg(&data, palette, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rasterise_svg;
+    use crate::{
+        core::{
+            colors::Color,
+            image::{Dither, ImportOptions},
+        },
+        inky::Canvas,
+    };
+
+    const SQUARE: &[u8] = br##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+        <rect x="0" y="0" width="5" height="10" fill="#ff0000"/>
+    </svg>"##;
+
+    #[test]
+    fn fits_and_centres() {
+        // A square SVG in a wide image is centred with white either side
+        let image = rasterise_svg(SQUARE, 40, 20).unwrap();
+        assert_eq!(image.dimensions(), (40, 20));
+        assert_eq!(image.get_pixel(5, 10).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(12, 10).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(25, 10).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn import_svg() {
+        let mut canvas = Canvas::new(20, 20);
+        let options = ImportOptions {
+            dither: Dither::None,
+            saturation: 1.0,
+            ..Default::default()
+        };
+        canvas
+            .import_svg(SQUARE, &[Color::White, Color::Red], &options)
+            .unwrap();
+        assert_eq!(canvas.get_pixel(10, 2), Color::Red);
+        assert_eq!(canvas.get_pixel(10, 15), Color::White);
+        assert!(canvas
+            .import_svg(b"not svg", &Color::ALL, &options)
+            .is_err());
+    }
+}
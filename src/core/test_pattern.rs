@@ -0,0 +1,105 @@
+//! Test patterns for checking a new panel: colour bars to confirm every ink, checkerboards to spot
+//! dead rows and columns, and gradient wedges to judge how dithered tints come out
+//!
+//! Patterns are drawn in the canvas's palette, so the same pattern suits every display.
+
+use crate::{core::colors::Color, core::pattern::Pattern, inky::Canvas};
+
+use alloc::vec::Vec;
+
+/// A pattern that fills the whole canvas, see [`crate::inky::Inky::show_test_pattern`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TestPattern {
+    /// A vertical bar of each palette colour, left to right in palette order
+    ColorBars,
+    /// Black and white squares `size` pixels wide
+    Checkerboard { size: usize },
+    /// A horizontal band for each ink, fading from white on the left to the solid ink on the
+    /// right with an ordered dither
+    GradientWedges,
+}
+
+// 4x4 Bayer matrix for the ordered dither
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl TestPattern {
+    /// The colour of the pixel at (x, y) on a `width` x `height` canvas showing `palette`
+    pub fn color_at(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        palette: &[Color],
+    ) -> Color {
+        match *self {
+            TestPattern::ColorBars => palette[x * palette.len() / width.max(1)],
+            TestPattern::Checkerboard { size } => {
+                let pattern = Pattern::Checkerboard { size };
+                if pattern.covers(x, y) {
+                    Color::Black
+                } else {
+                    Color::White
+                }
+            }
+            TestPattern::GradientWedges => {
+                let inks: Vec<Color> = palette
+                    .iter()
+                    .copied()
+                    .filter(|color| *color != Color::White)
+                    .collect();
+                if inks.is_empty() {
+                    return Color::White;
+                }
+                let ink = inks[y * inks.len() / height.max(1)];
+                // Compare the tint at this column against the dither threshold, both out of 16
+                let tint = 16 * (x + 1) / width.max(1);
+                if tint > BAYER[y % 4][x % 4] as usize {
+                    ink
+                } else {
+                    Color::White
+                }
+            }
+        }
+    }
+
+    /// Replace the contents of the canvas with the pattern
+    pub fn render(&self, canvas: &mut Canvas) {
+        let (width, height) = (canvas.width(), canvas.height());
+        let palette = canvas.palette();
+        for y in 0..height {
+            for x in 0..width {
+                let color = self.color_at(x, y, width, height, palette);
+                canvas.set_pixel(x, y, &color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestPattern;
+    use crate::{core::colors::Color, inky::Canvas};
+
+    #[test]
+    fn test_patterns() {
+        let mut canvas = Canvas::new(60, 24);
+        TestPattern::ColorBars.render(&mut canvas);
+        for (i, color) in Color::ALL.iter().enumerate() {
+            assert_eq!(canvas.get(i * 10 + 5, 12), Some(*color));
+        }
+
+        TestPattern::Checkerboard { size: 4 }.render(&mut canvas);
+        assert_eq!(canvas.get(0, 0), Some(Color::Black));
+        assert_eq!(canvas.get(4, 0), Some(Color::White));
+        assert_eq!(canvas.get(4, 4), Some(Color::Black));
+
+        // A single black band on a black and white panel, fading in from white
+        let mut canvas = Canvas::new(32, 8).with_palette(&[Color::Black, Color::White]);
+        TestPattern::GradientWedges.render(&mut canvas);
+        assert!((0..8).all(|y| canvas.get(0, y) == Some(Color::White)));
+        assert!((0..8).all(|y| canvas.get(31, y) == Some(Color::Black)));
+        let inked = canvas.pixels().filter(|(_, _, c)| *c == Color::Black).count();
+        assert!((100..156).contains(&inked), "{} black pixels", inked);
+    }
+}
@@ -0,0 +1,341 @@
+//! Text rendering onto the drawing canvas
+//!
+//! Text is rendered from bitmap fonts implementing [`Font`]. A built in 8x8 font covering
+//! Latin, Greek, Hiragana and box drawing characters is always available as [`BuiltinFont`], and
+//! [`FallbackFonts`] fills the gaps of one font with the glyphs of others.
+//!
+//! Text is normalised to composed form before it is drawn, so accents written as combining
+//! characters use the precomposed glyph of the font where it has one.
+
+use crate::{
+    core::{geometry::Rect, transform::Rotation},
+    inky::{Coordinates, Drawable},
+};
+
+use font8x8::{
+    UnicodeFonts, BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, HIRAGANA_FONTS, LATIN_FONTS,
+    MISC_FONTS,
+};
+use unicode_normalization::UnicodeNormalization;
+
+/// A single rendered character
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glyph {
+    pub width: usize,
+    pub height: usize,
+    /// Horizontal distance to the start of the next glyph
+    pub advance: usize,
+    /// Row-major coverage of the glyph, `width * height` entries long
+    pub bitmap: Vec<bool>,
+}
+
+impl Glyph {
+    /// Whether the pixel at (x, y) within the glyph is set
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.bitmap[y * self.width + x]
+    }
+}
+
+/// A source of glyphs for text rendering
+pub trait Font {
+    /// Get the glyph for a character, if the font has one
+    fn glyph(&self, c: char) -> Option<Glyph>;
+    /// Distance between the tops of consecutive lines
+    fn line_height(&self) -> usize;
+}
+
+/// The built in 8x8 bitmap font
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuiltinFont;
+
+impl Font for BuiltinFont {
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        let rows = BASIC_FONTS
+            .get(c)
+            .or_else(|| LATIN_FONTS.get(c))
+            .or_else(|| GREEK_FONTS.get(c))
+            .or_else(|| BOX_FONTS.get(c))
+            .or_else(|| BLOCK_FONTS.get(c))
+            .or_else(|| HIRAGANA_FONTS.get(c))
+            .or_else(|| MISC_FONTS.get(c))?;
+        // Each row is a byte with the leftmost pixel in the least significant bit
+        let bitmap = rows
+            .iter()
+            .flat_map(|row| (0..8).map(move |bit| row & (1 << bit) != 0))
+            .collect();
+        Some(Glyph {
+            width: 8,
+            height: 8,
+            advance: 8,
+            bitmap,
+        })
+    }
+
+    fn line_height(&self) -> usize {
+        8
+    }
+}
+
+/// A chain of fonts, each character drawn with the first font that has a glyph for it. Glyphs
+/// are top aligned, so fonts of similar size mix best
+pub struct FallbackFonts<'a> {
+    fonts: Vec<&'a dyn Font>,
+}
+
+impl<'a> FallbackFonts<'a> {
+    /// Start a chain with its primary font
+    pub fn new(primary: &'a dyn Font) -> Self {
+        Self {
+            fonts: vec![primary],
+        }
+    }
+
+    /// Add a font to try after the fonts already in the chain
+    pub fn with_fallback(mut self, font: &'a dyn Font) -> Self {
+        self.fonts.push(font);
+        self
+    }
+}
+
+impl Font for FallbackFonts<'_> {
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        self.fonts.iter().find_map(|font| font.glyph(c))
+    }
+
+    fn line_height(&self) -> usize {
+        self.fonts
+            .iter()
+            .map(|font| font.line_height())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// A string of text drawn with its top left corner at `position`
+pub struct Text<'a> {
+    position: (usize, usize),
+    content: String,
+    font: &'a dyn Font,
+    scale: usize,
+    rotation: Rotation,
+}
+
+impl Text<'static> {
+    /// Create text drawn in the built in font
+    pub fn new(position: (usize, usize), content: &str) -> Self {
+        Self {
+            position,
+            content: content.nfc().collect(),
+            font: &BuiltinFont,
+            scale: 1,
+            rotation: Rotation::None,
+        }
+    }
+
+    /// Create text in the built in font, scaled by `scale` and centered within `area`
+    pub fn centered(content: &str, scale: usize, area: Rect) -> Self {
+        let (width, height) = Text::new((0, 0), content).with_scale(scale).size();
+        let position = (
+            area.x + area.width.saturating_sub(width) / 2,
+            area.y + area.height.saturating_sub(height) / 2,
+        );
+        Text::new(position, content).with_scale(scale)
+    }
+}
+
+impl<'a> Text<'a> {
+    /// Draw the text with a different font
+    pub fn with_font(self, font: &dyn Font) -> Text<'_> {
+        Text {
+            position: self.position,
+            content: self.content,
+            font,
+            scale: self.scale,
+            rotation: self.rotation,
+        }
+    }
+
+    /// Scale every glyph pixel up to a `scale` x `scale` block
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Turn the text clockwise, so that at 90 degrees it reads top to bottom. `position` stays
+    /// the top left corner of the rotated text
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Width and height of the rendered text in pixels, after rotation
+    pub fn size(&self) -> (usize, usize) {
+        let (width, height) = self.unrotated_size();
+        match self.rotation {
+            Rotation::None | Rotation::Deg180 => (width, height),
+            Rotation::Deg90 | Rotation::Deg270 => (height, width),
+        }
+    }
+
+    fn unrotated_size(&self) -> (usize, usize) {
+        let mut width = 0;
+        let mut lines = 0;
+        for line in self.content.lines() {
+            let line_width: usize = line
+                .chars()
+                .filter_map(|c| self.font.glyph(c))
+                .map(|glyph| glyph.advance)
+                .sum();
+            width = width.max(line_width);
+            lines += 1;
+        }
+        (
+            width * self.scale,
+            lines * self.font.line_height() * self.scale,
+        )
+    }
+
+    // Returns the coordinates covered by the glyphs of the text
+    fn text_coordinates(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (x0, y0) = self.position;
+        let (width, height) = self.unrotated_size();
+        let rotation = self.rotation;
+        // Glyph pixels beyond the advance of the last character can fall outside of the
+        // rotated box, and are dropped
+        self.unrotated_coordinates().filter_map(move |(x, y)| {
+            let (x, y) = match rotation {
+                Rotation::None => (x, y),
+                Rotation::Deg90 => (height.checked_sub(y + 1)?, x),
+                Rotation::Deg180 => (width.checked_sub(x + 1)?, height.checked_sub(y + 1)?),
+                Rotation::Deg270 => (y, width.checked_sub(x + 1)?),
+            };
+            Some((x0 + x, y0 + y))
+        })
+    }
+
+    // Returns the coordinates of the glyphs relative to the top left of the text, before rotation
+    fn unrotated_coordinates(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let scale = self.scale;
+        let line_height = self.font.line_height() * scale;
+
+        self.content
+            .lines()
+            .enumerate()
+            .flat_map(move |(line_no, line)| {
+                let top = line_no * line_height;
+                // Characters missing from the font are skipped rather than drawn as boxes
+                line.chars()
+                    .filter_map(|c| self.font.glyph(c))
+                    .scan(0, move |left, glyph| {
+                        let glyph_left = *left;
+                        *left += glyph.advance * scale;
+                        Some((glyph_left, glyph))
+                    })
+                    .flat_map(move |(left, glyph)| {
+                        let set: Vec<(usize, usize)> = (0..glyph.height)
+                            .flat_map(|y| (0..glyph.width).map(move |x| (x, y)))
+                            .filter(|&(x, y)| glyph.is_set(x, y))
+                            .collect();
+                        set.into_iter().flat_map(move |(x, y)| {
+                            (0..scale * scale).map(move |d| {
+                                (left + x * scale + d % scale, top + y * scale + d / scale)
+                            })
+                        })
+                    })
+            })
+    }
+}
+
+impl Drawable for Text<'_> {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(self.text_coordinates())
+    }
+
+    fn bounds(&self) -> Rect {
+        let (width, height) = self.size();
+        Rect::new(self.position.0, self.position.1, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuiltinFont, FallbackFonts, Font, Glyph, Text};
+    use crate::core::{geometry::Rect, transform::Rotation};
+    use crate::inky::Drawable;
+
+    #[test]
+    fn test_builtin_glyph() {
+        let glyph = BuiltinFont.glyph('I').expect("Missing glyph");
+        assert_eq!((glyph.width, glyph.height), (8, 8));
+        assert!(glyph.bitmap.iter().any(|set| *set));
+        assert_eq!(
+            BuiltinFont.glyph(' ').map(|g| g.bitmap.contains(&true)),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_fallback_fonts() {
+        // A font with a single glyph, taller than the built in font
+        struct Block;
+        impl Font for Block {
+            fn glyph(&self, c: char) -> Option<Glyph> {
+                (c == '\u{4e00}').then(|| Glyph {
+                    width: 10,
+                    height: 10,
+                    advance: 10,
+                    bitmap: vec![true; 100],
+                })
+            }
+            fn line_height(&self) -> usize {
+                10
+            }
+        }
+
+        let fonts = FallbackFonts::new(&BuiltinFont).with_fallback(&Block);
+        assert_eq!(fonts.line_height(), 10);
+        assert_eq!(fonts.glyph('\u{4e00}').map(|g| g.width), Some(10));
+        assert_eq!(fonts.glyph('A'), BuiltinFont.glyph('A'));
+        // Characters no font has are skipped
+        let text = Text::new((0, 0), "A\u{4e00}\u{4e8c}").with_font(&fonts);
+        assert_eq!(text.size(), (18, 10));
+
+        // A decomposed accent uses the precomposed glyph
+        let decomposed = Text::new((0, 0), "e\u{301}");
+        assert_eq!(decomposed.size(), (8, 8));
+        assert!(decomposed
+            .coordinates()
+            .eq(Text::new((0, 0), "\u{e9}").coordinates()));
+        assert!(BuiltinFont.glyph('\u{3042}').is_some());
+    }
+
+    #[test]
+    fn test_rotated_text() {
+        let text = Text::new((2, 3), "I");
+        let upright: Vec<(usize, usize)> = text.coordinates().collect();
+        let turned = Text::new((2, 3), "Hi").with_rotation(Rotation::Deg90);
+        assert_eq!(turned.size(), (8, 16));
+        assert_eq!(turned.bounds(), Rect::new(2, 3, 8, 16));
+        assert!(turned
+            .coordinates()
+            .all(|(x, y)| (2..10).contains(&x) && (3..19).contains(&y)));
+
+        // Half a turn maps each pixel to the opposite corner of the box
+        let flipped = Text::new((2, 3), "I").with_rotation(Rotation::Deg180);
+        let mut expected: Vec<(usize, usize)> =
+            upright.iter().map(|&(x, y)| (11 - x, 13 - y)).collect();
+        let mut actual: Vec<(usize, usize)> = flipped.coordinates().collect();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_text_size() {
+        let text = Text::new((4, 4), "Hi\nthere").with_scale(2);
+        assert_eq!(text.size(), (80, 32));
+        assert!(text
+            .coordinates()
+            .all(|(x, y)| (4..84).contains(&x) && (4..36).contains(&y)));
+    }
+}
@@ -0,0 +1,237 @@
+//! Positioning drawables at draw time, so widgets can be written in their own local coordinates
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Canvas, Coordinates, Drawable},
+};
+
+use alloc::boxed::Box;
+
+/// Clockwise rotation in quarter turns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    None,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// Where the pixel at (x, y) of a `width` by `height` image lands once the image is turned
+    pub(crate) fn turn(
+        self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+    ) -> (usize, usize) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Deg90 => (height - 1 - y, x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (y, width - 1 - x),
+        }
+    }
+
+    /// Where a non-empty region of a `width` by `height` image lands once the image is turned
+    #[cfg(any(feature = "hardware", test))]
+    pub(crate) fn turn_rect(self, region: Rect, size: (usize, usize)) -> Rect {
+        Rect::from_corners(
+            self.turn((region.x, region.y), size),
+            self.turn((region.right() - 1, region.bottom() - 1), size),
+        )
+    }
+}
+
+/// Scales, rotates about the origin and then translates a drawable's coordinates, in that order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Transform {
+    pub translate: (isize, isize),
+    pub scale: usize,
+    pub rotation: Rotation,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translate: (0, 0),
+            scale: 1,
+            rotation: Rotation::None,
+        }
+    }
+}
+
+impl Transform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn translate(mut self, x: isize, y: isize) -> Self {
+        self.translate = (x, y);
+        self
+    }
+
+    /// Draw each pixel as a `scale` by `scale` block
+    pub fn scale(mut self, scale: usize) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn rotate(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    // Move a point that has already been scaled, without clipping
+    fn map(&self, (x, y): (isize, isize)) -> (isize, isize) {
+        // Screen y runs downwards, so these turn clockwise on the display
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Deg90 => (-y, x),
+            Rotation::Deg180 => (-x, -y),
+            Rotation::Deg270 => (y, -x),
+        };
+        (x + self.translate.0, y + self.translate.1)
+    }
+
+    /// Map a local point to the canvas pixels it covers, leaving out any that fall left of or
+    /// above the canvas
+    pub fn apply(&self, (x, y): (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+        let transform = *self;
+        let scale = self.scale as isize;
+        let (x, y) = (x as isize * scale, y as isize * scale);
+        (0..scale * scale).filter_map(move |d| {
+            let (x, y) = transform.map((x + d % scale, y + d / scale));
+            (x >= 0 && y >= 0).then_some((x as usize, y as usize))
+        })
+    }
+}
+
+/// A drawable with a [`Transform`] applied to it
+pub struct Transformed<D> {
+    drawable: D,
+    transform: Transform,
+}
+
+impl<D: Drawable> Transformed<D> {
+    pub fn new(drawable: D, transform: Transform) -> Self {
+        Self {
+            drawable,
+            transform,
+        }
+    }
+}
+
+impl<D: Drawable> Drawable for Transformed<D> {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.drawable
+                .coordinates()
+                .flat_map(|point| self.transform.apply(point)),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        let inner = self.drawable.bounds();
+        if inner.is_empty() {
+            return Rect::default();
+        }
+        let scale = self.transform.scale as isize;
+        let (a, b) = (
+            self.transform
+                .map((inner.x as isize * scale, inner.y as isize * scale)),
+            self.transform.map((
+                inner.right() as isize * scale - 1,
+                inner.bottom() as isize * scale - 1,
+            )),
+        );
+        Rect::from_signed_corners((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1)))
+    }
+}
+
+impl Canvas {
+    /// A copy of the canvas turned clockwise by `rotation`, e.g. to draw a portrait layout and
+    /// show it on a landscape panel mounted on its side
+    pub fn rotated(&self, rotation: Rotation) -> Canvas {
+        let (width, height) = (self.width(), self.height());
+        let mut rotated = match rotation {
+            Rotation::None | Rotation::Deg180 => Canvas::new(width, height),
+            Rotation::Deg90 | Rotation::Deg270 => Canvas::new(height, width),
+        }
+        .with_palette(self.palette());
+        for (x, y, color) in self.pixels() {
+            let (x, y) = rotation.turn((x, y), (width, height));
+            rotated.set_pixel(x, y, &color);
+        }
+        rotated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rotation, Transform, Transformed};
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::{Canvas, Drawable, Rectangle};
+
+    #[test]
+    fn translate_and_scale() {
+        let transform = Transform::new().translate(10, 20).scale(2);
+        assert_eq!(
+            transform.apply((1, 0)).collect::<Vec<_>>(),
+            vec![(12, 20), (13, 20), (12, 21), (13, 21)]
+        );
+    }
+
+    #[test]
+    fn rotations() {
+        let point = (3, 1);
+        let at = |rotation| {
+            Transform::new()
+                .rotate(rotation)
+                .translate(5, 5)
+                .apply(point)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(at(Rotation::None), vec![(8, 6)]);
+        assert_eq!(at(Rotation::Deg90), vec![(4, 8)]);
+        assert_eq!(at(Rotation::Deg180), vec![(2, 4)]);
+        assert_eq!(at(Rotation::Deg270), vec![(6, 2)]);
+        // Rotating without moving back onto the canvas clips the point
+        assert!(Transform::new()
+            .rotate(Rotation::Deg180)
+            .apply(point)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn transformed_drawable() {
+        let bar = Rectangle::new((0, 0), (3, 0));
+        let transform = Transform::new().rotate(Rotation::Deg90).translate(2, 0);
+        let mut coordinates: Vec<_> = Transformed::new(bar, transform).coordinates().collect();
+        coordinates.sort_unstable();
+        assert_eq!(coordinates, vec![(2, 0), (2, 1), (2, 2), (2, 3)]);
+        assert_eq!(
+            Transformed::new(bar, transform).bounds(),
+            Rect::new(2, 0, 1, 4)
+        );
+        let scaled = Transform::new().scale(3).translate(1, 1);
+        assert_eq!(
+            Transformed::new(bar, scaled).bounds(),
+            Rect::new(1, 1, 12, 3)
+        );
+    }
+
+    #[test]
+    fn rotated_canvas() {
+        let mut canvas = Canvas::new(4, 2);
+        canvas.draw(Rectangle::new((0, 0), (0, 0)), &Color::Red);
+        let rotated = canvas.rotated(Rotation::Deg90);
+        assert_eq!((rotated.width(), rotated.height()), (2, 4));
+        assert_eq!(rotated.get(1, 0), Some(Color::Red));
+        assert_eq!(canvas.rotated(Rotation::Deg180).get(3, 1), Some(Color::Red));
+        assert_eq!(canvas.rotated(Rotation::Deg270).get(0, 3), Some(Color::Red));
+
+        let region = Rotation::Deg90.turn_rect(Rect::new(0, 0, 2, 1), (4, 2));
+        assert_eq!(region, Rect::new(1, 0, 1, 2));
+    }
+}
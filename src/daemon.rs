@@ -0,0 +1,365 @@
+//! A display daemon that shares one panel between programs
+//!
+//! [`serve`] owns the display and listens on a Unix socket for local programs, and [`serve_tcp`]
+//! listens on a TCP address so rendering can be done on another machine with
+//! [`crate::remote::RemoteInky`]. Each client connection is handled on its own thread, and the
+//! requests of every client are run one at a time on an [`InkyHandle`], so programs take turns
+//! with the SPI bus and GPIO lines instead of fighting over them. See [`crate::protocol`] for the
+//! messages clients send.
+//!
+//! Both stop taking clients once their [`Shutdown`] is stopped, and leave the display once the
+//! requests already queued are done, see [`crate::shutdown`].
+
+use crate::{
+    core::colors::Color,
+    error::{InkyError, Result},
+    handle::InkyHandle,
+    inky::{Canvas, Inky, UpdateStatus},
+    metrics::Metrics,
+    protocol::{Request, Response},
+    shutdown::{Shutdown, POLL_INTERVAL},
+};
+
+use log::{error, info, warn};
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, ToSocketAddrs},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::Arc,
+    thread,
+};
+
+/// Copy `frame` onto the canvas, which must be the same size
+fn draw_frame(canvas: &mut Canvas, frame: &Canvas) -> Result<()> {
+    if (frame.width(), frame.height()) != (canvas.width(), canvas.height()) {
+        return Err(InkyError::InvalidArgument(format!(
+            "Frame is {}x{} but the display is {}x{}",
+            frame.width(),
+            frame.height(),
+            canvas.width(),
+            canvas.height()
+        )));
+    }
+    canvas.blit(frame, (0, 0), None);
+    Ok(())
+}
+
+/// Run `request` against the display, drawing images with colors from `palette` and the
+/// display's [`Inky::import_options`], and recording the outcome of updates in `metrics`
+fn handle_request(
+    inky: &mut Inky,
+    palette: &[Color],
+    metrics: &Metrics,
+    request: Request,
+) -> Result<Response> {
+    let status = match request {
+        Request::Frame(frame) => {
+            draw_frame(inky.canvas_mut(), &frame).and_then(|()| inky.update())
+        }
+        Request::Image(data) => ::image::load_from_memory(&data)
+            .map_err(InkyError::from)
+            .and_then(|image| {
+                let options = inky.import_options().clone();
+                inky.canvas_mut().import_image(&image, palette, &options);
+                inky.update()
+            }),
+        Request::Raw(data) => inky.update_raw(data),
+        Request::Clear => inky.clear(),
+        Request::Clean { cycles } => {
+            inky.clean(cycles as usize, &Color::White)?;
+            return Ok(Response::Updated);
+        }
+        Request::Info => {
+            let canvas = inky.canvas();
+            return Ok(Response::Info {
+                width: canvas.width(),
+                height: canvas.height(),
+                palette: palette.to_vec(),
+            });
+        }
+    };
+    metrics.observe(inky, &status);
+    Ok(match status? {
+        UpdateStatus::Updated => Response::Updated,
+        UpdateStatus::Unchanged => Response::Unchanged,
+    })
+}
+
+/// Answer the requests from `reader` on `writer` until the client disconnects
+fn handle_client(
+    handle: &InkyHandle,
+    palette: &'static [Color],
+    metrics: &Arc<Metrics>,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    while let Some(request) = Request::read(&mut reader)? {
+        let metrics = Arc::clone(metrics);
+        let response = handle
+            .call(move |inky| handle_request(inky, palette, &metrics, request))?
+            .unwrap_or_else(|e| Response::Error(e.to_string()));
+        response.write(&mut writer)?;
+    }
+    Ok(())
+}
+
+/// Wait for the next client of a nonblocking listener, or `None` once `shutdown` is stopped
+fn next_client<S>(
+    shutdown: &Shutdown,
+    mut accept: impl FnMut() -> io::Result<S>,
+) -> Result<Option<S>> {
+    while !shutdown.is_stopping() {
+        match accept() {
+            Ok(stream) => return Ok(Some(stream)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(None)
+}
+
+/// Leave the display once the requests queued before the shutdown are done
+fn finish(handle: &InkyHandle, shutdown: &Shutdown) -> Result<()> {
+    let shutdown = shutdown.clone();
+    handle.call(move |inky| shutdown.finish(inky))
+}
+
+/// Remove a socket left behind at `path` by a daemon that has exited. Anything that isn't a
+/// socket, or a socket a daemon is still listening on, is left alone and reported as an error
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(InkyError::InvalidArgument(format!(
+            "{} exists and is not a socket",
+            path.display()
+        )));
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Err(InkyError::InvalidArgument(format!(
+            "Another daemon is listening on {}",
+            path.display()
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            warn!("Removing stale socket {}", path.display());
+            Ok(fs::remove_file(path)?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Serve clients on a Unix socket at `path` until the socket fails or `shutdown` is stopped,
+/// drawing images with colors from `palette` and recording updates in `metrics`. A socket left
+/// behind at `path` by an earlier run that has exited is replaced, and the socket is removed on
+/// shutdown
+pub fn serve(
+    inky: Inky,
+    palette: &'static [Color],
+    metrics: Arc<Metrics>,
+    path: impl AsRef<Path>,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let path = path.as_ref();
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    info!("Listening on {}", path.display());
+
+    let handle = InkyHandle::spawn(inky);
+    while let Some((stream, _)) = next_client(shutdown, || listener.accept())? {
+        stream.set_nonblocking(false)?;
+        let reader = stream.try_clone()?;
+        let handle = handle.clone();
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            if let Err(e) = handle_client(&handle, palette, &metrics, reader, stream) {
+                error!("Client failed: {}", e);
+            }
+        });
+    }
+    finish(&handle, shutdown)?;
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Serve clients on the TCP address `addr` until the listener fails or `shutdown` is stopped,
+/// like [`serve`]. The protocol has no authentication, so only listen on a trusted network
+pub fn serve_tcp(
+    inky: Inky,
+    palette: &'static [Color],
+    metrics: Arc<Metrics>,
+    addr: impl ToSocketAddrs,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    info!("Listening on {}", listener.local_addr()?);
+
+    let handle = InkyHandle::spawn(inky);
+    while let Some((stream, peer)) = next_client(shutdown, || listener.accept())? {
+        stream.set_nonblocking(false)?;
+        let reader = stream.try_clone()?;
+        let handle = handle.clone();
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            if let Err(e) = handle_client(&handle, palette, &metrics, reader, stream) {
+                error!("Client {} failed: {}", peer, e);
+            }
+        });
+    }
+    finish(&handle, shutdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_client, remove_stale_socket, serve};
+    use crate::{
+        core::colors::Color,
+        eeprom::ColorMode,
+        handle::InkyHandle,
+        inky::{Canvas, Rectangle, UpdateStatus},
+        metrics::Metrics,
+        protocol::{Request, Response},
+        remote::RemoteInky,
+        shutdown::{OfflineScreen, Shutdown},
+        testutil::mock_inky,
+    };
+    use std::{
+        fs,
+        os::unix::net::{UnixListener, UnixStream},
+        process,
+        sync::Arc,
+        thread,
+        time::Duration,
+    };
+
+    fn mock_handle() -> InkyHandle {
+        InkyHandle::spawn(mock_inky())
+    }
+
+    #[test]
+    fn requests() {
+        let handle = mock_handle();
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let metrics = Arc::new(Metrics::new());
+        let daemon = {
+            let handle = handle.clone();
+            let metrics = Arc::clone(&metrics);
+            let palette = ColorMode::Spectra6.palette();
+            thread::spawn(move || handle_client(&handle, palette, &metrics, &server, &server))
+        };
+
+        Request::Info.write(&mut client).unwrap();
+        assert_eq!(
+            Response::read(&mut client).unwrap(),
+            Response::Info {
+                width: 800,
+                height: 480,
+                palette: ColorMode::Spectra6.palette().to_vec(),
+            }
+        );
+
+        let mut frame = Canvas::new(800, 480);
+        frame.draw(Rectangle::new((10, 10), (20, 20)), &Color::Blue);
+        Request::Frame(frame.clone()).write(&mut client).unwrap();
+        assert_eq!(Response::read(&mut client).unwrap(), Response::Updated);
+        Request::Frame(frame).write(&mut client).unwrap();
+        assert_eq!(Response::read(&mut client).unwrap(), Response::Unchanged);
+
+        Request::Frame(Canvas::new(10, 10)).write(&mut client).unwrap();
+        assert!(matches!(
+            Response::read(&mut client).unwrap(),
+            Response::Error(_)
+        ));
+
+        drop(client);
+        daemon.join().unwrap().unwrap();
+        let text = metrics.render();
+        assert!(text.contains("inky_updates_total{outcome=\"updated\"} 1\n"));
+        assert!(text.contains("inky_updates_total{outcome=\"unchanged\"} 1\n"));
+        assert!(text.contains("inky_updates_total{outcome=\"error\"} 1\n"));
+        let blue = handle
+            .call(|inky| inky.canvas().get(15, 15) == Some(Color::Blue))
+            .unwrap();
+        assert!(blue);
+    }
+
+    #[test]
+    fn remote_client() {
+        let handle = mock_handle();
+        let (client, server) = UnixStream::pair().unwrap();
+        let metrics = Arc::new(Metrics::new());
+        let daemon = {
+            let handle = handle.clone();
+            let metrics = Arc::clone(&metrics);
+            let palette = ColorMode::Spectra6.palette();
+            thread::spawn(move || handle_client(&handle, palette, &metrics, &server, &server))
+        };
+
+        let mut remote =
+            RemoteInky::with_stream(Box::new(client.try_clone().unwrap()), Box::new(client))
+                .unwrap();
+        assert_eq!(remote.canvas().width(), 800);
+        assert_eq!(remote.canvas().palette(), ColorMode::Spectra6.palette());
+        remote
+            .canvas_mut()
+            .draw(Rectangle::new((0, 0), (9, 9)), &Color::Green);
+        assert_eq!(remote.update().unwrap(), UpdateStatus::Updated);
+        assert_eq!(remote.update().unwrap(), UpdateStatus::Unchanged);
+        // Errors from the daemon come back as errors rather than closing the connection
+        assert!(remote.update_raw(vec![0; 3]).is_err());
+        assert_eq!(remote.clear().unwrap(), UpdateStatus::Updated);
+
+        drop(remote);
+        daemon.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn shutdown() {
+        let path = std::env::temp_dir().join(format!("inky-daemon-{}.sock", process::id()));
+        let shutdown = Shutdown::new().with_offline(OfflineScreen::Text("Offline".into()));
+        let daemon = {
+            let (path, shutdown) = (path.clone(), shutdown.clone());
+            let palette = ColorMode::Spectra6.palette();
+            let metrics = Arc::new(Metrics::new());
+            thread::spawn(move || serve(mock_inky(), palette, metrics, &path, &shutdown))
+        };
+        while !path.exists() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        shutdown.stop();
+        daemon.join().unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn stale_socket() {
+        let path = std::env::temp_dir().join(format!("inky-stale-{}.sock", process::id()));
+
+        fs::write(&path, "not a socket").unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+
+        let listener = UnixListener::bind(&path).unwrap();
+        assert!(remove_stale_socket(&path).is_err());
+        assert!(path.exists());
+
+        // Dropping the listener closes the socket but leaves the file behind
+        drop(listener);
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+        remove_stale_socket(&path).unwrap();
+    }
+}
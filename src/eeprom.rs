@@ -9,16 +9,23 @@
 //! - 4:4  - The screen color capabilities (BW, BWR, BWY, or 7-Color)
 //! - 5:5  - The PCB variant - Only has one valid value, reserved for future products
 //! - 6:6  - The display variant - determines what chip the display uses, and what its color
-//!          configuration is
+//!   configuration is
 //! - 7:30 - The time the EEPROM was written
 
 // use crate::inky::Color as InkyColor;
-use anyhow::{bail, ensure, Context, Error, Result};
+use crate::{
+    core::colors::Color,
+    error::{InkyError, Result},
+};
 use chrono::NaiveDateTime;
-use log::error;
+#[cfg(feature = "hardware")]
+use tracing::{debug, warn};
 use num::{FromPrimitive as ConvertFromPrimitive, ToPrimitive as ConvertToPrimitive};
 use num_derive::{FromPrimitive, ToPrimitive};
+#[cfg(feature = "hardware")]
 use rppal::i2c::I2c;
+use std::fmt;
+#[cfg(feature = "hardware")]
 use std::{thread::sleep, time::Duration};
 
 // Inky devices all use Bus 1
@@ -37,7 +44,7 @@ impl PascalString {
     fn with_capacity(capacity: u8) -> Self {
         Self {
             capacity,
-            data: Vec::with_capacity(capacity as usize - 1),
+            data: Vec::with_capacity((capacity as usize).saturating_sub(1)),
         }
     }
 
@@ -66,24 +73,28 @@ impl PascalString {
 }
 
 impl From<PascalString> for Vec<u8> {
-    /// Construct a vector from the data in a pascal string
+    /// Construct a vector from the data in a pascal string, zero padded to its capacity
     fn from(value: PascalString) -> Self {
-        let mut v = vec![value.capacity];
+        let mut v = vec![value.data.len() as u8];
         v.extend(value.data.iter());
+        v.resize((value.capacity as usize).max(1), 0);
         v
     }
 }
 
 impl TryFrom<&[u8]> for PascalString {
-    type Error = Error;
+    type Error = InkyError;
 
     /// Try to construct a pascal string from a slice
     fn try_from(value: &[u8]) -> Result<Self> {
-        ensure!(value.len() < (u8::MAX - 1) as usize, "Value is too large");
-        let mut s = Self::with_capacity(value.len().try_into()?);
+        if value.len() >= (u8::MAX - 1) as usize {
+            return Err(InkyError::Eeprom("Value is too large".into()));
+        }
+        let mut s = Self::with_capacity((value.len() as u8).max(1));
         if s.capacity > 1 {
-            let data = &value[1..];
-            s.set_capacity(data.len());
+            // The length byte may be shorter than the space reserved for the string
+            let len = (value[0] as usize).min(value.len() - 1);
+            let data = &value[1..=len];
             s.set_data(data.to_vec());
         }
         Ok(s)
@@ -102,6 +113,19 @@ pub enum ColorMode {
     RedYellow = 7,
 }
 
+impl ColorMode {
+    /// The ink colors a display with this color configuration can show
+    pub fn palette(&self) -> &'static [Color] {
+        match self {
+            ColorMode::Black => &[Color::Black, Color::White],
+            ColorMode::Red => &[Color::Black, Color::White, Color::Red],
+            ColorMode::Yellow => &[Color::Black, Color::White, Color::Yellow],
+            ColorMode::RedYellow => &[Color::Black, Color::White, Color::Yellow, Color::Red],
+            ColorMode::SevenColor | ColorMode::Spectra6 => &Color::ALL,
+        }
+    }
+}
+
 // impl TryFrom<ColorMode> for InkyColor {
 //     type Error = Error;
 
@@ -118,20 +142,22 @@ pub enum ColorMode {
 // }
 
 impl TryFrom<ColorMode> for u8 {
-    type Error = Error;
+    type Error = InkyError;
 
     /// Convert the color value back to a primitive u8
     fn try_from(value: ColorMode) -> Result<Self> {
-        ConvertToPrimitive::to_u8(&value).context(format!("Invalid ColorMode value {:?}", value))
+        ConvertToPrimitive::to_u8(&value)
+            .ok_or_else(|| InkyError::Eeprom(format!("Invalid ColorMode value {:?}", value)))
     }
 }
 
 impl TryFrom<u8> for ColorMode {
-    type Error = Error;
+    type Error = InkyError;
 
     /// Convert a primitive u8 value read from EEPROM to a color value
     fn try_from(value: u8) -> Result<Self> {
-        ConvertFromPrimitive::from_u8(value).context(format!("Invalid ColorMode value {}", value))
+        ConvertFromPrimitive::from_u8(value)
+            .ok_or_else(|| InkyError::Eeprom(format!("Invalid ColorMode value {}", value)))
     }
 }
 
@@ -173,24 +199,56 @@ pub enum DisplayVariant {
     JD79668,
 }
 
+impl DisplayVariant {
+    /// The EEPROM code for this variant with the given color configuration
+    pub fn code(&self, color: &ColorMode) -> u8 {
+        match (self, color) {
+            (Self::Phat, ColorMode::Black) => 4,
+            (Self::Phat, ColorMode::Yellow) => 5,
+            (Self::Phat, _) => 1,
+            (Self::PhatSsd1608, ColorMode::Red) => 11,
+            (Self::PhatSsd1608, ColorMode::Yellow) => 12,
+            (Self::PhatSsd1608, _) => 10,
+            (Self::What, ColorMode::Yellow) => 2,
+            (Self::What, ColorMode::Red) => 6,
+            (Self::What, _) => 3,
+            (Self::Uc8159_600x448, _) => 14,
+            (Self::Uc8159_640x400, _) => 15,
+            (Self::WhatSsd1683, ColorMode::Red) => 18,
+            (Self::WhatSsd1683, ColorMode::Yellow) => 19,
+            (Self::WhatSsd1683, _) => 17,
+            (Self::Ac073Tc1A, _) => 20,
+            (Self::EL133UF1, _) => 21,
+            (Self::E673, _) => 22,
+            (Self::JD79661, _) => 23,
+            (Self::JD79668, _) => 24,
+        }
+    }
+}
+
 impl TryFrom<u8> for DisplayVariant {
-    type Error = Error;
+    type Error = InkyError;
 
     /// Convert a display variant code read from EEPROM to a variant identifier
     fn try_from(value: u8) -> Result<Self> {
         Ok(match value {
             1 | 4 | 5 => Self::Phat,
-            10 | 11 | 12 => Self::PhatSsd1608,
-            2 | 3 | 6 | 7 | 8 => Self::What,
+            10..=12 => Self::PhatSsd1608,
+            2 | 3 | 6..=8 => Self::What,
             14 => Self::Uc8159_600x448,
             15 | 16 => Self::Uc8159_640x400,
-            17 | 18 | 19 => Self::WhatSsd1683,
+            17..=19 => Self::WhatSsd1683,
             20 => Self::EL133UF1,
             21 => Self::E673,
             22 => Self::E673,
             23 => Self::JD79661,
             24 => Self::JD79668,
-            _ => bail!("Invalid value {} for DisplayVariant", value),
+            _ => {
+                return Err(InkyError::Eeprom(format!(
+                    "Invalid value {} for DisplayVariant",
+                    value
+                )))
+            }
         })
     }
 }
@@ -204,6 +262,8 @@ pub struct EEPROM {
     color: ColorMode,
     pcb_variant: u8,
     display_variant: DisplayVariant,
+    // Raw variant code, as several codes share a DisplayVariant
+    display_variant_code: u8,
     eeprom_write_time: PascalString,
 }
 
@@ -214,8 +274,8 @@ impl From<EEPROM> for Vec<u8> {
         v.extend_from_slice(&value.width.to_le_bytes());
         v.extend_from_slice(&value.height.to_le_bytes());
         v.push(value.color as u8);
-        v.push(value.pcb_variant as u8);
-        v.push(value.display_variant as u8);
+        v.push(value.pcb_variant);
+        v.push(value.display_variant_code);
         let write_time: Vec<u8> = value.eeprom_write_time.into();
         v.extend(write_time);
         v
@@ -223,12 +283,18 @@ impl From<EEPROM> for Vec<u8> {
 }
 
 impl TryFrom<&[u8]> for EEPROM {
-    type Error = Error;
+    type Error = InkyError;
 
     /// Decode EEPROM data from bytes read from the I2C interface
     fn try_from(value: &[u8]) -> Result<Self> {
-        let width = u16::from_le_bytes(value[..2].try_into()?);
-        let height = u16::from_le_bytes(value[2..4].try_into()?);
+        if value.len() < 7 {
+            return Err(InkyError::Eeprom(format!(
+                "EEPROM data is too short ({} bytes)",
+                value.len()
+            )));
+        }
+        let width = u16::from_le_bytes([value[0], value[1]]);
+        let height = u16::from_le_bytes([value[2], value[3]]);
         let color = ColorMode::try_from(value[4])?;
         let pcb_variant = value[5];
         let display_variant = DisplayVariant::try_from(value[6])?;
@@ -246,49 +312,127 @@ impl TryFrom<&[u8]> for EEPROM {
             color,
             pcb_variant,
             display_variant,
+            display_variant_code: value[6],
             eeprom_write_time,
         })
     }
 }
 
+/// Display parameters normally read from the EEPROM, for panels without a working EEPROM
+#[derive(Clone, Debug)]
+pub struct DisplaySpec {
+    pub width: u16,
+    pub height: u16,
+    pub variant: DisplayVariant,
+    pub color: ColorMode,
+}
+
+impl From<DisplaySpec> for EEPROM {
+    /// Build an EEPROM image describing a manually specified display. The write time is left
+    /// empty
+    fn from(value: DisplaySpec) -> Self {
+        Self {
+            width: value.width,
+            height: value.height,
+            display_variant_code: value.variant.code(&value.color),
+            color: value.color,
+            pcb_variant: 12,
+            display_variant: value.variant,
+            eeprom_write_time: PascalString::with_capacity(1),
+        }
+    }
+}
+
 impl EEPROM {
     // Address of the i2c device
     pub const ADDRESS: u16 = 0x50;
     // Give up by default after 10 attempts to read the EEPROM
     pub const DEFAULT_TRIES: usize = 10;
+    // Length of the EEPROM contents in bytes
+    pub const LEN: usize = 29;
+    // Space for the write time, including its length byte
+    #[cfg_attr(not(feature = "eeprom-write"), allow(dead_code))]
+    const WRITE_TIME_LEN: u8 = 22;
 
     /// Try to initialize EEPROM by reading it from the chip a default number of times
+    #[cfg(feature = "hardware")]
     pub fn try_new() -> Result<Self> {
         Self::try_new_tries(Self::DEFAULT_TRIES)
     }
 
+    // Read the raw EEPROM contents from the start of its memory
+    #[cfg(feature = "hardware")]
+    fn read_raw(i2c_bus: &mut I2c) -> Result<[u8; Self::LEN]> {
+        i2c_bus.set_slave_address(Self::ADDRESS)?;
+        i2c_bus.write(&[0x00; 2])?;
+        // sleep(Duration::from_millis(1000));
+        let mut buffer = [0x00; Self::LEN];
+        i2c_bus.set_slave_address(Self::ADDRESS)?;
+        let read = i2c_bus.read(&mut buffer)?;
+        if read < Self::LEN {
+            return Err(InkyError::Eeprom(format!("Read length {} is too small", read)));
+        }
+        Ok(buffer)
+    }
+
+    /// Program the display EEPROM with `spec` and the current time, then read it back to verify
+    /// the write.
+    ///
+    /// This overwrites the data that identifies the display. Programming the wrong spec causes
+    /// the display to be driven with the wrong controller commands and voltages, which can damage
+    /// the panel. Only use it to repair a blank or corrupted EEPROM with the values for your exact
+    /// display.
+    #[cfg(feature = "eeprom-write")]
+    pub fn write(spec: DisplaySpec) -> Result<Self> {
+        let mut eeprom = EEPROM::from(spec);
+        let mut write_time = PascalString::with_capacity(Self::WRITE_TIME_LEN);
+        let now = chrono::Local::now().naive_local();
+        write_time.set_data(now.format("%Y-%m-%d %H:%M:%S%.f").to_string().into_bytes());
+        eeprom.eeprom_write_time = write_time;
+        let data: Vec<u8> = eeprom.into();
+
+        let mut i2c_bus = I2c::with_bus(INKY_BUS)?;
+        i2c_bus.set_slave_address(Self::ADDRESS)?;
+        // The two byte memory address is followed by the data, which fits in the first page
+        let mut message = vec![0x00, 0x00];
+        message.extend_from_slice(&data);
+        i2c_bus.write(&message)?;
+        // Wait out the EEPROM's internal write cycle
+        sleep(Duration::from_millis(20));
+
+        let written = Self::read_raw(&mut i2c_bus)?;
+        if written[..] != data[..] {
+            return Err(InkyError::Eeprom(format!(
+                "Read back {:?} after writing {:?}",
+                written, data
+            )));
+        }
+        Self::try_from(written.as_slice())
+    }
+
     /// Try to initialize EEPROM by reading it from the chip a specified number of times
+    #[cfg(feature = "hardware")]
     pub fn try_new_tries(max_tries: usize) -> Result<Self> {
         let mut i2c_bus = I2c::with_bus(INKY_BUS)?;
 
         for i in 0..max_tries {
-            println!{"Trying to connect: {}", i}
-            i2c_bus.set_slave_address(Self::ADDRESS)?;
-            i2c_bus.write(&[0x00; 2])?;
-            // sleep(Duration::from_millis(1000));
-             println!{"Reading: {}", i}
-            let buffer = &mut [0x00; 29];
-            i2c_bus.set_slave_address(Self::ADDRESS)?;
-            let read = i2c_bus.read(buffer)?;
-            ensure!(read >= 29, "Read length {} is too small", read);
+            debug!(attempt = i, "Reading EEPROM");
+            let buffer = &Self::read_raw(&mut i2c_bus)?;
             match buffer.as_slice().try_into() {
                 Ok(eeprom) => {
                     return Ok(eeprom);
                 }
                 Err(e) => {
-                    eprintln!{"Not parsed: {:?} {}",buffer, e}    
-                    error!("Failed to initialize eeprom, retrying: {}", e);
+                    warn!(?buffer, "Failed to parse EEPROM, retrying: {}", e);
                 }
             }
             sleep(Duration::from_secs_f32(0.1));
         }
 
-        bail!("Failed to initialize eeprom in {} tries", max_tries);
+        Err(InkyError::Eeprom(format!(
+            "Failed to initialize eeprom in {} tries",
+            max_tries
+        )))
     }
 
     /// Get the width value of the display
@@ -316,25 +460,50 @@ impl EEPROM {
         self.display_variant.clone()
     }
 
+    /// Get the raw display variant code stored in the EEPROM
+    pub fn display_variant_code(&self) -> u8 {
+        self.display_variant_code
+    }
+
     /// Get the last write time fo the display as a DateTime (with no TZ info)
     pub fn eeprom_write_time(&self) -> Result<NaiveDateTime> {
         let string = String::from_utf8_lossy(&self.eeprom_write_time.data);
-        Ok(NaiveDateTime::parse_from_str(
-            &string,
-            "%Y-%m-%d %H:%M:%S%.1f",
-        )?)
+        NaiveDateTime::parse_from_str(&string, "%Y-%m-%d %H:%M:%S%.f")
+            .map_err(|e| InkyError::Eeprom(format!("Invalid write time {:?}: {}", string, e)))
+    }
+}
+
+impl fmt::Display for EEPROM {
+    /// Summarise the display for logs and support requests
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {:?} display, {:?} colors (variant code {}), PCB variant {}, written ",
+            self.width,
+            self.height,
+            self.display_variant,
+            self.color,
+            self.display_variant_code,
+            self.pcb_variant
+        )?;
+        match self.eeprom_write_time() {
+            Ok(time) => write!(f, "{}", time),
+            Err(_) => write!(f, "at an unknown time"),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::eeprom::EEPROM;
+    use crate::eeprom::{ColorMode, DisplaySpec, DisplayVariant, EEPROM};
+    use crate::error::InkyError;
     // A buffer retrieved with this code:
     // 144, 1, 44, 1, 1, 12, 3, 21, 50, 48, 50, 48, 45, 49, 48, 45, 48, 49, 32, 49, 53, 58, 53, 49, 58, 52, 51, 46, 51, 255, 255, 255
     // A buffer retrieved with smbus2:
     // 144, 1, 44, 1, 1, 12, 3, 21, 50, 48, 50, 48, 45, 49, 48, 45, 48, 49, 32, 49, 53, 58, 53, 49, 58, 52, 51, 46, 51
 
     #[test]
+    #[cfg(feature = "hardware")]
     /// Tests that EEPROM can be initialized by reading it from the device
     /// no specific device is tested for, because you should be able to run
     /// this test on any device with an Inky e-ink display plugged into it.
@@ -342,4 +511,55 @@ mod tests {
     fn init_eeprom() {
         _ = EEPROM::try_new().expect("Failed to initialize eeprom");
     }
+
+    #[test]
+    /// Tests that a known good EEPROM image decodes, and that a bad variant is reported
+    fn decode_eeprom() {
+        let mut buffer = vec![
+            144, 1, 44, 1, 1, 12, 3, 21, 50, 48, 50, 48, 45, 49, 48, 45, 48, 49, 32, 49, 53, 58,
+            53, 49, 58, 52, 51, 46, 51,
+        ];
+        let eeprom = EEPROM::try_from(buffer.as_slice()).expect("Failed to decode eeprom");
+        assert_eq!((eeprom.width(), eeprom.height()), (400, 300));
+        assert!(matches!(eeprom.display_variant(), DisplayVariant::What));
+        assert_eq!(eeprom.pcb_variant(), 12);
+        assert_eq!(eeprom.display_variant_code(), 3);
+        assert_eq!(
+            eeprom.to_string(),
+            "400x300 What display, Black colors (variant code 3), PCB variant 12, written 2020-10-01 15:51:43.300"
+        );
+
+        buffer[6] = 99;
+        assert!(matches!(
+            EEPROM::try_from(buffer.as_slice()),
+            Err(InkyError::Eeprom(_))
+        ));
+    }
+
+    #[test]
+    /// Tests that a manual display spec produces an equivalent EEPROM
+    fn eeprom_from_spec() {
+        let eeprom = EEPROM::from(DisplaySpec {
+            width: 800,
+            height: 480,
+            variant: DisplayVariant::E673,
+            color: ColorMode::Spectra6,
+        });
+        assert_eq!((eeprom.width(), eeprom.height()), (800, 480));
+        assert!(matches!(eeprom.display_variant(), DisplayVariant::E673));
+        assert!(matches!(eeprom.color(), ColorMode::Spectra6));
+        assert!(eeprom.eeprom_write_time().is_err());
+    }
+
+    #[test]
+    /// Tests that an EEPROM image survives encoding and decoding
+    fn eeprom_round_trip() {
+        let buffer = [
+            144, 1, 44, 1, 1, 12, 3, 21, 50, 48, 50, 48, 45, 49, 48, 45, 48, 49, 32, 49, 53, 58,
+            53, 49, 58, 52, 51, 46, 51,
+        ];
+        let eeprom = EEPROM::try_from(buffer.as_slice()).expect("Failed to decode eeprom");
+        let encoded: Vec<u8> = eeprom.into();
+        assert_eq!(encoded, buffer);
+    }
 }
@@ -0,0 +1,143 @@
+//! Error types returned by the library
+
+use crate::core::colors::Color;
+#[cfg(feature = "std")]
+use crate::eeprom::DisplayVariant;
+
+use alloc::string::String;
+use core::time::Duration;
+use thiserror::Error;
+
+/// Errors that can occur while talking to or drawing for an Inky display
+#[derive(Debug, Error)]
+pub enum InkyError {
+    /// The EEPROM could not be read or its contents could not be decoded
+    #[error("EEPROM error: {0}")]
+    Eeprom(String),
+    /// The display variant has no driver in this library
+    #[cfg(feature = "std")]
+    #[error("Unsupported display variant {0:?}")]
+    UnsupportedVariant(DisplayVariant),
+    /// The display does not support the requested operation
+    #[error("Unsupported operation: {0}")]
+    Unsupported(String),
+    /// A frame passed to a driver contains a color the panel can't show. Colors drawn on the
+    /// canvas are mapped to the palette, so this only comes from frames built by hand
+    #[error("The display cannot show {0}")]
+    UnsupportedColor(Color),
+    /// An argument was outside of the range the display accepts
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[cfg(feature = "hardware")]
+    #[error("SPI error: {0}")]
+    Spi(#[from] rppal::spi::Error),
+    #[cfg(feature = "hardware")]
+    #[error("GPIO error: {0}")]
+    Gpio(#[from] rppal::gpio::Error),
+    #[cfg(feature = "hardware")]
+    #[error("I2C error: {0}")]
+    I2c(#[from] rppal::i2c::Error),
+    #[cfg(feature = "cdev")]
+    #[error("GPIO character device error: {0}")]
+    Cdev(#[from] gpio_cdev::Error),
+    /// The display did not signal that it was ready in time
+    #[error("Timed out after {elapsed:?} waiting for the display to become ready")]
+    BusyTimeout { elapsed: Duration },
+    /// The refresh policy does not allow another refresh yet
+    #[error("Refresh refused by the refresh policy, retry in {retry_after:?}")]
+    RefreshTooSoon { retry_after: Duration },
+    #[cfg(feature = "image")]
+    #[error("Image error: {0}")]
+    Image(#[from] image::ImageError),
+    /// An SVG document could not be parsed
+    #[cfg(feature = "svg")]
+    #[error("SVG error: {0}")]
+    Svg(#[from] resvg::usvg::Error),
+    /// A font file could not be parsed
+    #[cfg(feature = "text")]
+    #[error("Font error: {0}")]
+    Font(String),
+    #[cfg(feature = "std")]
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A directory could not be watched for changes
+    #[cfg(feature = "slideshow")]
+    #[error("Watch error: {0}")]
+    Watch(#[from] notify::Error),
+    /// The HTTP server could not be started
+    #[cfg(feature = "server")]
+    #[error("Server error: {0}")]
+    Server(String),
+    /// The MQTT connection failed
+    #[cfg(feature = "mqtt")]
+    #[error("MQTT error: {0}")]
+    Mqtt(String),
+    /// The display daemon refused a request, see [`crate::remote::RemoteInky`]
+    #[error("Remote display error: {0}")]
+    Remote(String),
+    /// The worker thread behind an [`crate::handle::InkyHandle`] has stopped, after a panic
+    #[error("The display worker thread has stopped")]
+    WorkerStopped,
+    /// A packed buffer did not have the length the display expects
+    #[error("Buffer length {actual} does not match the expected length {expected}")]
+    BufferSize { expected: usize, actual: usize },
+}
+
+impl InkyError {
+    /// Whether the error came from talking to the display, which may have been left part way
+    /// through a sequence with its high voltage supply on
+    pub fn is_transfer_error(&self) -> bool {
+        match self {
+            InkyError::BusyTimeout { .. } => true,
+            #[cfg(feature = "hardware")]
+            InkyError::Spi(_) | InkyError::Gpio(_) => true,
+            #[cfg(feature = "cdev")]
+            InkyError::Cdev(_) => true,
+            #[cfg(feature = "std")]
+            InkyError::Io(_) => true,
+            _ => false,
+        }
+    }
+
+    /// A short name for the kind of error, one of a fixed set, e.g. to label metrics with
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InkyError::Eeprom(_) => "eeprom",
+            #[cfg(feature = "std")]
+            InkyError::UnsupportedVariant(_) => "unsupported_variant",
+            InkyError::Unsupported(_) => "unsupported",
+            InkyError::UnsupportedColor(_) => "unsupported_color",
+            InkyError::InvalidArgument(_) => "invalid_argument",
+            #[cfg(feature = "hardware")]
+            InkyError::Spi(_) => "spi",
+            #[cfg(feature = "hardware")]
+            InkyError::Gpio(_) => "gpio",
+            #[cfg(feature = "hardware")]
+            InkyError::I2c(_) => "i2c",
+            #[cfg(feature = "cdev")]
+            InkyError::Cdev(_) => "cdev",
+            InkyError::BusyTimeout { .. } => "busy_timeout",
+            InkyError::RefreshTooSoon { .. } => "refresh_too_soon",
+            #[cfg(feature = "image")]
+            InkyError::Image(_) => "image",
+            #[cfg(feature = "svg")]
+            InkyError::Svg(_) => "svg",
+            #[cfg(feature = "text")]
+            InkyError::Font(_) => "font",
+            #[cfg(feature = "std")]
+            InkyError::Io(_) => "io",
+            #[cfg(feature = "slideshow")]
+            InkyError::Watch(_) => "watch",
+            #[cfg(feature = "server")]
+            InkyError::Server(_) => "server",
+            #[cfg(feature = "mqtt")]
+            InkyError::Mqtt(_) => "mqtt",
+            InkyError::Remote(_) => "remote",
+            InkyError::WorkerStopped => "worker_stopped",
+            InkyError::BufferSize { .. } => "buffer_size",
+        }
+    }
+}
+
+/// Result type used throughout the library
+pub type Result<T, E = InkyError> = core::result::Result<T, E>;
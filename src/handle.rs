@@ -0,0 +1,112 @@
+//! A cloneable handle for driving one display from several threads
+//!
+//! [`InkyHandle::spawn`] moves an [`Inky`] onto a worker thread that runs queued jobs one at a
+//! time, so a web server thread and a timer thread can both draw and refresh without building
+//! their own mutex and queue. The worker stops once every handle has been dropped.
+
+use crate::{
+    error::{InkyError, Result},
+    inky::{Canvas, Inky, UpdateStatus},
+};
+
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+type Job = Box<dyn FnOnce(&mut Inky) + Send>;
+
+#[derive(Clone)]
+pub struct InkyHandle {
+    jobs: Sender<Job>,
+}
+
+impl InkyHandle {
+    /// Move `inky` to a new worker thread and return a handle to it
+    pub fn spawn(mut inky: Inky) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("inky".into())
+            .spawn(move || {
+                for job in queue {
+                    job(&mut inky);
+                }
+            })
+            .expect("Failed to spawn the display worker thread");
+        Self { jobs }
+    }
+
+    /// Queue drawing on the canvas and return without waiting for it
+    pub fn draw(&self, draw: impl FnOnce(&mut Canvas) + Send + 'static) -> Result<()> {
+        self.jobs
+            .send(Box::new(move |inky| draw(inky.canvas_mut())))
+            .map_err(|_| InkyError::WorkerStopped)
+    }
+
+    /// Run `job` on the worker thread once the jobs queued before it are done, and wait for its
+    /// result
+    pub fn call<R: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut Inky) -> R + Send + 'static,
+    ) -> Result<R> {
+        let (reply, result) = mpsc::sync_channel(1);
+        self.jobs
+            .send(Box::new(move |inky| {
+                // The caller may have given up waiting
+                let _ = reply.send(job(inky));
+            }))
+            .map_err(|_| InkyError::WorkerStopped)?;
+        result.recv().map_err(|_| InkyError::WorkerStopped)
+    }
+
+    /// Refresh the display once the drawing queued so far is done, see [`Inky::update`]
+    pub fn update(&self) -> Result<UpdateStatus> {
+        self.call(Inky::update)?
+    }
+
+    /// Present the frame drawn so far, see [`Inky::present`]
+    pub fn present(&self) -> Result<UpdateStatus> {
+        self.call(Inky::present)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InkyHandle;
+    use crate::{
+        core::colors::Color,
+        error::InkyError,
+        inky::{Rectangle, UpdateStatus},
+        testutil::mock_inky,
+    };
+    use std::thread;
+
+    #[test]
+    fn draw_from_threads() {
+        let inky = mock_inky();
+        let handle = InkyHandle::spawn(inky);
+
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle.draw(move |canvas| {
+                        canvas.draw(Rectangle::new((i * 10, 0), (i * 10 + 4, 4)), &Color::Black)
+                    })
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap().unwrap();
+        }
+        assert_eq!(handle.update().unwrap(), UpdateStatus::Updated);
+        let inked = handle
+            .call(|inky| (0..4).all(|i| inky.canvas().get(i * 10, 0) == Some(Color::Black)))
+            .unwrap();
+        assert!(inked);
+
+        // A panicking job stops the worker
+        let _ = handle.call(|_| panic!("job failed"));
+        assert!(matches!(handle.update(), Err(InkyError::WorkerStopped)));
+    }
+}
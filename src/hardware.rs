@@ -1,3 +1,9 @@
+pub mod buttons;
+#[cfg(feature = "cdev")]
+pub mod cdev;
 pub mod display;
+pub mod dry_run;
 pub mod inkye673;
-pub mod inkywhat;
\ No newline at end of file
+pub mod inkywhat;
+pub mod mock;
+pub mod transcript;
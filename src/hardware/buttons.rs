@@ -0,0 +1,172 @@
+//! The four buttons on the side of the Inky Impression boards
+
+use crate::error::Result;
+
+use rppal::gpio::{Gpio, InputPin, Level, Trigger};
+
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Button {
+    pub const ALL: [Button; 4] = [Button::A, Button::B, Button::C, Button::D];
+
+    /// BCM pin the button is wired to on the Impression boards
+    pub fn pin(&self) -> u8 {
+        match self {
+            Button::A => 5,
+            Button::B => 6,
+            Button::C => 16,
+            Button::D => 24,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonAction {
+    Pressed,
+    Released,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub action: ButtonAction,
+    pub time: Instant,
+}
+
+/// Drops edges that arrive within `window` of the last accepted one, which filters out contact
+/// bounce
+struct Debouncer {
+    window: Duration,
+    last: Option<Instant>,
+}
+
+impl Debouncer {
+    fn new(window: Duration) -> Self {
+        Self { window, last: None }
+    }
+
+    fn accept(&mut self, now: Instant) -> bool {
+        match self.last {
+            Some(last) if now.saturating_duration_since(last) < self.window => false,
+            _ => {
+                self.last = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Debounced button events, delivered from GPIO interrupts. The buttons stop reporting events
+/// when this is dropped
+pub struct Buttons {
+    // Held so the interrupts stay registered
+    _pins: Vec<InputPin>,
+    events: Receiver<ButtonEvent>,
+}
+
+impl Buttons {
+    pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+    pub fn new() -> Result<Self> {
+        Self::with_debounce(Self::DEFAULT_DEBOUNCE)
+    }
+
+    pub fn with_debounce(debounce: Duration) -> Result<Self> {
+        let gpio = Gpio::new()?;
+        let (tx, events) = channel();
+        let pins = Button::ALL
+            .iter()
+            .map(|&button| {
+                let mut pin = gpio.get(button.pin())?.into_input_pullup();
+                pin.set_async_interrupt(
+                    Trigger::Both,
+                    Self::handler(button, debounce, tx.clone()),
+                )?;
+                Ok(pin)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            _pins: pins,
+            events,
+        })
+    }
+
+    fn handler(
+        button: Button,
+        debounce: Duration,
+        tx: Sender<ButtonEvent>,
+    ) -> impl FnMut(Level) + Send {
+        let mut debouncer = Debouncer::new(debounce);
+        move |level| {
+            let time = Instant::now();
+            if !debouncer.accept(time) {
+                return;
+            }
+            // The buttons pull the line low while held
+            let action = match level {
+                Level::Low => ButtonAction::Pressed,
+                Level::High => ButtonAction::Released,
+            };
+            // Nobody is listening any more once the receiver is gone, so the event can be dropped
+            let _ = tx.send(ButtonEvent {
+                button,
+                action,
+                time,
+            });
+        }
+    }
+
+    /// Wait for the next button event
+    pub fn next_event(&self) -> Option<ButtonEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next button event
+    pub fn next_event_timeout(&self, timeout: Duration) -> Option<ButtonEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Return an event if one is waiting, without blocking
+    pub fn try_next_event(&self) -> Option<ButtonEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Blocking iterator over button events
+    pub fn events(&self) -> impl Iterator<Item = ButtonEvent> + '_ {
+        self.events.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Button, Debouncer};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn debounce_drops_bounces() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(50));
+        let start = Instant::now();
+        assert!(debouncer.accept(start));
+        assert!(!debouncer.accept(start + Duration::from_millis(10)));
+        assert!(!debouncer.accept(start + Duration::from_millis(49)));
+        assert!(debouncer.accept(start + Duration::from_millis(60)));
+        assert!(!debouncer.accept(start + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn button_pins() {
+        let pins: Vec<u8> = Button::ALL.iter().map(Button::pin).collect();
+        assert_eq!(pins, vec![5, 6, 16, 24]);
+    }
+}
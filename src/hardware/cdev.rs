@@ -0,0 +1,267 @@
+//! A connection through the Linux GPIO character device and spidev, for boards other than the
+//! Raspberry Pi that carry the same HAT header, such as the Orange Pi and Rock Pi
+//!
+//! rppal only knows the Pi's own GPIO controller, so [`CdevConnection`] requests the pins from a
+//! `/dev/gpiochipN` device and writes to `/dev/spidevB.C` instead. Select it with
+//! [`Backend::Cdev`]. The pin numbers in the [`ConnectionConfig`] are line offsets on
+//! [`ConnectionConfig::gpio_chip`], which on other boards are not the BCM numbers of the HAT
+//! pinout; `gpioinfo` lists them. The busy line is read at an interval rather than waited on with
+//! an interrupt.
+//!
+//! Unlike rppal, spidev can switch the bus to 3-wire mode, where the controller answers on the
+//! data line, so this connection can read registers such as the panel temperature. The chip
+//! select line has to stay low from the command to the end of the reply, so reading needs
+//! [`ChipSelect::Software`].
+
+use crate::{
+    eeprom::EEPROM,
+    error::{InkyError, Result},
+    hardware::display::{
+        spi_chunk_size, BusyPolarity, BusyWait, ChipSelect, Connection, ConnectionConfig,
+        ConnectionTimings, InkyConnection, Retry, SpiPacket, Timing,
+    },
+};
+
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+use rppal::gpio::{Level, Trigger};
+use spidev::{SpiModeFlags, Spidev, SpidevOptions};
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use tracing::{trace, warn};
+
+const CONSUMER: &str = "inky";
+
+pub struct CdevConnection {
+    spi: Spidev,
+    cs: Option<LineHandle>,
+    dc: LineHandle,
+    reset: LineHandle,
+    busy: LineHandle,
+    eeprom: EEPROM,
+    busy_timeout: Duration,
+    busy_poll: Duration,
+    cs_setup_delay: Duration,
+    busy_polarity: Option<BusyPolarity>,
+    timing: Option<Timing>,
+    chunk_size: usize,
+    retry: Retry,
+    timings: ConnectionTimings,
+}
+
+fn output(chip: &mut Chip, pin: u8, level: Level) -> Result<LineHandle> {
+    Ok(chip
+        .get_line(pin.into())?
+        .request(LineRequestFlags::OUTPUT, level as u8, CONSUMER)?)
+}
+
+impl CdevConnection {
+    /// GPIO chip used when the configuration doesn't name one
+    pub const DEFAULT_GPIO_CHIP: &'static str = "/dev/gpiochip0";
+
+    /// Interval between reads of the busy line, unless the configuration asks to poll at another
+    pub const DEFAULT_BUSY_POLL: Duration = Duration::from_millis(10);
+
+    /// Connect using the SPI device and lines in `config`, clocking the bus at `default_speed` and
+    /// driving chip select as `default_cs` unless the configuration overrides them
+    pub fn with_config(
+        eeprom: EEPROM,
+        config: &ConnectionConfig,
+        default_speed: u32,
+        default_cs: ChipSelect,
+    ) -> Result<Self> {
+        let chip = config
+            .gpio_chip
+            .as_deref()
+            .unwrap_or(Path::new(Self::DEFAULT_GPIO_CHIP));
+        let mut chip = Chip::new(chip)?;
+
+        let mut spi = Spidev::open(format!(
+            "/dev/spidev{}.{}",
+            config.bus as u8, config.slave_select as u8
+        ))?;
+        spi.configure(
+            &SpidevOptions::new()
+                .bits_per_word(8)
+                .max_speed_hz(config.spi_speed.unwrap_or(default_speed))
+                .mode(SpiModeFlags::SPI_MODE_0)
+                .build(),
+        )?;
+
+        Ok(Self {
+            spi,
+            cs: match config.chip_select.unwrap_or(default_cs) {
+                ChipSelect::Hardware => None,
+                ChipSelect::Software(pin) => Some(output(&mut chip, pin, Level::High)?),
+            },
+            dc: output(&mut chip, config.dc_pin, Level::Low)?,
+            reset: output(&mut chip, config.reset_pin, Level::High)?,
+            busy: chip
+                .get_line(config.busy_pin.into())?
+                .request(LineRequestFlags::INPUT, 0, CONSUMER)?,
+            eeprom,
+            busy_timeout: InkyConnection::DEFAULT_BUSY_TIMEOUT,
+            busy_poll: match config.busy_wait {
+                BusyWait::Interrupt => Self::DEFAULT_BUSY_POLL,
+                BusyWait::Poll(interval) => interval,
+            },
+            cs_setup_delay: config.cs_setup_delay,
+            busy_polarity: config.busy_polarity,
+            timing: config.timing,
+            chunk_size: spi_chunk_size(config),
+            retry: config.retry,
+            timings: ConnectionTimings::default(),
+        })
+    }
+
+    fn transfer(&mut self, packet: SpiPacket) -> Result<()> {
+        let retry = self.retry;
+        self.dc.set_value(0)?;
+        retry.run(|| self.spi.write_all(&[packet.command]))?;
+
+        if let Some(data) = packet.data {
+            self.dc.set_value(1)?;
+            for chunk in data.chunks(self.chunk_size) {
+                retry.run(|| self.spi.write_all(chunk))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Switch the data line between driving the controller and listening to it
+    fn set_three_wire(&mut self, enabled: bool) -> Result<()> {
+        let mode = if enabled {
+            SpiModeFlags::SPI_MODE_0 | SpiModeFlags::SPI_3WIRE
+        } else {
+            SpiModeFlags::SPI_MODE_0
+        };
+        self.spi.configure(&SpidevOptions::new().mode(mode).build())?;
+        Ok(())
+    }
+
+    fn read_reply(&mut self, command: u8, len: usize) -> Result<Vec<u8>> {
+        self.dc.set_value(0)?;
+        self.spi.write_all(&[command])?;
+        self.dc.set_value(1)?;
+        self.set_three_wire(true)?;
+        let mut reply = vec![0; len];
+        let result = self.spi.read_exact(&mut reply);
+        self.set_three_wire(false)?;
+        result?;
+        Ok(reply)
+    }
+
+    fn read_busy(&self) -> Result<Level> {
+        Ok(match self.busy.get_value()? {
+            0 => Level::Low,
+            _ => Level::High,
+        })
+    }
+}
+
+impl Connection for CdevConnection {
+    /// Send a command and its data, framed by the chip select line when it is driven in software
+    fn send(&mut self, packet: SpiPacket) -> Result<()> {
+        trace!(
+            command = packet.command,
+            bytes = packet.data.as_ref().map_or(0, Vec::len),
+            "Sending packet"
+        );
+        let start = Instant::now();
+        if let Some(cs) = self.cs.as_ref() {
+            cs.set_value(0)?;
+            sleep(self.cs_setup_delay);
+        }
+        let result = self.transfer(packet);
+        if let Some(cs) = self.cs.as_ref() {
+            cs.set_value(1)?;
+        }
+        self.dc.set_value(0)?;
+        self.timings.transfer += start.elapsed();
+        result
+    }
+
+    fn set_reset(&mut self, level: Level) {
+        if let Err(e) = self.reset.set_value(level as u8) {
+            warn!("Failed to drive the reset line: {}", e);
+        }
+    }
+
+    fn busy(&self) -> Level {
+        self.read_busy().unwrap_or_else(|e| {
+            warn!("Failed to read the busy line: {}", e);
+            Level::Low
+        })
+    }
+
+    fn wait_for_busy(&mut self, trigger: Trigger, timeout: Option<Duration>) -> Result<()> {
+        let timeout = timeout.unwrap_or(self.busy_timeout);
+        let ready = match trigger {
+            Trigger::FallingEdge => Level::Low,
+            _ => Level::High,
+        };
+        let start = Instant::now();
+        let result = loop {
+            match self.read_busy() {
+                Ok(level) if level == ready => break Ok(()),
+                Ok(_) if start.elapsed() >= timeout => {
+                    break Err(InkyError::BusyTimeout {
+                        elapsed: start.elapsed(),
+                    })
+                }
+                Ok(_) => sleep(self.busy_poll),
+                Err(e) => break Err(e),
+            }
+        };
+        self.timings.busy_wait += start.elapsed();
+        result
+    }
+
+    fn eeprom(&self) -> &EEPROM {
+        &self.eeprom
+    }
+
+    fn busy_timeout(&self) -> Duration {
+        self.busy_timeout
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
+    fn take_timings(&mut self) -> ConnectionTimings {
+        std::mem::take(&mut self.timings)
+    }
+
+    fn busy_polarity(&self) -> Option<BusyPolarity> {
+        self.busy_polarity
+    }
+
+    fn timing(&self) -> Option<Timing> {
+        self.timing
+    }
+
+    fn can_read(&self) -> bool {
+        self.cs.is_some()
+    }
+
+    fn read(&mut self, command: u8, len: usize) -> Result<Vec<u8>> {
+        let Some(cs) = self.cs.as_ref() else {
+            return Err(InkyError::Unsupported(
+                "Reading from the display needs a software chip select".into(),
+            ));
+        };
+        cs.set_value(0)?;
+        sleep(self.cs_setup_delay);
+        let result = self.read_reply(command, len);
+        if let Some(cs) = self.cs.as_ref() {
+            cs.set_value(1)?;
+        }
+        self.dc.set_value(0)?;
+        result
+    }
+}
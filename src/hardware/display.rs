@@ -1,16 +1,27 @@
 use crate::{
     eeprom::{EEPROM},
-    core::colors::Color,
+    core::{colors::Color, geometry::Rect},
+    error::{InkyError, Result},
+    lut::LutKind,
 };
+#[cfg(feature = "cdev")]
+use crate::hardware::cdev::CdevConnection;
 
+use derive_builder::Builder;
 use rppal::{
-    gpio::{Gpio, InputPin, OutputPin},
+    gpio::{Gpio, InputPin, Level, OutputPin, Trigger},
     spi::{Bus, Mode, SlaveSelect as SecondarySelect, Spi},
 };
 
-use anyhow::Result;
-use std::time::Duration;
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use tracing::{trace, warn};
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SpiPacket {
     pub command: u8,
     pub data: Option<Vec<u8>>,
@@ -25,62 +36,715 @@ impl SpiPacket {
     }
 }
 
+/// Trade-off between refresh speed and image quality for full refreshes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RefreshMode {
+    /// Flash the panel to clear it before drawing, for the cleanest image
+    #[default]
+    Full,
+    /// Skip most of the clearing flashes for much faster refreshes with more ghosting
+    Fast,
+}
+
+/// What the pixels of a frame can be
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelMode {
+    /// The inks of the panel
+    #[default]
+    Palette,
+    /// Black, white and two greys from [`Color::GREYSCALE`], on black and white SSD panels.
+    /// Each pixel takes a bit in both RAM planes to select one of four waveforms, so frames are
+    /// twice the size and partial refreshes aren't available
+    Greyscale4,
+}
+
+/// Order in which the controller fills its RAM from the data it is sent. Reversing both axes
+/// turns the frame around for a panel mounted upside down, without rotating it on the CPU
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DataEntryMode {
+    /// Right to left and bottom to top, turning the frame by 180°
+    XDecrementYDecrement = 0x00,
+    /// Left to right and bottom to top, flipping the frame vertically
+    XIncrementYDecrement = 0x01,
+    /// Right to left and top to bottom, flipping the frame horizontally
+    XDecrementYIncrement = 0x02,
+    /// Left to right and top to bottom
+    #[default]
+    XIncrementYIncrement = 0x03,
+}
+
+impl DataEntryMode {
+    pub fn x_increments(&self) -> bool {
+        *self as u8 & 0b01 != 0
+    }
+
+    pub fn y_increments(&self) -> bool {
+        *self as u8 & 0b10 != 0
+    }
+}
+
+/// How the chip select line is driven
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChipSelect {
+    /// The kernel asserts the SPI device's own slave select line around each transfer
+    #[default]
+    Hardware,
+    /// Hold the given BCM pin low for a whole command and its data. If the pin is also the SPI
+    /// device's slave select line, release it from the kernel with the `spi0-0cs` overlay
+    Software(u8),
+}
+
+/// What drives the SPI bus and GPIO lines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// rppal, which talks to the Raspberry Pi's own GPIO controller
+    #[default]
+    Rppal,
+    /// The Linux GPIO character device and spidev, for other boards with the HAT header, see
+    /// [`crate::hardware::cdev`]
+    #[cfg(feature = "cdev")]
+    Cdev,
+}
+
+/// How often a connection retries a failed SPI write before giving up, and how long it waits
+/// before the first retry. The wait doubles with each further retry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retry {
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl Retry {
+    /// Don't retry at all
+    pub const NEVER: Retry = Retry {
+        retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    /// Run `f` until it succeeds or the retries are used up, returning its last result
+    pub fn run<T, E: fmt::Display>(&self, mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    warn!(attempt, "SPI write failed, retrying: {}", e);
+                    sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// How a connection waits for the busy line to signal that the controller is ready
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BusyWait {
+    /// Sleep until the GPIO interrupt for the ready edge fires
+    #[default]
+    Interrupt,
+    /// Read the line at this interval, for boards whose GPIO interrupts are unreliable
+    Poll(Duration),
+}
+
+/// Level of the busy line while the controller is busy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusyPolarity {
+    /// The line is high while busy and falls when the controller is ready, as on the wHAT
+    ActiveHigh,
+    /// The line is low while busy and rises when the controller is ready, as on the E673
+    ActiveLow,
+}
+
+impl BusyPolarity {
+    /// Whether the busy line at `level` means the controller is busy
+    pub fn is_busy(&self, level: Level) -> bool {
+        match self {
+            BusyPolarity::ActiveHigh => level == Level::High,
+            BusyPolarity::ActiveLow => level == Level::Low,
+        }
+    }
+
+    /// The edge the busy line makes when the controller becomes ready
+    pub fn ready_trigger(&self) -> Trigger {
+        match self {
+            BusyPolarity::ActiveHigh => Trigger::FallingEdge,
+            BusyPolarity::ActiveLow => Trigger::RisingEdge,
+        }
+    }
+}
+
+/// Hardware reset pulse timing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    /// How long the reset line is held low
+    pub reset_pulse: Duration,
+    /// How long to wait after releasing the reset line before sending commands
+    pub reset_settle: Duration,
+}
+
+/// SPI device and GPIO pins a display is wired to. The defaults match the standard HAT pinout;
+/// distinct configurations allow several displays to be driven from one Pi
+#[derive(Builder, Clone, Debug)]
+pub struct ConnectionConfig {
+    #[builder(default = "Bus::Spi0")]
+    pub bus: Bus,
+    #[builder(default = "SecondarySelect::Ss0")]
+    pub slave_select: SecondarySelect,
+    /// How chip select is driven. Each driver picks the mode its controller needs when unset
+    #[builder(default, setter(strip_option))]
+    pub chip_select: Option<ChipSelect>,
+    /// BCM number of the data/command pin
+    #[builder(default = "22")]
+    pub dc_pin: u8,
+    /// BCM number of the reset pin
+    #[builder(default = "27")]
+    pub reset_pin: u8,
+    /// BCM number of the busy pin
+    #[builder(default = "17")]
+    pub busy_pin: u8,
+    /// SPI clock in Hz. Each driver picks a speed its panel is known to tolerate when unset
+    #[builder(default, setter(strip_option))]
+    pub spi_speed: Option<u32>,
+    /// Time to hold chip select low before clocking out a command when it is driven in software
+    #[builder(default = "InkyConnection::DEFAULT_CS_SETUP_DELAY")]
+    pub cs_setup_delay: Duration,
+    /// Busy line polarity, for clone panels whose controller differs from the driver's. Each
+    /// driver uses its own controller's polarity when unset
+    #[builder(default, setter(strip_option))]
+    pub busy_polarity: Option<BusyPolarity>,
+    /// Reset pulse timing. Each driver uses its own controller's timing when unset
+    #[builder(default, setter(strip_option))]
+    pub timing: Option<Timing>,
+    #[builder(default)]
+    pub busy_wait: BusyWait,
+    #[builder(default)]
+    pub backend: Backend,
+    /// Retries of failed SPI writes
+    #[builder(default)]
+    pub retry: Retry,
+    /// Largest SPI transfer to make, in bytes. Read from the spidev module's `bufsiz`
+    /// parameter when unset, see [`spi_chunk_size`]
+    #[builder(default, setter(strip_option))]
+    pub spi_chunk_size: Option<usize>,
+    /// GPIO character device the cdev backend requests its lines from, `/dev/gpiochip0` when
+    /// unset. Connecting with the rppal backend fails when this is set
+    #[builder(default, setter(into, strip_option))]
+    pub gpio_chip: Option<PathBuf>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        ConnectionConfigBuilder::default()
+            .build()
+            .expect("every connection setting has a default")
+    }
+}
+
+/// Time a connection has spent talking to the display, see [`Connection::take_timings`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionTimings {
+    /// Time spent clocking commands and data out over SPI
+    pub transfer: Duration,
+    /// Time spent waiting for the busy line
+    pub busy_wait: Duration,
+}
+
 pub struct InkyConnection {
     pub spi: Spi,
-    pub cs: OutputPin,
+    /// Chip select pin when it is driven in software
+    pub cs: Option<OutputPin>,
     pub dc: OutputPin,
     pub reset: OutputPin,
     pub busy: InputPin,
     pub eeprom: EEPROM,
+    /// How long to wait for the busy line before giving up, when a driver does not ask for a
+    /// specific timeout
+    pub busy_timeout: Duration,
+    /// Time to hold chip select low before clocking out a command
+    pub cs_setup_delay: Duration,
+    /// Busy line polarity overriding the driver's
+    pub busy_polarity: Option<BusyPolarity>,
+    /// Reset timing overriding the driver's
+    pub timing: Option<Timing>,
+    pub busy_wait: BusyWait,
+    /// Largest SPI transfer in bytes
+    pub chunk_size: usize,
+    pub retry: Retry,
+    timings: ConnectionTimings,
 }
 
 impl InkyConnection {
+    /// Default time to wait for the display to become ready. A full refresh of the largest
+    /// panels takes around 30 seconds
+    pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(45);
+
+    /// SPI clock used when neither the configuration nor the driver choose one
+    pub const DEFAULT_SPI_SPEED: u32 = 488_000;
+
+    /// Chip select setup time. The controllers need well under a microsecond, this leaves margin
+    pub const DEFAULT_CS_SETUP_DELAY: Duration = Duration::from_micros(10);
+
     pub fn new(
         eeprom: EEPROM,
     ) -> Result<Self> {
+        Self::with_config(
+            eeprom,
+            &ConnectionConfig::default(),
+            Self::DEFAULT_SPI_SPEED,
+            ChipSelect::default(),
+        )
+    }
+
+    /// Connect using the SPI device and pins in `config`, clocking the bus at `default_speed` and
+    /// driving chip select as `default_cs` unless the configuration overrides them
+    pub fn with_config(
+        eeprom: EEPROM,
+        config: &ConnectionConfig,
+        default_speed: u32,
+        default_cs: ChipSelect,
+    ) -> Result<Self> {
+        // rppal always drives the Pi's own GPIO controller and has no way to pick another
+        if let Some(chip) = &config.gpio_chip {
+            return Err(InkyError::InvalidArgument(format!(
+                "{} can only be used with the cdev backend",
+                chip.display()
+            )));
+        }
         let gpio = Gpio::new()?;
 
         Ok(Self {
             spi: Spi::new(
-                Bus::Spi0,
-                SecondarySelect::Ss0,
-                488_000,
+                config.bus,
+                config.slave_select,
+                config.spi_speed.unwrap_or(default_speed),
                 Mode::Mode0,
             )?,
-            cs: gpio.get(8)?.into_output_high(),
-            dc: gpio.get(22)?.into_output_low(),
-            reset: gpio.get(27)?.into_output_high(),
-            busy: gpio.get(17)?.into_input(),
-            eeprom: eeprom,
+            cs: match config.chip_select.unwrap_or(default_cs) {
+                ChipSelect::Hardware => None,
+                ChipSelect::Software(pin) => Some(gpio.get(pin)?.into_output_high()),
+            },
+            dc: gpio.get(config.dc_pin)?.into_output_low(),
+            reset: gpio.get(config.reset_pin)?.into_output_high(),
+            busy: gpio.get(config.busy_pin)?.into_input(),
+            eeprom,
+            busy_timeout: Self::DEFAULT_BUSY_TIMEOUT,
+            cs_setup_delay: config.cs_setup_delay,
+            busy_polarity: config.busy_polarity,
+            timing: config.timing,
+            busy_wait: config.busy_wait,
+            chunk_size: spi_chunk_size(config),
+            retry: config.retry,
+            timings: ConnectionTimings::default(),
         })
     }
+
+    /// Send a command and its data, framed by the chip select line when it is driven in software
+    pub fn send(&mut self, packet: SpiPacket) -> Result<()> {
+        trace!(
+            command = packet.command,
+            bytes = packet.data.as_ref().map_or(0, Vec::len),
+            "Sending packet"
+        );
+        let start = Instant::now();
+        if let Some(cs) = self.cs.as_mut() {
+            cs.set_low();
+            sleep(self.cs_setup_delay);
+        }
+        let result = self.transfer(packet);
+        if let Some(cs) = self.cs.as_mut() {
+            cs.set_high();
+        }
+        self.dc.set_low();
+        self.timings.transfer += start.elapsed();
+        result
+    }
+
+    fn transfer(&mut self, packet: SpiPacket) -> Result<()> {
+        let retry = self.retry;
+        self.dc.set_low();
+        retry.run(|| self.spi.write(&[packet.command]))?;
+
+        if let Some(data) = packet.data {
+            self.dc.set_high();
+            for chunk in data.chunks(self.chunk_size) {
+                retry.run(|| self.spi.write(chunk))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the busy line to signal `trigger`, failing with `BusyTimeout` if it does not
+    /// happen within `timeout` (or the connection's default timeout)
+    pub fn wait_for_busy(&mut self, trigger: Trigger, timeout: Option<Duration>) -> Result<()> {
+        let timeout = timeout.unwrap_or(self.busy_timeout);
+        let ready = match trigger {
+            Trigger::FallingEdge => Level::Low,
+            _ => Level::High,
+        };
+        let start = Instant::now();
+        let signalled = match self.busy_wait {
+            BusyWait::Interrupt => self.wait_for_interrupt(trigger, ready, timeout),
+            BusyWait::Poll(interval) => Ok(self.poll_busy(ready, interval, timeout)),
+        };
+        self.timings.busy_wait += start.elapsed();
+        if !signalled? {
+            return Err(InkyError::BusyTimeout {
+                elapsed: start.elapsed(),
+            });
+        }
+        trace!(?trigger, elapsed = ?start.elapsed(), "Busy line signalled");
+        Ok(())
+    }
+
+    // Whether the line reached `ready` within `timeout`
+    fn wait_for_interrupt(
+        &mut self,
+        trigger: Trigger,
+        ready: Level,
+        timeout: Duration,
+    ) -> Result<bool> {
+        self.busy.set_interrupt(trigger)?;
+        // The edge may have passed before the interrupt was armed, which happens on the Pi 5
+        // when the controller finishes quickly, and would otherwise leave us waiting for the
+        // whole timeout
+        let signalled = if self.busy.read() == ready {
+            Ok(true)
+        } else {
+            self.busy
+                .poll_interrupt(false, Some(timeout))
+                .map(|level| level.is_some())
+        };
+        self.busy.clear_interrupt()?;
+        Ok(signalled?)
+    }
+
+    fn poll_busy(&self, ready: Level, interval: Duration, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while self.busy.read() != ready {
+            if start.elapsed() >= timeout {
+                return false;
+            }
+            sleep(interval);
+        }
+        true
+    }
+}
+
+/// The SPI bus and control lines a driver talks to its panel through. [`InkyConnection`] drives
+/// the real hardware; drivers built with [`InkyDisplay::with_connection`] accept any other
+/// implementation, such as a mock that lets the driver logic be tested without a display.
+/// Connections are `Send` so that a display can be driven from a worker thread
+pub trait Connection: Send {
+    /// Send a command and its data
+    fn send(&mut self, packet: SpiPacket) -> Result<()>;
+    /// Drive the reset line
+    fn set_reset(&mut self, level: Level);
+    /// Current level of the busy line
+    fn busy(&self) -> Level;
+    /// Wait for the busy line to signal `trigger`, failing with `BusyTimeout` if it does not
+    /// happen within `timeout` (or the connection's default timeout)
+    fn wait_for_busy(&mut self, trigger: Trigger, timeout: Option<Duration>) -> Result<()>;
+    /// The EEPROM describing the connected display
+    fn eeprom(&self) -> &EEPROM;
+    /// How long to wait for the busy line when a driver does not ask for a specific timeout
+    fn busy_timeout(&self) -> Duration;
+    fn set_busy_timeout(&mut self, timeout: Duration);
+    /// Time spent in `send` and `wait_for_busy` since the last call, which starts the count
+    /// again. Connections that don't measure it report zero
+    fn take_timings(&mut self) -> ConnectionTimings {
+        ConnectionTimings::default()
+    }
+    /// Busy line polarity to use instead of the driver's, if any
+    fn busy_polarity(&self) -> Option<BusyPolarity> {
+        None
+    }
+    /// Reset timing to use instead of the driver's, if any
+    fn timing(&self) -> Option<Timing> {
+        None
+    }
+    /// Pause for a delay the controller needs between steps of a sequence
+    fn delay(&mut self, duration: Duration) {
+        sleep(duration)
+    }
+    /// Whether `read` can get data back from the controller
+    fn can_read(&self) -> bool {
+        false
+    }
+    /// Send `command` and read `len` bytes of its reply. The Pi's SPI driver through rppal is
+    /// write only, so reading needs a connection such as the cdev backend's
+    fn read(&mut self, _command: u8, _len: usize) -> Result<Vec<u8>> {
+        Err(InkyError::Unsupported(
+            "This connection cannot read from the display".into(),
+        ))
+    }
+}
+
+impl Connection for InkyConnection {
+    fn send(&mut self, packet: SpiPacket) -> Result<()> {
+        InkyConnection::send(self, packet)
+    }
+
+    fn set_reset(&mut self, level: Level) {
+        self.reset.write(level);
+    }
+
+    fn busy(&self) -> Level {
+        self.busy.read()
+    }
+
+    fn wait_for_busy(&mut self, trigger: Trigger, timeout: Option<Duration>) -> Result<()> {
+        InkyConnection::wait_for_busy(self, trigger, timeout)
+    }
+
+    fn eeprom(&self) -> &EEPROM {
+        &self.eeprom
+    }
+
+    fn busy_timeout(&self) -> Duration {
+        self.busy_timeout
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
+    fn take_timings(&mut self) -> ConnectionTimings {
+        std::mem::take(&mut self.timings)
+    }
+
+    fn busy_polarity(&self) -> Option<BusyPolarity> {
+        self.busy_polarity
+    }
+
+    fn timing(&self) -> Option<Timing> {
+        self.timing
+    }
+}
+
+/// Where the spidev module publishes the largest transfer it accepts
+const SPIDEV_BUFSIZ: &str = "/sys/module/spidev/parameters/bufsiz";
+
+/// The kernel's default limit, used when `bufsiz` can't be read
+pub const DEFAULT_SPI_CHUNK_SIZE: usize = 4096;
+
+/// Largest SPI transfer to make for `config`: its own setting, or the limit the spidev module was
+/// loaded with. Raising the limit with `spidev.bufsiz=65536` on the kernel command line lets a
+/// frame go out in far fewer transfers, which speeds up the larger panels
+pub fn spi_chunk_size(config: &ConnectionConfig) -> usize {
+    config
+        .spi_chunk_size
+        .or_else(|| fs::read_to_string(SPIDEV_BUFSIZ).ok()?.trim().parse().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_SPI_CHUNK_SIZE)
+}
+
+/// Open a connection to the display in `config` with its backend, clocking the bus at
+/// `default_speed` and driving chip select as `default_cs` unless the configuration overrides them
+pub(crate) fn connect(
+    eeprom: EEPROM,
+    config: &ConnectionConfig,
+    default_speed: u32,
+    default_cs: ChipSelect,
+) -> Result<Box<dyn Connection>> {
+    Ok(match config.backend {
+        Backend::Rppal => Box::new(InkyConnection::with_config(
+            eeprom,
+            config,
+            default_speed,
+            default_cs,
+        )?),
+        #[cfg(feature = "cdev")]
+        Backend::Cdev => Box::new(CdevConnection::with_config(
+            eeprom,
+            config,
+            default_speed,
+            default_cs,
+        )?),
+    })
 }
 
 pub trait InkyConnectionProvider {
-    fn connection(&mut self) -> &InkyConnection;
+    fn connection(&self) -> &dyn Connection;
+    fn connection_mut(&mut self) -> &mut dyn Connection;
+}
+
+/// Check that every pixel of a frame is in `palette`, before a driver packs it into a format
+/// that has no way to represent other colors
+pub(crate) fn check_colors(buf: &[Vec<Color>], palette: &[Color]) -> Result<()> {
+    match buf.iter().flatten().find(|color| !palette.contains(color)) {
+        Some(color) => Err(InkyError::UnsupportedColor(*color)),
+        None => Ok(()),
+    }
 }
 
-pub trait InkyDisplay : InkyConnectionProvider {
-    fn new(eeprom: EEPROM) -> Result<Self> where Self: Sized;
+pub trait InkyDisplay : InkyConnectionProvider + Send {
+    fn new(eeprom: EEPROM) -> Result<Self> where Self: Sized {
+        Self::with_config(eeprom, &ConnectionConfig::default())
+    }
+    /// Connect to a display wired to the SPI device and pins in `config`
+    fn with_config(eeprom: EEPROM, config: &ConnectionConfig) -> Result<Self> where Self: Sized;
+    /// Drive the display through an already established connection, failing if the connection's
+    /// EEPROM describes a display this driver does not support
+    fn with_connection(connection: Box<dyn Connection>) -> Result<Self> where Self: Sized;
     fn reset(&mut self) -> Result<()>;
-    fn convert(&self, buf: &Vec<Vec<Color>>) -> Result<Vec<u8>>;
+    fn convert(&self, buf: &[Vec<Color>]) -> Result<Vec<u8>>;
+    /// Length in bytes of a full frame as produced by `convert`
+    fn buffer_len(&self) -> usize;
     fn update(&mut self, buf: Vec<u8>) -> Result<()>;
+    /// Send `buf` to the display and start refreshing the panel without waiting for the refresh
+    /// to complete. Drivers that cannot split the update perform it in full here
+    fn start_update(&mut self, buf: Vec<u8>) -> Result<()> {
+        self.update(buf)
+    }
+    /// Whether the display is still busy refreshing the panel
+    fn is_busy(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+    /// Finish an update started with `start_update` once the display is no longer busy
+    fn finish_update(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Put the controller into its lowest power state. The display keeps showing its image
+    fn sleep(&mut self) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not support deep sleep".into(),
+        ))
+    }
+    /// Bring the controller out of deep sleep without redrawing the panel
+    fn wake(&mut self) -> Result<()> {
+        self.reset()
+    }
+    /// Switch off the panel's high voltage supply, e.g. after an update failed part way
+    fn power_off(&mut self) -> Result<()> {
+        self.sleep()
+    }
+    /// Select between full and fast refreshes
+    fn set_refresh_mode(&mut self, _mode: RefreshMode) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not support refresh modes".into(),
+        ))
+    }
+    /// Switch between the panel's inks and greyscale. The palette and buffer format follow the
+    /// mode
+    fn set_pixel_mode(&mut self, _mode: PixelMode) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not support greyscale".into(),
+        ))
+    }
+    /// Choose the order the controller fills its RAM in. Frames are still converted from the
+    /// canvas as it is drawn, and partial refresh regions are given in its coordinates
+    fn set_data_entry_mode(&mut self, _mode: DataEntryMode) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not support data entry modes".into(),
+        ))
+    }
+    /// Color of the border around the active area, taken from the panel's palette. Applied on the
+    /// next full refresh
+    fn set_border(&mut self, _color: &Color) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not support border colors".into(),
+        ))
+    }
+    /// Colors the panel can show
+    fn palette(&self) -> &'static [Color] {
+        self.connection().eeprom().color().palette()
+    }
+    /// Temperature of the panel in degrees Celsius, as measured by the controller at the start of
+    /// the last update, or the ambient temperature if the connection can't read the measurement.
+    /// `None` if neither is known
+    fn temperature(&self) -> Option<f32> {
+        None
+    }
+    /// Temperature in degrees Celsius to adjust the waveform for when the connection can't read
+    /// the controller's measurement, e.g. from a room sensor
+    fn set_ambient_temperature(&mut self, _celsius: Option<f32>) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not adjust its waveform for temperature".into(),
+        ))
+    }
+    /// Whether the display implements `update_region`
+    fn supports_partial(&self) -> bool {
+        false
+    }
+    /// Refresh only `region` of the display from a full converted buffer, for controllers that
+    /// support windowed RAM writes
+    fn update_region(&mut self, _buf: Vec<u8>, _region: Rect) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "Partial refresh is not supported by this display".into(),
+        ))
+    }
     fn wait(&mut self, timeout: Option<Duration>) -> Result<()>;
     fn spi_send(&mut self, packet: SpiPacket) -> Result<()>;
+    /// Select the waveform used for full refreshes
+    fn set_lut(&mut self, _lut: LutKind) -> Result<()> {
+        Err(InkyError::Unsupported(
+            "This display does not use a lookup table".into(),
+        ))
+    }
 }
 
 macro_rules! add_inky_display_type {
-    ( $type:ident )=> {
+    ( $type:ident $(, $field:ident : $field_type:ty)* )=> {
         pub struct $type {
-            connection: InkyConnection,
+            connection: Box<dyn Connection>,
+            $($field: $field_type,)*
         }
 
         impl InkyConnectionProvider for $type {
-            fn connection(&mut self) -> &InkyConnection {
-                &self.connection
+            fn connection(&self) -> &dyn Connection {
+                self.connection.as_ref()
+            }
+
+            fn connection_mut(&mut self) -> &mut dyn Connection {
+                self.connection.as_mut()
             }
         }
     };
 }
 
 pub(crate) use add_inky_display_type;
+
+#[cfg(test)]
+mod tests {
+    use super::Retry;
+    use std::time::Duration;
+
+    #[test]
+    fn retry() {
+        let retry = Retry {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+        let mut attempts = 0;
+        let result: Result<(), &str> = retry.run(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err("busy")
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!((result, attempts), (Ok(()), 3));
+
+        attempts = 0;
+        let result: Result<(), &str> = retry.run(|| {
+            attempts += 1;
+            Err("unplugged")
+        });
+        assert_eq!((result, attempts), (Err("unplugged"), 3));
+        assert_eq!(Retry::NEVER.run(|| Err::<(), _>("failed")), Err("failed"));
+    }
+}
@@ -0,0 +1,104 @@
+//! A connection that logs the traffic a driver would send instead of touching the hardware
+//!
+//! [`DryRunConnection`] logs every command byte and data length, every change of the reset line
+//! and every delay and wait at `info` level, and returns straight away, so init and update
+//! sequences can be reviewed on any machine. Wrap it in a
+//! [`RecordingConnection`](crate::hardware::transcript::RecordingConnection) to save the traffic
+//! as a transcript and diff it against a capture from another library.
+
+use crate::{
+    eeprom::EEPROM,
+    error::Result,
+    hardware::display::{Connection, InkyConnection, SpiPacket},
+};
+
+use rppal::gpio::{Level, Trigger};
+
+use std::time::Duration;
+use tracing::info;
+
+pub struct DryRunConnection {
+    eeprom: EEPROM,
+    busy_timeout: Duration,
+}
+
+impl DryRunConnection {
+    /// Pretend to be connected to the display described by `eeprom`
+    pub fn new(eeprom: EEPROM) -> Self {
+        Self {
+            eeprom,
+            busy_timeout: InkyConnection::DEFAULT_BUSY_TIMEOUT,
+        }
+    }
+}
+
+impl Connection for DryRunConnection {
+    fn send(&mut self, packet: SpiPacket) -> Result<()> {
+        let bytes = packet.data.as_ref().map_or(0, Vec::len);
+        info!("SPI command {:#04x}, {} data bytes", packet.command, bytes);
+        Ok(())
+    }
+
+    fn set_reset(&mut self, level: Level) {
+        info!("Reset line {:?}", level);
+    }
+
+    /// Always low, like [`MockConnection`](crate::hardware::mock::MockConnection)
+    fn busy(&self) -> Level {
+        Level::Low
+    }
+
+    fn wait_for_busy(&mut self, trigger: Trigger, timeout: Option<Duration>) -> Result<()> {
+        info!("Wait for busy {:?}, timeout {:?}", trigger, timeout);
+        Ok(())
+    }
+
+    fn eeprom(&self) -> &EEPROM {
+        &self.eeprom
+    }
+
+    fn busy_timeout(&self) -> Duration {
+        self.busy_timeout
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
+    /// Logs the delay without sleeping
+    fn delay(&mut self, duration: Duration) {
+        info!("Sleep {:?}", duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DryRunConnection;
+    use crate::{
+        eeprom::{ColorMode, DisplaySpec, DisplayVariant, EEPROM},
+        hardware::{display::InkyDisplay, inkywhat::InkyWhat, transcript::RecordingConnection},
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn update_without_hardware() {
+        let eeprom = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Black,
+        });
+        let connection = RecordingConnection::new(DryRunConnection::new(eeprom));
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        let started = Instant::now();
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        // The driver's delays are logged rather than slept
+        assert!(started.elapsed() < Duration::from_millis(50));
+        assert!(recorder
+            .lock()
+            .unwrap()
+            .packets()
+            .any(|packet| packet.command == 0x24));
+    }
+}
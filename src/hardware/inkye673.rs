@@ -1,17 +1,20 @@
 use crate::{
-    core::colors::Color,
+    core::{colors::Color, packing::pack_4bpp},
     eeprom::{DisplayVariant, EEPROM},
+    error::{InkyError, Result},
     hardware::display::{
-        add_inky_display_type, InkyConnection, InkyConnectionProvider, InkyDisplay, SpiPacket,
+        add_inky_display_type, check_colors, connect, BusyPolarity, ChipSelect, Connection,
+        ConnectionConfig, InkyConnectionProvider, InkyDisplay, SpiPacket, Timing,
     },
 };
 
-use rppal::gpio::Trigger;
+use rppal::gpio::Level;
 
-use anyhow::{ensure, Result};
-
-use std::{thread::sleep, time::Duration};
+use std::time::Duration;
+use tracing::instrument;
 
+// Command names follow the EL673 datasheet
+#[allow(non_camel_case_types, dead_code)]
 #[repr(u8)]
 enum DisplayCommands {
     EL673_PSR = 0x00,
@@ -43,29 +46,64 @@ fn as_u8(color: &Color) -> u8 {
         Color::Red => 3,
         Color::Blue => 5,
         Color::Green => 6,
+        // Not in the palette, so check_colors rejects them before packing
+        Color::DarkGrey => 0,
+        Color::LightGrey => 1,
     }
 }
 
 add_inky_display_type!(InkyE673);
 
+impl InkyE673 {
+    /// Default SPI clock in Hz. Matches the clock used by the Pimoroni library
+    pub const SPI_SPEED: u32 = 1_000_000;
+
+    /// The E673 controller drops a command whose chip select is released before its data, which
+    /// the kernel does between transfers, so the slave select pin is held low in software. This
+    /// needs the `spi0-0cs` overlay to release the pin from the kernel
+    pub const CHIP_SELECT: ChipSelect = ChipSelect::Software(8);
+
+    /// The E673 controller holds the busy line low while busy
+    pub const BUSY_POLARITY: BusyPolarity = BusyPolarity::ActiveLow;
+
+    /// Reset pulse timing from the inky library
+    pub const TIMING: Timing = Timing {
+        reset_pulse: Duration::from_millis(30),
+        reset_settle: Duration::from_millis(30),
+    };
+
+    fn busy_polarity(&self) -> BusyPolarity {
+        self.connection.busy_polarity().unwrap_or(Self::BUSY_POLARITY)
+    }
+}
+
 impl InkyDisplay for InkyE673 {
-    fn new(eeprom: EEPROM) -> Result<Self> {
-        ensure!(
-            matches!(eeprom.display_variant(), DisplayVariant::E673),
-            "Only the Inky E673 is supported!"
-        );
+    fn with_config(eeprom: EEPROM, config: &ConnectionConfig) -> Result<Self> {
+        if !matches!(eeprom.display_variant(), DisplayVariant::E673) {
+            return Err(InkyError::UnsupportedVariant(eeprom.display_variant()));
+        }
+
+        Self::with_connection(connect(eeprom, config, Self::SPI_SPEED, Self::CHIP_SELECT)?)
+    }
+
+    fn with_connection(connection: Box<dyn Connection>) -> Result<Self> {
+        let variant = connection.eeprom().display_variant();
+        if !matches!(variant, DisplayVariant::E673) {
+            return Err(InkyError::UnsupportedVariant(variant));
+        }
 
         Ok(Self {
-            connection: InkyConnection::new(eeprom)?,
+            connection,
         })
     }
 
+    #[instrument(level = "debug", skip_all)]
     fn reset(&mut self) -> Result<()> {
-        self.connection.reset.set_low();
-        // Sleep time from inky library
-        sleep(Duration::from_millis(30));
-        self.connection.reset.set_high();
-        sleep(Duration::from_millis(30));
+        let timing = self.connection.timing().unwrap_or(Self::TIMING);
+        self.connection.set_reset(Level::Low);
+        self.connection.delay(timing.reset_pulse);
+        self.connection.set_reset(Level::High);
+        self.connection.delay(timing.reset_settle);
 
         self.wait(Some(Duration::from_millis(300)))?;
 
@@ -127,7 +165,15 @@ impl InkyDisplay for InkyE673 {
         Ok(())
     }
 
+    #[instrument(level = "debug", skip_all, fields(bytes = buf.len()))]
     fn update(&mut self, buf: Vec<u8>) -> Result<()> {
+        self.start_update(buf)?;
+        self.wait(Some(Duration::from_millis(32000)))?;
+        self.finish_update()
+    }
+
+    #[instrument(level = "debug", skip_all, fields(bytes = buf.len()))]
+    fn start_update(&mut self, buf: Vec<u8>) -> Result<()> {
         self.reset()?;
 
         self.spi_send(SpiPacket::with_data(DisplayCommands::EL673_DTM1 as u8, buf))?;
@@ -142,63 +188,58 @@ impl InkyDisplay for InkyE673 {
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::EL673_DRF as u8,
             vec![0x00],
-        ))?;
-        self.wait(Some(Duration::from_millis(32000)))?;
+        ))
+    }
+
+    fn is_busy(&mut self) -> Result<bool> {
+        Ok(self.busy_polarity().is_busy(self.connection.busy()))
+    }
 
+    fn finish_update(&mut self) -> Result<()> {
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::EL673_POF as u8,
             vec![0x00],
         ))?;
-        self.wait(Some(Duration::from_millis(300)))?;
+        self.wait(Some(Duration::from_millis(300)))
+    }
 
-        Ok(())
+    fn power_off(&mut self) -> Result<()> {
+        self.finish_update()
+    }
+
+    fn sleep(&mut self) -> Result<()> {
+        self.spi_send(SpiPacket::with_data(
+            DisplayCommands::EL673_DSLP as u8,
+            vec![0xA5],
+        ))
     }
 
     fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
-        // If the busy_pin is *high* (pulled up by host)
+        // If the busy pin is already idle (pulled up by the host)
         // then assume we're not getting a signal from inky
         // and wait the timeout period to be safe.
-        if self.connection.busy.is_high() {
-            sleep(timeout.unwrap_or(Duration::from_millis(100)));
+        let polarity = self.busy_polarity();
+        if !polarity.is_busy(self.connection.busy()) {
+            self.connection
+                .delay(timeout.unwrap_or(Duration::from_millis(100)));
             return Ok(());
         }
 
-        self.connection.busy.set_interrupt(Trigger::RisingEdge)?;
-        self.connection.busy.poll_interrupt(false, timeout)?;
-        self.connection.busy.clear_interrupt()?;
-        Ok(())
+        self.connection.wait_for_busy(polarity.ready_trigger(), timeout)
     }
 
     fn spi_send(&mut self, packet: SpiPacket) -> Result<()> {
-        self.connection.cs.set_low();
-        self.connection.dc.set_low();
-        sleep(Duration::from_millis(300));
-        self.connection.spi.write(&[packet.command])?;
-
-        if let Some(data) = packet.data {
-            self.connection.dc.set_high();
-            for chunk in data.chunks(4096) {
-                self.connection.spi.write(chunk)?;
-            }
-        }
-
-        self.connection.cs.set_high();
-        self.connection.dc.set_low();
+        self.connection.send(packet)
+    }
 
-        Ok(())
+    fn buffer_len(&self) -> usize {
+        // Two pixels to a byte
+        let eeprom = self.connection.eeprom();
+        eeprom.width() as usize * eeprom.height() as usize / 2
     }
 
-    fn convert(&self, buf: &Vec<Vec<Color>>) -> Result<Vec<u8>> {
-        let mut result = Vec::new();
-        for row in buf {
-            ensure!(row.len() % 2 == 0, "Row length must be even!");
-            // Take pairs of pixel values and packs them into single bytes
-            for pair in row.chunks(2) {
-                let pixel1 = as_u8(&pair[0]);
-                let pixel2 = as_u8(&pair[1]);
-                result.push(((pixel1 << 4) & 0xF0) | (pixel2 & 0x0F));
-            }
-        }
-        Ok(result)
+    fn convert(&self, buf: &[Vec<Color>]) -> Result<Vec<u8>> {
+        check_colors(buf, self.palette())?;
+        pack_4bpp(buf, as_u8)
     }
 }
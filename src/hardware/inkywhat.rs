@@ -1,21 +1,28 @@
 use crate::{
-    core::colors::Color,
-    eeprom::{DisplayVariant, EEPROM},
+    core::{
+        colors::Color,
+        geometry::Rect,
+        packing::{pack_1bpp, BitOrder},
+    },
+    eeprom::{ColorMode, DisplayVariant, EEPROM},
+    error::{InkyError, Result},
     hardware::display::{
-        add_inky_display_type, InkyConnection, InkyConnectionProvider, InkyDisplay, SpiPacket,
+        add_inky_display_type, check_colors, connect, BusyPolarity, ChipSelect, Connection,
+        ConnectionConfig, DataEntryMode, InkyConnectionProvider, InkyDisplay, PixelMode,
+        RefreshMode, SpiPacket, Timing,
     },
-    lut::LUT_BLACK,
+    lut::{compensate, LutKind, LUT_BLACK_FAST, LUT_BLACK_PARTIAL, LUT_GREYSCALE},
 };
 
-use rppal::gpio::Trigger;
-
-use anyhow::{ensure, Result};
+use rppal::gpio::Level;
 
-use std::{thread::sleep, time::Duration};
+use std::time::Duration;
+use tracing::{instrument, warn};
 
+#[allow(dead_code)]
 #[repr(u8)]
 enum DisplayCommands {
-    DataEntryMode = 0x11, // X/Y increment
+    DataEntryMode = 0x11,
     DisplayUpdateSequence = 0x22,
     DummyLinePeriod = 0x3a,
     EnterDeepSleep = 0x10,
@@ -36,42 +43,107 @@ enum DisplayCommands {
     VComRegister = 0x2c,
     SetBWBuffer = 0x24,
     SetRYBuffer = 0x26,
+    TemperatureSensorControl = 0x18,
+    ReadTemperature = 0x1b,
 }
 
-fn as_u8(color: &Color) -> u8 {
-    if !matches!(color, Color::Black) {
-        1
-    } else {
-        0
+add_inky_display_type!(
+    InkyWhat,
+    lut: LutKind,
+    refresh_mode: RefreshMode,
+    border: Color,
+    pixel_mode: PixelMode,
+    // Whether the red RAM still holds the colored ink or the second plane of a greyscale frame
+    ry_plane: bool,
+    temperature: Option<f32>,
+    // Used in place of a measurement when the connection can't read
+    ambient: Option<f32>,
+    data_entry: DataEntryMode
+);
+
+impl InkyWhat {
+    /// Default SPI clock in Hz. Matches the clock used by the Pimoroni library
+    pub const SPI_SPEED: u32 = 488_000;
+
+    /// The SSD1683 latches each byte on its own, so the kernel can release chip select between
+    /// the command and its data
+    pub const CHIP_SELECT: ChipSelect = ChipSelect::Hardware;
+
+    /// The SSD1683 holds the busy line high while busy
+    pub const BUSY_POLARITY: BusyPolarity = BusyPolarity::ActiveHigh;
+
+    /// Reset pulse timing from the inky library
+    pub const TIMING: Timing = Timing {
+        reset_pulse: Duration::from_millis(100),
+        reset_settle: Duration::from_millis(100),
+    };
+
+    fn busy_polarity(&self) -> BusyPolarity {
+        self.connection.busy_polarity().unwrap_or(Self::BUSY_POLARITY)
     }
-}
 
-add_inky_display_type!(InkyWhat);
+    /// The panel's third ink, when the selected waveform drives it. Frames then carry a second
+    /// plane for the red RAM, set where that ink is drawn
+    fn ink(&self) -> Option<Color> {
+        if self.pixel_mode != PixelMode::Palette {
+            return None;
+        }
+        match (&self.lut, self.connection.eeprom().color()) {
+            (LutKind::Red | LutKind::RedHighTemp, ColorMode::Red) => Some(Color::Red),
+            (LutKind::Yellow, ColorMode::Yellow) => Some(Color::Yellow),
+            _ => None,
+        }
+    }
 
-impl InkyDisplay for InkyWhat {
-    fn new(eeprom: EEPROM) -> Result<Self> {
-        ensure!(
-            matches!(eeprom.display_variant(), DisplayVariant::What),
-            "Only the Inky What is supported!"
-        );
+    /// Length in bytes of one RAM plane
+    fn plane_len(&self) -> usize {
+        let eeprom = self.connection.eeprom();
+        (eeprom.width() as usize).div_ceil(8) * eeprom.height() as usize
+    }
 
-        Ok(Self {
-            connection: InkyConnection::new(eeprom)?,
-        })
+    /// Measure the panel with the controller's internal sensor, if the connection can read the
+    /// result back, or fall back to the ambient temperature. A failed reading keeps the last one
+    fn measure_temperature(&mut self) {
+        if !self.connection.can_read() {
+            self.temperature = self.ambient;
+            return;
+        }
+        match self.read_temperature() {
+            Ok(celsius) => self.temperature = Some(celsius),
+            Err(e) => warn!("Failed to read the panel temperature: {}", e),
+        }
     }
 
-    fn reset(&mut self) -> Result<()> {
-        self.connection.reset.set_low();
-        // Sleep time from inky library
-        sleep(Duration::from_millis(100));
-        self.connection.reset.set_high();
-        sleep(Duration::from_millis(100));
-        self.spi_send(SpiPacket::no_data(DisplayCommands::SoftReset as u8))?;
+    fn read_temperature(&mut self) -> Result<f32> {
+        self.spi_send(SpiPacket::with_data(
+            DisplayCommands::TemperatureSensorControl as u8,
+            vec![0x80],
+        ))?;
+        // Enable the clock, load the temperature and disable the clock again
+        self.spi_send(SpiPacket::with_data(
+            DisplayCommands::DisplayUpdateSequence as u8,
+            vec![0xa1],
+        ))?;
+        self.spi_send(SpiPacket::no_data(
+            DisplayCommands::TriggerDisplayUpdate as u8,
+        ))?;
         self.wait(None)?;
-        Ok(())
+
+        // Twelve bit two's complement in sixteenths of a degree, left aligned in two bytes
+        let reply = self
+            .connection
+            .read(DisplayCommands::ReadTemperature as u8, 2)?;
+        match reply[..] {
+            [msb, lsb] => Ok((i16::from_be_bytes([msb, lsb]) >> 4) as f32 / 16.0),
+            _ => Err(InkyError::InvalidArgument(
+                "Short temperature reading".into(),
+            )),
+        }
     }
 
-    fn update(&mut self, buf: Vec<u8>) -> Result<()> {
+    /// Program the controller registers and waveform ahead of writing the display RAM. The
+    /// controller must have been reset, as it ignores commands in deep sleep
+    fn setup(&mut self, lut: &[u8]) -> Result<()> {
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetAnalogBlockControl as u8,
             vec![0x54],
@@ -82,7 +154,7 @@ impl InkyDisplay for InkyWhat {
             vec![0x3b],
         ))?;
 
-        let mut gate_setting_data = (self.connection.eeprom.height() as u16).to_le_bytes().to_vec();
+        let mut gate_setting_data = self.connection.eeprom().height().to_le_bytes().to_vec();
         gate_setting_data.push(0x00);
 
         self.spi_send(SpiPacket::with_data(
@@ -111,7 +183,7 @@ impl InkyDisplay for InkyWhat {
         ))?;
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::DataEntryMode as u8,
-            vec![0x03],
+            vec![self.data_entry as u8],
         ))?;
 
         self.spi_send(SpiPacket::with_data(
@@ -119,56 +191,162 @@ impl InkyDisplay for InkyWhat {
             vec![0x3c],
         ))?;
 
-        // TODO: Make this depend on color:
-        // if self.border_colour == self.BLACK:
-        //     self._send_command(0x3c, 0b00000000)  # GS Transition Define A + VSS + LUT0
-        // elif self.border_colour == self.RED and self.colour == 'red':
-        //     self._send_command(0x3c, 0b01110011)  # Fix Level Define A + VSH2 + LUT3
-        // elif self.border_colour == self.YELLOW and self.colour == 'yellow':
-        //     self._send_command(0x3c, 0b00110011)  # GS Transition Define A + VSH2 + LUT3
-        // elif self.border_colour == self.WHITE:
-        //     self._send_command(0x3c, 0b00110001)  # GS Transition Define A + VSH2 + LUT1
+        let border = match self.border {
+            // GS Transition Define A + VSS + LUT0
+            Color::Black => 0b00000000,
+            // Fix Level Define A + VSH2 + LUT3
+            Color::Red => 0b01110011,
+            // GS Transition Define A + VSH2 + LUT3
+            Color::Yellow => 0b00110011,
+            // GS Transition Define A + VSH2 + LUT1
+            _ => 0b00110001,
+        };
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::GSTransition as u8,
-            vec![0b00110001],
+            vec![border],
         ))?;
 
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetLUT as u8,
-            LUT_BLACK.to_vec(),
+            lut.to_vec(),
         ))?;
 
+        Ok(())
+    }
+
+    /// Set the RAM window and move the RAM pointers to the corner the data entry mode starts
+    /// from. The window is in RAM addresses, and its X extent must be byte aligned
+    fn set_window(&mut self, window: Rect) -> Result<()> {
+        let mut x = [(window.x / 8) as u8, ((window.right() / 8) - 1) as u8];
+        // The full window ends on the display height rather than the last row, as in inky
+        let full_height =
+            window.y == 0 && window.height == self.connection.eeprom().height() as usize;
+        let y_end = if full_height && self.data_entry.y_increments() {
+            window.height
+        } else {
+            window.bottom() - 1
+        };
+        let mut y = [window.y as u16, y_end as u16];
+        // The address counters run from the start to the end, so decrementing ones start from
+        // the far edge
+        if !self.data_entry.x_increments() {
+            x.reverse();
+        }
+        if !self.data_entry.y_increments() {
+            y.reverse();
+        }
+
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetRamXStartEnd as u8,
-            vec![0x00, ((self.connection.eeprom.width() / 8) - 1) as u8],
+            x.to_vec(),
         ))?;
 
-        let mut data = vec![0x00, 0x00];
-        data.extend_from_slice(&(self.connection.eeprom.height() as u16).to_le_bytes());
-
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetRamYStartEnd as u8,
-            data,
+            y.iter().flat_map(|row| row.to_le_bytes()).collect(),
         ))?;
 
-        // 0 because nothing == RED
-        // let ry_buf = vec![0; bw_buf.len()];
-
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetRamXPointerStart as u8,
-            vec![0x00],
+            vec![x[0]],
         ))?;
 
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetRamYPointerStart as u8,
-            vec![0x00, 0x00],
+            y[0].to_le_bytes().to_vec(),
         ))?;
 
+        Ok(())
+    }
+
+    /// Order of the pixels within each byte, which is reversed along with the X addresses so
+    /// that a decrementing X still reads the frame from the left
+    fn bit_order(&self) -> BitOrder {
+        if self.data_entry.x_increments() {
+            BitOrder::LsbFirst
+        } else {
+            BitOrder::MsbFirst
+        }
+    }
+
+    /// Trigger the display update, wait for it to finish and put the controller to sleep
+    fn refresh(&mut self) -> Result<()> {
+        self.trigger()?;
+
+        // Defined by inky
+        self.connection.delay(Duration::from_secs_f32(0.05));
+
+        self.wait(None)?;
+
+        self.finish_update()
+    }
+
+    /// Start refreshing the panel from the contents of the display RAM
+    fn trigger(&mut self) -> Result<()> {
+        // Display mode 2 in the update sequence skips the initial temperature/LUT load
+        let sequence = match self.refresh_mode {
+            RefreshMode::Full => 0xc7,
+            RefreshMode::Fast => 0xcf,
+        };
+        self.spi_send(SpiPacket::with_data(
+            DisplayCommands::DisplayUpdateSequence as u8,
+            vec![sequence],
+        ))?;
+
+        self.spi_send(SpiPacket::no_data(
+            DisplayCommands::TriggerDisplayUpdate as u8,
+        ))
+    }
+
+    /// Program the controller and write a full frame to the black/white RAM, and its second
+    /// plane, for the colored ink or in greyscale, to the red RAM
+    fn write_frame(&mut self, mut buf: Vec<u8>) -> Result<()> {
+        self.reset()?;
+        self.measure_temperature();
+
+        // Fast refreshes use the fast waveform regardless of the selected LUT, unless that
+        // drives the colored ink, which the fast waveform can't move. Greyscale needs its own
+        // unless a custom one was given. Each is adjusted for the temperature
+        let celsius = self.temperature;
+        let colored = self.ink().is_some();
+        let lut = match (self.pixel_mode, self.refresh_mode, &self.lut) {
+            (PixelMode::Greyscale4, _, LutKind::Custom(lut)) => lut.as_bytes().to_vec(),
+            (PixelMode::Greyscale4, _, _) => compensate(LUT_GREYSCALE, celsius).into_owned(),
+            (PixelMode::Palette, RefreshMode::Fast, _) if !colored => {
+                compensate(LUT_BLACK_FAST, celsius).into_owned()
+            }
+            (PixelMode::Palette, _, lut) => lut.for_temperature(celsius).into_owned(),
+        };
+        self.setup(&lut)?;
+
+        let width = self.connection.eeprom().width() as usize;
+        let height = self.connection.eeprom().height() as usize;
+        let window = Rect::new(0, 0, width, height);
+        self.set_window(window)?;
+
+        let plane = (buf.len() > self.plane_len()).then(|| buf.split_off(self.plane_len()));
+        let written = plane.is_some();
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::SetBWBuffer as u8,
             buf,
         ))?;
 
+        // Clear the red RAM after a colored or greyscale frame, or its bits would still pick
+        // those waveforms
+        let plane = match plane {
+            Some(plane) => Some(plane),
+            None if self.ry_plane => Some(vec![0; self.plane_len()]),
+            None => None,
+        };
+        if let Some(plane) = plane {
+            self.set_window(window)?;
+            self.spi_send(SpiPacket::with_data(
+                DisplayCommands::SetRYBuffer as u8,
+                plane,
+            ))?;
+            self.ry_plane = written;
+        }
+
         // TODO: Support additional displays
         // self.spi_send(
         //     SpiPacketBuilder::default()
@@ -191,67 +369,485 @@ impl InkyDisplay for InkyWhat {
         //         .build()?,
         // )?;
 
-        self.spi_send(SpiPacket::with_data(
-            DisplayCommands::DisplayUpdateSequence as u8,
-            vec![0xc7],
-        ))?;
+        Ok(())
+    }
+}
 
-        self.spi_send(SpiPacket::no_data(
-            DisplayCommands::TriggerDisplayUpdate as u8,
-        ))?;
+impl InkyDisplay for InkyWhat {
+    fn with_config(eeprom: EEPROM, config: &ConnectionConfig) -> Result<Self> {
+        if !matches!(eeprom.display_variant(), DisplayVariant::What) {
+            return Err(InkyError::UnsupportedVariant(eeprom.display_variant()));
+        }
 
-        // Defined by inky
-        sleep(Duration::from_secs_f32(0.05));
+        Self::with_connection(connect(eeprom, config, Self::SPI_SPEED, Self::CHIP_SELECT)?)
+    }
+
+    fn with_connection(connection: Box<dyn Connection>) -> Result<Self> {
+        let variant = connection.eeprom().display_variant();
+        if !matches!(variant, DisplayVariant::What) {
+            return Err(InkyError::UnsupportedVariant(variant));
+        }
+
+        Ok(Self {
+            connection,
+            lut: LutKind::default(),
+            refresh_mode: RefreshMode::default(),
+            border: Color::White,
+            pixel_mode: PixelMode::default(),
+            ry_plane: false,
+            temperature: None,
+            ambient: None,
+            data_entry: DataEntryMode::default(),
+        })
+    }
 
+    #[instrument(level = "debug", skip_all)]
+    fn reset(&mut self) -> Result<()> {
+        let timing = self.connection.timing().unwrap_or(Self::TIMING);
+        self.connection.set_reset(Level::Low);
+        self.connection.delay(timing.reset_pulse);
+        self.connection.set_reset(Level::High);
+        self.connection.delay(timing.reset_settle);
+        self.spi_send(SpiPacket::no_data(DisplayCommands::SoftReset as u8))?;
         self.wait(None)?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all, fields(bytes = buf.len()))]
+    fn update(&mut self, buf: Vec<u8>) -> Result<()> {
+        self.write_frame(buf)?;
+        self.refresh()
+    }
+
+    #[instrument(level = "debug", skip_all, fields(bytes = buf.len()))]
+    fn start_update(&mut self, buf: Vec<u8>) -> Result<()> {
+        self.write_frame(buf)?;
+        self.trigger()
+    }
+
+    fn is_busy(&mut self) -> Result<bool> {
+        Ok(self.busy_polarity().is_busy(self.connection.busy()))
+    }
+
+    fn finish_update(&mut self) -> Result<()> {
+        self.sleep()
+    }
 
+    fn sleep(&mut self) -> Result<()> {
         self.spi_send(SpiPacket::with_data(
             DisplayCommands::EnterDeepSleep as u8,
             vec![0x01],
-        ))?;
+        ))
+    }
 
+    fn set_lut(&mut self, lut: LutKind) -> Result<()> {
+        self.lut = lut;
         Ok(())
     }
 
-    fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
-        self.connection.busy.set_interrupt(Trigger::FallingEdge)?;
-        self.connection.busy.poll_interrupt(false, timeout)?;
-        self.connection.busy.clear_interrupt()?;
+    fn set_refresh_mode(&mut self, mode: RefreshMode) -> Result<()> {
+        self.refresh_mode = mode;
         Ok(())
     }
 
-    fn spi_send(&mut self, packet: SpiPacket) -> Result<()> {
-        self.connection.dc.set_low();
-        self.connection.spi.write(&[packet.command])?;
+    fn set_border(&mut self, color: &Color) -> Result<()> {
+        // The border is driven separately from the frame, so it can use the panel's third ink
+        if !self.connection.eeprom().color().palette().contains(color) {
+            return Err(InkyError::InvalidArgument(format!(
+                "This display cannot show a {} border",
+                color
+            )));
+        }
+        self.border = *color;
+        Ok(())
+    }
 
-        if let Some(data) = packet.data {
-            self.connection.dc.set_high();
-            for chunk in data.chunks(4096) {
-                self.connection.spi.write(chunk)?;
+    fn set_data_entry_mode(&mut self, mode: DataEntryMode) -> Result<()> {
+        self.data_entry = mode;
+        Ok(())
+    }
+
+    /// Four level greyscale is only driven on black and white panels, whose particles settle
+    /// part way between black and white
+    fn set_pixel_mode(&mut self, mode: PixelMode) -> Result<()> {
+        let color = self.connection.eeprom().color();
+        if mode == PixelMode::Greyscale4 && !matches!(color, ColorMode::Black) {
+            return Err(InkyError::Unsupported(format!(
+                "Greyscale needs a black and white panel, not {:?}",
+                color
+            )));
+        }
+        self.pixel_mode = mode;
+        Ok(())
+    }
+
+    /// Black and white, with the panel's red or yellow while its waveform is selected with
+    /// [`InkyDisplay::set_lut`], or four greys in greyscale
+    fn palette(&self) -> &'static [Color] {
+        match self.pixel_mode {
+            PixelMode::Palette if self.ink().is_some() => {
+                self.connection.eeprom().color().palette()
             }
+            PixelMode::Palette => ColorMode::Black.palette(),
+            PixelMode::Greyscale4 => &Color::GREYSCALE,
         }
+    }
+
+    fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
 
+    fn set_ambient_temperature(&mut self, celsius: Option<f32>) -> Result<()> {
+        self.ambient = celsius;
         Ok(())
     }
 
-    fn convert(&self, buf: &Vec<Vec<Color>>) -> Result<Vec<u8>> {
-        let mut result = Vec::new();
-        let mut bit_pos: u8 = 0;
-        let mut cur_byte: u8 = 0;
-        for row in buf {
-            for b in row {
-                cur_byte |= (as_u8(b)) << bit_pos;
-                bit_pos += 1;
-                if bit_pos == 8 {
-                    result.push(cur_byte);
-                    cur_byte = 0;
-                    bit_pos = 0;
-                }
-            }
+    /// Partial refreshes only write the black/white RAM with the black waveform
+    fn supports_partial(&self) -> bool {
+        self.pixel_mode == PixelMode::Palette && self.ink().is_none()
+    }
+
+    #[instrument(level = "debug", skip(self, buf))]
+    fn update_region(&mut self, buf: Vec<u8>, region: Rect) -> Result<()> {
+        if self.pixel_mode == PixelMode::Greyscale4 {
+            return Err(InkyError::Unsupported(
+                "Partial refreshes are not available in greyscale".into(),
+            ));
+        }
+        // The controller RAM is addressed in whole bytes along X, so widen the window to the
+        // enclosing byte boundaries
+        let row_bytes = (self.connection.eeprom().width() as usize).div_ceil(8);
+        let x_start = region.x / 8;
+        let x_end = region.right().div_ceil(8);
+        if x_end > row_bytes || region.bottom() > self.connection.eeprom().height() as usize {
+            return Err(InkyError::InvalidArgument(format!(
+                "Region {:?} is outside of the display",
+                region
+            )));
+        }
+        let expected = row_bytes * self.connection.eeprom().height() as usize;
+        if buf.len() != expected {
+            return Err(InkyError::BufferSize {
+                expected,
+                actual: buf.len(),
+            });
+        }
+
+        let mut window = Vec::with_capacity((x_end - x_start) * region.height);
+        for row in buf.chunks(row_bytes).skip(region.y).take(region.height) {
+            window.extend_from_slice(&row[x_start..x_end]);
+        }
+
+        // The region is in the frame's coordinates, which run backwards through the RAM along
+        // the axes the controller decrements
+        let ram_x = if self.data_entry.x_increments() {
+            x_start
+        } else {
+            row_bytes - x_end
+        };
+        let ram_y = if self.data_entry.y_increments() {
+            region.y
+        } else {
+            self.connection.eeprom().height() as usize - region.bottom()
+        };
+
+        self.reset()?;
+        self.setup(LUT_BLACK_PARTIAL)?;
+        self.set_window(Rect::new(
+            ram_x * 8,
+            ram_y,
+            (x_end - x_start) * 8,
+            region.height,
+        ))?;
+
+        self.spi_send(SpiPacket::with_data(
+            DisplayCommands::SetBWBuffer as u8,
+            window,
+        ))?;
+
+        self.refresh()
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
+        let trigger = self.busy_polarity().ready_trigger();
+        self.connection.wait_for_busy(trigger, timeout)
+    }
+
+    fn spi_send(&mut self, packet: SpiPacket) -> Result<()> {
+        self.connection.send(packet)
+    }
+
+    fn buffer_len(&self) -> usize {
+        match self.pixel_mode {
+            PixelMode::Palette if self.ink().is_none() => self.plane_len(),
+            _ => self.plane_len() * 2,
+        }
+    }
+
+    fn convert(&self, buf: &[Vec<Color>]) -> Result<Vec<u8>> {
+        check_colors(buf, self.palette())?;
+        // Set bits are white, or the colored ink, cleared bits are black
+        let mut packed = pack_1bpp(buf, self.bit_order(), |color| {
+            !matches!(color, Color::Black | Color::DarkGrey)
+        });
+        if let Some(ink) = self.ink() {
+            // The red RAM plane, set for the colored ink
+            packed.extend(pack_1bpp(buf, self.bit_order(), |color| *color == ink));
         }
-        if bit_pos != 0 {
-            result.push(cur_byte);
+        if self.pixel_mode == PixelMode::Greyscale4 {
+            // The red RAM plane, set for the greys
+            packed.extend(pack_1bpp(buf, self.bit_order(), |color| {
+                matches!(color, Color::DarkGrey | Color::LightGrey)
+            }));
         }
-        Ok(result)
+        Ok(packed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InkyWhat;
+    use crate::{
+        core::{colors::Color, geometry::Rect},
+        eeprom::{ColorMode, DisplaySpec, DisplayVariant, EEPROM},
+        error::InkyError,
+        hardware::{
+            display::{BusyPolarity, DataEntryMode, InkyDisplay, PixelMode, SpiPacket},
+            mock::MockConnection,
+            transcript::RecordingConnection,
+        },
+        lut::LutKind,
+    };
+    use rppal::gpio::Level;
+
+    fn eeprom(variant: DisplayVariant) -> EEPROM {
+        EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant,
+            color: ColorMode::Black,
+        })
+    }
+
+    #[test]
+    fn update_sequence() {
+        let connection =
+            RecordingConnection::new(MockConnection::new(eeprom(DisplayVariant::What)));
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        assert!(!display.is_busy().unwrap());
+
+        let buf = vec![0xff; 400 / 8 * 300];
+        display.update(buf.clone()).unwrap();
+        let transcript = recorder.lock().unwrap();
+        let packets: Vec<&SpiPacket> = transcript.packets().collect();
+        // Soft reset, then the height in the gate setting
+        assert_eq!(packets[0], &SpiPacket::no_data(0x12));
+        assert_eq!(packets[3], &SpiPacket::with_data(0x01, vec![0x2c, 0x01, 0x00]));
+        assert!(packets.contains(&&SpiPacket::with_data(0x24, buf)));
+        assert_eq!(packets.last().unwrap(), &&SpiPacket::with_data(0x10, vec![0x01]));
+        assert!(packets.contains(&&SpiPacket::with_data(0x3c, vec![0b00110001])));
+    }
+
+    #[test]
+    fn border_color() {
+        let connection =
+            RecordingConnection::new(MockConnection::new(eeprom(DisplayVariant::What)));
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        // A black and white panel has no red border
+        assert!(display.set_border(&Color::Red).is_err());
+        display.set_border(&Color::Black).unwrap();
+
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        let transcript = recorder.lock().unwrap();
+        assert!(transcript
+            .packets()
+            .any(|packet| *packet == SpiPacket::with_data(0x3c, vec![0b00000000])));
+    }
+
+    #[test]
+    fn unsupported_colors() {
+        let connection = MockConnection::new(eeprom(DisplayVariant::What));
+        let display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        let mut frame = vec![vec![Color::White; 400]; 300];
+        frame[0][0] = Color::Green;
+        assert!(matches!(
+            display.convert(&frame),
+            Err(InkyError::UnsupportedColor(Color::Green))
+        ));
+        frame[0][0] = Color::Black;
+        assert!(display.convert(&frame).is_ok());
+    }
+
+    #[test]
+    fn data_entry_mode() {
+        let connection =
+            RecordingConnection::new(MockConnection::new(eeprom(DisplayVariant::What)));
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        display
+            .set_data_entry_mode(DataEntryMode::XDecrementYDecrement)
+            .unwrap();
+
+        // The left of the frame is the high bit of the first byte, which fills the RAM from its
+        // far end
+        let mut frame = vec![vec![Color::White; 400]; 300];
+        frame[0][0] = Color::Black;
+        let buf = display.convert(&frame).unwrap();
+        assert_eq!(buf[0], 0x7f);
+
+        display.update(buf).unwrap();
+        display
+            .update_region(vec![0xff; 400 / 8 * 300], Rect::new(8, 10, 16, 20))
+            .unwrap();
+        let transcript = recorder.lock().unwrap();
+        let packets: Vec<&SpiPacket> = transcript.packets().collect();
+        assert!(packets.contains(&&SpiPacket::with_data(0x11, vec![0x00])));
+        // The full window, from the last byte and row
+        assert!(packets.contains(&&SpiPacket::with_data(0x44, vec![49, 0])));
+        assert!(packets.contains(&&SpiPacket::with_data(0x45, vec![0x2b, 0x01, 0x00, 0x00])));
+        assert!(packets.contains(&&SpiPacket::with_data(0x4e, vec![49])));
+        // The region, mirrored through the RAM
+        assert!(packets.contains(&&SpiPacket::with_data(0x44, vec![48, 47])));
+        assert!(packets.contains(&&SpiPacket::with_data(0x45, vec![0x21, 0x01, 0x0e, 0x01])));
+    }
+
+    #[test]
+    fn temperature() {
+        // 5°C in sixteenths of a degree
+        let connection = MockConnection::new(eeprom(DisplayVariant::What))
+            .with_reply(0x1b, vec![0x05, 0x00]);
+        let connection = RecordingConnection::new(connection);
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        assert_eq!(display.temperature(), None);
+
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        assert_eq!(display.temperature(), Some(5.0));
+        let transcript = recorder.lock().unwrap();
+        let lut = transcript
+            .packets()
+            .find(|packet| packet.command == 0x32)
+            .and_then(|packet| packet.data.clone())
+            .unwrap();
+        // The cold panel repeats each phase of the waveform more
+        assert_eq!(lut[35..40], [0x10, 0x04, 0x04, 0x04, 0x05]);
+
+        // Without a way to read, the temperature is never measured, but an ambient one is used
+        let mut display =
+            InkyWhat::with_connection(Box::new(MockConnection::new(eeprom(DisplayVariant::What))))
+                .unwrap();
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        assert_eq!(display.temperature(), None);
+        display.set_ambient_temperature(Some(35.0)).unwrap();
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        assert_eq!(display.temperature(), Some(35.0));
+    }
+
+    #[test]
+    fn greyscale() {
+        let connection =
+            RecordingConnection::new(MockConnection::new(eeprom(DisplayVariant::What)));
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        display.set_pixel_mode(PixelMode::Greyscale4).unwrap();
+        assert_eq!(display.palette(), Color::GREYSCALE);
+        assert!(!display.supports_partial());
+
+        let mut frame = vec![vec![Color::White; 400]; 300];
+        frame[0][..4].copy_from_slice(&Color::GREYSCALE);
+        let buf = display.convert(&frame).unwrap();
+        assert_eq!(buf.len(), display.buffer_len());
+        assert_eq!(buf.len(), 2 * 400 / 8 * 300);
+        // Black, dark grey, light grey, white, least significant bit first
+        assert_eq!(buf[0] & 0x0f, 0b1100);
+        assert_eq!(buf[400 / 8 * 300] & 0x0f, 0b0110);
+
+        display.update(buf.clone()).unwrap();
+        display.set_pixel_mode(PixelMode::Palette).unwrap();
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        let transcript = recorder.lock().unwrap();
+        let planes: Vec<&SpiPacket> = transcript
+            .packets()
+            .filter(|packet| packet.command == 0x26)
+            .collect();
+        // The grey plane, then zeros once back in black and white
+        assert_eq!(planes.len(), 2);
+        assert_eq!(planes[0].data.as_deref(), Some(&buf[400 / 8 * 300..]));
+        assert!(planes[1].data.as_ref().unwrap().iter().all(|byte| *byte == 0));
+
+        let red = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Red,
+        });
+        let mut display = InkyWhat::with_connection(Box::new(MockConnection::new(red))).unwrap();
+        assert!(display.set_pixel_mode(PixelMode::Greyscale4).is_err());
+    }
+
+    #[test]
+    fn color_lut() {
+        let red = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Red,
+        });
+        let connection = RecordingConnection::new(MockConnection::new(red));
+        let recorder = connection.recorder();
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        assert_eq!(display.palette(), ColorMode::Black.palette());
+        display.set_lut(LutKind::Red).unwrap();
+        assert_eq!(display.palette(), ColorMode::Red.palette());
+        assert!(!display.supports_partial());
+
+        let mut frame = vec![vec![Color::White; 400]; 300];
+        frame[0][0] = Color::Red;
+        frame[0][1] = Color::Black;
+        let buf = display.convert(&frame).unwrap();
+        assert_eq!(buf.len(), display.buffer_len());
+        // Red is white in the black/white plane and set in the red one
+        assert_eq!(buf[0] & 0x0f, 0b1101);
+        assert_eq!(buf[400 / 8 * 300] & 0x0f, 0b0001);
+
+        display.update(buf.clone()).unwrap();
+        display.set_lut(LutKind::Black).unwrap();
+        display.update(vec![0xff; 400 / 8 * 300]).unwrap();
+        let transcript = recorder.lock().unwrap();
+        let planes: Vec<&SpiPacket> = transcript
+            .packets()
+            .filter(|packet| packet.command == 0x26)
+            .collect();
+        assert_eq!(planes.len(), 2);
+        assert_eq!(planes[0].data.as_deref(), Some(&buf[400 / 8 * 300..]));
+        assert!(planes[1].data.as_ref().unwrap().iter().all(|byte| *byte == 0));
+
+        // A black and white panel has no red to drive
+        let mut display =
+            InkyWhat::with_connection(Box::new(MockConnection::new(eeprom(DisplayVariant::What))))
+                .unwrap();
+        display.set_lut(LutKind::Red).unwrap();
+        assert_eq!(display.palette(), ColorMode::Black.palette());
+    }
+
+    #[test]
+    fn rejects_other_variants() {
+        let connection = MockConnection::new(eeprom(DisplayVariant::E673));
+        assert!(InkyWhat::with_connection(Box::new(connection)).is_err());
+    }
+
+    #[test]
+    fn busy_polarity_override() {
+        let connection = MockConnection::new(eeprom(DisplayVariant::What)).with_busy(Level::Low);
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        assert!(!display.is_busy().unwrap());
+
+        let connection = MockConnection::new(eeprom(DisplayVariant::What))
+            .with_busy(Level::Low)
+            .with_busy_polarity(BusyPolarity::ActiveLow);
+        let mut display = InkyWhat::with_connection(Box::new(connection)).unwrap();
+        assert!(display.is_busy().unwrap());
     }
 }
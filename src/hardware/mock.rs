@@ -0,0 +1,136 @@
+//! A stand-in for the SPI bus and GPIO lines, for unit testing drivers without a display
+//!
+//! [`MockConnection`] accepts every packet and reports a fixed busy level, so drivers run through
+//! their sequences instantly. The level can be changed through [`MockConnection::busy_line`] once
+//! the connection belongs to a display. Wrap it in a [`RecordingConnection`] to check what was
+//! sent.
+//!
+//! [`RecordingConnection`]: crate::hardware::transcript::RecordingConnection
+
+use crate::{
+    eeprom::EEPROM,
+    error::{InkyError, Result},
+    hardware::display::{BusyPolarity, Connection, InkyConnection, SpiPacket, Timing},
+};
+
+use rppal::gpio::{Level, Trigger};
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub struct MockConnection {
+    eeprom: EEPROM,
+    busy: Arc<Mutex<Level>>,
+    reset: Level,
+    busy_timeout: Duration,
+    busy_polarity: Option<BusyPolarity>,
+    timing: Option<Timing>,
+    replies: HashMap<u8, Vec<u8>>,
+}
+
+impl MockConnection {
+    /// Create a connection to the display described by `eeprom`, whose busy line is low
+    pub fn new(eeprom: EEPROM) -> Self {
+        Self {
+            eeprom,
+            busy: Arc::new(Mutex::new(Level::Low)),
+            reset: Level::High,
+            busy_timeout: InkyConnection::DEFAULT_BUSY_TIMEOUT,
+            busy_polarity: None,
+            timing: None,
+            replies: HashMap::new(),
+        }
+    }
+
+    /// Report the busy line at `level`
+    pub fn with_busy(self, level: Level) -> Self {
+        *self.busy.lock().expect("busy line lock poisoned") = level;
+        self
+    }
+
+    /// The busy line, shared with the connection, to change its level while a display owns it
+    pub fn busy_line(&self) -> Arc<Mutex<Level>> {
+        Arc::clone(&self.busy)
+    }
+
+    /// Override the driver's busy line polarity, like [`ConnectionConfig::busy_polarity`]
+    ///
+    /// [`ConnectionConfig::busy_polarity`]: crate::hardware::display::ConnectionConfig::busy_polarity
+    pub fn with_busy_polarity(mut self, polarity: BusyPolarity) -> Self {
+        self.busy_polarity = Some(polarity);
+        self
+    }
+
+    /// Override the driver's reset timing, e.g. with zero durations to skip the delays
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Answer reads of `command` with `reply`, e.g. a temperature register. A connection with no
+    /// replies can't read, like the rppal one
+    pub fn with_reply(mut self, command: u8, reply: Vec<u8>) -> Self {
+        self.replies.insert(command, reply);
+        self
+    }
+
+    /// The level the reset line was last driven to
+    pub fn reset(&self) -> Level {
+        self.reset
+    }
+}
+
+impl Connection for MockConnection {
+    fn send(&mut self, _packet: SpiPacket) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_reset(&mut self, level: Level) {
+        self.reset = level;
+    }
+
+    fn busy(&self) -> Level {
+        *self.busy.lock().expect("busy line lock poisoned")
+    }
+
+    /// Returns immediately, as if the display had signalled straight away
+    fn wait_for_busy(&mut self, _trigger: Trigger, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    fn eeprom(&self) -> &EEPROM {
+        &self.eeprom
+    }
+
+    fn busy_timeout(&self) -> Duration {
+        self.busy_timeout
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
+    fn busy_polarity(&self) -> Option<BusyPolarity> {
+        self.busy_polarity
+    }
+
+    fn timing(&self) -> Option<Timing> {
+        self.timing
+    }
+
+    fn can_read(&self) -> bool {
+        !self.replies.is_empty()
+    }
+
+    /// The reply given with [`MockConnection::with_reply`], cut or padded with zeros to `len`
+    fn read(&mut self, command: u8, len: usize) -> Result<Vec<u8>> {
+        let mut reply = self.replies.get(&command).cloned().ok_or_else(|| {
+            InkyError::Unsupported(format!("No reply to command {:#04x}", command))
+        })?;
+        reply.resize(len, 0);
+        Ok(reply)
+    }
+}
@@ -0,0 +1,356 @@
+//! Recording of the SPI traffic sent to a display, for regression tests of the init and update
+//! sequences
+//!
+//! A [`RecordingConnection`] wraps a connection and keeps every packet sent through it, with the
+//! time it was sent, in a [`Transcript`]. Transcripts are saved as text, one packet per line:
+//!
+//! ```text
+//! # inky transcript
+//! 0 12 -
+//! 105331 74 54
+//! 105402 01 2b0100
+//! ```
+//!
+//! giving the microseconds since recording started, the command and the data in hex, or `-` for
+//! a command without data and `:` for a command sent with empty data. Comparing transcripts ignores the timing, which varies from run to run.
+
+use crate::{
+    eeprom::EEPROM,
+    error::{InkyError, Result},
+    hardware::display::{
+        BusyPolarity, Connection, ConnectionTimings, InkyConnection, SpiPacket, Timing,
+    },
+};
+
+use rppal::gpio::{Level, Trigger};
+
+use std::{
+    fmt::{self, Write as _},
+    fs,
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const HEADER: &str = "# inky transcript";
+
+/// A packet and when it was sent, relative to the start of the recording
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    pub packet: SpiPacket,
+    pub elapsed: Duration,
+}
+
+/// The packets sent to a display, in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+}
+
+/// The first difference between two transcripts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub index: usize,
+    /// The expected packet, or `None` if the actual transcript is longer
+    pub expected: Option<SpiPacket>,
+    /// The actual packet, or `None` if the actual transcript is shorter
+    pub actual: Option<SpiPacket>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |packet: &Option<SpiPacket>| match packet {
+            Some(packet) => format_packet(packet),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "packet {}: expected {}, found {}",
+            self.index,
+            describe(&self.expected),
+            describe(&self.actual)
+        )
+    }
+}
+
+fn format_packet(packet: &SpiPacket) -> String {
+    let mut line = format!("{:02x} ", packet.command);
+    match &packet.data {
+        Some(data) if data.is_empty() => line.push(':'),
+        Some(data) => data.iter().for_each(|byte| {
+            let _ = write!(line, "{:02x}", byte);
+        }),
+        None => line.push('-'),
+    }
+    line
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl Transcript {
+    /// The packets without their timing
+    pub fn packets(&self) -> impl Iterator<Item = &SpiPacket> {
+        self.entries.iter().map(|entry| &entry.packet)
+    }
+
+    /// Render the transcript in its text form
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for entry in &self.entries {
+            let _ = writeln!(
+                text,
+                "{} {}",
+                entry.elapsed.as_micros(),
+                format_packet(&entry.packet)
+            );
+        }
+        text
+    }
+
+    /// Parse the text form of a transcript. Blank lines and lines starting with `#` are ignored
+    pub fn from_text(text: &str) -> Result<Self> {
+        let invalid =
+            |n: usize| InkyError::InvalidArgument(format!("Invalid transcript line {}", n + 1));
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let &[elapsed, command, data] = fields.as_slice() else {
+                return Err(invalid(n));
+            };
+            let elapsed = elapsed.parse().map_err(|_| invalid(n))?;
+            let command = u8::from_str_radix(command, 16).map_err(|_| invalid(n))?;
+            let data = match data {
+                "-" => None,
+                ":" => Some(Vec::new()),
+                hex => Some(parse_hex(hex).ok_or_else(|| invalid(n))?),
+            };
+            entries.push(TranscriptEntry {
+                packet: SpiPacket { command, data },
+                elapsed: Duration::from_micros(elapsed),
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        Ok(fs::write(path, self.to_text())?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Find the first packet that differs from `expected`, ignoring timing
+    pub fn compare(&self, expected: &Transcript) -> Option<Mismatch> {
+        let length = self.entries.len().max(expected.entries.len());
+        (0..length).find_map(|index| {
+            let actual = self.entries.get(index).map(|entry| &entry.packet);
+            let wanted = expected.entries.get(index).map(|entry| &entry.packet);
+            (actual != wanted).then(|| Mismatch {
+                index,
+                expected: wanted.cloned(),
+                actual: actual.cloned(),
+            })
+        })
+    }
+
+    /// Panic with the first difference if the transcript does not match the golden transcript
+    /// saved at `path`. Setting `INKY_UPDATE_TRANSCRIPTS` saves this transcript there instead,
+    /// to accept an intended change
+    pub fn assert_matches(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if std::env::var_os("INKY_UPDATE_TRANSCRIPTS").is_some() {
+            self.save(path).expect("Failed to save the transcript");
+            return;
+        }
+        let expected = Transcript::load(path)
+            .unwrap_or_else(|e| panic!("Failed to load {}: {}", path.display(), e));
+        if let Some(mismatch) = self.compare(&expected) {
+            panic!("Transcript differs from {}: {}", path.display(), mismatch);
+        }
+    }
+}
+
+/// A connection that records every packet sent through it before passing it on to `C`
+pub struct RecordingConnection<C = InkyConnection> {
+    connection: C,
+    transcript: Arc<Mutex<Transcript>>,
+    start: Instant,
+}
+
+impl<C: Connection> RecordingConnection<C> {
+    pub fn new(connection: C) -> Self {
+        Self {
+            connection,
+            transcript: Arc::default(),
+            start: Instant::now(),
+        }
+    }
+
+    /// The packets recorded so far
+    pub fn transcript(&self) -> Transcript {
+        self.transcript.lock().expect("transcript lock poisoned").clone()
+    }
+
+    /// A handle to the transcript that keeps filling once the connection has been handed to a
+    /// driver with [`InkyDisplay::with_connection`](crate::hardware::display::InkyDisplay::with_connection)
+    pub fn recorder(&self) -> Arc<Mutex<Transcript>> {
+        Arc::clone(&self.transcript)
+    }
+
+    /// Stop recording, returning the connection and the transcript
+    pub fn into_parts(self) -> (C, Transcript) {
+        let transcript = self.transcript();
+        (self.connection, transcript)
+    }
+}
+
+impl<C: Connection> Connection for RecordingConnection<C> {
+    /// Record and send a command and its data
+    fn send(&mut self, packet: SpiPacket) -> Result<()> {
+        self.transcript
+            .lock()
+            .expect("transcript lock poisoned")
+            .entries
+            .push(TranscriptEntry {
+                packet: packet.clone(),
+                elapsed: self.start.elapsed(),
+            });
+        self.connection.send(packet)
+    }
+
+    fn set_reset(&mut self, level: Level) {
+        self.connection.set_reset(level)
+    }
+
+    fn busy(&self) -> Level {
+        self.connection.busy()
+    }
+
+    fn wait_for_busy(&mut self, trigger: Trigger, timeout: Option<Duration>) -> Result<()> {
+        self.connection.wait_for_busy(trigger, timeout)
+    }
+
+    fn eeprom(&self) -> &EEPROM {
+        self.connection.eeprom()
+    }
+
+    fn busy_timeout(&self) -> Duration {
+        self.connection.busy_timeout()
+    }
+
+    fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.connection.set_busy_timeout(timeout)
+    }
+
+    fn take_timings(&mut self) -> ConnectionTimings {
+        self.connection.take_timings()
+    }
+
+    fn busy_polarity(&self) -> Option<BusyPolarity> {
+        self.connection.busy_polarity()
+    }
+
+    fn timing(&self) -> Option<Timing> {
+        self.connection.timing()
+    }
+
+    fn delay(&mut self, duration: Duration) {
+        self.connection.delay(duration)
+    }
+
+    /// Read without recording, as transcripts only hold what was sent
+    fn read(&mut self, command: u8, len: usize) -> Result<Vec<u8>> {
+        self.connection.read(command, len)
+    }
+
+    fn can_read(&self) -> bool {
+        self.connection.can_read()
+    }
+}
+
+impl<C> Deref for RecordingConnection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.connection
+    }
+}
+
+impl<C> DerefMut for RecordingConnection<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.connection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Transcript, TranscriptEntry};
+    use crate::hardware::display::SpiPacket;
+    use std::time::Duration;
+
+    fn transcript(packets: &[SpiPacket]) -> Transcript {
+        Transcript {
+            entries: packets
+                .iter()
+                .enumerate()
+                .map(|(i, packet)| TranscriptEntry {
+                    packet: packet.clone(),
+                    elapsed: Duration::from_micros(i as u64 * 100),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let recorded = transcript(&[
+            SpiPacket::no_data(0x12),
+            SpiPacket::with_data(0x01, vec![0x2b, 0x01, 0x00]),
+            SpiPacket::with_data(0x20, vec![]),
+        ]);
+        let text = recorded.to_text();
+        assert_eq!(
+            text,
+            "# inky transcript\n0 12 -\n100 01 2b0100\n200 20 :\n"
+        );
+        assert_eq!(Transcript::from_text(&text).unwrap(), recorded);
+        assert!(Transcript::from_text("0 12 2").is_err());
+        assert!(Transcript::from_text("0 12").is_err());
+    }
+
+    #[test]
+    fn compare_ignores_timing() {
+        let expected = transcript(&[
+            SpiPacket::no_data(0x12),
+            SpiPacket::with_data(0x74, vec![0x54]),
+        ]);
+        let mut actual = expected.clone();
+        actual.entries[1].elapsed = Duration::from_secs(1);
+        assert_eq!(actual.compare(&expected), None);
+
+        actual.entries[1].packet = SpiPacket::with_data(0x74, vec![0x55]);
+        let mismatch = actual.compare(&expected).unwrap();
+        assert_eq!(mismatch.index, 1);
+        assert_eq!(
+            mismatch.to_string(),
+            "packet 1: expected 74 54, found 74 55"
+        );
+
+        actual.entries.truncate(1);
+        assert_eq!(actual.compare(&expected).unwrap().actual, None);
+    }
+}
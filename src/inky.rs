@@ -1,75 +1,186 @@
 //! Control and draw to the Inky display
 
+use crate::core::{
+    colors::Color,
+    geometry::Rect,
+    mask::Mask,
+    pattern::{FillStyle, Styled},
+    transform::{Transform, Transformed},
+};
+#[cfg(feature = "hardware")]
 use crate::{
-    eeprom::{DisplayVariant, EEPROM},
+    core::{
+        layers::{Layer, Layers},
+        test_pattern::TestPattern,
+        transform::Rotation,
+    },
+    eeprom::{DisplaySpec, DisplayVariant, EEPROM},
+    error::{InkyError, Result},
     hardware::{
-        display::{InkyDisplay},
+        display::{
+            Connection, ConnectionConfig, DataEntryMode, InkyDisplay, PixelMode, RefreshMode,
+        },
+        dry_run::DryRunConnection,
         inkye673::InkyE673,
         inkywhat::InkyWhat,
     },
-    core::colors::Color,
+    lut::LutKind,
+    refresh_counter::RefreshCounter,
 };
+#[cfg(all(feature = "hardware", feature = "image"))]
+use crate::core::image::ImportOptions;
 
-use anyhow::{Error, Result, bail};
+use alloc::{boxed::Box, vec, vec::Vec};
+#[cfg(feature = "hardware")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "hardware")]
+use tracing::{debug, info, info_span, warn};
+
+/// Pixels covered by a [`Drawable`], produced lazily so large shapes don't need a buffer
+pub type Coordinates<'a> = Box<dyn Iterator<Item = (usize, usize)> + 'a>;
 
 pub trait Drawable {
-    fn coordinates(&self) -> Vec<(usize, usize)>;
+    /// The (x, y) pixels covered by the shape. A pixel may be produced more than once
+    fn coordinates(&self) -> Coordinates<'_>;
+    /// A rectangle containing every pixel of the shape, used to skip shapes that are entirely
+    /// off the canvas and to track the region a shape changes
+    fn bounds(&self) -> Rect;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Line {
     start: (isize, isize),
     end: (isize, isize),
+    width: usize,
 }
 
 impl Line {
     pub fn new(start: (isize, isize), end: (isize, isize)) -> Self {
-        Self { start, end }
+        Self { start, end, width: 1 }
     }
 
-    // Returns a vector of coordinates along the line using Bresenham's algorithm
-    fn line_coordinates(&self) -> Vec<(usize, usize)> {
-        let mut result = Vec::new();
+    /// Set the stroke width in pixels. Wide lines have rounded ends
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
 
-        let (mut x0, mut y0) = self.start;
-        let (x1, y1) = self.end;
+    // Offsets of the brush from the centre of the line, inclusive
+    fn brush_extent(&self) -> (isize, isize) {
+        (
+            -((self.width / 2) as isize),
+            (self.width.saturating_sub(1) / 2) as isize,
+        )
+    }
 
-        let dx = x1 - x0;
-        let dy = -(y1 - y0);
+    // Returns the coordinates covered by the line, stamping a round brush of the stroke width
+    // at every point along its centre
+    pub(crate) fn thick_line_coordinates(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (lo, hi) = self.brush_extent();
+        let radius = (self.width * self.width / 4) as isize;
+        let brush: Vec<(isize, isize)> = (lo..=hi)
+            .flat_map(|oy| (lo..=hi).map(move |ox| (ox, oy)))
+            .filter(|(ox, oy)| ox * ox + oy * oy <= radius)
+            .collect();
+        self.line_coordinates().flat_map(move |(x, y)| {
+            brush
+                .clone()
+                .into_iter()
+                .map(move |(ox, oy)| (x + ox, y + oy))
+                .filter(|&(px, py)| px >= 0 && py >= 0)
+                .map(|(px, py)| (px as usize, py as usize))
+        })
+    }
 
-        let sx = if x0 < x1 { 1 } else { -1 };
-        let sy = if y0 < y1 { 1 } else { -1 };
+    // Returns the coordinates along the line using Bresenham's algorithm
+    fn line_coordinates(&self) -> Bresenham {
+        Bresenham::new(self.start, self.end)
+    }
+}
 
-        let mut err = dx + dy;
+impl Drawable for Line {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(self.thick_line_coordinates())
+    }
 
-        loop {
-            result.push((x0 as usize, y0 as usize));
-            if x0 == x1 && y0 == y1 {
-                break;
-            }
-            let e2 = 2 * err;
-            if e2 >= dy {
-                err += dy;
-                x0 += sx;
-            }
-            if e2 <= dx {
-                err += dx;
-                y0 += sy;
-            }
-        }
+    fn bounds(&self) -> Rect {
+        let (lo, hi) = self.brush_extent();
+        Rect::from_signed_corners(
+            (self.start.0.min(self.end.0) + lo, self.start.1.min(self.end.1) + lo),
+            (self.start.0.max(self.end.0) + hi, self.start.1.max(self.end.1) + hi),
+        )
+    }
+}
 
-        result
+// Bounds of a shape drawn between two inclusive corners, which is empty if the corners are the
+// wrong way round
+fn corner_bounds(top_left: (usize, usize), bottom_right: (usize, usize)) -> Rect {
+    if top_left.0 > bottom_right.0 || top_left.1 > bottom_right.1 {
+        Rect::default()
+    } else {
+        Rect::from_corners(top_left, bottom_right)
     }
 }
 
-impl Drawable for Line {
-    fn coordinates(&self) -> Vec<(usize, usize)> {
-        self.line_coordinates()
+// Steps along a line one pixel at a time
+struct Bresenham {
+    current: (isize, isize),
+    end: (isize, isize),
+    delta: (isize, isize),
+    step: (isize, isize),
+    err: isize,
+    done: bool,
+}
+
+impl Bresenham {
+    fn new(start: (isize, isize), end: (isize, isize)) -> Self {
+        let dx = (end.0 - start.0).abs();
+        let dy = -(end.1 - start.1).abs();
+        let sx = if start.0 < end.0 { 1 } else { -1 };
+        let sy = if start.1 < end.1 { 1 } else { -1 };
+        Self {
+            current: start,
+            end,
+            delta: (dx, dy),
+            step: (sx, sy),
+            err: dx + dy,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Bresenham {
+    type Item = (isize, isize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let point = self.current;
+        if point == self.end {
+            self.done = true;
+            return Some(point);
+        }
+        let (dx, dy) = self.delta;
+        let e2 = 2 * self.err;
+        if e2 >= dy {
+            self.err += dy;
+            self.current.0 += self.step.0;
+        }
+        if e2 <= dx {
+            self.err += dx;
+            self.current.1 += self.step.1;
+        }
+        Some(point)
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Rectangle {
     top_left: (usize, usize),
     bottom_right: (usize, usize),
+    // Width of the outline, or None for a filled rectangle
+    stroke: Option<usize>,
 }
 
 impl Rectangle {
@@ -77,61 +188,475 @@ impl Rectangle {
         Self {
             top_left,
             bottom_right,
+            stroke: None,
         }
     }
 
-    // Returns a vector of coordinates inside the rectangle
-    fn rectangle_coordinates(&self) -> Vec<(usize, usize)> {
-        let mut result = Vec::new();
+    /// An unfilled rectangle with an outline `width` pixels wide, drawn inside the corners
+    pub fn outline(top_left: (usize, usize), bottom_right: (usize, usize), width: usize) -> Self {
+        Self {
+            top_left,
+            bottom_right,
+            stroke: Some(width),
+        }
+    }
 
-        for row in self.top_left.0..=self.bottom_right.0 {
-            for col in self.top_left.1..=self.bottom_right.1 {
-                result.push((row, col));
+    fn on_outline(&self, row: usize, col: usize) -> bool {
+        match self.stroke {
+            None => true,
+            Some(width) => {
+                row < self.top_left.0 + width
+                    || row + width > self.bottom_right.0
+                    || col < self.top_left.1 + width
+                    || col + width > self.bottom_right.1
             }
         }
+    }
 
-        result
+    // Returns the coordinates inside the rectangle
+    pub(crate) fn rectangle_coordinates(self) -> impl Iterator<Item = (usize, usize)> {
+        let (top, bottom) = (self.top_left.1, self.bottom_right.1);
+        (self.top_left.0..=self.bottom_right.0)
+            .flat_map(move |row| (top..=bottom).map(move |col| (row, col)))
+            .filter(move |&(row, col)| self.on_outline(row, col))
     }
 }
 
 impl Drawable for Rectangle {
-    fn coordinates(&self) -> Vec<(usize, usize)> {
-        self.rectangle_coordinates()
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(self.rectangle_coordinates())
+    }
+
+    fn bounds(&self) -> Rect {
+        corner_bounds(self.top_left, self.bottom_right)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundedRectangle {
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+    radius: usize,
+    // Width of the outline, or None for a filled rectangle
+    stroke: Option<usize>,
+}
+
+impl RoundedRectangle {
+    pub fn new(top_left: (usize, usize), bottom_right: (usize, usize), radius: usize) -> Self {
+        Self {
+            top_left,
+            bottom_right,
+            radius,
+            stroke: None,
+        }
+    }
+
+    /// An unfilled rounded rectangle with an outline `width` pixels wide, drawn inside the corners
+    pub fn outline(
+        top_left: (usize, usize),
+        bottom_right: (usize, usize),
+        radius: usize,
+        width: usize,
+    ) -> Self {
+        Self {
+            top_left,
+            bottom_right,
+            radius,
+            stroke: Some(width),
+        }
+    }
+
+    // Whether a point lies inside the rounded rectangle with the given corners and radius. The
+    // radius is limited so opposite corners never overlap
+    fn contains(
+        top_left: (usize, usize),
+        bottom_right: (usize, usize),
+        radius: usize,
+        (x, y): (usize, usize),
+    ) -> bool {
+        if x < top_left.0 || x > bottom_right.0 || y < top_left.1 || y > bottom_right.1 {
+            return false;
+        }
+        let width = bottom_right.0 - top_left.0 + 1;
+        let height = bottom_right.1 - top_left.1 + 1;
+        let radius = radius.min(width / 2).min(height / 2);
+        // Distance past the centre of the nearest corner circle along one axis
+        let offset = |v: usize, lo: usize, hi: usize| {
+            (lo + radius).saturating_sub(v).max((v + radius).saturating_sub(hi))
+        };
+        let dx = offset(x, top_left.0, bottom_right.0);
+        let dy = offset(y, top_left.1, bottom_right.1);
+        dx * dx + dy * dy <= radius * radius
+    }
+
+    // Whether a point is drawn: inside the shape, and outside the inner edge of any outline
+    fn covers(&self, point: (usize, usize)) -> bool {
+        if !Self::contains(self.top_left, self.bottom_right, self.radius, point) {
+            return false;
+        }
+        let Some(width) = self.stroke else {
+            return true;
+        };
+        let inner_right = self.bottom_right.0.checked_sub(width);
+        let inner_bottom = self.bottom_right.1.checked_sub(width);
+        match (inner_right, inner_bottom) {
+            (Some(right), Some(bottom)) => !Self::contains(
+                (self.top_left.0 + width, self.top_left.1 + width),
+                (right, bottom),
+                self.radius.saturating_sub(width),
+                point,
+            ),
+            _ => true,
+        }
+    }
+
+    fn rounded_rectangle_coordinates(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (top, bottom) = (self.top_left.1, self.bottom_right.1);
+        (self.top_left.0..=self.bottom_right.0)
+            .flat_map(move |x| (top..=bottom).map(move |y| (x, y)))
+            .filter(|&point| self.covers(point))
+    }
+}
+
+impl Drawable for RoundedRectangle {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(self.rounded_rectangle_coordinates())
+    }
+
+    fn bounds(&self) -> Rect {
+        corner_bounds(self.top_left, self.bottom_right)
     }
 }
 
+#[derive(Clone)]
 pub struct Canvas {
     width: usize,
     height: usize,
     pixels: Vec<Vec<Color>>,
+    // Bounding box of pixels modified since the display was last updated
+    dirty: Option<Rect>,
+    // Colors the display can show
+    palette: &'static [Color],
+    // Pixels that drawing may change, or None for all of them
+    mask: Option<Mask>,
 }
 
 impl Canvas {
-    /// Create a new drawing canvas with a width and height
-    fn new(width: usize, height: usize) -> Canvas {
+    /// Create a new drawing canvas with a width and height. Canvases don't need a display, so
+    /// they can be used to render sprites off-screen for [`Canvas::blit`] or to develop and test
+    /// rendering code on any machine
+    pub fn new(width: usize, height: usize) -> Canvas {
         Canvas {
             width,
             height,
             pixels: vec![vec![Color::White; width ]; height],
-        } 
+            dirty: None,
+            palette: &Color::ALL,
+            mask: None,
+        }
+    }
+
+    /// Create a canvas with every pixel set to `color`
+    pub(crate) fn filled(width: usize, height: usize, color: Color) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        canvas.pixels = vec![vec![color; width]; height];
+        canvas
+    }
+
+    /// Restrict the canvas to the colors in `palette`, e.g. [`crate::eeprom::ColorMode::palette`]
+    /// to preview a particular panel off-screen
+    pub fn with_palette(mut self, palette: &'static [Color]) -> Canvas {
+        self.palette = palette;
+        self
+    }
+
+    /// Change the palette, replacing every pixel with its nearest color in `palette`
+    #[cfg(feature = "hardware")]
+    pub(crate) fn remap(&mut self, palette: &'static [Color]) {
+        self.palette = palette;
+        for row in self.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                if !palette.contains(pixel) {
+                    let (r, g, b) = pixel.to_rgb();
+                    *pixel = Color::nearest(palette, r, g, b);
+                }
+            }
+        }
+        self.mark_dirty(Rect::new(0, 0, self.width, self.height));
     }
 
     /// Get the color of a given pixel
-    fn get_pixel(&self, col: usize, row: usize) -> Color {
-        self.pixels[col][row].clone()
+    #[allow(dead_code)]
+    pub(crate) fn get_pixel(&self, col: usize, row: usize) -> Color {
+        self.pixels[col][row]
+    }
+
+    /// Get the color of the pixel at (x, y), or `None` if it is outside of the canvas
+    pub fn get(&self, x: usize, y: usize) -> Option<Color> {
+        self.pixels.get(y)?.get(x).copied()
+    }
+
+    /// Iterate over every pixel as (x, y, color), row by row from the top left
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, Color)> + '_ {
+        self.pixels.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, color)| (x, y, *color))
+        })
+    }
+
+    /// Set the color of a given pixel. Colors the display can't show are replaced with the
+    /// nearest one it can
+    pub(crate) fn set_pixel(&mut self,  row: usize, col: usize, color: &Color) {
+        if !self.writable(row, col) {
+            return;
+        }
+        self.pixels[col][row] = self.map_color(color);
+        self.mark_dirty(Rect::new(row, col, 1, 1));
+    }
+
+    /// Restrict drawing to the pixels set in `mask`, or allow drawing anywhere with `None`
+    pub fn set_mask(&mut self, mask: Option<Mask>) {
+        self.mask = mask;
+    }
+
+    pub fn mask(&self) -> Option<&Mask> {
+        self.mask.as_ref()
+    }
+
+    // Whether the mask allows drawing to the pixel at (x, y)
+    fn writable(&self, x: usize, y: usize) -> bool {
+        match &self.mask {
+            Some(mask) => mask.is_set(x, y),
+            None => true,
+        }
+    }
+
+    /// Extend the dirty region to cover `region`
+    pub(crate) fn mark_dirty(&mut self, region: Rect) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(&region),
+            None => region,
+        });
+    }
+
+    /// Get the bounding box of all pixels modified since the display was last updated
+    pub fn dirty_region(&self) -> Option<Rect> {
+        self.dirty
     }
 
-    /// Set the color of a given pixel
-    fn set_pixel(&mut self,  row: usize, col: usize, color: &Color) {
-        self.pixels[col][row] = color.clone();
+    /// Forget about any modifications made since the display was last updated
+    pub fn clear_dirty(&mut self) {
+        self.dirty = None;
     }
 
+    /// Draw a shape onto the canvas. Any part of the shape outside of the canvas is clipped
     pub fn draw<D: Drawable>(&mut self, drawable: D, color: &Color) {
-        for (row, col) in drawable.coordinates() {
-            self.set_pixel(row, col, &color);
+        let canvas = Rect::new(0, 0, self.width, self.height);
+        let Some(visible) = drawable.bounds().intersection(&canvas) else {
+            return;
+        };
+        let color = self.map_color(color);
+        let mut drawn = false;
+        for (x, y) in drawable.coordinates() {
+            if visible.contains(x, y) && self.writable(x, y) {
+                self.pixels[y][x] = color;
+                drawn = true;
+            }
+        }
+        if drawn {
+            self.mark_dirty(visible);
+        }
+    }
+
+    /// Set every pixel the mask allows to `color`
+    pub fn fill(&mut self, color: &Color) {
+        let color = self.map_color(color);
+        match &self.mask {
+            None => {
+                for row in self.pixels.iter_mut() {
+                    row.fill(color);
+                }
+            }
+            Some(mask) => {
+                for (y, row) in self.pixels.iter_mut().enumerate() {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        if mask.is_set(x, y) {
+                            *pixel = color;
+                        }
+                    }
+                }
+            }
+        }
+        self.mark_dirty(Rect::new(0, 0, self.width, self.height));
+    }
+
+    /// Set every pixel to white
+    pub fn clear(&mut self) {
+        self.fill(&Color::White);
+    }
+
+    /// Fill the region of same-colored pixels connected to (`x`, `y`) with `color`, as with a
+    /// paint bucket. Pixels only connect horizontally and vertically, so one pixel wide diagonal
+    /// outlines hold the fill in. The edge of the mask, if any, also holds the fill in
+    pub fn flood_fill(&mut self, x: usize, y: usize, color: &Color) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let target = self.pixels[y][x];
+        let color = self.map_color(color);
+        if target == color {
+            return;
+        }
+        let open = |canvas: &Canvas, x: usize, y: usize| {
+            canvas.pixels[y][x] == target && canvas.writable(x, y)
+        };
+        let mut filled: Option<Rect> = None;
+        let mut seeds = vec![(x, y)];
+        while let Some((x, y)) = seeds.pop() {
+            if !open(self, x, y) {
+                continue;
+            }
+            // Extend the span to the left and right of the seed
+            let mut left = x;
+            while left > 0 && open(self, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < self.width && open(self, right + 1, y) {
+                right += 1;
+            }
+            self.pixels[y][left..=right].fill(color);
+            let span = Rect::from_corners((left, y), (right, y));
+            filled = Some(filled.map_or(span, |filled| filled.union(&span)));
+
+            // Seed each run of matching pixels in the rows above and below the span
+            let neighbours = [y.checked_sub(1), Some(y + 1).filter(|&row| row < self.height)];
+            for row in neighbours.into_iter().flatten() {
+                let mut in_run = false;
+                for col in left..=right {
+                    let matches = open(self, col, row);
+                    if matches && !in_run {
+                        seeds.push((col, row));
+                    }
+                    in_run = matches;
+                }
+            }
+        }
+        if let Some(filled) = filled {
+            self.mark_dirty(filled);
+        }
+    }
+
+    /// Swap black and white within `region`, e.g. to highlight a selection or show a button
+    /// being pressed. With `complement_colors` other inks become their [`Color::complement`] in
+    /// the palette, otherwise they are left alone
+    pub fn invert_region(&mut self, region: Rect, complement_colors: bool) {
+        let Some(region) = region.intersection(&Rect::new(0, 0, self.width, self.height)) else {
+            return;
+        };
+        for y in region.y..region.bottom() {
+            for x in region.x..region.right() {
+                if !self.writable(x, y) {
+                    continue;
+                }
+                let color = self.pixels[y][x];
+                self.pixels[y][x] = match color {
+                    Color::Black | Color::White => color.complement(self.palette),
+                    color if complement_colors => color.complement(self.palette),
+                    color => color,
+                };
+            }
+        }
+        self.mark_dirty(region);
+    }
+
+    /// Replace every pixel with the color `f` returns for its position and current color, e.g.
+    /// to add a vignette or a procedural texture
+    pub fn map_pixels<F>(&mut self, f: F)
+    where
+        F: FnMut(usize, usize, Color) -> Color,
+    {
+        self.map_region(Rect::new(0, 0, self.width, self.height), f);
+    }
+
+    /// Replace every pixel within `region` with the color `f` returns for its position and
+    /// current color. Pixels the mask protects are skipped
+    pub fn map_region<F>(&mut self, region: Rect, mut f: F)
+    where
+        F: FnMut(usize, usize, Color) -> Color,
+    {
+        let Some(region) = region.intersection(&Rect::new(0, 0, self.width, self.height)) else {
+            return;
+        };
+        let mut changed: Option<Rect> = None;
+        for y in region.y..region.bottom() {
+            for x in region.x..region.right() {
+                if !self.writable(x, y) {
+                    continue;
+                }
+                let color = self.map_color(&f(x, y, self.pixels[y][x]));
+                if color != self.pixels[y][x] {
+                    self.pixels[y][x] = color;
+                    let pixel = Rect::new(x, y, 1, 1);
+                    changed = Some(changed.map_or(pixel, |changed| changed.union(&pixel)));
+                }
+            }
+        }
+        if let Some(changed) = changed {
+            self.mark_dirty(changed);
+        }
+    }
+
+    /// Copy `source` onto this canvas with its top left corner at `position`, skipping pixels
+    /// of the `transparent` color. Anything outside of the canvas is clipped
+    pub fn blit(&mut self, source: &Canvas, position: (usize, usize), transparent: Option<Color>) {
+        let (x, y) = position;
+        for (row, line) in source.pixels.iter().enumerate().take(self.height.saturating_sub(y)) {
+            for (col, color) in line.iter().enumerate().take(self.width.saturating_sub(x)) {
+                if Some(*color) != transparent {
+                    self.set_pixel(x + col, y + row, color);
+                }
+            }
+        }
+    }
+
+    /// Get the colors the display can show
+    pub fn palette(&self) -> &'static [Color] {
+        self.palette
+    }
+
+    /// Whether the display can show `color`
+    pub fn supports(&self, color: &Color) -> bool {
+        self.palette.contains(color)
+    }
+
+    /// Get the color that will be shown when drawing with `color`: the color itself if the
+    /// display supports it, otherwise the nearest color in the palette
+    pub fn map_color(&self, color: &Color) -> Color {
+        if self.supports(color) {
+            *color
+        } else {
+            let (r, g, b) = color.to_rgb();
+            Color::nearest(self.palette, r, g, b)
         }
     }
 
+    /// Draw a shape after positioning it with `transform`
+    pub fn draw_transformed<D: Drawable>(
+        &mut self,
+        drawable: D,
+        transform: &Transform,
+        color: &Color,
+    ) {
+        self.draw(Transformed::new(drawable, *transform), color);
+    }
+
+    /// Draw a shape filled with `style`, e.g. a hatching pattern to stand in for a shade of grey
+    pub fn draw_styled<D: Drawable>(&mut self, drawable: D, style: &FillStyle, color: &Color) {
+        self.draw(Styled::new(drawable, *style), color);
+    }
+
     /// Get the height of the canvas
     pub fn height(&self) -> usize {
         self.height
@@ -145,71 +670,1461 @@ impl Canvas {
 
 
 
+/// Outcome of a call to [`Inky::update`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// The buffer was sent to the display and the panel was refreshed
+    Updated,
+    /// The buffer matched the last one sent, so the refresh was skipped
+    Unchanged,
+}
+
+#[cfg(feature = "hardware")]
+/// Progress of an update started with [`Inky::begin_update`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateState {
+    /// The buffer has been sent and the panel is refreshing
+    Refreshing,
+    /// The refresh has finished, or was skipped because the buffer had not changed
+    Done(UpdateStatus),
+    /// The refresh was abandoned with [`UpdateHandle::cancel`]
+    Cancelled,
+}
+
+#[cfg(feature = "hardware")]
+/// Handle to an in-progress update that can be driven with [`UpdateHandle::poll`] instead of
+/// blocking until the panel finishes refreshing
+pub struct UpdateHandle<'a> {
+    inky: &'a mut Inky,
+    state: UpdateState,
+    buf: Option<Vec<u8>>,
+    started: Instant,
+    // When the conversion began and how long it took
+    begun: Instant,
+    convert: Duration,
+}
+
+#[cfg(feature = "hardware")]
+impl UpdateHandle<'_> {
+    // Time for the controller to raise the busy line after an update is triggered
+    const BUSY_SETTLE: Duration = Duration::from_millis(50);
+
+    /// Check on the refresh, completing it if the display is no longer busy
+    pub fn poll(&mut self) -> Result<UpdateState> {
+        if self.state != UpdateState::Refreshing {
+            return Ok(self.state);
+        }
+
+        let elapsed = self.started.elapsed();
+        if elapsed < Self::BUSY_SETTLE || self.inky.display.is_busy()? {
+            if elapsed > self.inky.display.connection().busy_timeout() {
+                self.state = UpdateState::Cancelled;
+                return Err(self.inky.recover(InkyError::BusyTimeout { elapsed }));
+            }
+            return Ok(self.state);
+        }
+
+        self.inky
+            .display
+            .finish_update()
+            .map_err(|e| self.inky.recover(e))?;
+        self.inky.in_flight = false;
+        info!(elapsed = ?elapsed, "Refreshed display");
+        self.inky.record_stats(self.begun, self.convert, elapsed);
+        self.inky.last_buf = self.buf.take();
+        self.inky.shown_mut().clear_dirty();
+        self.state = UpdateState::Done(UpdateStatus::Updated);
+        Ok(self.state)
+    }
+
+    /// Whether the update has finished or been cancelled
+    pub fn is_done(&self) -> bool {
+        self.state != UpdateState::Refreshing
+    }
+
+    /// Stop tracking the refresh and put the display to sleep. The panel may be left showing a
+    /// partially refreshed image
+    pub fn cancel(mut self) -> Result<()> {
+        if self.state == UpdateState::Refreshing {
+            self.state = UpdateState::Cancelled;
+            self.inky.last_buf = None;
+            self.inky.display.finish_update()?;
+            self.inky.in_flight = false;
+        }
+        Ok(())
+    }
+
+    /// The current state of the update, as of the last call to `poll`
+    pub fn state(&self) -> UpdateState {
+        self.state
+    }
+}
+
+/// Dropping a handle before the refresh is done powers the panel off, as [`Inky`] does
+#[cfg(feature = "hardware")]
+impl Drop for UpdateHandle<'_> {
+    fn drop(&mut self) {
+        if self.state == UpdateState::Refreshing {
+            self.state = UpdateState::Cancelled;
+            self.inky.last_buf = None;
+            self.inky.power_off_if_in_flight();
+        }
+    }
+}
+
+#[cfg(feature = "hardware")]
+/// Where the time went in the last refresh, see [`Inky::last_update_stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UpdateStats {
+    /// Time spent converting the canvas to the display's buffer format
+    pub convert: Duration,
+    /// Time spent clocking commands and data out over SPI
+    pub transfer: Duration,
+    /// Time spent waiting for the display to finish refreshing
+    pub busy_wait: Duration,
+    /// Time from the start of the conversion to the end of the refresh
+    pub total: Duration,
+}
+
+#[cfg(feature = "hardware")]
+/// Limits that protect the panel from being refreshed too hard, see
+/// [`Inky::set_refresh_policy`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefreshPolicy {
+    /// Minimum time between the start of one refresh and the next. Refreshing sooner fails with
+    /// `InkyError::RefreshTooSoon`
+    pub min_interval: Duration,
+    /// Partial refreshes allowed in a row before `update_region` refreshes the whole panel
+    /// instead, to clear the ghosting they leave behind. `None` allows any number
+    pub max_partials: Option<usize>,
+}
+
+#[cfg(feature = "hardware")]
+impl Default for RefreshPolicy {
+    /// A few seconds between refreshes and a full refresh after every five partial ones, which
+    /// suits the controllers of every supported panel
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_secs(5),
+            max_partials: Some(5),
+        }
+    }
+}
+
+#[cfg(feature = "hardware")]
+/// How often [`Inky`] should clean the panel on its own, see [`Inky::set_auto_clean`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoClean {
+    /// Number of refreshes between cleans
+    pub every: usize,
+    /// Number of passes through the palette for each clean
+    pub cycles: usize,
+}
+
+#[cfg(feature = "hardware")]
 pub struct Inky {
     display: Box<dyn InkyDisplay>,
     canvas: Canvas,
+    // Last buffer successfully sent to the display
+    last_buf: Option<Vec<u8>>,
+    auto_clean: Option<AutoClean>,
+    refreshes_since_clean: usize,
+    layers: Option<Layers>,
+    last_stats: Option<UpdateStats>,
+    // The last frame presented with `present`, which updates refresh from once double
+    // buffering is in use
+    front: Option<Canvas>,
+    policy: Option<RefreshPolicy>,
+    // When the last refresh started, and partial refreshes since the last full one
+    last_refresh: Option<Instant>,
+    partials_since_full: usize,
+    refresh_counter: RefreshCounter,
+    // How the canvas is turned onto the panel
+    rotation: Rotation,
+    // Whether the panel may be part way through an update with its high voltage supply on
+    in_flight: bool,
+    // Whether the controller turns frames by 180° itself, so they are converted unrotated
+    controller_rotates: bool,
+    #[cfg(feature = "image")]
+    import_options: ImportOptions,
 }
 
+#[cfg(feature = "hardware")]
 impl Inky {
-    pub fn canvas(&self) -> &Canvas {
-        &self.canvas
+    fn new(display: Box<dyn InkyDisplay>, canvas: Canvas) -> Self {
+        let canvas = canvas.with_palette(display.palette());
+        Self {
+            display,
+            canvas,
+            last_buf: None,
+            auto_clean: None,
+            refreshes_since_clean: 0,
+            layers: None,
+            last_stats: None,
+            front: None,
+            policy: None,
+            last_refresh: None,
+            partials_since_full: 0,
+            refresh_counter: RefreshCounter::in_memory(),
+            rotation: Rotation::None,
+            in_flight: false,
+            controller_rotates: false,
+            #[cfg(feature = "image")]
+            import_options: ImportOptions::default(),
+        }
     }
 
-    pub fn canvas_mut(&mut self) -> &mut Canvas {
-        &mut self.canvas
+    /// Start configuring a display, see [`InkyBuilder`]
+    pub fn builder() -> InkyBuilder {
+        InkyBuilder::default()
     }
 
-    pub fn update(&mut self) -> Result<()> {
-        let buf = self.display.convert(&self.canvas.pixels)?;
-        self.display.update(buf)
+    /// Connect to a display described by `spec` without reading its EEPROM, for clone panels and
+    /// hand-wired setups that have no EEPROM
+    pub fn new_manual(spec: DisplaySpec) -> Result<Self> {
+        Self::try_from(EEPROM::from(spec))
     }
-    
-}
 
-impl TryFrom<EEPROM> for Inky {
-    type Error = Error;
+    /// Connect to a display wired to the SPI device and pins in `config` rather than the
+    /// standard HAT pinout, e.g. when driving several displays from one Pi
+    pub fn with_config(eeprom: EEPROM, config: &ConnectionConfig) -> Result<Self> {
+        let canvas = Canvas::new(eeprom.width() as usize, eeprom.height() as usize);
+        match eeprom.display_variant() {
+            DisplayVariant::E673 => {
+                Ok(Self::new(Box::new(InkyE673::with_config(eeprom, config)?), canvas))
+            },
+            DisplayVariant::What => {
+                Ok(Self::new(Box::new(InkyWhat::with_config(eeprom, config)?), canvas))
+            },
+            variant => Err(InkyError::UnsupportedVariant(variant)),
+        }
+    }
 
-    fn try_from(value: EEPROM) -> Result<Self> {
-        print!("Creating Inky display of type {:?}\n", value.display_variant());
-        print!("Display dimensions: {}x{}\n", value.width(), value.height());
-        let canvas = Canvas::new(value.width() as usize, value.height() as usize);
-        match value.display_variant() {
+    /// Drive the display described by `spec` without any hardware, logging the SPI traffic,
+    /// reset line and delays instead, see [`DryRunConnection`]
+    pub fn dry_run(spec: DisplaySpec) -> Result<Self> {
+        Self::with_connection(Box::new(DryRunConnection::new(EEPROM::from(spec))))
+    }
+
+    /// Drive a display through an already established connection, picking the driver from the
+    /// connection's EEPROM. Passing a mock connection exercises the drivers without hardware
+    pub fn with_connection(connection: Box<dyn Connection>) -> Result<Self> {
+        let eeprom = connection.eeprom();
+        let canvas = Canvas::new(eeprom.width() as usize, eeprom.height() as usize);
+        match eeprom.display_variant() {
             DisplayVariant::E673 => {
-                Ok(Self {display : Box::new(InkyE673::new(value)?), canvas: canvas })
+                Ok(Self::new(Box::new(InkyE673::with_connection(connection)?), canvas))
             },
             DisplayVariant::What => {
-                Ok(Self {display : Box::new(InkyWhat::new(value)?), canvas: canvas })
+                Ok(Self::new(Box::new(InkyWhat::with_connection(connection)?), canvas))
             },
-            _ => bail!("Unsupported display variant"),
+            variant => Err(InkyError::UnsupportedVariant(variant)),
         }
     }
-}
 
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
 
-#[cfg(test)]
-mod tests {
+    pub fn canvas_mut(&mut self) -> &mut Canvas {
+        &mut self.canvas
+    }
 
-    use super::{Inky, Rectangle};
-    use crate::eeprom::EEPROM;
-    use crate::core::colors::Color;
-    use anyhow::Result;
+    /// Turn everything drawn on the canvas clockwise by `rotation` on its way to the panel, for
+    /// a panel mounted on its side or upside down. The canvas takes the size of the panel as it
+    /// is viewed, so it is replaced with a blank one, and any layers are dropped. Controllers
+    /// that can fill their RAM backwards turn by 180° themselves, at no cost to the CPU
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.controller_rotates = match rotation {
+            Rotation::Deg180 => self
+                .display
+                .set_data_entry_mode(DataEntryMode::XDecrementYDecrement)
+                .is_ok(),
+            _ => {
+                // Displays without data entry modes always fill their RAM forwards
+                let _ = self.display.set_data_entry_mode(DataEntryMode::default());
+                false
+            }
+        };
+        let eeprom = self.display.connection().eeprom();
+        let (width, height) = (eeprom.width() as usize, eeprom.height() as usize);
+        let (width, height) = match rotation {
+            Rotation::None | Rotation::Deg180 => (width, height),
+            Rotation::Deg90 | Rotation::Deg270 => (height, width),
+        };
+        self.canvas = Canvas::new(width, height).with_palette(self.display.palette());
+        self.front = None;
+        self.layers = None;
+        // The same buffer lands differently once the controller's data entry mode changes
+        self.last_buf = None;
+        self.rotation = rotation;
+    }
 
-    #[test]
-    fn test_blank() -> Result<()> {
-        let eeprom = EEPROM::try_new().expect("Failed to initialize eeprom");
-        let mut inky = Inky::try_from(eeprom)?;
-        inky.update()?;
-        Ok(())
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
     }
 
-    #[test]
-    fn test_draw_box() -> Result<()> {
-        let eeprom = EEPROM::try_new().expect("Failed to initialize eeprom");
-        let mut inky = Inky::try_from(eeprom)?;
+    /// Width of the display as it is viewed, after any rotation. The same as the canvas width
+    pub fn width(&self) -> usize {
+        self.canvas.width
+    }
 
-        inky.canvas_mut().draw(Rectangle::new((20, 20), (780, 460)), &Color::Black);
+    /// Height of the display as it is viewed, after any rotation
+    pub fn height(&self) -> usize {
+        self.canvas.height
+    }
 
-        inky.update()?;
+    /// Which panel is attached, as read from its EEPROM or given in its spec
+    pub fn variant(&self) -> DisplayVariant {
+        self.display.connection().eeprom().display_variant()
+    }
+
+    /// Colors the panel can show. Other colors are drawn as the nearest of these
+    pub fn supported_colors(&self) -> &'static [Color] {
+        self.display.palette()
+    }
+
+    /// Temperature of the panel in degrees Celsius, measured by the controller at the start of
+    /// the last update and used to pick its waveform. Reading the measurement needs the cdev
+    /// backend with a software chip select, see [`crate::hardware::cdev`]; on other connections
+    /// this is the temperature given to [`Inky::set_ambient_temperature`]. `None` until the first
+    /// update, and when neither is available
+    pub fn panel_temperature(&self) -> Option<f32> {
+        self.display.temperature()
+    }
+
+    /// Temperature in degrees Celsius to adjust the waveform for when the panel can't be measured,
+    /// e.g. from a room sensor. Used from the next update. Fails on displays that don't adjust
+    /// for temperature
+    pub fn set_ambient_temperature(&mut self, celsius: Option<f32>) -> Result<()> {
+        self.display.set_ambient_temperature(celsius)
+    }
+
+    /// Options for [`Inky::import_image`]
+    #[cfg(feature = "image")]
+    pub fn import_options(&self) -> &ImportOptions {
+        &self.import_options
+    }
+
+    #[cfg(feature = "image")]
+    pub fn set_import_options(&mut self, options: ImportOptions) {
+        self.import_options = options;
+    }
+
+    /// Start drawing on separate background, content and overlay layers, which are composited
+    /// into the canvas on each update. The current canvas becomes the background
+    pub fn enable_layers(&mut self, transparent: Color) -> &mut Layers {
+        let mut layers = Layers::new(self.canvas.width, self.canvas.height, transparent);
+        layers.layer_mut(Layer::Background).blit(&self.canvas, (0, 0), None);
+        self.layers.insert(layers)
+    }
+
+    /// Stop compositing layers, leaving the canvas as it was last composited
+    pub fn disable_layers(&mut self) {
+        self.composite();
+        self.layers = None;
+    }
+
+    pub fn layers_mut(&mut self) -> Option<&mut Layers> {
+        self.layers.as_mut()
+    }
+
+    fn composite(&mut self) {
+        if let Some(layers) = self.layers.as_mut() {
+            layers.composite(&mut self.canvas);
+        }
+    }
+
+    /// Clear the canvas to white and refresh the display
+    pub fn clear(&mut self) -> Result<UpdateStatus> {
+        self.canvas.clear();
+        self.refresh_canvas()
+    }
+
+    /// Present the frame drawn on the canvas: it becomes the front buffer and the display is
+    /// refreshed from it. From the first call on, the canvas is a back buffer and the other
+    /// update methods refresh from the front buffer, so a refresh triggered while a frame is
+    /// half drawn shows the last complete frame instead
+    pub fn present(&mut self) -> Result<UpdateStatus> {
+        self.composite();
+        let mut frame = self.canvas.clone();
+        if let Some(front) = self.front.as_ref() {
+            // Keep anything the front buffer still had to send
+            if let Some(dirty) = front.dirty {
+                frame.mark_dirty(dirty);
+            }
+        }
+        self.front = Some(frame);
+        self.canvas.clear_dirty();
+        self.update()
+    }
+
+    /// The last frame passed to the display with [`Inky::present`], or `None` if double
+    /// buffering is not in use
+    pub fn front(&self) -> Option<&Canvas> {
+        self.front.as_ref()
+    }
+
+    /// The canvas updates refresh from: the front buffer when double buffering, or the canvas
+    fn shown(&self) -> &Canvas {
+        self.front.as_ref().unwrap_or(&self.canvas)
+    }
+
+    fn shown_mut(&mut self) -> &mut Canvas {
+        self.front.as_mut().unwrap_or(&mut self.canvas)
+    }
+
+    // Refresh after redrawing the whole canvas, presenting it when double buffering
+    fn refresh_canvas(&mut self) -> Result<UpdateStatus> {
+        if self.front.is_some() {
+            self.present()
+        } else {
+            self.update()
+        }
+    }
+
+    /// Refresh the display with the contents of the canvas, skipping the refresh if the
+    /// converted buffer is identical to the one last sent
+    pub fn update(&mut self) -> Result<UpdateStatus> {
+        self.composite();
+        if self.shown().dirty.is_none() && self.last_buf.is_some() {
+            return Ok(UpdateStatus::Unchanged);
+        }
+        let begun = Instant::now();
+        let buf = self.convert(&self.shown().pixels)?;
+        if self.last_buf.as_ref() == Some(&buf) {
+            self.shown_mut().clear_dirty();
+            return Ok(UpdateStatus::Unchanged);
+        }
+        self.send(buf, begun)
+    }
+
+    /// Refresh the display with the contents of the canvas even if it has not changed
+    pub fn force_update(&mut self) -> Result<UpdateStatus> {
+        self.composite();
+        let begun = Instant::now();
+        let buf = self.convert(&self.shown().pixels)?;
+        self.send(buf, begun)
+    }
+
+    /// Replace the canvas with a test pattern and refresh the display, to check a new panel
+    pub fn show_test_pattern(&mut self, pattern: TestPattern) -> Result<UpdateStatus> {
+        pattern.render(&mut self.canvas);
+        self.refresh_canvas()
+    }
+
+    /// Refresh the display with a frame already packed in the display's buffer format, e.g. one
+    /// pre-rendered on a server, skipping the canvas and conversion entirely. The refresh is
+    /// skipped if the frame is identical to the one last sent. The canvas is left untouched and
+    /// will be redrawn by the next update
+    pub fn update_raw(&mut self, buf: Vec<u8>) -> Result<UpdateStatus> {
+        let expected = self.display.buffer_len();
+        if buf.len() != expected {
+            return Err(InkyError::BufferSize {
+                expected,
+                actual: buf.len(),
+            });
+        }
+        if self.last_buf.as_ref() == Some(&buf) {
+            return Ok(UpdateStatus::Unchanged);
+        }
+        let status = self.send(buf, Instant::now())?;
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        self.shown_mut().mark_dirty(Rect::new(0, 0, width, height));
+        Ok(status)
+    }
+
+    /// Start refreshing the display from the canvas and return immediately. The returned handle
+    /// must be polled until it is done to complete the update
+    pub fn begin_update(&mut self) -> Result<UpdateHandle<'_>> {
+        self.composite();
+        let begun = Instant::now();
+        let buf = self.convert(&self.shown().pixels)?;
+        let convert = begun.elapsed();
+        let state = if self.last_buf.as_ref() == Some(&buf) {
+            self.shown_mut().clear_dirty();
+            UpdateState::Done(UpdateStatus::Unchanged)
+        } else {
+            self.check_policy()?;
+            self.auto_clean_if_due()?;
+            debug!(bytes = buf.len(), "Starting update");
+            self.display.connection_mut().take_timings();
+            self.last_refresh = Some(Instant::now());
+            self.in_flight = true;
+            self.display
+                .start_update(buf.clone())
+                .map_err(|e| self.recover(e))?;
+            self.count_refresh();
+            self.refreshes_since_clean += 1;
+            self.partials_since_full = 0;
+            UpdateState::Refreshing
+        };
+        Ok(UpdateHandle {
+            inky: self,
+            state,
+            buf: Some(buf),
+            started: Instant::now(),
+            begun,
+            convert,
+        })
+    }
+
+    /// Refresh only `region` of the display using the controller's partial update waveform.
+    /// This is much faster than a full refresh but leaves more ghosting behind
+    pub fn update_region(&mut self, region: Rect) -> Result<UpdateStatus> {
+        self.composite();
+        let size = (self.canvas.width, self.canvas.height);
+        let bounds = Rect::new(0, 0, size.0, size.1);
+        let region = region
+            .intersection(&bounds)
+            .ok_or_else(|| {
+                InkyError::InvalidArgument("Region does not overlap the display".into())
+            })?;
+        let region = self.software_rotation().turn_rect(region, size);
+        if let Some(max) = self.policy.and_then(|policy| policy.max_partials) {
+            if self.partials_since_full >= max {
+                debug!(partials = self.partials_since_full, "Refreshing the whole panel");
+                return self.force_update();
+            }
+        }
+        self.check_policy()?;
+        let _span = info_span!("update_region", ?region).entered();
+        let begun = Instant::now();
+        let buf = self.convert(&self.shown().pixels)?;
+        let convert = begun.elapsed();
+        self.display.connection_mut().take_timings();
+        self.last_refresh = Some(Instant::now());
+        self.in_flight = true;
+        self.display
+            .update_region(buf, region)
+            .map_err(|e| self.recover(e))?;
+        self.in_flight = false;
+        self.count_refresh();
+        info!(elapsed = ?begun.elapsed(), "Refreshed region");
+        self.record_stats(begun, convert, Duration::ZERO);
+        self.refreshes_since_clean += 1;
+        self.partials_since_full += 1;
+        // Only part of the panel was redrawn, so the last full buffer no longer describes it
+        self.last_buf = None;
+        self.shown_mut().clear_dirty();
+        Ok(UpdateStatus::Updated)
+    }
+
+    /// Refresh whatever has been drawn since the last update, using a partial refresh of the
+    /// dirty region when the display supports it and a full refresh otherwise
+    pub fn update_dirty(&mut self) -> Result<UpdateStatus> {
+        self.composite();
+        match self.shown().dirty_region() {
+            None => Ok(UpdateStatus::Unchanged),
+            Some(region) if self.display.supports_partial() => self.update_region(region),
+            Some(_) => self.update(),
+        }
+    }
+
+    /// Put the display controller into deep sleep to save power. The panel keeps showing its
+    /// current image, and the next update wakes the controller again
+    pub fn sleep(&mut self) -> Result<()> {
+        self.display.sleep()
+    }
+
+    /// Wake the display controller from deep sleep without redrawing the panel
+    pub fn wake(&mut self) -> Result<()> {
+        self.display.wake()
+    }
+
+    /// Select the waveform used for full refreshes, e.g. the red or yellow tables or a custom
+    /// measured waveform. The red and yellow tables add that ink to the palette of a panel that
+    /// has it, and the canvas takes the new palette as with [`Inky::set_pixel_mode`]. Fails on
+    /// displays that do not use lookup tables
+    pub fn set_lut(&mut self, lut: LutKind) -> Result<()> {
+        self.display.set_lut(lut)?;
+        self.take_palette();
+        self.last_buf = None;
+        Ok(())
+    }
+
+    /// Set the color of the border around the active area of the panel, shown from the next
+    /// update. Fails on displays without a border setting
+    pub fn set_border(&mut self, color: &Color) -> Result<()> {
+        self.display.set_border(color)?;
+        self.last_buf = None;
+        Ok(())
+    }
+
+    /// Choose between full refreshes and faster refreshes that leave more ghosting, used from
+    /// the next update
+    pub fn set_refresh_mode(&mut self, mode: RefreshMode) -> Result<()> {
+        self.display.set_refresh_mode(mode)?;
+        self.last_buf = None;
+        Ok(())
+    }
+
+    /// Switch between the panel's inks and four level greyscale. The canvas takes the new
+    /// palette, with what was drawn mapped to its nearest colors, and the next update is a full
+    /// refresh
+    pub fn set_pixel_mode(&mut self, mode: PixelMode) -> Result<()> {
+        self.display.set_pixel_mode(mode)?;
+        self.take_palette();
+        self.last_buf = None;
+        Ok(())
+    }
+
+    // Map what was drawn to the nearest colors of the display's palette after a setting changed it
+    fn take_palette(&mut self) {
+        let palette = self.display.palette();
+        self.canvas.remap(palette);
+        if let Some(front) = self.front.as_mut() {
+            front.remap(palette);
+        }
+    }
+
+    /// Set how long to wait for the display to finish an operation before failing with
+    /// `InkyError::BusyTimeout`. Defaults to [`crate::hardware::display::InkyConnection::DEFAULT_BUSY_TIMEOUT`]
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.display.connection_mut().set_busy_timeout(timeout);
+    }
+
+    /// Reduce ghosting by flashing the whole panel through every color in its palette `cycles`
+    /// times, then leaving it filled with `color`. The canvas is untouched and will be redrawn by
+    /// the next update
+    pub fn clean(&mut self, cycles: usize, color: &Color) -> Result<()> {
+        let _span = info_span!("clean", cycles).entered();
+        let palette = self.canvas.palette();
+        for _ in 0..cycles {
+            for ink in palette {
+                self.fill_panel(ink)?;
+            }
+        }
+        let buf = self.fill_panel(&self.canvas.map_color(color))?;
+        self.last_buf = Some(buf);
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        self.shown_mut().mark_dirty(Rect::new(0, 0, width, height));
+        self.refreshes_since_clean = 0;
+        self.partials_since_full = 0;
+        self.last_refresh = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Clean the panel automatically before a full refresh once `every` refreshes have happened
+    /// since the last clean, or never if `None`
+    pub fn set_auto_clean(&mut self, auto_clean: Option<AutoClean>) {
+        self.auto_clean = auto_clean;
+    }
+
+    /// Limit how often the panel is refreshed, or lift the limits with `None`. There is no policy
+    /// unless one is set
+    pub fn set_refresh_policy(&mut self, policy: Option<RefreshPolicy>) {
+        self.policy = policy;
+    }
+
+    pub fn refresh_policy(&self) -> Option<RefreshPolicy> {
+        self.policy
+    }
+
+    /// Run `f` with the refresh policy lifted, for the odd refresh that has to happen regardless
+    pub fn without_policy<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let policy = self.policy.take();
+        let result = f(self);
+        self.policy = policy;
+        result
+    }
+
+    /// Number of times the panel has been refreshed, including cleaning passes. This counts
+    /// from zero for each `Inky` unless a persistent counter is set with
+    /// [`Inky::set_refresh_counter`]
+    pub fn refresh_count(&self) -> u64 {
+        self.refresh_counter.count()
+    }
+
+    /// Keep the refresh count in `counter`, e.g. [`RefreshCounter::open`] on a state file to
+    /// track the lifetime wear of the panel across restarts
+    pub fn set_refresh_counter(&mut self, counter: RefreshCounter) {
+        self.refresh_counter = counter;
+    }
+
+    // A failure to save the count shouldn't fail the refresh that has already happened
+    fn count_refresh(&mut self) {
+        if let Err(e) = self.refresh_counter.increment() {
+            warn!("Failed to save the refresh count: {}", e);
+        }
+    }
+
+    fn check_policy(&self) -> Result<()> {
+        if let (Some(policy), Some(last)) = (self.policy, self.last_refresh) {
+            let elapsed = last.elapsed();
+            if elapsed < policy.min_interval {
+                return Err(InkyError::RefreshTooSoon {
+                    retry_after: policy.min_interval - elapsed,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn auto_clean_if_due(&mut self) -> Result<()> {
+        match self.auto_clean {
+            Some(auto) if self.refreshes_since_clean >= auto.every => {
+                self.clean(auto.cycles, &Color::White)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Fill the panel with a single color, returning the buffer that was sent
+    fn fill_panel(&mut self, color: &Color) -> Result<Vec<u8>> {
+        let pixels = vec![vec![*color; self.canvas.width]; self.canvas.height];
+        let buf = self.convert(&pixels)?;
+        self.in_flight = true;
+        self.display
+            .update(buf.clone())
+            .map_err(|e| self.recover(e))?;
+        self.in_flight = false;
+        self.count_refresh();
+        Ok(buf)
+    }
+
+    /// Timings of the last refresh of the panel, to tune the SPI speed and LUTs or notice a
+    /// panel that has started refreshing abnormally slowly. `None` until the first refresh
+    pub fn last_update_stats(&self) -> Option<UpdateStats> {
+        self.last_stats
+    }
+
+    // `polled` is time spent polling for the end of a refresh started with `begin_update`,
+    // which the connection doesn't see as waiting on the busy line
+    fn record_stats(&mut self, begun: Instant, convert: Duration, polled: Duration) {
+        let timings = self.display.connection_mut().take_timings();
+        let stats = UpdateStats {
+            convert,
+            transfer: timings.transfer,
+            busy_wait: timings.busy_wait + polled,
+            total: begun.elapsed(),
+        };
+        debug!(?stats, "Update timings");
+        self.last_stats = Some(stats);
+    }
+
+    fn power_off_if_in_flight(&mut self) {
+        if !self.in_flight {
+            return;
+        }
+        warn!("Powering off the display part way through an update");
+        match self.display.power_off() {
+            Ok(()) => self.in_flight = false,
+            Err(e) => warn!("Failed to power off the display: {}", e),
+        }
+    }
+
+    // Reset the controller and switch off its high voltage supply after a transfer failed part
+    // way through a sequence, passing the error on
+    fn recover(&mut self, error: InkyError) -> InkyError {
+        if error.is_transfer_error() {
+            warn!("Resetting the display after a failed update: {}", error);
+            match self.display.reset().and_then(|()| self.display.power_off()) {
+                Ok(()) => self.in_flight = false,
+                Err(e) => warn!("Failed to recover the display: {}", e),
+            }
+            // The panel may show anything now
+            self.last_buf = None;
+        } else {
+            self.in_flight = false;
+        }
+        error
+    }
+
+    // The rotation left to do on the CPU, after any the controller does
+    fn software_rotation(&self) -> Rotation {
+        if self.controller_rotates {
+            Rotation::None
+        } else {
+            self.rotation
+        }
+    }
+
+    fn convert(&self, pixels: &[Vec<Color>]) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let buf = match self.software_rotation() {
+            Rotation::None => self.display.convert(pixels)?,
+            rotation => self.display.convert(&rotate(pixels, rotation))?,
+        };
+        debug!(bytes = buf.len(), elapsed = ?start.elapsed(), "Converted canvas");
+        Ok(buf)
+    }
+
+    fn send(&mut self, buf: Vec<u8>, begun: Instant) -> Result<UpdateStatus> {
+        let _span = info_span!("update", bytes = buf.len()).entered();
+        let convert = begun.elapsed();
+        self.check_policy()?;
+        self.auto_clean_if_due()?;
+        let start = Instant::now();
+        self.display.connection_mut().take_timings();
+        self.last_refresh = Some(start);
+        self.in_flight = true;
+        self.display
+            .update(buf.clone())
+            .map_err(|e| self.recover(e))?;
+        self.in_flight = false;
+        self.count_refresh();
+        info!(elapsed = ?start.elapsed(), "Refreshed display");
+        // Leave any automatic clean out of the total
+        self.record_stats(start - convert, convert, Duration::ZERO);
+        self.refreshes_since_clean += 1;
+        self.partials_since_full = 0;
+        self.last_buf = Some(buf);
+        self.shown_mut().clear_dirty();
+        Ok(UpdateStatus::Updated)
+    }
+}
+
+// Turn rows of pixels clockwise by `rotation`
+#[cfg(feature = "hardware")]
+fn rotate(pixels: &[Vec<Color>], rotation: Rotation) -> Vec<Vec<Color>> {
+    let height = pixels.len();
+    let width = pixels.first().map_or(0, Vec::len);
+    let (turned_width, turned_height) = match rotation {
+        Rotation::None | Rotation::Deg180 => (width, height),
+        Rotation::Deg90 | Rotation::Deg270 => (height, width),
+    };
+    let mut turned = vec![vec![Color::White; turned_width]; turned_height];
+    for (y, row) in pixels.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            let (x, y) = rotation.turn((x, y), (width, height));
+            turned[y][x] = *color;
+        }
+    }
+    turned
+}
+
+/// Connects to a display with its EEPROM read automatically, or from a [`DisplaySpec`] for
+/// panels without one, and sets it up in one go:
+///
+/// ```no_run
+/// # use inky::{core::{colors::Color, transform::Rotation}, inky::Inky};
+/// let inky = Inky::builder()
+///     .rotation(Rotation::Deg90)
+///     .border(Color::Black)
+///     .spi_speed(1_000_000)
+///     .build()?;
+/// # Ok::<(), inky::error::InkyError>(())
+/// ```
+#[cfg(feature = "hardware")]
+#[derive(Clone, Debug, Default)]
+pub struct InkyBuilder {
+    spec: Option<DisplaySpec>,
+    connection: Option<ConnectionConfig>,
+    spi_speed: Option<u32>,
+    rotation: Rotation,
+    border: Option<Color>,
+    ambient_temperature: Option<f32>,
+    #[cfg(feature = "image")]
+    import_options: Option<ImportOptions>,
+    #[cfg(feature = "image")]
+    saturation: Option<f32>,
+}
+
+#[cfg(feature = "hardware")]
+impl InkyBuilder {
+    /// Describe the display instead of reading its EEPROM
+    pub fn spec(&mut self, spec: DisplaySpec) -> &mut Self {
+        self.spec = Some(spec);
+        self
+    }
+
+    /// Drive the display through the SPI device and pins in `config` rather than the standard
+    /// HAT pinout
+    pub fn connection(&mut self, config: ConnectionConfig) -> &mut Self {
+        self.connection = Some(config);
+        self
+    }
+
+    /// SPI clock in Hz, overriding the connection's
+    pub fn spi_speed(&mut self, hz: u32) -> &mut Self {
+        self.spi_speed = Some(hz);
+        self
+    }
+
+    /// See [`Inky::set_rotation`]
+    pub fn rotation(&mut self, rotation: Rotation) -> &mut Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// See [`Inky::set_border`]
+    pub fn border(&mut self, color: Color) -> &mut Self {
+        self.border = Some(color);
+        self
+    }
+
+    /// See [`Inky::set_ambient_temperature`]
+    pub fn ambient_temperature(&mut self, celsius: f32) -> &mut Self {
+        self.ambient_temperature = Some(celsius);
+        self
+    }
+
+    /// Options for [`Inky::import_image`]
+    #[cfg(feature = "image")]
+    pub fn import_options(&mut self, options: ImportOptions) -> &mut Self {
+        self.import_options = Some(options);
+        self
+    }
+
+    /// Saturation for [`Inky::import_image`], overriding the import options'
+    #[cfg(feature = "image")]
+    pub fn saturation(&mut self, saturation: f32) -> &mut Self {
+        self.saturation = Some(saturation);
+        self
+    }
+
+    /// Read the EEPROM unless a spec was given, and connect to the display
+    pub fn build(&self) -> Result<Inky> {
+        let eeprom = match &self.spec {
+            Some(spec) => EEPROM::from(spec.clone()),
+            None => EEPROM::try_new()?,
+        };
+        let mut connection = self.connection.clone().unwrap_or_default();
+        if let Some(speed) = self.spi_speed {
+            connection.spi_speed = Some(speed);
+        }
+        let mut inky = Inky::with_config(eeprom, &connection)?;
+        self.configure(&mut inky)?;
+        Ok(inky)
+    }
+
+    // Apply the settings that don't concern the connection
+    fn configure(&self, inky: &mut Inky) -> Result<()> {
+        inky.set_rotation(self.rotation);
+        if let Some(color) = &self.border {
+            inky.set_border(color)?;
+        }
+        if let Some(celsius) = self.ambient_temperature {
+            inky.set_ambient_temperature(Some(celsius))?;
+        }
+        #[cfg(feature = "image")]
+        {
+            let mut options = self.import_options.clone().unwrap_or_default();
+            if let Some(saturation) = self.saturation {
+                options.saturation = saturation;
+            }
+            inky.set_import_options(options);
+        }
+        Ok(())
+    }
+}
+
+/// Powers the panel off if it is dropped part way through an update, e.g. while unwinding from a
+/// panic or after a signal handler has ended the program, so the panel's high voltage booster
+/// isn't left on
+#[cfg(feature = "hardware")]
+impl Drop for Inky {
+    fn drop(&mut self) {
+        self.power_off_if_in_flight();
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl TryFrom<EEPROM> for Inky {
+    type Error = InkyError;
+
+    fn try_from(value: EEPROM) -> Result<Self> {
+        info!(eeprom = %value, "Creating Inky display");
+        Self::with_config(value, &ConnectionConfig::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Canvas, Coordinates, Drawable, Line, Rectangle, RoundedRectangle};
+    use crate::core::geometry::Rect;
+    use crate::core::colors::Color;
+
+    #[test]
+    fn test_thick_line() {
+        let line = Line::new((5, 5), (9, 5)).with_width(3);
+        let mut coordinates: Vec<_> = line.coordinates().collect();
+        coordinates.sort_unstable();
+        coordinates.dedup();
+        for x in 5..=9 {
+            for y in 4..=6 {
+                assert!(coordinates.contains(&(x, y)));
+            }
+        }
+        assert!(!coordinates.contains(&(5, 7)));
+        // Backwards lines cover the same pixels
+        let mut reversed: Vec<_> = Line::new((9, 5), (5, 5)).with_width(3).coordinates().collect();
+        reversed.sort_unstable();
+        reversed.dedup();
+        assert_eq!(reversed, coordinates);
+        // Pixels past the top left edge are dropped rather than wrapping
+        let clipped = Line::new((0, 0), (3, 0)).with_width(4);
+        assert!(clipped.coordinates().all(|(x, y)| x < 5 && y < 3));
+    }
+
+    #[test]
+    fn test_outline_rectangle() {
+        let coordinates: Vec<_> = Rectangle::outline((0, 0), (4, 4), 1).coordinates().collect();
+        assert_eq!(coordinates.len(), 16);
+        assert!(!coordinates.contains(&(2, 2)));
+        assert_eq!(Rectangle::outline((0, 0), (4, 4), 2).coordinates().count(), 24);
+    }
+
+    #[test]
+    fn test_rounded_rectangle() {
+        let coordinates: Vec<_> = RoundedRectangle::new((0, 0), (9, 5), 2).coordinates().collect();
+        assert!(!coordinates.contains(&(0, 0)));
+        assert!(!coordinates.contains(&(9, 5)));
+        assert!(coordinates.contains(&(0, 2)));
+        assert!(coordinates.contains(&(2, 0)));
+        assert!(coordinates.contains(&(5, 3)));
+        // A zero radius is an ordinary rectangle
+        assert_eq!(RoundedRectangle::new((0, 0), (9, 5), 0).coordinates().count(), 60);
+
+        let outline = RoundedRectangle::outline((0, 0), (9, 5), 2, 1);
+        let outline: Vec<_> = outline.coordinates().collect();
+        assert!(outline.contains(&(0, 2)));
+        assert!(outline.contains(&(1, 1)));
+        assert!(!outline.contains(&(5, 3)));
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.draw(Rectangle::outline((2, 2), (7, 7), 1), &Color::Black);
+        canvas.clear_dirty();
+        canvas.flood_fill(4, 5, &Color::Red);
+        assert_eq!(canvas.get_pixel(3, 3), Color::Red);
+        assert_eq!(canvas.get_pixel(6, 6), Color::Red);
+        assert_eq!(canvas.get_pixel(2, 2), Color::Black);
+        assert_eq!(canvas.get_pixel(0, 0), Color::White);
+        assert_eq!(canvas.get_pixel(8, 8), Color::White);
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(3, 3, 4, 4)));
+
+        // Filling the outside wraps around the outline
+        canvas.flood_fill(0, 0, &Color::Yellow);
+        assert_eq!(canvas.get_pixel(9, 9), Color::Yellow);
+        assert_eq!(canvas.get_pixel(5, 0), Color::Yellow);
+        assert_eq!(canvas.get_pixel(4, 4), Color::Red);
+
+        // Out of bounds is ignored
+        canvas.flood_fill(10, 0, &Color::Black);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut canvas = Canvas::new(3, 2).with_palette(&[Color::Black, Color::White]);
+        canvas.fill(&Color::Black);
+        assert_eq!(canvas.get_pixel(1, 2), Color::Black);
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(0, 0, 3, 2)));
+        canvas.fill(&Color::Yellow);
+        assert_eq!(canvas.get_pixel(0, 0), Color::White);
+        canvas.fill(&Color::Black);
+        canvas.clear();
+        assert_eq!(canvas.get_pixel(1, 1), Color::White);
+    }
+
+    #[test]
+    fn test_invert_region() {
+        let mut canvas = Canvas::new(4, 2);
+        canvas.draw(Rectangle::new((0, 0), (0, 1)), &Color::Black);
+        canvas.draw(Rectangle::new((1, 0), (1, 1)), &Color::Yellow);
+        canvas.invert_region(Rect::new(0, 0, 10, 1), false);
+        assert_eq!(canvas.get_pixel(0, 0), Color::White);
+        assert_eq!(canvas.get_pixel(0, 1), Color::Yellow);
+        assert_eq!(canvas.get_pixel(0, 2), Color::Black);
+        assert_eq!(canvas.get_pixel(1, 0), Color::Black);
+
+        canvas.invert_region(Rect::new(0, 0, 4, 2), true);
+        assert_eq!(canvas.get_pixel(0, 1), Color::Blue);
+        assert_eq!(canvas.get_pixel(1, 1), Color::Blue);
+        assert_eq!(canvas.get_pixel(0, 0), Color::Black);
+    }
+
+    #[test]
+    fn test_map_pixels() {
+        let mut canvas = Canvas::new(4, 4).with_palette(&[Color::Black, Color::White]);
+        canvas.map_pixels(|x, y, color| if x == y { Color::Black } else { color });
+        assert_eq!(canvas.get_pixel(2, 2), Color::Black);
+        assert_eq!(canvas.get_pixel(2, 1), Color::White);
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(0, 0, 4, 4)));
+
+        canvas.clear_dirty();
+        canvas.map_region(Rect::new(2, 0, 2, 4), |_, _, _| Color::Yellow);
+        // Colors are mapped to the palette and only changed pixels are dirty
+        assert_eq!(canvas.get_pixel(3, 3), Color::White);
+        assert_eq!(canvas.get_pixel(0, 2), Color::White);
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(2, 2, 2, 2)));
+    }
+
+    #[test]
+    fn test_pixel_access() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.draw(Rectangle::new((2, 0), (2, 0)), &Color::Red);
+        assert_eq!(canvas.get(2, 0), Some(Color::Red));
+        assert_eq!(canvas.get(0, 1), Some(Color::White));
+        assert_eq!(canvas.get(3, 0), None);
+        assert_eq!(canvas.get(0, 2), None);
+
+        let pixels: Vec<_> = canvas.pixels().collect();
+        assert_eq!(pixels.len(), 6);
+        assert_eq!(pixels[1], (1, 0, Color::White));
+        assert_eq!(pixels[2], (2, 0, Color::Red));
+        assert_eq!(pixels[3], (0, 1, Color::White));
+    }
+
+    #[test]
+    fn test_blit() {
+        let mut sprite = Canvas::new(3, 2);
+        sprite.draw(Rectangle::new((0, 0), (2, 0)), &Color::Red);
+        let mut canvas = Canvas::new(4, 4);
+        canvas.blit(&sprite, (2, 1), Some(Color::White));
+        assert_eq!(canvas.get_pixel(1, 2), Color::Red);
+        assert_eq!(canvas.get_pixel(1, 3), Color::Red);
+        assert_eq!(canvas.get_pixel(1, 1), Color::White);
+        // The transparent row leaves the canvas untouched, so only the opaque row is dirty
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(2, 1, 2, 1)));
+
+        canvas.draw(Rectangle::new((0, 2), (3, 2)), &Color::Black);
+        canvas.blit(&sprite, (2, 1), None);
+        assert_eq!(canvas.get_pixel(2, 2), Color::White);
+    }
+
+    #[test]
+    fn test_palette_mapping() {
+        let mut canvas = Canvas::new(4, 1).with_palette(&[Color::Black, Color::White]);
+        assert!(canvas.supports(&Color::Black));
+        assert!(!canvas.supports(&Color::Yellow));
+        canvas.draw(Rectangle::new((0, 0), (0, 0)), &Color::Yellow);
+        canvas.draw(Rectangle::new((1, 0), (1, 0)), &Color::Blue);
+        assert_eq!(canvas.get_pixel(0, 0), Color::White);
+        assert_eq!(canvas.get_pixel(0, 1), Color::Black);
+    }
+
+    struct OffCanvas;
+
+    impl Drawable for OffCanvas {
+        fn coordinates(&self) -> Coordinates<'_> {
+            panic!("Shapes outside of the canvas should not be rasterised")
+        }
+
+        fn bounds(&self) -> Rect {
+            Rect::new(200, 0, 10, 10)
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        assert_eq!(Line::new((2, 3), (-4, 8)).bounds(), Rect::new(0, 3, 3, 6));
+        assert_eq!(Line::new((5, 5), (9, 5)).with_width(3).bounds(), Rect::new(4, 4, 7, 3));
+        assert_eq!(Rectangle::new((3, 3), (1, 1)).bounds(), Rect::default());
+        assert_eq!(RoundedRectangle::new((1, 2), (5, 6), 2).bounds(), Rect::new(1, 2, 5, 5));
+
+        let mut canvas = Canvas::new(100, 50);
+        canvas.draw(OffCanvas, &Color::Black);
+        assert_eq!(canvas.dirty_region(), None);
+    }
+
+    #[test]
+    fn test_dirty_region() {
+        let mut canvas = Canvas::new(100, 50);
+        assert_eq!(canvas.dirty_region(), None);
+
+        canvas.draw(Line::new((10, 5), (20, 5)), &Color::Black);
+        canvas.draw(Rectangle::new((30, 10), (40, 12)), &Color::Black);
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(10, 5, 31, 8)));
+
+        canvas.clear_dirty();
+        assert_eq!(canvas.dirty_region(), None);
+    }
+}
+
+#[cfg(all(test, feature = "hardware"))]
+mod display_tests {
+    use super::{Canvas, Inky, Rect, Rectangle, RefreshPolicy, UpdateState, UpdateStatus};
+    use crate::core::{colors::Color, transform::Rotation};
+    use crate::eeprom::{ColorMode, DisplaySpec, DisplayVariant, EEPROM};
+    use crate::error::InkyError;
+    use crate::hardware::{
+        display::RefreshMode, mock::MockConnection, transcript::RecordingConnection,
+    };
+    use crate::lut::LutKind;
+    use crate::testutil::{mock_eeprom, mock_inky};
+    use rppal::gpio::Level;
+    use std::time::Duration;
+    use anyhow::Result;
+
+    #[test]
+    fn test_blank() -> Result<()> {
+        let eeprom = EEPROM::try_new().expect("Failed to initialize eeprom");
+        let mut inky = Inky::try_from(eeprom)?;
+        inky.update()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_draw_box() -> Result<()> {
+        let eeprom = EEPROM::try_new().expect("Failed to initialize eeprom");
+        let mut inky = Inky::try_from(eeprom)?;
+
+        inky.canvas_mut().draw(Rectangle::new((20, 20), (780, 460)), &Color::Black);
+
+        inky.update()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_unchanged() -> Result<()> {
+        let connection = RecordingConnection::new(MockConnection::new(mock_eeprom()));
+        let recorder = connection.recorder();
+        let sent = || recorder.lock().unwrap().entries.len();
+        let mut inky = Inky::with_connection(Box::new(connection))?;
+
+        assert_eq!(inky.update()?, UpdateStatus::Updated);
+        let after_update = sent();
+        assert_eq!(inky.update()?, UpdateStatus::Unchanged);
+        assert_eq!(sent(), after_update);
+        assert_eq!(inky.force_update()?, UpdateStatus::Updated);
+        assert!(sent() > after_update);
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_redraw() -> Result<()> {
+        let eeprom = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Red,
+        });
+        let connection = RecordingConnection::new(MockConnection::new(eeprom));
+        let recorder = connection.recorder();
+        let sent = || recorder.lock().unwrap().entries.len();
+        let mut inky = Inky::with_connection(Box::new(connection))?;
+        inky.update()?;
+
+        // Each setting is only sent with a frame, so the unchanged canvas is sent again
+        inky.set_border(&Color::Red)?;
+        let before = sent();
+        assert_eq!(inky.update()?, UpdateStatus::Updated);
+        assert!(sent() > before);
+        inky.set_refresh_mode(RefreshMode::Fast)?;
+        assert_eq!(inky.update()?, UpdateStatus::Updated);
+
+        // The red waveform brings red into the palette
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (0, 0)), &Color::Red);
+        assert_ne!(inky.canvas().get(0, 0), Some(Color::Red));
+        inky.set_lut(LutKind::Red)?;
+        assert_eq!(inky.update()?, UpdateStatus::Updated);
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (0, 0)), &Color::Red);
+        assert_eq!(inky.canvas().get(0, 0), Some(Color::Red));
+        Ok(())
+    }
+
+    #[test]
+    fn test_begin_update() -> Result<()> {
+        // The E673's busy line is active low, so the mock's low line keeps it refreshing
+        let mock = MockConnection::new(mock_eeprom());
+        let busy = mock.busy_line();
+        let connection = RecordingConnection::new(mock);
+        let recorder = connection.recorder();
+        let power_offs = || {
+            recorder
+                .lock()
+                .unwrap()
+                .packets()
+                .filter(|packet| packet.command == 0x02)
+                .count()
+        };
+        let mut inky = Inky::with_connection(Box::new(connection))?;
+
+        inky.canvas_mut().draw(Rectangle::new((20, 20), (100, 100)), &Color::Black);
+        let mut handle = inky.begin_update()?;
+        for _ in 0..3 {
+            std::thread::sleep(Duration::from_millis(30));
+            assert_eq!(handle.poll()?, UpdateState::Refreshing);
+        }
+        assert_eq!(power_offs(), 0);
+
+        *busy.lock().unwrap() = Level::High;
+        assert_eq!(handle.poll()?, UpdateState::Done(UpdateStatus::Updated));
+        assert!(handle.is_done());
+        assert_eq!(power_offs(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_stats() -> Result<()> {
+        let mut inky = mock_inky();
+        assert_eq!(inky.last_update_stats(), None);
+
+        inky.update()?;
+        let stats = inky.last_update_stats().unwrap();
+        assert!(stats.total >= stats.convert + stats.transfer + stats.busy_wait);
+
+        // Skipped refreshes keep the stats of the last real one
+        assert_eq!(inky.update()?, UpdateStatus::Unchanged);
+        assert_eq!(inky.last_update_stats(), Some(stats));
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_raw() -> Result<()> {
+        let mut inky = mock_inky();
+        assert!(inky.update_raw(vec![0x11; 100]).is_err());
+
+        let frame = vec![0x00; 800 * 480 / 2];
+        assert_eq!(inky.update_raw(frame.clone())?, UpdateStatus::Updated);
+        assert_eq!(inky.update_raw(frame)?, UpdateStatus::Unchanged);
+        // The canvas no longer matches the panel, so it is redrawn
+        assert_eq!(inky.update()?, UpdateStatus::Updated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_present() -> Result<()> {
+        let mut inky = mock_inky();
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (9, 9)), &Color::Black);
+        assert_eq!(inky.present()?, UpdateStatus::Updated);
+        assert_eq!(inky.front().unwrap().get(5, 5), Some(Color::Black));
+
+        // A half drawn frame on the back buffer is not shown until it is presented
+        inky.canvas_mut().draw(Rectangle::new((20, 20), (29, 29)), &Color::Red);
+        assert_eq!(inky.update()?, UpdateStatus::Unchanged);
+        assert_eq!(inky.front().unwrap().get(25, 25), Some(Color::White));
+        assert_eq!(inky.present()?, UpdateStatus::Updated);
+        assert_eq!(inky.front().unwrap().get(25, 25), Some(Color::Red));
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_policy() -> Result<()> {
+        let mut inky = mock_inky();
+        inky.set_refresh_policy(Some(RefreshPolicy {
+            min_interval: Duration::from_secs(3600),
+            max_partials: None,
+        }));
+        inky.update()?;
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (9, 9)), &Color::Black);
+        assert!(matches!(inky.update(), Err(InkyError::RefreshTooSoon { .. })));
+        assert_eq!(inky.without_policy(Inky::update)?, UpdateStatus::Updated);
+        assert!(inky.refresh_policy().is_some());
+
+        // Partial refreshes give way to a full one once the limit is reached
+        let eeprom = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Black,
+        });
+        let mut inky = Inky::with_connection(Box::new(MockConnection::new(eeprom)))?;
+        inky.set_refresh_policy(Some(RefreshPolicy {
+            min_interval: Duration::ZERO,
+            max_partials: Some(1),
+        }));
+        let region = Rect::new(0, 0, 8, 8);
+        inky.update_region(region)?;
+        assert_eq!(inky.partials_since_full, 1);
+        inky.update_region(region)?;
+        assert_eq!(inky.partials_since_full, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_count() -> Result<()> {
+        let mut inky = mock_inky();
+        inky.update()?;
+        inky.update()?;
+        inky.clean(1, &Color::White)?;
+        // One update, six palette colors and the final fill
+        assert_eq!(inky.refresh_count(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation() -> Result<()> {
+        let mut inky = mock_inky();
+        Inky::builder().rotation(Rotation::Deg90).configure(&mut inky)?;
+        assert_eq!((inky.width(), inky.height()), (480, 800));
+        assert!(matches!(inky.variant(), DisplayVariant::E673));
+        assert_eq!(inky.supported_colors(), ColorMode::Spectra6.palette());
+
+        // The top left of the portrait canvas is the top right of the panel
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (3, 1)), &Color::Red);
+        let mut panel = Canvas::new(800, 480);
+        panel.draw(Rectangle::new((798, 0), (799, 3)), &Color::Red);
+        let unrotated = mock_inky();
+        assert_eq!(
+            inky.convert(&inky.canvas().pixels)?,
+            unrotated.convert(&panel.pixels)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_panel_temperature() -> Result<()> {
+        let eeprom = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Black,
+        });
+        // The mock can't read, like the rppal connection and cdev with a hardware chip select
+        let mut inky = Inky::with_connection(Box::new(MockConnection::new(eeprom)))?;
+        inky.update()?;
+        assert_eq!(inky.panel_temperature(), None);
+
+        inky.set_ambient_temperature(Some(8.0))?;
+        inky.force_update()?;
+        assert_eq!(inky.panel_temperature(), Some(8.0));
+        assert!(mock_inky().set_ambient_temperature(Some(8.0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_rotation() -> Result<()> {
+        let eeprom = EEPROM::from(DisplaySpec {
+            width: 400,
+            height: 300,
+            variant: DisplayVariant::What,
+            color: ColorMode::Black,
+        });
+        let mut inky = Inky::with_connection(Box::new(MockConnection::new(eeprom)))?;
+        inky.set_rotation(Rotation::Deg180);
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (0, 0)), &Color::Black);
+        // The frame is sent as drawn and the controller turns it, so the pixel stays at the
+        // start of the buffer instead of moving to its end
+        let buf = inky.convert(&inky.canvas().pixels)?;
+        assert_eq!(buf[0], 0x7f);
+        assert_eq!(buf[buf.len() - 1], 0xff);
+
+        inky.set_rotation(Rotation::None);
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (0, 0)), &Color::Black);
+        assert_eq!(inky.convert(&inky.canvas().pixels)?[0], 0xfe);
+        Ok(())
+    }
+
+    #[test]
+    fn test_power_off_on_drop() -> Result<()> {
+        let connection = RecordingConnection::new(MockConnection::new(mock_eeprom()));
+        let recorder = connection.recorder();
+        let power_offs = || {
+            recorder
+                .lock()
+                .unwrap()
+                .packets()
+                .filter(|packet| packet.command == 0x02)
+                .count()
+        };
+        let mut inky = Inky::with_connection(Box::new(connection))?;
+
+        // A finished update leaves nothing to power off
+        inky.update()?;
+        let after_update = power_offs();
+
+        inky.canvas_mut().draw(Rectangle::new((0, 0), (9, 9)), &Color::Red);
+        drop(inky.begin_update()?);
+        assert_eq!(power_offs(), after_update + 1);
+        drop(inky);
+        assert_eq!(power_offs(), after_update + 1);
         Ok(())
     }
 }
@@ -3,9 +3,54 @@
 //! This is a low level library that allows you to connect to, read data from, and write
 //! to/control the screen of the Inky wHat e-ink display from Pimoroni. To get started, see the
 //! examples!
+//!
+//! Without the default `std` feature the crate is `no_std`, leaving the colors, canvas, shapes
+//! and buffer packing for firmware that brings its own allocator.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
+#[cfg(feature = "std")]
 pub mod eeprom;
+pub mod error;
+#[cfg(feature = "hardware")]
+pub mod handle;
 pub mod inky;
+#[cfg(feature = "hardware")]
 pub mod hardware;
 pub mod core;
-mod lut;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "scene")]
+pub mod scene;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "shutdown")]
+pub mod shutdown;
+#[cfg(feature = "slideshow")]
+pub mod slideshow;
+#[cfg(feature = "template")]
+pub mod template;
+#[cfg(any(feature = "testutil", all(test, feature = "hardware")))]
+pub mod testutil;
+#[cfg(feature = "std")]
+pub mod lut;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod refresh_counter;
+#[cfg(feature = "hardware")]
+pub mod scheduler;
+#[cfg(feature = "std")]
+pub mod preview;
+#[cfg(feature = "std")]
+pub mod protocol;
+#[cfg(feature = "std")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod widgets;
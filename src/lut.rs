@@ -48,6 +48,114 @@ mitigate image retention. The flashing effect is actually the ink particles bein
 the top of the display repeatedly in an attempt to reset them back into a sensible resting position.
  */
 
+//! Waveform lookup tables for the SSD-based wHAT and pHAT controllers
+
+use crate::error::{InkyError, Result};
+
+use std::borrow::Cow;
+
+/// Panels below this temperature in degrees Celsius get longer waveforms, see [`compensate`]
+pub const COLD: f32 = 10.0;
+
+/// Panels above this temperature in degrees Celsius use [`LUT_RED_HIGHTEMP`] in place of
+/// [`LUT_RED`]
+pub const HOT: f32 = 30.0;
+
+// Offset of the phase timings, and of the repeat count within each phase's timings
+const TIMINGS: usize = 35;
+const REPEAT: usize = 4;
+
+/// A waveform lookup table: 35 bytes of phase voltages (five rows of seven phases) followed by
+/// 35 bytes of phase timings (seven rows of four durations and a repeat count)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lut {
+    data: Vec<u8>,
+}
+
+impl Lut {
+    /// Length of a lookup table in bytes
+    pub const LEN: usize = 70;
+
+    /// Create a lookup table from its raw bytes, as sent to the controller
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() != Self::LEN {
+            return Err(InkyError::BufferSize {
+                expected: Self::LEN,
+                actual: data.len(),
+            });
+        }
+        Ok(Self { data })
+    }
+
+    /// Create a lookup table from the voltage rows (LUT0 - black, LUT1 - white, unused,
+    /// LUT3 - yellow or red, LUT4 - VCOM) and the per-phase timings
+    pub fn from_parts(voltages: [[u8; 7]; 5], timings: [[u8; 5]; 7]) -> Self {
+        let data = voltages
+            .iter()
+            .flatten()
+            .chain(timings.iter().flatten())
+            .copied()
+            .collect();
+        Self { data }
+    }
+
+    /// The raw bytes of the table
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// The lookup table a display uses for full refreshes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LutKind {
+    #[default]
+    Black,
+    Red,
+    /// Red waveform tuned for panels running hot
+    RedHighTemp,
+    Yellow,
+    /// A user supplied waveform
+    Custom(Lut),
+}
+
+impl LutKind {
+    /// The raw bytes of the selected table
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            LutKind::Black => LUT_BLACK,
+            LutKind::Red => LUT_RED,
+            LutKind::RedHighTemp => LUT_RED_HIGHTEMP,
+            LutKind::Yellow => LUT_YELLOW,
+            LutKind::Custom(lut) => lut.as_bytes(),
+        }
+    }
+
+    /// The table for a panel at `celsius`: the high temperature red waveform on a hot panel, and
+    /// the built-in tables lengthened on a cold one. Custom tables are sent as they are, as is
+    /// every table when the temperature is unknown
+    pub fn for_temperature(&self, celsius: Option<f32>) -> Cow<'_, [u8]> {
+        match (self, celsius) {
+            (LutKind::Custom(lut), _) => Cow::Borrowed(lut.as_bytes()),
+            (LutKind::Red, Some(celsius)) if celsius > HOT => Cow::Borrowed(LUT_RED_HIGHTEMP),
+            (lut, celsius) => compensate(lut.as_bytes(), celsius),
+        }
+    }
+}
+
+/// Lengthen a waveform for a cold panel, whose ink moves more slowly. Below [`COLD`] each phase
+/// is repeated a quarter more often for every 5°C, up to twice as often
+pub fn compensate(lut: &[u8], celsius: Option<f32>) -> Cow<'_, [u8]> {
+    let factor = match celsius {
+        Some(celsius) if celsius < COLD => (1.0 + (COLD - celsius) / 20.0).min(2.0),
+        _ => return Cow::Borrowed(lut),
+    };
+    let mut lut = lut.to_vec();
+    for phase in lut[TIMINGS..].chunks_mut(REPEAT + 1) {
+        phase[REPEAT] = (phase[REPEAT] as f32 * factor).round().min(u8::MAX as f32) as u8;
+    }
+    Cow::Owned(lut)
+}
+
 pub const LUT_BLACK: &[u8] = &[
     0b01001000, 0b10100000, 0b00010000, 0b00010000, 0b00010011, 0b00000000, 0b00000000, 0b01001000,
     0b10100000, 0b10000000, 0b00000000, 0b00000011, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
@@ -87,3 +195,77 @@ pub const LUT_YELLOW: &[u8] = &[
     0x08, 0x08, 0x03, 0x08, 0x20, 0x08, 0x04, 0x00, 0x00, 0x10, 0x10, 0x08, 0x08, 0x00, 0x20, 0x00,
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
+
+/// Fast refresh waveform for the black wHAT. A single short flash replaces the two long clearing
+/// phases of LUT_BLACK, giving refreshes of around a second with more ghosting
+pub const LUT_BLACK_FAST: &[u8] = &[
+    0b01001000, 0b10100000, 0b00010000, 0b00010000, 0b00010011, 0b00000000, 0b00000000, 0b01001000,
+    0b10100000, 0b10000000, 0b00000000, 0b00000011, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b01001000, 0b10100101, 0b00000000,
+    0b10111011, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0x02, 0x02, 0x02, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x08, 0x08, 0x10, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Partial refresh waveform for the black wHAT. The flash/clear phases are dropped and only the
+/// final drive phase of LUT_BLACK is kept, so the panel updates without flashing at the cost of
+/// some ghosting
+pub const LUT_BLACK_PARTIAL: &[u8] = &[
+    0b00010000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b10000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0x04, 0x08, 0x08, 0x10, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Four level greyscale waveform for the black wHAT, used with both RAM planes. The black and
+/// white RAM bit and the red RAM bit of each pixel select a row: black (00) and white (01) are
+/// driven as in LUT_BLACK, while dark grey (10) and light grey (11) are driven white and then
+/// pulled back towards black for six and two frames in a fourth phase
+pub const LUT_GREYSCALE: &[u8] = &[
+    0b01001000, 0b10100000, 0b00010000, 0b00010000, 0b00000000, 0b00000000, 0b00000000, 0b01001000,
+    0b10100000, 0b10000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b01001000, 0b10100000,
+    0b10000000, 0b01010100, 0b00000000, 0b00000000, 0b00000000, 0b01001000, 0b10100000, 0b10000000,
+    0b01000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0x10, 0x04, 0x04, 0x04, 0x04, 0x10, 0x04, 0x04, 0x04, 0x04,
+    0x04, 0x08, 0x08, 0x10, 0x10, 0x02, 0x02, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{compensate, Lut, LutKind, LUT_BLACK, LUT_GREYSCALE, LUT_RED, LUT_RED_HIGHTEMP};
+
+    #[test]
+    fn test_lut_from_parts() {
+        let voltages = [[0b01001000; 7]; 5];
+        let timings = [[0x04, 0x08, 0x08, 0x10, 0x01]; 7];
+        let lut = Lut::from_parts(voltages, timings);
+        assert_eq!(lut.as_bytes().len(), Lut::LEN);
+        assert_eq!(lut.as_bytes()[35..40], [0x04, 0x08, 0x08, 0x10, 0x01]);
+
+        assert!(Lut::new(vec![0; 12]).is_err());
+        assert_eq!(LutKind::Black.as_bytes(), LUT_BLACK);
+        assert_eq!(LUT_GREYSCALE.len(), Lut::LEN);
+    }
+
+    #[test]
+    fn test_temperature() {
+        assert_eq!(LutKind::Red.for_temperature(Some(35.0)), LUT_RED_HIGHTEMP);
+        assert_eq!(LutKind::Red.for_temperature(Some(20.0)), LUT_RED);
+        assert_eq!(LutKind::Red.for_temperature(None), LUT_RED);
+
+        // A quarter longer at 5°C, and at most twice as long
+        let cold = compensate(LUT_BLACK, Some(5.0));
+        assert_eq!(cold[35..40], [0x10, 0x04, 0x04, 0x04, 0x05]);
+        assert_eq!(cold[..35], LUT_BLACK[..35]);
+        let frozen = compensate(LUT_BLACK, Some(-40.0));
+        assert_eq!(frozen[45..50], [0x04, 0x08, 0x08, 0x10, 0x20]);
+
+        let custom = LutKind::Custom(Lut::new(LUT_BLACK.to_vec()).unwrap());
+        assert_eq!(custom.for_temperature(Some(-40.0)), LUT_BLACK);
+    }
+}
@@ -0,0 +1,291 @@
+//! Prometheus metrics for a display driven by the HTTP server or the daemon
+//!
+//! [`Metrics`] counts update outcomes, keeps a histogram of refresh durations and remembers when
+//! each kind of error last happened, see [`InkyError::kind`], and [`Metrics::render`] writes them in the Prometheus text format. The HTTP server
+//! serves them at `GET /metrics`; for the daemon, [`serve`] answers scrapes on a separate TCP
+//! port.
+//!
+//! The temperature gauge is reported once a refresh has measured the panel, see
+//! [`crate::inky::Inky::panel_temperature`], or once it has been set from another sensor with
+//! [`Metrics::set_temperature`] on panels and connections that can't measure it.
+
+use crate::error::{InkyError, Result};
+#[cfg(feature = "hardware")]
+use crate::inky::{Inky, UpdateStatus};
+
+use log::{error, info};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    io::{Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Upper bounds of the refresh duration histogram buckets in seconds, from fast partial refreshes
+/// to slow multi-colour ones
+const BUCKETS: [f64; 9] = [0.5, 1.0, 2.0, 5.0, 10.0, 15.0, 20.0, 30.0, 60.0];
+
+/// Content type of [`Metrics::render`]'s output
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Default)]
+struct State {
+    refreshes: u64,
+    updated: u64,
+    unchanged: u64,
+    errors: u64,
+    // Count of durations up to each bucket bound, then the sum and count of all of them
+    buckets: [u64; BUCKETS.len()],
+    duration_sum: f64,
+    duration_count: u64,
+    // When each kind of error last happened
+    last_errors: BTreeMap<&'static str, SystemTime>,
+    temperature: Option<f32>,
+}
+
+/// Metrics shared between the thread driving the display and the one serving scrapes
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64())
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // Metrics are still worth serving after a panic elsewhere
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Count a refresh of the panel that took `duration`
+    pub fn record_refresh(&self, duration: Duration) {
+        let mut state = self.state();
+        let seconds = duration.as_secs_f64();
+        state.updated += 1;
+        for (count, bound) in state.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        state.duration_sum += seconds;
+        state.duration_count += 1;
+    }
+
+    /// Count an update skipped because the frame had not changed
+    pub fn record_unchanged(&self) {
+        self.state().unchanged += 1;
+    }
+
+    /// Count a failed update and remember when its kind of error happened
+    pub fn record_error(&self, error: &InkyError) {
+        let mut state = self.state();
+        state.errors += 1;
+        state.last_errors.insert(error.kind(), SystemTime::now());
+    }
+
+    /// Set the lifetime refresh count of the panel, see [`crate::inky::Inky::refresh_count`]
+    pub fn set_refresh_count(&self, count: u64) {
+        self.state().refreshes = count;
+    }
+
+    /// Set the panel temperature in degrees Celsius, or `None` if it is unknown
+    pub fn set_temperature(&self, celsius: Option<f32>) {
+        self.state().temperature = celsius;
+    }
+
+    /// Record the outcome of an update of `inky`
+    #[cfg(feature = "hardware")]
+    pub fn observe(&self, inky: &Inky, result: &Result<UpdateStatus>) {
+        match result {
+            Ok(UpdateStatus::Updated) => self.record_refresh(
+                inky.last_update_stats()
+                    .map_or(Duration::ZERO, |stats| stats.total),
+            ),
+            Ok(UpdateStatus::Unchanged) => self.record_unchanged(),
+            Err(e) => self.record_error(e),
+        }
+        self.set_refresh_count(inky.refresh_count());
+        if let Some(celsius) = inky.panel_temperature() {
+            self.set_temperature(Some(celsius));
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_metrics(&self.state(), &mut out).expect("Writing to a String can't fail");
+        out
+    }
+}
+
+fn write_metrics(state: &State, out: &mut String) -> fmt::Result {
+    writeln!(out, "# HELP inky_refreshes_total Lifetime refreshes of the panel.")?;
+    writeln!(out, "# TYPE inky_refreshes_total counter")?;
+    writeln!(out, "inky_refreshes_total {}", state.refreshes)?;
+
+    writeln!(out, "# HELP inky_updates_total Updates requested, by outcome.")?;
+    writeln!(out, "# TYPE inky_updates_total counter")?;
+    for (outcome, count) in [
+        ("updated", state.updated),
+        ("unchanged", state.unchanged),
+        ("error", state.errors),
+    ] {
+        writeln!(out, "inky_updates_total{{outcome=\"{}\"}} {}", outcome, count)?;
+    }
+
+    writeln!(
+        out,
+        "# HELP inky_refresh_duration_seconds Time from converting a frame to refreshing it."
+    )?;
+    writeln!(out, "# TYPE inky_refresh_duration_seconds histogram")?;
+    for (bound, count) in BUCKETS.iter().zip(state.buckets) {
+        writeln!(
+            out,
+            "inky_refresh_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, count
+        )?;
+    }
+    writeln!(
+        out,
+        "inky_refresh_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        state.duration_count
+    )?;
+    writeln!(out, "inky_refresh_duration_seconds_sum {}", state.duration_sum)?;
+    writeln!(out, "inky_refresh_duration_seconds_count {}", state.duration_count)?;
+
+    if !state.last_errors.is_empty() {
+        writeln!(
+            out,
+            "# HELP inky_last_error_timestamp_seconds When an update last failed, by kind of error."
+        )?;
+        writeln!(out, "# TYPE inky_last_error_timestamp_seconds gauge")?;
+        for (kind, time) in &state.last_errors {
+            writeln!(
+                out,
+                "inky_last_error_timestamp_seconds{{kind=\"{}\"}} {}",
+                kind,
+                seconds(*time)
+            )?;
+        }
+    }
+
+    if let Some(celsius) = state.temperature {
+        writeln!(out, "# HELP inky_panel_temperature_celsius Temperature of the panel.")?;
+        writeln!(out, "# TYPE inky_panel_temperature_celsius gauge")?;
+        writeln!(out, "inky_panel_temperature_celsius {}", celsius)?;
+    }
+    Ok(())
+}
+
+/// Answer a scrape on `stream` with the metrics
+fn answer(metrics: &Metrics, mut stream: TcpStream) {
+    // The request line and headers are ignored, but reading them keeps clients from seeing a
+    // reset connection
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+    let _ = stream.read(&mut [0; 1024]);
+    let body = metrics.render();
+    let response = format!(
+        "HTTP/1.0 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        error!("Failed to send metrics: {}", e);
+    }
+}
+
+/// Answer every HTTP request on `addr` with the metrics, on background threads. Each scrape is
+/// answered on its own thread, so a slow client doesn't hold up the others
+pub fn serve(metrics: Arc<Metrics>, addr: impl ToSocketAddrs) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving metrics on {}", listener.local_addr()?);
+    thread::Builder::new()
+        .name("inky-metrics".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!("Failed to accept a metrics scrape: {}", e);
+                        continue;
+                    }
+                };
+                let metrics = Arc::clone(&metrics);
+                thread::spawn(move || answer(&metrics, stream));
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serve, Metrics};
+    use crate::error::InkyError;
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new();
+        metrics.record_refresh(Duration::from_millis(1500));
+        metrics.record_refresh(Duration::from_secs(25));
+        metrics.record_unchanged();
+        metrics.set_refresh_count(1234);
+        let text = metrics.render();
+        assert!(text.contains("inky_refreshes_total 1234\n"));
+        assert!(text.contains("inky_updates_total{outcome=\"updated\"} 2\n"));
+        assert!(text.contains("inky_updates_total{outcome=\"unchanged\"} 1\n"));
+        assert!(text.contains("inky_refresh_duration_seconds_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("inky_refresh_duration_seconds_bucket{le=\"2\"} 1\n"));
+        assert!(text.contains("inky_refresh_duration_seconds_bucket{le=\"30\"} 2\n"));
+        assert!(text.contains("inky_refresh_duration_seconds_sum 26.5\n"));
+        assert!(!text.contains("inky_last_error"));
+        assert!(!text.contains("inky_panel_temperature_celsius"));
+
+        metrics.record_error(&InkyError::InvalidArgument("a \"bad\" frame".into()));
+        metrics.set_temperature(Some(21.5));
+        let text = metrics.render();
+        assert!(text.contains("inky_updates_total{outcome=\"error\"} 1\n"));
+        assert!(text.contains("inky_last_error_timestamp_seconds{kind=\"invalid_argument\"}"));
+        metrics.record_error(&InkyError::InvalidArgument("another frame".into()));
+        let text = metrics.render();
+        assert_eq!(text.matches("inky_last_error_timestamp_seconds{").count(), 1);
+        assert!(text.contains("inky_panel_temperature_celsius 21.5\n"));
+    }
+
+    #[test]
+    fn slow_scrape() {
+        // Find a free port, then serve on it
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        serve(Arc::new(Metrics::new()), addr).unwrap();
+
+        // A client that connects and sends nothing doesn't hold up the next scrape
+        let _idle = TcpStream::connect(addr).unwrap();
+        let started = Instant::now();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.0 200 OK"));
+        assert!(response.contains("inky_refreshes_total 0\n"));
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+}
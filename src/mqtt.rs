@@ -0,0 +1,299 @@
+//! MQTT integration for home automation dashboards
+//!
+//! [`run`] connects to a broker, subscribes to a set of topics under a base topic and drives the
+//! display from the messages it receives:
+//!
+//! - `<base>/image` - an image in any format supported by `image`, shown full screen
+//! - `<base>/text` - UTF-8 text shown centered on a white background
+//! - `<base>/draw` - a JSON array of [`DrawCommand`]s drawn over the current canvas. Image
+//!   commands, which read files on the host, are refused
+//! - `<base>/clear` - clear the display, the payload is ignored
+//!
+//! After every refresh a PNG preview of the canvas is published to `<base>/preview`. When a
+//! discovery prefix is configured, Home Assistant MQTT discovery messages are published on
+//! connection so the display shows up as a device with a text entity, a clear button and an
+//! image entity for the preview.
+
+use crate::{
+    core::{colors::Color, geometry::Rect, image::ImportOptions, text::Text},
+    error::{InkyError, Result},
+    inky::{Canvas, Inky},
+};
+
+use derive_builder::Builder;
+use log::{error, info, warn};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::{thread, time::Duration};
+
+// Time to wait before reconnecting to the broker after the connection fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Connection and topic settings for the MQTT integration
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into))]
+pub struct MqttConfig {
+    /// Hostname of the broker
+    host: String,
+    #[builder(default = "1883")]
+    port: u16,
+    #[builder(default = "\"inky\".to_string()")]
+    client_id: String,
+    /// Topic that all of the display's topics live under
+    #[builder(default = "\"inky\".to_string()")]
+    base_topic: String,
+    /// Home Assistant discovery prefix, usually "homeassistant". Discovery is disabled if unset
+    #[builder(default, setter(strip_option))]
+    discovery_prefix: Option<String>,
+    #[builder(default, setter(strip_option))]
+    credentials: Option<(String, String)>,
+}
+
+impl MqttConfig {
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.base_topic, name)
+    }
+}
+
+/// A drawing operation received on the `draw` topic
+pub use crate::scene::Element as DrawCommand;
+
+/// Apply a message to the canvas, importing images with `options`, returning whether the display
+/// should be refreshed
+fn handle_message(
+    canvas: &mut Canvas,
+    palette: &[Color],
+    options: &ImportOptions,
+    config: &MqttConfig,
+    topic: &str,
+    payload: &[u8],
+) -> std::result::Result<bool, String> {
+    let name = topic
+        .strip_prefix(&config.base_topic)
+        .and_then(|name| name.strip_prefix('/'))
+        .unwrap_or_default();
+    match name {
+        "image" => {
+            let image = ::image::load_from_memory(payload).map_err(|e| e.to_string())?;
+            canvas.import_image(&image, palette, options);
+        }
+        "text" => {
+            let text = std::str::from_utf8(payload).map_err(|e| e.to_string())?;
+            let area = Rect::new(0, 0, canvas.width(), canvas.height());
+            canvas.clear();
+            canvas.draw(Text::centered(text, 4, area), &Color::Black);
+        }
+        "draw" => {
+            let commands: Vec<DrawCommand> =
+                serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+            if commands.iter().any(DrawCommand::reads_files) {
+                return Err("Draw commands can't load images from files".into());
+            }
+            // Draw on a copy, so a batch that fails part way leaves the canvas as it was
+            let mut drawn = canvas.clone();
+            for command in &commands {
+                command.draw(&mut drawn).map_err(|e| e.to_string())?;
+            }
+            *canvas = drawn;
+        }
+        "clear" => canvas.clear(),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Publish Home Assistant discovery messages for the display
+fn publish_discovery(client: &Client, config: &MqttConfig, prefix: &str) {
+    let device = json!({
+        "identifiers": [config.client_id],
+        "name": "Inky",
+        "manufacturer": "Pimoroni",
+    });
+    let entities = [
+        (
+            "text",
+            "message",
+            json!({"name": "Message", "command_topic": config.topic("text"), "max": 255}),
+        ),
+        (
+            "button",
+            "clear",
+            json!({"name": "Clear", "command_topic": config.topic("clear")}),
+        ),
+        (
+            "image",
+            "preview",
+            json!({"name": "Preview", "image_topic": config.topic("preview"), "content_type": "image/png"}),
+        ),
+    ];
+    for (component, object_id, mut payload) in entities {
+        payload["unique_id"] = json!(format!("{}_{}", config.client_id, object_id));
+        payload["device"] = device.clone();
+        let topic = format!(
+            "{}/{}/{}/{}/config",
+            prefix, component, config.client_id, object_id
+        );
+        if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload.to_string()) {
+            warn!("Failed to publish discovery message: {}", e);
+        }
+    }
+}
+
+/// Connect to the broker and drive the display from received messages, drawing with colors from
+/// `palette` and importing images with the display's [`Inky::import_options`]. Connection errors
+/// are logged and the broker is reconnected to, so this only returns if subscribing fails
+pub fn run(mut inky: Inky, palette: &[Color], config: &MqttConfig) -> Result<()> {
+    let import = inky.import_options().clone();
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    // Leave room for full frame images
+    options.set_max_packet_size(32 * 1024 * 1024, 32 * 1024 * 1024);
+    if let Some((username, password)) = &config.credentials {
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut connection) = Client::new(options, 16);
+    for notification in connection.iter() {
+        let event = match notification {
+            Ok(event) => event,
+            Err(e) => {
+                // The next iteration reconnects, and the subscriptions are made again on ConnAck
+                warn!("Connection to {}:{} failed: {}", config.host, config.port, e);
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            }
+        };
+        match event {
+            Event::Incoming(Packet::ConnAck(_)) => {
+                info!("Connected to {}:{}", config.host, config.port);
+                // Subscriptions do not survive a reconnect with a clean session
+                for name in ["image", "text", "draw", "clear"] {
+                    client
+                        .try_subscribe(config.topic(name), QoS::AtLeastOnce)
+                        .map_err(|e| InkyError::Mqtt(e.to_string()))?;
+                }
+                if let Some(prefix) = &config.discovery_prefix {
+                    publish_discovery(&client, config, prefix);
+                }
+            }
+            Event::Incoming(Packet::Publish(publish)) => {
+                match handle_message(
+                    inky.canvas_mut(),
+                    palette,
+                    &import,
+                    config,
+                    &publish.topic,
+                    &publish.payload,
+                ) {
+                    Ok(true) => {
+                        if let Err(e) = inky.update() {
+                            error!("Failed to update display: {}", e);
+                            continue;
+                        }
+                        match inky.canvas().to_png() {
+                            Ok(png) => {
+                                if let Err(e) = client.try_publish(
+                                    config.topic("preview"),
+                                    QoS::AtMostOnce,
+                                    true,
+                                    png,
+                                ) {
+                                    warn!("Failed to publish preview: {}", e);
+                                }
+                            }
+                            Err(e) => warn!("Failed to render preview: {}", e),
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => warn!("Ignoring message on {}: {}", publish.topic, e),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_message, DrawCommand, MqttConfigBuilder};
+    use crate::{
+        core::{colors::Color, image::ImportOptions},
+        inky::Canvas,
+    };
+
+    const PALETTE: &[Color] = &[Color::Black, Color::White];
+
+    #[test]
+    fn test_draw_commands() {
+        let config = MqttConfigBuilder::default()
+            .host("localhost")
+            .build()
+            .unwrap();
+        let mut canvas = Canvas::new(32, 16);
+        let options = ImportOptions::default();
+        let payload = br#"[
+            {"type": "fill", "color": "white"},
+            {"type": "rectangle", "top_left": [1, 1], "bottom_right": [3, 3], "color": "black"},
+            {"type": "text", "position": [8, 0], "text": "Hi", "color": "red"}
+        ]"#;
+        assert_eq!(
+            handle_message(
+                &mut canvas,
+                PALETTE,
+                &options,
+                &config,
+                "inky/draw",
+                payload
+            ),
+            Ok(true)
+        );
+        assert_eq!(canvas.get_pixel(2, 2), Color::Black);
+
+        assert!(handle_message(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &config,
+            "inky/draw",
+            b"[{}]"
+        )
+        .is_err());
+        let payload = br#"[
+            {"type": "fill", "color": "red"},
+            {"type": "image", "path": "/nonexistent/inky.png"}
+        ]"#;
+        assert!(handle_message(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &config,
+            "inky/draw",
+            payload
+        )
+        .is_err());
+        assert_eq!(canvas.get_pixel(2, 2), Color::Black);
+        assert_eq!(
+            handle_message(&mut canvas, PALETTE, &options, &config, "other/draw", b""),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let command: DrawCommand = serde_json::from_str(
+            r#"{"type": "line", "start": [0, 0], "end": [4, 4], "color": "blue"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            command,
+            DrawCommand::Line {
+                start: (0, 0),
+                end: (4, 4),
+                width: 1,
+                color: Color::Blue
+            }
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Previews of a canvas in the terminal, to check a layout over SSH without a display or a PNG
+//! round trip
+//!
+//! Terminals with sixel graphics get a pixel exact image. Elsewhere every character cell shows two
+//! pixels with a half block character, coloured with 24-bit ANSI escapes, and wide canvases are
+//! sampled down to fit the terminal.
+
+use crate::{core::colors::Color, inky::Canvas};
+
+use std::{
+    env,
+    io::{self, Write},
+};
+
+/// How a preview is drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Half block characters with ANSI colours, which any modern terminal can show
+    Ansi { max_width: usize },
+    /// A sixel image
+    Sixel,
+}
+
+impl Mode {
+    /// Guess what the terminal supports from the environment. `INKY_PREVIEW=sixel` or
+    /// `INKY_PREVIEW=ansi` overrides the guess, and the ANSI width follows `COLUMNS`
+    pub fn detect() -> Self {
+        let sixel = match env::var("INKY_PREVIEW").as_deref() {
+            Ok("sixel") => true,
+            Ok("ansi") => false,
+            _ => {
+                let term = env::var("TERM").unwrap_or_default();
+                let program = env::var("TERM_PROGRAM").unwrap_or_default();
+                term.contains("sixel")
+                    || ["mlterm", "foot", "yaft"].iter().any(|t| term.starts_with(t))
+                    || ["WezTerm", "iTerm.app"].contains(&program.as_str())
+            }
+        };
+        if sixel {
+            Mode::Sixel
+        } else {
+            let max_width = env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.parse().ok())
+                .unwrap_or(100);
+            Mode::Ansi { max_width }
+        }
+    }
+}
+
+/// Print the canvas to standard output in the mode the terminal seems to support
+pub fn terminal(canvas: &Canvas) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    write(canvas, Mode::detect(), &mut stdout)?;
+    stdout.flush()
+}
+
+/// Write a preview of the canvas in the given mode
+pub fn write(canvas: &Canvas, mode: Mode, out: &mut impl Write) -> io::Result<()> {
+    match mode {
+        Mode::Ansi { max_width } => ansi(canvas, max_width, out),
+        Mode::Sixel => sixel(canvas, out),
+    }
+}
+
+fn ansi(canvas: &Canvas, max_width: usize, out: &mut impl Write) -> io::Result<()> {
+    // Sample every `step`th pixel in both directions to keep the aspect ratio
+    let step = canvas.width().div_ceil(max_width.max(1)).max(1);
+    let pixel = |x: usize, y: usize| canvas.get(x, y).map(|color| color.to_rgb());
+    for y in (0..canvas.height()).step_by(2 * step) {
+        for x in (0..canvas.width()).step_by(step) {
+            let (r, g, b) = pixel(x, y).unwrap_or_default();
+            write!(out, "\x1b[38;2;{};{};{}m", r, g, b)?;
+            // The bottom half of the last row of an odd height canvas is left blank
+            match pixel(x, y + step) {
+                Some((r, g, b)) => write!(out, "\x1b[48;2;{};{};{}m\u{2580}", r, g, b)?,
+                None => write!(out, "\x1b[49m\u{2580}")?,
+            }
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    Ok(())
+}
+
+fn sixel(canvas: &Canvas, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "\x1bPq\"1;1;{};{}", canvas.width(), canvas.height())?;
+    for (register, color) in Color::EVERY.iter().enumerate() {
+        let (r, g, b) = color.to_rgb();
+        let percent = |c: u8| c as usize * 100 / 255;
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            register,
+            percent(r),
+            percent(g),
+            percent(b)
+        )?;
+    }
+    // Each band is six rows, drawn once per colour in it
+    for top in (0..canvas.height()).step_by(6) {
+        for (register, color) in Color::EVERY.iter().enumerate() {
+            let columns: Vec<u8> = (0..canvas.width())
+                .map(|x| {
+                    (0..6)
+                        .filter(|dy| canvas.get(x, top + dy) == Some(*color))
+                        .fold(0, |bits, dy| bits | 1 << dy)
+                })
+                .collect();
+            if columns.iter().all(|bits| *bits == 0) {
+                continue;
+            }
+            write!(out, "#{}", register)?;
+            // Run length encode repeated columns
+            let mut x = 0;
+            while x < columns.len() {
+                let run = columns[x..].iter().take_while(|c| **c == columns[x]).count();
+                let c = char::from(63 + columns[x]);
+                if run > 3 {
+                    write!(out, "!{}{}", run, c)?;
+                } else {
+                    write!(out, "{}", c.to_string().repeat(run))?;
+                }
+                x += run;
+            }
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    writeln!(out, "\x1b\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write, Mode};
+    use crate::{
+        core::colors::Color,
+        inky::{Canvas, Rectangle},
+    };
+
+    #[test]
+    fn ansi_half_blocks() {
+        let mut canvas = Canvas::new(4, 3);
+        canvas.draw(Rectangle::new((0, 0), (0, 0)), &Color::Red);
+        let mut out = Vec::new();
+        write(&canvas, Mode::Ansi { max_width: 80 }, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert_eq!(text.matches('\u{2580}').count(), 8);
+        let (r, g, b) = Color::Red.to_rgb();
+        assert!(text.starts_with(&format!("\x1b[38;2;{};{};{}m", r, g, b)));
+
+        // Wide canvases are sampled down to the width
+        let mut out = Vec::new();
+        write(&Canvas::new(40, 4), Mode::Ansi { max_width: 10 }, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().next().unwrap().matches('\u{2580}').count(), 10);
+    }
+
+    #[test]
+    fn sixel_image() {
+        let mut canvas = Canvas::new(8, 6);
+        canvas.draw(Rectangle::new((0, 0), (7, 0)), &Color::Black);
+        let mut out = Vec::new();
+        write(&canvas, Mode::Sixel, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("\x1bPq\"1;1;8;6"));
+        assert!(text.trim_end().ends_with("\x1b\\"));
+        // The top row in black and the five rows below it in white, eight columns each
+        assert!(text.contains("#0!8@$"));
+        assert!(text.contains("#1!8}$"));
+
+        // Greys, as a greyscale wHAT shows them, get registers of their own
+        let mut canvas = Canvas::new(4, 6).with_palette(&Color::GREYSCALE);
+        canvas.draw(Rectangle::new((0, 0), (3, 0)), &Color::DarkGrey);
+        canvas.draw(Rectangle::new((0, 1), (3, 1)), &Color::LightGrey);
+        let mut out = Vec::new();
+        write(&canvas, Mode::Sixel, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("#6;2;33;33;33"));
+        assert!(text.contains("#6!4@$"));
+        assert!(text.contains("#7!4A$"));
+    }
+}
@@ -0,0 +1,266 @@
+//! The wire protocol spoken by the display daemon, see the `daemon` module, and its client
+//! [`crate::remote::RemoteInky`]
+//!
+//! Every message is a one byte kind, the length of the payload as a big endian `u32` and the
+//! payload. A client sends a request and reads one response before sending the next:
+//!
+//! | Request | Kind | Payload |
+//! |---------|------|---------|
+//! | Frame   | 1    | width and height as big endian `u16`, then one colour index per pixel |
+//! | Image   | 2    | an image in any format `image` can decode |
+//! | Raw     | 3    | a frame packed in the display's own format |
+//! | Clear   | 4    | none |
+//! | Clean   | 5    | the number of cycles as one byte |
+//! | Info    | 6    | none |
+//!
+//! | Response  | Kind | Payload |
+//! |-----------|------|---------|
+//! | Updated   | 1    | none |
+//! | Unchanged | 2    | none |
+//! | Info      | 3    | width and height as big endian `u16`, then the colour index of each ink in the palette |
+//! | Error     | 4    | a UTF-8 message |
+//!
+//! Colour indices are positions in [`Color::ALL`], followed by dark grey (6) and light grey (7).
+
+use crate::{
+    core::colors::Color,
+    error::{InkyError, Result},
+    inky::Canvas,
+};
+
+use std::io::{Read, Write};
+
+/// Largest payload accepted, enough for an uncompressed 13.3" frame
+pub const MAX_PAYLOAD: u32 = 32 * 1024 * 1024;
+
+pub enum Request {
+    /// Replace the canvas with a frame and refresh the display
+    Frame(Canvas),
+    /// Replace the canvas with an encoded image, fitted and dithered to the display, and refresh
+    Image(Vec<u8>),
+    /// Refresh the display with a packed frame, see [`crate::inky::Inky::update_raw`]
+    Raw(Vec<u8>),
+    /// Clear the canvas to white and refresh the display
+    Clear,
+    /// Clean the panel, see [`crate::inky::Inky::clean`]
+    Clean { cycles: u8 },
+    /// Ask for the size and palette of the display
+    Info,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    Updated,
+    Unchanged,
+    Info {
+        width: usize,
+        height: usize,
+        palette: Vec<Color>,
+    },
+    Error(String),
+}
+
+fn invalid(message: &str) -> InkyError {
+    InkyError::InvalidArgument(format!("Invalid message: {}", message))
+}
+
+fn color_index(color: &Color) -> u8 {
+    Color::EVERY
+        .iter()
+        .position(|c| c == color)
+        .expect("every color is in Color::EVERY") as u8
+}
+
+fn color_from_index(index: u8) -> Result<Color> {
+    Color::EVERY
+        .get(index as usize)
+        .copied()
+        .ok_or_else(|| invalid("unknown color"))
+}
+
+fn size(width: usize, height: usize) -> Result<Vec<u8>> {
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(InkyError::InvalidArgument(format!(
+            "{}x{} is too large to send",
+            width, height
+        )));
+    };
+    let mut payload = width.to_be_bytes().to_vec();
+    payload.extend_from_slice(&height.to_be_bytes());
+    Ok(payload)
+}
+
+// Split a payload starting with a size into the size and the rest
+fn split_size(payload: &[u8]) -> Result<(usize, usize, &[u8])> {
+    let [w0, w1, h0, h1, rest @ ..] = payload else {
+        return Err(invalid("missing size"));
+    };
+    let width = u16::from_be_bytes([*w0, *w1]) as usize;
+    let height = u16::from_be_bytes([*h0, *h1]) as usize;
+    Ok((width, height, rest))
+}
+
+fn write_message(out: &mut impl Write, kind: u8, payload: &[u8]) -> Result<()> {
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|length| *length <= MAX_PAYLOAD)
+        .ok_or_else(|| invalid("payload too large"))?;
+    out.write_all(&[kind])?;
+    out.write_all(&length.to_be_bytes())?;
+    out.write_all(payload)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Read a message, or `None` if the stream ended cleanly before it
+fn read_message(input: &mut impl Read) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut header = [0; 5];
+    // Distinguish a clean end of stream from one part way through a message
+    match input.read(&mut header[..1])? {
+        0 => return Ok(None),
+        _ => input.read_exact(&mut header[1..])?,
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    if length > MAX_PAYLOAD {
+        return Err(invalid("payload too large"));
+    }
+    let mut payload = vec![0; length as usize];
+    input.read_exact(&mut payload)?;
+    Ok(Some((header[0], payload)))
+}
+
+impl Request {
+    pub fn write(&self, out: &mut impl Write) -> Result<()> {
+        match self {
+            Request::Frame(canvas) => {
+                let mut payload = size(canvas.width(), canvas.height())?;
+                payload.extend(canvas.pixels().map(|(_, _, color)| color_index(&color)));
+                write_message(out, 1, &payload)
+            }
+            Request::Image(data) => write_message(out, 2, data),
+            Request::Raw(data) => write_message(out, 3, data),
+            Request::Clear => write_message(out, 4, &[]),
+            Request::Clean { cycles } => write_message(out, 5, &[*cycles]),
+            Request::Info => write_message(out, 6, &[]),
+        }
+    }
+
+    /// Read the next request, or `None` once the client has closed the stream
+    pub fn read(input: &mut impl Read) -> Result<Option<Self>> {
+        let Some((kind, payload)) = read_message(input)? else {
+            return Ok(None);
+        };
+        let request = match kind {
+            1 => {
+                let (width, height, pixels) = split_size(&payload)?;
+                if pixels.len() != width * height {
+                    return Err(invalid("frame size does not match its pixels"));
+                }
+                // Keep greys, which the default palette would map to black and white
+                let mut canvas = Canvas::new(width, height).with_palette(&Color::EVERY);
+                for (i, index) in pixels.iter().enumerate() {
+                    canvas.set_pixel(i % width, i / width, &color_from_index(*index)?);
+                }
+                Request::Frame(canvas.with_palette(&Color::ALL))
+            }
+            2 => Request::Image(payload),
+            3 => Request::Raw(payload),
+            4 => Request::Clear,
+            5 => match payload.as_slice() {
+                [cycles] => Request::Clean { cycles: *cycles },
+                _ => return Err(invalid("clean takes one byte")),
+            },
+            6 => Request::Info,
+            _ => return Err(invalid("unknown request")),
+        };
+        Ok(Some(request))
+    }
+}
+
+impl Response {
+    pub fn write(&self, out: &mut impl Write) -> Result<()> {
+        match self {
+            Response::Updated => write_message(out, 1, &[]),
+            Response::Unchanged => write_message(out, 2, &[]),
+            Response::Info {
+                width,
+                height,
+                palette,
+            } => {
+                let mut payload = size(*width, *height)?;
+                payload.extend(palette.iter().map(color_index));
+                write_message(out, 3, &payload)
+            }
+            Response::Error(message) => write_message(out, 4, message.as_bytes()),
+        }
+    }
+
+    pub fn read(input: &mut impl Read) -> Result<Self> {
+        let (kind, payload) = read_message(input)?.ok_or_else(|| {
+            InkyError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "The daemon closed the connection",
+            ))
+        })?;
+        match kind {
+            1 => Ok(Response::Updated),
+            2 => Ok(Response::Unchanged),
+            3 => {
+                let (width, height, palette) = split_size(&payload)?;
+                Ok(Response::Info {
+                    width,
+                    height,
+                    palette: palette
+                        .iter()
+                        .map(|index| color_from_index(*index))
+                        .collect::<Result<_>>()?,
+                })
+            }
+            4 => Ok(Response::Error(String::from_utf8_lossy(&payload).into_owned())),
+            _ => Err(invalid("unknown response")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Request, Response};
+    use crate::{
+        core::colors::Color,
+        inky::{Canvas, Rectangle},
+    };
+
+    #[test]
+    fn round_trip() {
+        let mut canvas = Canvas::new(5, 3);
+        canvas.draw(Rectangle::new((1, 1), (2, 1)), &Color::Red);
+        let mut wire = Vec::new();
+        Request::Frame(canvas).write(&mut wire).unwrap();
+        Request::Clean { cycles: 2 }.write(&mut wire).unwrap();
+        assert_eq!(&wire[..5], &[1, 0, 0, 0, 4 + 15]);
+
+        let mut input = wire.as_slice();
+        let Some(Request::Frame(frame)) = Request::read(&mut input).unwrap() else {
+            panic!("expected a frame");
+        };
+        assert_eq!((frame.width(), frame.height()), (5, 3));
+        assert_eq!(frame.get(2, 1), Some(Color::Red));
+        assert_eq!(frame.get(3, 1), Some(Color::White));
+        assert!(matches!(
+            Request::read(&mut input).unwrap(),
+            Some(Request::Clean { cycles: 2 })
+        ));
+        assert!(Request::read(&mut input).unwrap().is_none());
+
+        let info = Response::Info {
+            width: 800,
+            height: 480,
+            palette: vec![Color::Black, Color::White, Color::Red],
+        };
+        let mut wire = Vec::new();
+        info.write(&mut wire).unwrap();
+        assert_eq!(Response::read(&mut wire.as_slice()).unwrap(), info);
+        // A message cut short is an error rather than the end of the stream
+        assert!(Request::read(&mut &[6, 0, 0][..]).is_err());
+    }
+}
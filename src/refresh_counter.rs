@@ -0,0 +1,91 @@
+//! A lifetime count of panel refreshes, for monitoring wear on long running installations
+//!
+//! The count is kept in a small text file holding just the number, rewritten after every refresh
+//! so that it survives restarts and power cuts.
+
+use crate::error::{InkyError, Result};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Counts refreshes, optionally persisting the count, see [`crate::inky::Inky::refresh_count`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RefreshCounter {
+    count: u64,
+    path: Option<PathBuf>,
+}
+
+impl RefreshCounter {
+    /// A counter starting from zero that is not saved anywhere
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// A counter kept in the state file at `path`, starting from zero if the file does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let count = match fs::read_to_string(path) {
+            Ok(text) => text.trim().parse().map_err(|_| {
+                InkyError::InvalidArgument(format!(
+                    "{} does not contain a refresh count",
+                    path.display()
+                ))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            count,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Count a refresh and save the new count
+    pub fn increment(&mut self) -> Result<()> {
+        self.count += 1;
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Write a temporary file and rename it over the old one, so a power cut never leaves a
+        // truncated count behind
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, format!("{}\n", self.count))?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RefreshCounter;
+
+    #[test]
+    fn persists_count() {
+        let path = std::env::temp_dir().join(format!("inky-refreshes-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut counter = RefreshCounter::open(&path).unwrap();
+        assert_eq!(counter.count(), 0);
+        counter.increment().unwrap();
+        counter.increment().unwrap();
+        assert_eq!(RefreshCounter::open(&path).unwrap().count(), 2);
+
+        std::fs::write(&path, "many").unwrap();
+        assert!(RefreshCounter::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! A client for a display driven by the daemon on another machine or process
+//!
+//! [`RemoteInky`] keeps a local canvas with the size and palette of the remote display, so fonts,
+//! charts and dithering run on the client and only finished frames cross the network. Start the
+//! other end with `inky daemon --tcp 0.0.0.0:7800` on the Pi, see [`crate::protocol`].
+
+use crate::{
+    core::colors::Color,
+    eeprom::ColorMode,
+    error::{InkyError, Result},
+    inky::{Canvas, UpdateStatus},
+    protocol::{Request, Response},
+};
+
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+};
+
+pub struct RemoteInky {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: BufWriter<Box<dyn Write + Send>>,
+    canvas: Canvas,
+}
+
+// The palettes of the known color modes, for giving the local canvas a 'static palette. Panels
+// in greyscale report Color::GREYSCALE
+const PALETTES: [ColorMode; 5] = [
+    ColorMode::Black,
+    ColorMode::Red,
+    ColorMode::Yellow,
+    ColorMode::RedYellow,
+    ColorMode::Spectra6,
+];
+
+fn static_palette(palette: &[Color]) -> &'static [Color] {
+    PALETTES
+        .iter()
+        .map(ColorMode::palette)
+        .chain([&Color::GREYSCALE[..]])
+        .find(|known| *known == palette)
+        .unwrap_or(&Color::ALL)
+}
+
+impl RemoteInky {
+    /// Connect to a daemon listening on the TCP address `addr`
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Self::with_stream(Box::new(stream.try_clone()?), Box::new(stream))
+    }
+
+    /// Connect to a daemon listening on the Unix socket at `path`
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        Self::with_stream(Box::new(stream.try_clone()?), Box::new(stream))
+    }
+
+    /// Talk to a daemon over an already connected stream, split into its two halves
+    pub fn with_stream(
+        reader: Box<dyn Read + Send>,
+        writer: Box<dyn Write + Send>,
+    ) -> Result<Self> {
+        let mut remote = Self {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+            canvas: Canvas::new(0, 0),
+        };
+        match remote.request(Request::Info)? {
+            Response::Info {
+                width,
+                height,
+                palette,
+            } => {
+                remote.canvas = Canvas::new(width, height).with_palette(static_palette(&palette));
+                Ok(remote)
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn request(&mut self, request: Request) -> Result<Response> {
+        request.write(&mut self.writer)?;
+        match Response::read(&mut self.reader)? {
+            Response::Error(message) => Err(InkyError::Remote(message)),
+            response => Ok(response),
+        }
+    }
+
+    fn request_update(&mut self, request: Request) -> Result<UpdateStatus> {
+        match self.request(request)? {
+            Response::Updated => Ok(UpdateStatus::Updated),
+            Response::Unchanged => Ok(UpdateStatus::Unchanged),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn canvas_mut(&mut self) -> &mut Canvas {
+        &mut self.canvas
+    }
+
+    /// Send the canvas to the daemon and refresh the display. Frames that match the one on the
+    /// display are skipped by the daemon
+    pub fn update(&mut self) -> Result<UpdateStatus> {
+        let status = self.request_update(Request::Frame(self.canvas.clone()))?;
+        self.canvas.clear_dirty();
+        Ok(status)
+    }
+
+    /// Clear the canvas to white and refresh the display
+    pub fn clear(&mut self) -> Result<UpdateStatus> {
+        self.canvas.clear();
+        self.update()
+    }
+
+    /// Send an encoded image for the daemon to fit and dither itself, and refresh the display.
+    /// The local canvas is untouched
+    pub fn show_image(&mut self, data: Vec<u8>) -> Result<UpdateStatus> {
+        self.request_update(Request::Image(data))
+    }
+
+    /// Refresh the display with a frame already packed for it, see
+    /// [`crate::inky::Inky::update_raw`]
+    pub fn update_raw(&mut self, buf: Vec<u8>) -> Result<UpdateStatus> {
+        self.request_update(Request::Raw(buf))
+    }
+
+    /// Clean the panel, see [`crate::inky::Inky::clean`]
+    pub fn clean(&mut self, cycles: u8) -> Result<()> {
+        self.request_update(Request::Clean { cycles }).map(|_| ())
+    }
+}
+
+fn unexpected(response: Response) -> InkyError {
+    InkyError::Remote(format!("Unexpected response {:?}", response))
+}
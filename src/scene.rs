@@ -0,0 +1,314 @@
+//! Declarative scene descriptions rendered onto the canvas
+//!
+//! A [`Scene`] is a list of [`Element`]s drawn in order, read from JSON or TOML so that tools
+//! written in other languages can describe a whole screen without compiling against the crate:
+//!
+//! ```json
+//! {
+//!     "background": "white",
+//!     "elements": [
+//!         {"type": "rectangle", "top_left": [0, 0], "bottom_right": [399, 19], "color": "black"},
+//!         {"type": "text", "position": [4, 6], "text": "Kitchen", "color": "white"},
+//!         {"type": "bar_chart", "area": {"x": 0, "y": 30, "width": 200, "height": 100},
+//!          "values": [3, 5, 2], "color": "red"}
+//!     ]
+//! }
+//! ```
+//!
+//! Each element is tagged with its `type` in snake case, and optional fields take the same
+//! defaults as the matching drawable.
+
+use crate::{
+    core::{colors::Color, geometry::Rect, text::Text},
+    error::{InkyError, Result},
+    inky::{Canvas, Line, Rectangle, RoundedRectangle},
+    widgets::chart::{BarChart, LineGraph},
+};
+
+use serde::Deserialize;
+#[cfg(feature = "image")]
+use serde::Deserializer;
+#[cfg(feature = "image")]
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+/// A full screen of elements
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Scene {
+    /// Color the canvas is filled with before drawing, or `None` to draw over what is there
+    #[serde(default)]
+    pub background: Option<Color>,
+    #[serde(default)]
+    pub elements: Vec<Element>,
+}
+
+impl Scene {
+    /// Parse a scene from JSON
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json)
+            .map_err(|e| InkyError::InvalidArgument(format!("Invalid scene: {}", e)))
+    }
+
+    /// Parse a scene from TOML, with the elements as an array of tables
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml)
+            .map_err(|e| InkyError::InvalidArgument(format!("Invalid scene: {}", e)))
+    }
+
+    /// Whether any element reads a file, see [`Element::reads_files`]
+    pub fn reads_files(&self) -> bool {
+        self.elements.iter().any(Element::reads_files)
+    }
+
+    /// Draw the scene onto the canvas. Elements before one that fails stay drawn
+    pub fn render(&self, canvas: &mut Canvas) -> Result<()> {
+        if let Some(background) = &self.background {
+            canvas.fill(background);
+        }
+        for element in &self.elements {
+            element.draw(canvas)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single drawing operation in a [`Scene`]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Element {
+    /// Fill the whole canvas with a color
+    Fill { color: Color },
+    Line {
+        start: (isize, isize),
+        end: (isize, isize),
+        #[serde(default = "default_one")]
+        width: usize,
+        color: Color,
+    },
+    /// A filled rectangle, or an outline if `stroke` is given. Corners are rounded if `radius`
+    /// is given
+    Rectangle {
+        top_left: (usize, usize),
+        bottom_right: (usize, usize),
+        #[serde(default)]
+        stroke: Option<usize>,
+        #[serde(default)]
+        radius: usize,
+        color: Color,
+    },
+    Text {
+        position: (usize, usize),
+        text: String,
+        #[serde(default = "default_one")]
+        scale: usize,
+        color: Color,
+    },
+    BarChart {
+        area: Rect,
+        values: Vec<f32>,
+        #[serde(default)]
+        range: Option<(f32, f32)>,
+        #[serde(default)]
+        ticks: usize,
+        #[serde(default = "default_one")]
+        gap: usize,
+        color: Color,
+    },
+    LineGraph {
+        area: Rect,
+        values: Vec<f32>,
+        #[serde(default)]
+        range: Option<(f32, f32)>,
+        #[serde(default)]
+        ticks: usize,
+        #[serde(default = "default_one")]
+        width: usize,
+        color: Color,
+    },
+    /// An image file fitted to `area`, or to the whole canvas if no area is given, and
+    /// quantised to the canvas palette
+    #[cfg(feature = "image")]
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        area: Option<Rect>,
+        /// A fit mode as accepted by [`crate::core::image::Fit::from_str`]
+        #[serde(default, deserialize_with = "parse")]
+        fit: crate::core::image::Fit,
+        /// A dithering algorithm as accepted by [`crate::core::image::Dither::from_str`]
+        #[serde(default, deserialize_with = "parse")]
+        dither: crate::core::image::Dither,
+    },
+}
+
+fn default_one() -> usize {
+    1
+}
+
+// Deserialize a value from its string form
+#[cfg(feature = "image")]
+fn parse<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+impl Element {
+    /// Whether drawing the element reads a file. Scenes received over the network are refused if
+    /// they do, so clients can't read files on the host, or see them in the preview
+    pub fn reads_files(&self) -> bool {
+        match self {
+            #[cfg(feature = "image")]
+            Element::Image { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Draw the element onto the canvas
+    pub fn draw(&self, canvas: &mut Canvas) -> Result<()> {
+        match self {
+            Element::Fill { color } => canvas.fill(color),
+            Element::Line {
+                start,
+                end,
+                width,
+                color,
+            } => canvas.draw(Line::new(*start, *end).with_width(*width), color),
+            Element::Rectangle {
+                top_left,
+                bottom_right,
+                stroke,
+                radius,
+                color,
+            } => match (stroke, radius) {
+                (None, 0) => canvas.draw(Rectangle::new(*top_left, *bottom_right), color),
+                (Some(width), 0) => {
+                    canvas.draw(Rectangle::outline(*top_left, *bottom_right, *width), color)
+                }
+                (None, radius) => canvas.draw(
+                    RoundedRectangle::new(*top_left, *bottom_right, *radius),
+                    color,
+                ),
+                (Some(width), radius) => canvas.draw(
+                    RoundedRectangle::outline(*top_left, *bottom_right, *radius, *width),
+                    color,
+                ),
+            },
+            Element::Text {
+                position,
+                text,
+                scale,
+                color,
+            } => canvas.draw(Text::new(*position, text).with_scale(*scale), color),
+            Element::BarChart {
+                area,
+                values,
+                range,
+                ticks,
+                gap,
+                color,
+            } => {
+                let mut chart = BarChart::new(*area, values.clone())
+                    .with_ticks(*ticks)
+                    .with_gap(*gap);
+                if let Some((min, max)) = range {
+                    chart = chart.with_range(*min, *max);
+                }
+                canvas.draw(chart, color)
+            }
+            Element::LineGraph {
+                area,
+                values,
+                range,
+                ticks,
+                width,
+                color,
+            } => {
+                let mut graph = LineGraph::new(*area, values.clone())
+                    .with_ticks(*ticks)
+                    .with_width(*width);
+                if let Some((min, max)) = range {
+                    graph = graph.with_range(*min, *max);
+                }
+                canvas.draw(graph, color)
+            }
+            #[cfg(feature = "image")]
+            Element::Image {
+                path,
+                area,
+                fit,
+                dither,
+            } => {
+                let area = area.unwrap_or(Rect::new(0, 0, canvas.width(), canvas.height()));
+                let options = crate::core::image::ImportOptions {
+                    dither: *dither,
+                    ..Default::default()
+                };
+                let mut image =
+                    fit.apply(&::image::open(path)?, area.width as u32, area.height as u32);
+                crate::core::image::adjust(&mut image, &options);
+                let palette = canvas.palette();
+                canvas.draw_image(&image, (area.x, area.y), palette, options.dither);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Element, Scene};
+    use crate::{core::colors::Color, inky::Canvas};
+
+    #[test]
+    fn render_json() {
+        let scene = Scene::from_json(
+            r#"{
+                "background": "black",
+                "elements": [
+                    {"type": "rectangle", "top_left": [1, 1], "bottom_right": [3, 3],
+                     "color": "white"},
+                    {"type": "bar_chart", "area": {"x": 10, "y": 0, "width": 6, "height": 8},
+                     "values": [1, 2], "color": "red"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(scene.background, Some(Color::Black));
+        let mut canvas = Canvas::new(16, 8);
+        scene.render(&mut canvas).unwrap();
+        assert_eq!(canvas.get_pixel(0, 0), Color::Black);
+        assert_eq!(canvas.get_pixel(2, 2), Color::White);
+        // The y axis of the chart
+        assert_eq!(canvas.get_pixel(0, 10), Color::Red);
+
+        assert!(Scene::from_json(r#"{"elements": [{"type": "circle"}]}"#).is_err());
+    }
+
+    #[test]
+    fn parse_toml() {
+        let scene = Scene::from_toml(
+            r#"
+            [[elements]]
+            type = "line"
+            start = [0, 0]
+            end = [4, 4]
+            color = "blue"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            scene.elements,
+            vec![Element::Line {
+                start: (0, 0),
+                end: (4, 4),
+                width: 1,
+                color: Color::Blue
+            }]
+        );
+    }
+}
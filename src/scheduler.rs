@@ -0,0 +1,156 @@
+//! Coalescing of redraw requests into as few refreshes as the panel should take
+//!
+//! Refreshing an e-ink panel takes seconds, and refreshing it too often wears it out. An
+//! [`UpdateScheduler`] collects redraw requests from anywhere in a program, e.g. several sensors
+//! reporting within the same second, and turns each burst into a single refresh, never starting
+//! refreshes closer together than a minimum interval.
+
+use crate::{
+    error::{InkyError, Result},
+    handle::InkyHandle,
+    inky::Canvas,
+};
+
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Timing of the refreshes made by an [`UpdateScheduler`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// How long to wait after a request for others to join it
+    pub coalesce: Duration,
+    /// Minimum time between the start of one refresh and the next
+    pub min_interval: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            coalesce: Duration::from_secs(1),
+            min_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// When to refresh for a burst that started at `requested`, given when the last refresh
+    /// started
+    pub fn next_refresh(&self, requested: Instant, last_refresh: Option<Instant>) -> Instant {
+        let coalesced = requested + self.coalesce;
+        match last_refresh {
+            Some(last) => coalesced.max(last + self.min_interval),
+            None => coalesced,
+        }
+    }
+}
+
+/// Collects redraw requests for a display behind an [`InkyHandle`] and refreshes it on a
+/// background thread. The thread makes any refresh still pending and stops once every clone of
+/// the scheduler has been dropped
+#[derive(Clone)]
+pub struct UpdateScheduler {
+    requests: Sender<()>,
+}
+
+impl UpdateScheduler {
+    pub fn new(handle: InkyHandle, config: SchedulerConfig) -> Self {
+        let (requests, queue) = mpsc::channel();
+        thread::Builder::new()
+            .name("inky-scheduler".into())
+            .spawn(move || {
+                let mut last_refresh = None;
+                // Wait for the first request of each burst
+                while queue.recv().is_ok() {
+                    let due = config.next_refresh(Instant::now(), last_refresh);
+                    // Fold in everything requested until the refresh is due
+                    while queue
+                        .recv_timeout(due.saturating_duration_since(Instant::now()))
+                        .is_ok()
+                    {}
+                    last_refresh = Some(Instant::now());
+                    if let Err(e) = handle.update() {
+                        warn!("Scheduled refresh failed: {}", e);
+                        if matches!(e, InkyError::WorkerStopped) {
+                            return;
+                        }
+                    }
+                }
+            })
+            .expect("Failed to spawn the scheduler thread");
+        Self { requests }
+    }
+
+    /// Ask for the display to be refreshed with whatever has been drawn by then
+    pub fn request(&self) -> Result<()> {
+        self.requests.send(()).map_err(|_| InkyError::WorkerStopped)
+    }
+
+    /// Queue drawing through `handle` and ask for a refresh to show it
+    pub fn request_with(
+        &self,
+        handle: &InkyHandle,
+        draw: impl FnOnce(&mut Canvas) + Send + 'static,
+    ) -> Result<()> {
+        handle.draw(draw)?;
+        self.request()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SchedulerConfig, UpdateScheduler};
+    use crate::{
+        core::colors::Color,
+        handle::InkyHandle,
+        hardware::{mock::MockConnection, transcript::RecordingConnection},
+        inky::{Inky, Rectangle},
+        testutil::mock_eeprom,
+    };
+    use std::{
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn next_refresh() {
+        let config = SchedulerConfig {
+            coalesce: Duration::from_secs(1),
+            min_interval: Duration::from_secs(10),
+        };
+        let now = Instant::now();
+        assert_eq!(config.next_refresh(now, None), now + Duration::from_secs(1));
+        let last = now - Duration::from_secs(3);
+        assert_eq!(config.next_refresh(now, Some(last)), now + Duration::from_secs(7));
+    }
+
+    #[test]
+    fn bursts_coalesce() {
+        let connection = RecordingConnection::new(MockConnection::new(mock_eeprom()));
+        let recorder = connection.recorder();
+        let handle = InkyHandle::spawn(Inky::with_connection(Box::new(connection)).unwrap());
+        let scheduler = UpdateScheduler::new(
+            handle.clone(),
+            SchedulerConfig {
+                coalesce: Duration::from_millis(200),
+                min_interval: Duration::from_millis(200),
+            },
+        );
+
+        for i in 0..5 {
+            scheduler
+                .request_with(&handle, move |canvas| {
+                    canvas.draw(Rectangle::new((i * 10, 0), (i * 10 + 4, 4)), &Color::Black)
+                })
+                .unwrap();
+        }
+        sleep(Duration::from_millis(800));
+        // The E673 sends one display refresh command per refresh
+        let transcript = recorder.lock().unwrap();
+        let refreshes = transcript.packets().filter(|packet| packet.command == 0x12);
+        assert_eq!(refreshes.count(), 1);
+    }
+}
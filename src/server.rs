@@ -0,0 +1,348 @@
+//! HTTP server for driving the display over the network
+//!
+//! The server owns the display and handles one request at a time:
+//!
+//! - `POST /image` - draw the image in the request body, in any format supported by `image`,
+//!   and refresh the display
+//! - `POST /text?scale=4&color=black` - draw the UTF-8 text in the request body centered on a
+//!   white background and refresh the display
+//! - `POST /scene` - draw the JSON [`crate::scene::Scene`] in the request body and refresh the
+//!   display, when built with the `scene` feature. Image elements, which read files on the host,
+//!   are refused
+//! - `GET /preview.png` - a PNG preview of the current canvas
+//! - `GET /metrics` - Prometheus metrics, see [`crate::metrics`]
+//!
+//! Requests are handled until the [`Shutdown`] passed to [`serve`] is stopped, see
+//! [`crate::shutdown`].
+
+use crate::{
+    core::{colors::Color, geometry::Rect, image::ImportOptions, text::Text},
+    error::{InkyError, Result},
+    inky::{Canvas, Inky, UpdateStatus},
+    metrics::{Metrics, CONTENT_TYPE},
+    shutdown::{Shutdown, POLL_INTERVAL},
+};
+
+use log::{error, info};
+use std::{io::Read, net::ToSocketAddrs};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// Largest request body accepted, enough for an uncompressed 13.3" frame
+const MAX_BODY: u64 = 32 * 1024 * 1024;
+
+/// Outcome of routing a request against the canvas
+#[derive(Debug, PartialEq)]
+enum Reply {
+    /// The canvas was redrawn and the display should be refreshed
+    Refresh,
+    /// Return a PNG image
+    Png(Vec<u8>),
+    /// Return the metrics
+    Metrics,
+    /// Return an error status and message
+    Error(u16, String),
+}
+
+/// Serve requests on `addr` until the server fails or `shutdown` is stopped, drawing with colors
+/// from `palette` and importing images with the display's [`Inky::import_options`]
+pub fn serve<A: ToSocketAddrs>(
+    mut inky: Inky,
+    palette: &[Color],
+    addr: A,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| InkyError::Server(e.to_string()))?;
+    info!("Listening on {}", server.server_addr());
+    let metrics = Metrics::new();
+    let options = inky.import_options().clone();
+
+    while !shutdown.is_stopping() {
+        let Some(mut request) = server.recv_timeout(POLL_INTERVAL)? else {
+            continue;
+        };
+        if request
+            .body_length()
+            .is_some_and(|len| len as u64 > MAX_BODY)
+        {
+            respond(request, too_large());
+            continue;
+        }
+        // Bodies sent without a length are only found to be too large once read
+        let mut body = Vec::new();
+        if let Err(e) = request
+            .as_reader()
+            .take(MAX_BODY + 1)
+            .read_to_end(&mut body)
+        {
+            error!("Failed to read request body: {}", e);
+            continue;
+        }
+        if body.len() as u64 > MAX_BODY {
+            respond(request, too_large());
+            continue;
+        }
+
+        let reply = route(
+            inky.canvas_mut(),
+            palette,
+            &options,
+            request.method(),
+            request.url(),
+            &body,
+        );
+        let response = match reply {
+            Reply::Refresh => {
+                let result = inky.update();
+                metrics.observe(&inky, &result);
+                match result {
+                    Ok(UpdateStatus::Updated) => text_response(200, "Updated"),
+                    Ok(UpdateStatus::Unchanged) => text_response(200, "Unchanged"),
+                    Err(e) => text_response(500, &e.to_string()),
+                }
+            }
+            Reply::Png(png) => Response::from_data(png).with_header(content_type("image/png")),
+            Reply::Metrics => {
+                Response::from_string(metrics.render()).with_header(content_type(CONTENT_TYPE))
+            }
+            Reply::Error(status, message) => text_response(status, &message),
+        };
+        respond(request, response);
+    }
+
+    shutdown.finish(&mut inky);
+    Ok(())
+}
+
+/// Apply a request to the canvas, importing images with `options`
+fn route(
+    canvas: &mut Canvas,
+    palette: &[Color],
+    options: &ImportOptions,
+    method: &Method,
+    url: &str,
+    body: &[u8],
+) -> Reply {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    match (method, path) {
+        (Method::Post, "/image") => match ::image::load_from_memory(body) {
+            Ok(image) => {
+                canvas.import_image(&image, palette, options);
+                Reply::Refresh
+            }
+            Err(e) => Reply::Error(400, format!("Invalid image: {}", e)),
+        },
+        (Method::Post, "/text") => {
+            let Ok(text) = std::str::from_utf8(body) else {
+                return Reply::Error(400, "Text must be UTF-8".into());
+            };
+            let mut scale = 4;
+            let mut color = Color::Black;
+            for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                match key {
+                    "scale" => match value.parse() {
+                        Ok(value) => scale = value,
+                        Err(_) => return Reply::Error(400, format!("Invalid scale {:?}", value)),
+                    },
+                    "color" => match value.parse() {
+                        Ok(value) => color = value,
+                        Err(e) => return Reply::Error(400, e),
+                    },
+                    _ => {}
+                }
+            }
+            let (width, height) = (canvas.width(), canvas.height());
+            // Beyond a single line of 8 pixel high glyphs filling the canvas the text can't
+            // fit, and drawing it only costs time
+            let max_scale = (height / 8).max(1);
+            if !(1..=max_scale).contains(&scale) {
+                return Reply::Error(
+                    400,
+                    format!("Scale must be between 1 and {}, not {}", max_scale, scale),
+                );
+            }
+            canvas.clear();
+            canvas.draw(
+                Text::centered(text, scale, Rect::new(0, 0, width, height)),
+                &color,
+            );
+            Reply::Refresh
+        }
+        #[cfg(feature = "scene")]
+        (Method::Post, "/scene") => {
+            let scene = std::str::from_utf8(body)
+                .map_err(|e| InkyError::InvalidArgument(e.to_string()))
+                .and_then(crate::scene::Scene::from_json);
+            let scene = match scene {
+                Ok(scene) if scene.reads_files() => {
+                    return Reply::Error(400, "Scenes can't load images from files".into())
+                }
+                Ok(scene) => scene,
+                Err(e) => return Reply::Error(400, e.to_string()),
+            };
+            // Render on a copy, so a scene that fails part way leaves the canvas as it was
+            let mut drawn = canvas.clone();
+            match scene.render(&mut drawn) {
+                Ok(()) => {
+                    *canvas = drawn;
+                    Reply::Refresh
+                }
+                Err(e) => Reply::Error(400, e.to_string()),
+            }
+        }
+        (Method::Get, "/preview.png") => match canvas.to_png() {
+            Ok(png) => Reply::Png(png),
+            Err(e) => Reply::Error(500, e.to_string()),
+        },
+        (Method::Get, "/metrics") => Reply::Metrics,
+        _ => Reply::Error(404, "Not found".into()),
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("Invalid header")
+}
+
+fn text_response(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(message)
+        .with_status_code(status)
+        .with_header(content_type("text/plain; charset=utf-8"))
+}
+
+fn too_large() -> Response<std::io::Cursor<Vec<u8>>> {
+    text_response(
+        413,
+        &format!("Request body is larger than {} bytes", MAX_BODY),
+    )
+}
+
+fn respond(request: Request, response: Response<std::io::Cursor<Vec<u8>>>) {
+    if let Err(e) = request.respond(response) {
+        error!("Failed to send response: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{route, Reply};
+    use crate::{
+        core::{colors::Color, image::ImportOptions},
+        inky::Canvas,
+    };
+    use tiny_http::Method;
+
+    const PALETTE: &[Color] = &[Color::Black, Color::White];
+
+    #[test]
+    fn test_route_text() {
+        let mut canvas = Canvas::new(64, 32);
+        let options = ImportOptions::default();
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Post,
+            "/text?scale=2&color=black",
+            b"Hi",
+        );
+        assert_eq!(reply, Reply::Refresh);
+        assert!(canvas.dirty_region().is_some());
+
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Post,
+            "/text?color=mauve",
+            b"Hi",
+        );
+        assert!(matches!(reply, Reply::Error(400, _)));
+
+        for scale in ["0", "5", "100000"] {
+            let reply = route(
+                &mut canvas,
+                PALETTE,
+                &options,
+                &Method::Post,
+                &format!("/text?scale={}", scale),
+                b"Hi",
+            );
+            assert!(matches!(reply, Reply::Error(400, _)));
+        }
+    }
+
+    #[test]
+    fn test_route_preview_and_errors() {
+        let mut canvas = Canvas::new(8, 8);
+        let options = ImportOptions::default();
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Get,
+            "/preview.png",
+            b"",
+        );
+        assert!(matches!(reply, Reply::Png(png) if png.starts_with(b"\x89PNG")));
+
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Post,
+            "/image",
+            b"not an image",
+        );
+        assert!(matches!(reply, Reply::Error(400, _)));
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Get,
+            "/metrics",
+            b"",
+        );
+        assert_eq!(reply, Reply::Metrics);
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Get,
+            "/nothing",
+            b"",
+        );
+        assert!(matches!(reply, Reply::Error(404, _)));
+    }
+
+    #[cfg(feature = "scene")]
+    #[test]
+    fn test_route_scene() {
+        let mut canvas = Canvas::new(8, 8);
+        let options = ImportOptions::default();
+        let scene = br#"{"elements": [{"type": "fill", "color": "black"}]}"#;
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Post,
+            "/scene",
+            scene,
+        );
+        assert_eq!(reply, Reply::Refresh);
+        assert_eq!(canvas.get(0, 0), Some(Color::Black));
+
+        let scene = br#"{"elements": [
+            {"type": "fill", "color": "white"},
+            {"type": "image", "path": "/etc/hostname.png"}
+        ]}"#;
+        let reply = route(
+            &mut canvas,
+            PALETTE,
+            &options,
+            &Method::Post,
+            "/scene",
+            scene,
+        );
+        assert!(matches!(reply, Reply::Error(400, _)));
+        assert_eq!(canvas.get(0, 0), Some(Color::Black));
+    }
+}
@@ -0,0 +1,144 @@
+//! Stopping the server and daemon cleanly on SIGTERM and SIGINT
+//!
+//! A [`Shutdown`] is passed to the serve functions of the HTTP server and the daemon. Once
+//! [`Shutdown::register_signals`] has been called, a signal stops them from taking new requests,
+//! lets the refresh in progress finish, draws the [`OfflineScreen`] if one was set and returns, so
+//! a panel stopped or restarted by systemd doesn't keep showing content that is no longer being
+//! kept up to date. A second signal ends the program straight away.
+
+use crate::{
+    core::{colors::Color, geometry::Rect, text::Text},
+    error::Result,
+    inky::Inky,
+};
+
+use log::{error, info};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    flag,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How often the serve loops check whether they have been asked to stop
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to leave on the panel after shutting down
+#[derive(Clone, Debug, PartialEq)]
+pub enum OfflineScreen {
+    /// An image file in any format `image` supports, fitted with the display's import options
+    Image(PathBuf),
+    /// A message in black, centered on a white background
+    Text(String),
+}
+
+impl OfflineScreen {
+    /// Draw the screen and refresh the display, regardless of the refresh policy or of what the
+    /// display already shows
+    pub fn show(&self, inky: &mut Inky) -> Result<()> {
+        match self {
+            OfflineScreen::Image(path) => inky.import_image(&::image::open(path)?),
+            OfflineScreen::Text(message) => {
+                let canvas = inky.canvas_mut();
+                let area = Rect::new(0, 0, canvas.width(), canvas.height());
+                canvas.clear();
+                canvas.draw(Text::centered(message, 4, area), &Color::Black);
+            }
+        }
+        inky.without_policy(Inky::force_update)?;
+        Ok(())
+    }
+}
+
+/// When the serve loops should stop and what they leave on the display. Clones share the same
+/// stop flag
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown {
+    stop: Arc<AtomicBool>,
+    offline: Option<OfflineScreen>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show `screen` once stopped
+    pub fn with_offline(mut self, screen: OfflineScreen) -> Self {
+        self.offline = Some(screen);
+        self
+    }
+
+    /// Stop once the process receives SIGTERM or SIGINT
+    pub fn register_signals(&self) -> Result<()> {
+        for signal in [SIGTERM, SIGINT] {
+            // Registered first, so it only ends the program if the flag was set by an earlier signal
+            flag::register_conditional_shutdown(signal, 1, Arc::clone(&self.stop))?;
+            flag::register(signal, Arc::clone(&self.stop))?;
+        }
+        Ok(())
+    }
+
+    /// Ask the serve loop to stop, as a signal would
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// Leave the display once the serve loop has stopped, drawing the offline screen if there is
+    /// one
+    pub(crate) fn finish(&self, inky: &mut Inky) {
+        info!("Shutting down");
+        if let Some(screen) = &self.offline {
+            if let Err(e) = screen.show(inky) {
+                error!("Failed to draw the offline screen: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OfflineScreen, Shutdown};
+    use crate::{
+        core::colors::Color,
+        inky::RefreshPolicy,
+        testutil::mock_inky,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn offline_screen() {
+        let mut inky = mock_inky();
+
+        let shutdown = Shutdown::new().with_offline(OfflineScreen::Text("Offline".into()));
+        assert!(!shutdown.is_stopping());
+        shutdown.clone().stop();
+        assert!(shutdown.is_stopping());
+
+        shutdown.finish(&mut inky);
+        assert_eq!(inky.refresh_count(), 1);
+        assert!(inky
+            .canvas()
+            .pixels()
+            .any(|(_, _, color)| color == Color::Black));
+
+        // The offline screen is drawn even straight after another refresh with the same content
+        inky.set_refresh_policy(Some(RefreshPolicy {
+            min_interval: Duration::from_secs(3600),
+            max_partials: None,
+        }));
+        shutdown.finish(&mut inky);
+        assert_eq!(inky.refresh_count(), 2);
+        assert!(inky.refresh_policy().is_some());
+    }
+}
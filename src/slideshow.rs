@@ -0,0 +1,191 @@
+//! A photo frame slideshow of the images in a directory
+//!
+//! The images are shown in name order, or shuffled, changing every interval. The directory is
+//! watched for changes, so an image copied into it is shown straight away and then joins the
+//! rotation, and deleted images drop out of it.
+
+use crate::{
+    core::{colors::Color, image::ImportOptions},
+    error::{InkyError, Result},
+    inky::Inky,
+};
+
+use log::{error, info};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Extensions of the files shown, all of which `image` can decode
+const EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+
+/// Settings for [`run`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlideshowOptions {
+    /// How long each image stays on the display
+    pub interval: Duration,
+    /// Show the images in a random order, reshuffled on every pass
+    pub shuffle: bool,
+    /// How each image is fitted and dithered
+    pub import: ImportOptions,
+}
+
+impl Default for SlideshowOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            shuffle: false,
+            import: ImportOptions::default(),
+        }
+    }
+}
+
+/// Parse an interval such as `90s`, `10m`, `1h` or a plain number of seconds
+pub fn parse_interval(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => 0,
+    };
+    match number.parse::<u64>() {
+        Ok(n) if seconds > 0 && n > 0 => Ok(Duration::from_secs(n * seconds)),
+        _ => Err(InkyError::InvalidArgument(format!(
+            "Invalid interval {:?}, expected e.g. 30s, 10m or 1h",
+            text
+        ))),
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// The images in `dir`, sorted by name
+pub fn images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_image(path))
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+// Fisher-Yates with a xorshift generator seeded from the clock, which is plenty for picking the
+// order of photos
+fn shuffle(paths: &mut [PathBuf]) {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |time| time.as_nanos() as u64)
+        | 1;
+    for i in (1..paths.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        paths.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+fn show(inky: &mut Inky, palette: &[Color], path: &Path, options: &ImportOptions) -> Result<()> {
+    info!("Showing {}", path.display());
+    let image = image::open(path)?;
+    inky.canvas_mut().import_image(&image, palette, options);
+    inky.update()?;
+    Ok(())
+}
+
+/// Cycle through the images in `dir` until the directory can no longer be watched, drawing with
+/// colors from `palette`. Images that fail to load are skipped
+pub fn run(
+    mut inky: Inky,
+    palette: &[Color],
+    dir: &Path,
+    options: &SlideshowOptions,
+) -> Result<()> {
+    let (events, changes) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    let mut queue: Vec<PathBuf> = Vec::new();
+    let mut next_change = Instant::now();
+    loop {
+        let wait = next_change.saturating_duration_since(Instant::now());
+        let path = match changes.recv_timeout(wait) {
+            Ok(event) => {
+                let event = event?;
+                // Show images as soon as they have been written
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    continue;
+                }
+                match event.paths.into_iter().find(|path| path.is_file() && is_image(path)) {
+                    Some(path) => path,
+                    None => continue,
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if queue.is_empty() {
+                    queue = images(dir)?;
+                    if options.shuffle {
+                        shuffle(&mut queue);
+                    }
+                    // Take images from the end of the queue
+                    queue.reverse();
+                }
+                // Skip images deleted since the directory was listed
+                match std::iter::from_fn(|| queue.pop()).find(|path| path.is_file()) {
+                    Some(path) => path,
+                    None => {
+                        next_change = Instant::now() + options.interval;
+                        continue;
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        if let Err(e) = show(&mut inky, palette, &path, &options.import) {
+            error!("Failed to show {}: {}", path.display(), e);
+        }
+        next_change = Instant::now() + options.interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_image, parse_interval, shuffle};
+    use std::{path::PathBuf, time::Duration};
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_interval("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_interval("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_interval("45").unwrap(), Duration::from_secs(45));
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("10 minutes").is_err());
+        assert!(parse_interval("m").is_err());
+    }
+
+    #[test]
+    fn image_files() {
+        assert!(is_image(&PathBuf::from("holiday/beach.JPG")));
+        assert!(!is_image(&PathBuf::from("notes.txt")));
+        assert!(!is_image(&PathBuf::from("README")));
+
+        let mut paths: Vec<PathBuf> = (0..20).map(|i| PathBuf::from(i.to_string())).collect();
+        shuffle(&mut paths);
+        paths.sort();
+        assert_eq!(paths.len(), 20);
+        paths.dedup();
+        assert_eq!(paths.len(), 20);
+    }
+}
@@ -0,0 +1,200 @@
+//! Template-driven text layouts for screens where only a few values change
+//!
+//! A [`Layout`] is a [`Scene`] of static elements plus text regions whose content is a
+//! [minijinja](https://docs.rs/minijinja) template. Rendering a layout with a JSON data blob
+//! draws the static elements and then the rendered text of each region, aligned within and
+//! clipped to the region:
+//!
+//! ```toml
+//! background = "white"
+//!
+//! [[elements]]
+//! type = "rectangle"
+//! top_left = [0, 0]
+//! bottom_right = [399, 29]
+//! color = "black"
+//!
+//! [[regions]]
+//! area = { x = 0, y = 40, width = 400, height = 40 }
+//! template = "{{ temperature }}°C"
+//! scale = 4
+//! align = "center"
+//! ```
+
+use crate::{
+    core::{colors::Color, geometry::Rect, text::Text},
+    error::{InkyError, Result},
+    inky::{Canvas, Coordinates, Drawable, Rectangle},
+    scene::{Element, Scene},
+    widgets::table::Alignment,
+};
+
+use minijinja::Environment;
+use serde::Deserialize;
+
+/// Static elements plus templated text regions
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Layout {
+    /// Color the canvas is filled with before drawing, or `None` to draw over what is there
+    #[serde(default)]
+    pub background: Option<Color>,
+    /// Elements drawn before the text regions
+    #[serde(default)]
+    pub elements: Vec<Element>,
+    #[serde(default)]
+    pub regions: Vec<Region>,
+}
+
+/// An area of the screen filled with the text rendered from a template
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Region {
+    pub area: Rect,
+    /// A minijinja template rendered against the layout data
+    pub template: String,
+    #[serde(default = "default_scale")]
+    pub scale: usize,
+    #[serde(default = "default_color")]
+    pub color: Color,
+    /// Horizontal alignment of each line within the area
+    #[serde(default)]
+    pub align: Alignment,
+    /// Color the area is filled with before the text is drawn, so a region can be redrawn over
+    /// its old value
+    #[serde(default)]
+    pub fill: Option<Color>,
+}
+
+fn default_scale() -> usize {
+    1
+}
+
+fn default_color() -> Color {
+    Color::Black
+}
+
+impl Layout {
+    /// Parse a layout from JSON
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json)
+            .map_err(|e| InkyError::InvalidArgument(format!("Invalid layout: {}", e)))
+    }
+
+    /// Parse a layout from TOML
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml)
+            .map_err(|e| InkyError::InvalidArgument(format!("Invalid layout: {}", e)))
+    }
+
+    /// Draw the layout onto the canvas, rendering each region's template against `data`
+    pub fn render(&self, canvas: &mut Canvas, data: &serde_json::Value) -> Result<()> {
+        Scene {
+            background: self.background,
+            elements: self.elements.clone(),
+        }
+        .render(canvas)?;
+        let env = Environment::new();
+        for region in &self.regions {
+            let text = env
+                .render_str(&region.template, data)
+                .map_err(|e| InkyError::InvalidArgument(format!("Invalid template: {}", e)))?;
+            region.draw(canvas, &text);
+        }
+        Ok(())
+    }
+}
+
+impl Region {
+    // Draw already rendered text into the region
+    fn draw(&self, canvas: &mut Canvas, text: &str) {
+        if let Some(fill) = &self.fill {
+            if !self.area.is_empty() {
+                let bottom_right = (self.area.right() - 1, self.area.bottom() - 1);
+                canvas.draw(
+                    Rectangle::new((self.area.x, self.area.y), bottom_right),
+                    fill,
+                );
+            }
+        }
+        let mut y = self.area.y;
+        for line in text.lines() {
+            let (width, height) = Text::new((0, 0), line).with_scale(self.scale).size();
+            let x = self.area.x
+                + match self.align {
+                    Alignment::Left => 0,
+                    Alignment::Center => self.area.width.saturating_sub(width) / 2,
+                    Alignment::Right => self.area.width.saturating_sub(width),
+                };
+            canvas.draw(
+                Clipped {
+                    drawable: Text::new((x, y), line).with_scale(self.scale),
+                    area: self.area,
+                },
+                &self.color,
+            );
+            y += height;
+        }
+    }
+}
+
+// A drawable with everything outside of `area` left out
+struct Clipped<D> {
+    drawable: D,
+    area: Rect,
+}
+
+impl<D: Drawable> Drawable for Clipped<D> {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.drawable
+                .coordinates()
+                .filter(|&(x, y)| self.area.contains(x, y)),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.drawable
+            .bounds()
+            .intersection(&self.area)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+    use crate::{core::colors::Color, inky::Canvas};
+
+    #[test]
+    fn render_regions() {
+        let layout = Layout::from_toml(
+            r#"
+            [[regions]]
+            area = { x = 0, y = 0, width = 40, height = 8 }
+            template = "{{ value }}"
+            align = "right"
+
+            [[regions]]
+            area = { x = 0, y = 8, width = 8, height = 8 }
+            template = "{{ missing | default('WW') }}"
+            fill = "red"
+            "#,
+        )
+        .unwrap();
+        let mut canvas = Canvas::new(40, 16);
+        layout
+            .render(&mut canvas, &serde_json::json!({"value": "I"}))
+            .unwrap();
+        // A right aligned I in the last 8 pixels of the first row
+        let ink = |xs: std::ops::Range<usize>, ys: std::ops::Range<usize>, color| {
+            ys.clone()
+                .any(|y| xs.clone().any(|x| canvas.get_pixel(y, x) == color))
+        };
+        assert!(ink(32..40, 0..8, Color::Black));
+        assert!(!ink(0..32, 0..8, Color::Black));
+        // The second region is filled and its text clipped to the area
+        assert!(ink(0..8, 8..16, Color::Red) && ink(0..8, 8..16, Color::Black));
+        assert!(!ink(8..40, 8..16, Color::Black) && !ink(8..40, 8..16, Color::Red));
+
+        assert!(Layout::from_json(r#"{"regions": [{"template": "x"}]}"#).is_err());
+    }
+}
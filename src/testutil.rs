@@ -0,0 +1,36 @@
+//! Helpers for tests
+//!
+//! With the `testutil` feature this holds the golden image comparisons for drawing code, see
+//! `golden`. The crate's own tests of the display and the modes built on it share
+//! [`mock_inky`], which needs no hardware.
+
+#[cfg(feature = "testutil")]
+pub mod golden;
+#[cfg(feature = "testutil")]
+pub use golden::*;
+
+#[cfg(feature = "hardware")]
+use crate::{
+    eeprom::{ColorMode, DisplaySpec, DisplayVariant, EEPROM},
+    hardware::mock::MockConnection,
+    inky::Inky,
+};
+
+/// The EEPROM of an 800x480 Spectra 6 panel, for wrapping a [`MockConnection`] before building
+/// the display, e.g. in a [`RecordingConnection`](crate::hardware::transcript::RecordingConnection)
+#[cfg(feature = "hardware")]
+pub fn mock_eeprom() -> EEPROM {
+    EEPROM::from(DisplaySpec {
+        width: 800,
+        height: 480,
+        variant: DisplayVariant::E673,
+        color: ColorMode::Spectra6,
+    })
+}
+
+/// An 800x480 Spectra 6 display on a [`MockConnection`], which accepts every packet
+#[cfg(feature = "hardware")]
+pub fn mock_inky() -> Inky {
+    Inky::with_connection(Box::new(MockConnection::new(mock_eeprom())))
+        .expect("Failed to set up the mock display")
+}
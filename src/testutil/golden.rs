@@ -0,0 +1,204 @@
+//! Golden image regression tests for drawing code
+//!
+//! A test renders a canvas and checks it with [`assert_golden`] against a PNG checked in next to
+//! the test. When the rendering changes the test fails with a report of the pixels that differ,
+//! and writes an image of them beside the golden file with a `.diff.png` extension. Run the tests
+//! with `INKY_UPDATE_GOLDEN` set to accept an intended change by rewriting the golden files.
+
+use crate::{
+    core::{colors::Color, geometry::Rect},
+    error::{InkyError, Result},
+    inky::Canvas,
+};
+
+use image::{Rgb, RgbImage};
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Number of differing pixels listed in a report
+const REPORT_LIMIT: usize = 10;
+
+/// Render a `width` x `height` canvas with `draw`
+pub fn render(width: usize, height: usize, draw: impl FnOnce(&mut Canvas)) -> Canvas {
+    let mut canvas = Canvas::new(width, height);
+    draw(&mut canvas);
+    canvas
+}
+
+/// Load a golden PNG as a canvas, mapping every pixel to the nearest ink colour
+pub fn load_golden(path: impl AsRef<Path>) -> Result<Canvas> {
+    let image = image::open(path)?.to_rgb8();
+    let mut canvas = Canvas::new(image.width() as usize, image.height() as usize);
+    for (x, y, Rgb([r, g, b])) in image.enumerate_pixels() {
+        canvas.set_pixel(x as usize, y as usize, &Color::from_rgb(*r, *g, *b));
+    }
+    Ok(canvas)
+}
+
+/// A pixel that differs from the golden image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelMismatch {
+    pub x: usize,
+    pub y: usize,
+    pub expected: Color,
+    pub actual: Color,
+}
+
+/// The pixels that differ between two canvases of the same size
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PixelDiff {
+    pub width: usize,
+    pub height: usize,
+    pub mismatches: Vec<PixelMismatch>,
+}
+
+impl PixelDiff {
+    /// Compare `actual` against `expected` pixel by pixel, failing if their sizes differ
+    pub fn new(actual: &Canvas, expected: &Canvas) -> Result<Self> {
+        if (actual.width(), actual.height()) != (expected.width(), expected.height()) {
+            return Err(InkyError::InvalidArgument(format!(
+                "Canvas is {}x{} but the golden image is {}x{}",
+                actual.width(),
+                actual.height(),
+                expected.width(),
+                expected.height()
+            )));
+        }
+        let mismatches = actual
+            .pixels()
+            .zip(expected.pixels())
+            .filter(|((_, _, actual), (_, _, expected))| actual != expected)
+            .map(|((x, y, actual), (_, _, expected))| PixelMismatch {
+                x,
+                y,
+                expected,
+                actual,
+            })
+            .collect();
+        Ok(Self {
+            width: actual.width(),
+            height: actual.height(),
+            mismatches,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// The smallest rectangle containing every differing pixel
+    pub fn bounds(&self) -> Option<Rect> {
+        self.mismatches
+            .iter()
+            .map(|m| Rect::new(m.x, m.y, 1, 1))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// An image of the differences: matching pixels are faded out and differing pixels are shown
+    /// in magenta, which no panel can display
+    pub fn to_image(&self, actual: &Canvas) -> RgbImage {
+        let mut image = RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let (r, g, b) = actual
+                .get(x as usize, y as usize)
+                .unwrap_or(Color::White)
+                .to_rgb();
+            let fade = |c: u8| 191 + c / 4;
+            Rgb([fade(r), fade(g), fade(b)])
+        });
+        for m in &self.mismatches {
+            image.put_pixel(m.x as u32, m.y as u32, Rgb([255, 0, 255]));
+        }
+        image
+    }
+}
+
+impl fmt::Display for PixelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} pixels differ",
+            self.mismatches.len(),
+            self.width * self.height
+        )?;
+        if let Some(bounds) = self.bounds() {
+            write!(f, " within {:?}", bounds)?;
+        }
+        for m in self.mismatches.iter().take(REPORT_LIMIT) {
+            write!(
+                f,
+                "\n  ({}, {}): expected {:?}, found {:?}",
+                m.x, m.y, m.expected, m.actual
+            )?;
+        }
+        if self.mismatches.len() > REPORT_LIMIT {
+            write!(f, "\n  ...")?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_path(path: &Path) -> PathBuf {
+    path.with_extension("diff.png")
+}
+
+/// Panic with a pixel diff report if the canvas does not match the golden PNG at `path`, saving
+/// an image of the differences beside it. Setting `INKY_UPDATE_GOLDEN` saves the canvas there
+/// instead, to accept an intended change
+pub fn assert_golden(canvas: &Canvas, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    if std::env::var_os("INKY_UPDATE_GOLDEN").is_some() {
+        canvas.save_png(path).expect("Failed to save the golden image");
+        return;
+    }
+    let expected =
+        load_golden(path).unwrap_or_else(|e| panic!("Failed to load {}: {}", path.display(), e));
+    let diff = PixelDiff::new(canvas, &expected)
+        .unwrap_or_else(|e| panic!("{} does not match: {}", path.display(), e));
+    if diff.is_empty() {
+        let _ = std::fs::remove_file(diff_path(path));
+        return;
+    }
+    let _ = diff.to_image(canvas).save(diff_path(path));
+    panic!("{} does not match: {}", path.display(), diff);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_golden, load_golden, render, PixelDiff};
+    use crate::{
+        core::{colors::Color, geometry::Rect},
+        inky::{Line, Rectangle},
+    };
+
+    #[test]
+    fn golden_round_trip() {
+        let canvas = render(16, 8, |canvas| {
+            canvas.draw(Line::new((0, 0), (15, 7)), &Color::Black);
+            canvas.draw(Rectangle::new((2, 5), (4, 6)), &Color::Red);
+        });
+        let path = std::env::temp_dir().join(format!("inky-golden-{}.png", std::process::id()));
+        canvas.save_png(&path).unwrap();
+        let golden = load_golden(&path).unwrap();
+        assert!(PixelDiff::new(&canvas, &golden).unwrap().is_empty());
+        assert_golden(&canvas, &path);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn diff_report() {
+        let expected = render(8, 8, |_| {});
+        let actual = render(8, 8, |canvas| {
+            canvas.draw(Rectangle::new((2, 3), (3, 3)), &Color::Black)
+        });
+        let diff = PixelDiff::new(&actual, &expected).unwrap();
+        assert_eq!(diff.mismatches.len(), 2);
+        assert_eq!(diff.bounds(), Some(Rect::new(2, 3, 2, 1)));
+        assert!(diff.to_string().starts_with("2 of 64 pixels differ within"));
+        assert_eq!(diff.to_image(&actual).get_pixel(2, 3).0, [255, 0, 255]);
+
+        assert!(PixelDiff::new(&actual, &render(8, 4, |_| {})).is_err());
+    }
+}
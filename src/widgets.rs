@@ -0,0 +1,16 @@
+//! Ready-made drawables for dashboard style layouts
+
+pub mod barcode;
+#[cfg(feature = "text")]
+pub mod calendar;
+pub mod chart;
+#[cfg(feature = "text")]
+pub mod clock;
+#[cfg(feature = "text")]
+pub mod markdown;
+pub mod seven_segment;
+pub mod status;
+#[cfg(feature = "text")]
+pub mod table;
+#[cfg(feature = "weather-icons")]
+pub mod weather;
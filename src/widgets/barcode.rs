@@ -0,0 +1,298 @@
+//! Code 128 and EAN-13 barcodes for shelf labels and inventory tags
+//!
+//! A barcode is a row of modules, each `module_width` pixels wide, that are either bars or
+//! spaces. The quiet zone either side of the bars is part of the barcode's size and bounds but is
+//! not drawn, so draw a white rectangle over [`Drawable::bounds`] first when the background isn't
+//! already white.
+
+use crate::{
+    core::geometry::Rect,
+    error::{InkyError, Result},
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+// Bar and space widths of every Code 128 symbol, starting with a bar. Symbols 103 to 105 are the
+// start codes for code sets A, B and C
+const CODE128: [&str; 106] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232",
+];
+const CODE128_STOP: &str = "2331112";
+const CODE128_START_B: usize = 104;
+const CODE128_START_C: usize = 105;
+/// Modules of white space either side of a Code 128 barcode
+const CODE128_QUIET_ZONE: usize = 10;
+
+// EAN-13 digits in the left hand odd parity set. The even parity set is these reversed and
+// inverted, and the right hand set is these inverted
+const EAN_L: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011,
+    0b0110111, 0b0001011,
+];
+// Which of the six left hand digits use even parity, from the leftmost, encoding the first digit
+const EAN_PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110,
+    0b011010,
+];
+/// Modules of white space left and right of an EAN-13 barcode
+const EAN_QUIET_ZONE: (usize, usize) = (11, 7);
+
+/// A Code 128 or EAN-13 barcode with its top left corner, including the quiet zone, at
+/// `position`
+pub struct Barcode {
+    position: (usize, usize),
+    // Whether each module is a bar, not including the quiet zones
+    modules: Vec<bool>,
+    quiet_zone: (usize, usize),
+    module_width: usize,
+    height: usize,
+}
+
+impl Barcode {
+    /// Encode printable ASCII as Code 128. Strings of an even number of digits use the denser
+    /// code set C
+    pub fn code128(position: (usize, usize), content: &str, height: usize) -> Result<Self> {
+        if content.is_empty() || !content.chars().all(|c| (' '..='~').contains(&c)) {
+            return Err(InkyError::InvalidArgument(format!(
+                "Code 128 barcodes need printable ASCII, got {:?}",
+                content
+            )));
+        }
+        let bytes = content.as_bytes();
+        let mut symbols = if bytes.len().is_multiple_of(2) && bytes.iter().all(u8::is_ascii_digit) {
+            let mut symbols = vec![CODE128_START_C];
+            symbols.extend(
+                bytes
+                    .chunks(2)
+                    .map(|pair| ((pair[0] - b'0') * 10 + pair[1] - b'0') as usize),
+            );
+            symbols
+        } else {
+            let mut symbols = vec![CODE128_START_B];
+            symbols.extend(bytes.iter().map(|b| (b - b' ') as usize));
+            symbols
+        };
+        let checksum = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| i.max(1) * symbol)
+            .sum::<usize>()
+            % 103;
+        symbols.push(checksum);
+
+        let mut modules = Vec::new();
+        for symbol in symbols {
+            push_widths(&mut modules, CODE128[symbol]);
+        }
+        push_widths(&mut modules, CODE128_STOP);
+        Ok(Self::from_modules(
+            position,
+            modules,
+            (CODE128_QUIET_ZONE, CODE128_QUIET_ZONE),
+            height,
+        ))
+    }
+
+    /// Encode an EAN-13 number from 12 digits, or 13 digits including a check digit which must
+    /// be correct
+    pub fn ean13(position: (usize, usize), digits: &str, height: usize) -> Result<Self> {
+        let mut values: Vec<u8> = digits
+            .chars()
+            .map(|c| c.to_digit(10).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .filter(|values: &Vec<u8>| values.len() == 12 || values.len() == 13)
+            .ok_or_else(|| {
+                InkyError::InvalidArgument(format!(
+                    "EAN-13 needs 12 or 13 digits, got {:?}",
+                    digits
+                ))
+            })?;
+        let check = ean_check_digit(&values[..12]);
+        match values.get(12) {
+            Some(&given) if given != check => {
+                return Err(InkyError::InvalidArgument(format!(
+                    "EAN-13 check digit of {} should be {}",
+                    digits, check
+                )));
+            }
+            Some(_) => {}
+            None => values.push(check),
+        }
+
+        let mut modules = Vec::with_capacity(95);
+        let push_bits = |modules: &mut Vec<bool>, bits: u8| {
+            modules.extend((0..7).rev().map(|bit| bits & (1 << bit) != 0));
+        };
+        modules.extend([true, false, true]);
+        let parity = EAN_PARITY[values[0] as usize];
+        for (i, &digit) in values[1..7].iter().enumerate() {
+            let odd = EAN_L[digit as usize];
+            let bits = if parity & (1 << (5 - i)) != 0 {
+                // Even parity: the inverted pattern read backwards
+                (!odd & 0x7f).reverse_bits() >> 1
+            } else {
+                odd
+            };
+            push_bits(&mut modules, bits);
+        }
+        modules.extend([false, true, false, true, false]);
+        for &digit in &values[7..] {
+            push_bits(&mut modules, !EAN_L[digit as usize] & 0x7f);
+        }
+        modules.extend([true, false, true]);
+        Ok(Self::from_modules(
+            position,
+            modules,
+            EAN_QUIET_ZONE,
+            height,
+        ))
+    }
+
+    fn from_modules(
+        position: (usize, usize),
+        modules: Vec<bool>,
+        quiet_zone: (usize, usize),
+        height: usize,
+    ) -> Self {
+        Self {
+            position,
+            modules,
+            quiet_zone,
+            module_width: 1,
+            height,
+        }
+    }
+
+    /// Set the width of the narrowest bar in pixels, which defaults to 1. Scanners read wider
+    /// modules more reliably
+    pub fn with_module_width(mut self, module_width: usize) -> Self {
+        self.module_width = module_width.max(1);
+        self
+    }
+
+    /// Width and height of the barcode, including its quiet zones, in pixels
+    pub fn size(&self) -> (usize, usize) {
+        let modules = self.quiet_zone.0 + self.modules.len() + self.quiet_zone.1;
+        (modules * self.module_width, self.height)
+    }
+
+    // One rectangle for each run of bar modules
+    fn bars(&self) -> Vec<Rectangle> {
+        let (x0, y) = self.position;
+        let left = x0 + self.quiet_zone.0 * self.module_width;
+        let mut bars = Vec::new();
+        let mut start = None;
+        for (i, bar) in self.modules.iter().chain([&false]).enumerate() {
+            match (*bar, start) {
+                (true, None) => start = Some(i),
+                (false, Some(first)) => {
+                    bars.push(Rectangle::new(
+                        (left + first * self.module_width, y),
+                        (left + i * self.module_width - 1, y + self.height - 1),
+                    ));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        bars
+    }
+}
+
+// Append alternating bar and space modules for a string of element widths, starting with a bar
+fn push_widths(modules: &mut Vec<bool>, widths: &str) {
+    for (i, width) in widths.bytes().enumerate() {
+        let bar = i % 2 == 0;
+        modules.resize(modules.len() + (width - b'0') as usize, bar);
+    }
+}
+
+// The EAN check digit for 12 digits, which weights digits alternately by 1 and 3
+fn ean_check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, &d)| d as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+impl Drawable for Barcode {
+    fn coordinates(&self) -> Coordinates<'_> {
+        if self.height == 0 {
+            return Box::new(std::iter::empty());
+        }
+        Box::new(
+            self.bars()
+                .into_iter()
+                .flat_map(Rectangle::rectangle_coordinates),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        let (width, height) = self.size();
+        Rect::new(self.position.0, self.position.1, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Barcode, CODE128, CODE128_STOP};
+    use crate::core::colors::Color;
+    use crate::inky::Canvas;
+
+    #[test]
+    fn symbol_widths() {
+        assert!(CODE128
+            .iter()
+            .all(|s| s.bytes().map(|b| (b - b'0') as usize).sum::<usize>() == 11));
+        assert_eq!(
+            CODE128_STOP
+                .bytes()
+                .map(|b| (b - b'0') as usize)
+                .sum::<usize>(),
+            13
+        );
+    }
+
+    #[test]
+    fn code128() {
+        // Start, three characters, checksum and stop, between 10 module quiet zones
+        let barcode = Barcode::code128((0, 0), "Pi!", 20).unwrap();
+        assert_eq!(barcode.size(), (20 + 5 * 11 + 13, 20));
+        // Even length digit strings pack two digits per symbol
+        let digits = Barcode::code128((0, 0), "123456", 20).unwrap();
+        assert_eq!(digits.size().0, 20 + 5 * 11 + 13);
+        assert!(Barcode::code128((0, 0), "", 20).is_err());
+        assert!(Barcode::code128((0, 0), "café", 20).is_err());
+    }
+
+    #[test]
+    fn ean13() {
+        let barcode = Barcode::ean13((0, 0), "400638133393", 30).unwrap();
+        assert_eq!(barcode.size(), (11 + 95 + 7, 30));
+        assert!(Barcode::ean13((0, 0), "4006381333931", 30).is_ok());
+        assert!(Barcode::ean13((0, 0), "4006381333932", 30).is_err());
+        assert!(Barcode::ean13((0, 0), "40063813", 30).is_err());
+
+        // Guard bars start after the quiet zone, scaled by the module width
+        let barcode = barcode.with_module_width(2);
+        let mut canvas = Canvas::new(230, 30);
+        canvas.draw(barcode, &Color::Black);
+        assert_eq!(canvas.get_pixel(10, 21), Color::White);
+        assert_eq!(canvas.get_pixel(10, 22), Color::Black);
+        assert_eq!(canvas.get_pixel(10, 23), Color::Black);
+        assert_eq!(canvas.get_pixel(10, 24), Color::White);
+        assert_eq!(canvas.get_pixel(10, 26), Color::Black);
+    }
+}
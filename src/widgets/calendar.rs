@@ -0,0 +1,189 @@
+//! A month of days laid out as a calendar grid
+//!
+//! The area is split into seven columns and seven rows: a header of weekday names and up to six
+//! weeks. Today is drawn as a filled cell with its number knocked out, and marked days get a dot
+//! under their number.
+
+use crate::{
+    core::{geometry::Rect, text::Text},
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::HashSet;
+
+pub struct CalendarMonth {
+    area: Rect,
+    first: NaiveDate,
+    first_weekday: Weekday,
+    today: Option<NaiveDate>,
+    markers: HashSet<u32>,
+    scale: usize,
+}
+
+impl CalendarMonth {
+    /// Create a calendar for the month containing `date`, with weeks starting on Monday
+    pub fn new(area: Rect, date: NaiveDate) -> Self {
+        Self {
+            area,
+            first: date.with_day(1).unwrap_or(date),
+            first_weekday: Weekday::Mon,
+            today: None,
+            markers: HashSet::new(),
+            scale: 1,
+        }
+    }
+
+    /// Start each week on a different day
+    pub fn with_first_weekday(mut self, weekday: Weekday) -> Self {
+        self.first_weekday = weekday;
+        self
+    }
+
+    /// Highlight a day. Dates outside of the month are ignored
+    pub fn with_today(mut self, today: NaiveDate) -> Self {
+        self.today = Some(today);
+        self
+    }
+
+    /// Mark days of the month, such as the days with events
+    pub fn with_markers(mut self, days: impl IntoIterator<Item = u32>) -> Self {
+        self.markers.extend(days);
+        self
+    }
+
+    /// Scale the day numbers and weekday names
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// The number of days in the month
+    pub fn days(&self) -> u32 {
+        let next = if self.first.month() == 12 {
+            NaiveDate::from_ymd_opt(self.first.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(self.first.year(), self.first.month() + 1, 1)
+        };
+        next.map_or(31, |next| {
+            next.signed_duration_since(self.first).num_days() as u32
+        })
+    }
+
+    // The grid cell of a row and column, where row 0 is the weekday header
+    fn cell(&self, row: usize, column: usize) -> Rect {
+        let (width, height) = (self.area.width / 7, self.area.height / 7);
+        Rect::new(
+            self.area.x + column * width,
+            self.area.y + row * height,
+            width,
+            height,
+        )
+    }
+
+    // The row and column of a day of the month
+    fn position(&self, day: u32) -> (usize, usize) {
+        let offset = (self.first.weekday().num_days_from_monday() + 7
+            - self.first_weekday.num_days_from_monday())
+            % 7;
+        let index = (offset + day - 1) as usize;
+        (1 + index / 7, index % 7)
+    }
+
+    fn header(&self) -> Vec<(usize, usize)> {
+        let mut weekday = self.first_weekday;
+        let mut coordinates = Vec::new();
+        for column in 0..7 {
+            let name: String = weekday.to_string().chars().take(2).collect();
+            let text = Text::centered(&name, self.scale, self.cell(0, column));
+            coordinates.extend(text.coordinates());
+            weekday = weekday.succ();
+        }
+        coordinates
+    }
+
+    fn day(&self, day: u32) -> Vec<(usize, usize)> {
+        let (row, column) = self.position(day);
+        let cell = self.cell(row, column);
+        if cell.is_empty() {
+            return Vec::new();
+        }
+        let text = Text::centered(&day.to_string(), self.scale, cell);
+        let mut coordinates: Vec<(usize, usize)> = text.coordinates().collect();
+
+        if self.markers.contains(&day) {
+            let size = 2 * self.scale;
+            let x = cell.x + cell.width.saturating_sub(size) / 2;
+            let bottom = cell.bottom().saturating_sub(self.scale + 1);
+            let top = bottom.saturating_sub(size - 1).max(cell.y);
+            coordinates
+                .extend(Rectangle::new((x, top), (x + size - 1, bottom)).rectangle_coordinates());
+        }
+
+        if self.today == self.first.with_day(day) {
+            let number: HashSet<(usize, usize)> = coordinates.into_iter().collect();
+            return Rectangle::new((cell.x, cell.y), (cell.right() - 1, cell.bottom() - 1))
+                .rectangle_coordinates()
+                .filter(|point| !number.contains(point))
+                .collect();
+        }
+        coordinates
+    }
+}
+
+impl Drawable for CalendarMonth {
+    fn coordinates(&self) -> Coordinates<'_> {
+        let mut coordinates = self.header();
+        for day in 1..=self.days() {
+            coordinates.extend(self.day(day));
+        }
+        Box::new(
+            coordinates
+                .into_iter()
+                .filter(|&(x, y)| self.area.contains(x, y)),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.area
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CalendarMonth;
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::Canvas;
+    use chrono::{Datelike, NaiveDate, Weekday};
+
+    #[test]
+    fn grid_positions() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 14).unwrap();
+        let area = Rect::new(0, 0, 140, 140);
+        // The 1st of February 2024 was a Thursday
+        let calendar = CalendarMonth::new(area, date);
+        assert_eq!(calendar.days(), 29);
+        assert_eq!(calendar.position(1), (1, 3));
+        assert_eq!(calendar.position(29), (5, 3));
+
+        let calendar = CalendarMonth::new(area, date).with_first_weekday(Weekday::Sun);
+        assert_eq!(calendar.position(1), (1, 4));
+        assert_eq!(
+            CalendarMonth::new(area, date.with_month(12).unwrap()).days(),
+            31
+        );
+    }
+
+    #[test]
+    fn today_is_filled() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+        let mut canvas = Canvas::new(140, 140);
+        canvas.draw(
+            CalendarMonth::new(Rect::new(0, 0, 140, 140), date).with_today(date),
+            &Color::Black,
+        );
+        // The corner of the cell for the 1st, in the fourth column of the first week
+        assert_eq!(canvas.get_pixel(20, 60), Color::Black);
+        assert_eq!(canvas.get_pixel(20, 80), Color::White);
+    }
+}
@@ -0,0 +1,294 @@
+//! Bar charts and line graphs of a single data series
+//!
+//! Charts fill an area of the canvas with a y axis down the left edge and an x axis along the
+//! bottom. Values are plotted against a range that is taken from the data unless it is set with
+//! `with_range`, and optional tick marks divide the y axis into equal steps.
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Coordinates, Drawable, Line, Rectangle},
+};
+
+/// Length of the tick marks to the left of the y axis
+const TICK_LENGTH: usize = 3;
+
+// The axes shared by every chart, and the mapping from values to rows
+struct Axes {
+    area: Rect,
+    range: (f32, f32),
+    ticks: usize,
+}
+
+impl Axes {
+    fn new(area: Rect, values: &[f32], range: Option<(f32, f32)>, ticks: usize) -> Self {
+        let range = range.unwrap_or_else(|| {
+            let values = values.iter().copied().filter(|v| v.is_finite());
+            // Charts read most naturally from zero unless the data goes below it
+            values.fold((0.0_f32, 0.0_f32), |(min, max), v| (min.min(v), max.max(v)))
+        });
+        Self { area, range, ticks }
+    }
+
+    // The region inside the axes that data is drawn in
+    fn plot(&self) -> Rect {
+        let margin = if self.ticks > 0 { TICK_LENGTH } else { 0 };
+        let left = self.area.x + margin + 1;
+        Rect::new(
+            left,
+            self.area.y,
+            self.area.right().saturating_sub(left),
+            self.area.height.saturating_sub(1),
+        )
+    }
+
+    // The row a value is drawn at, clamped to the plot
+    fn row(&self, value: f32) -> usize {
+        let plot = self.plot();
+        let (min, max) = self.range;
+        let fraction = if max > min {
+            ((value - min) / (max - min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let offset = (fraction * plot.height.saturating_sub(1) as f32).round() as usize;
+        (plot.bottom().saturating_sub(1)).saturating_sub(offset)
+    }
+
+    fn parts(&self) -> Vec<Rectangle> {
+        let plot = self.plot();
+        if self.area.is_empty() || plot.x > self.area.right() {
+            return Vec::new();
+        }
+        let axis_x = plot.x - 1;
+        let axis_y = self.area.bottom() - 1;
+        let mut parts = vec![
+            Rectangle::new((axis_x, self.area.y), (axis_x, axis_y)),
+            Rectangle::new((axis_x, axis_y), (self.area.right() - 1, axis_y)),
+        ];
+        if self.ticks > 0 {
+            let (min, max) = self.range;
+            for tick in 0..=self.ticks {
+                let y = self.row(min + (max - min) * tick as f32 / self.ticks as f32);
+                parts.push(Rectangle::new((self.area.x, y), (axis_x - 1, y)));
+            }
+        }
+        parts
+    }
+}
+
+/// Vertical bars, one per value, growing up from zero (or down for negative values)
+pub struct BarChart {
+    area: Rect,
+    values: Vec<f32>,
+    range: Option<(f32, f32)>,
+    ticks: usize,
+    gap: usize,
+}
+
+impl BarChart {
+    pub fn new(area: Rect, values: impl Into<Vec<f32>>) -> Self {
+        Self {
+            area,
+            values: values.into(),
+            range: None,
+            ticks: 0,
+            gap: 1,
+        }
+    }
+
+    /// Plot values between `min` and `max` instead of fitting the range to the data
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Divide the y axis into `ticks` equal steps
+    pub fn with_ticks(mut self, ticks: usize) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    /// Set the space between bars in pixels
+    pub fn with_gap(mut self, gap: usize) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    fn parts(&self) -> Vec<Rectangle> {
+        let axes = Axes::new(self.area, &self.values, self.range, self.ticks);
+        let mut parts = axes.parts();
+        let plot = axes.plot();
+        let count = self.values.len();
+        if count == 0 || plot.is_empty() {
+            return parts;
+        }
+        let gaps = self.gap * (count - 1);
+        let bar_width = (plot.width.saturating_sub(gaps) / count).max(1);
+        let baseline = axes.row(0.0_f32.clamp(axes.range.0, axes.range.1));
+        for (i, value) in self.values.iter().enumerate() {
+            let left = plot.x + i * (bar_width + self.gap);
+            if !value.is_finite() || left >= plot.right() {
+                continue;
+            }
+            let right = (left + bar_width - 1).min(plot.right() - 1);
+            let top = axes.row(*value);
+            parts.push(Rectangle::new(
+                (left, top.min(baseline)),
+                (right, top.max(baseline)),
+            ));
+        }
+        parts
+    }
+}
+
+impl Drawable for BarChart {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.parts()
+                .into_iter()
+                .flat_map(Rectangle::rectangle_coordinates),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.area
+    }
+}
+
+/// A line joining consecutive values, spread evenly across the chart
+pub struct LineGraph {
+    area: Rect,
+    values: Vec<f32>,
+    range: Option<(f32, f32)>,
+    ticks: usize,
+    width: usize,
+}
+
+impl LineGraph {
+    pub fn new(area: Rect, values: impl Into<Vec<f32>>) -> Self {
+        Self {
+            area,
+            values: values.into(),
+            range: None,
+            ticks: 0,
+            width: 1,
+        }
+    }
+
+    /// Plot values between `min` and `max` instead of fitting the range to the data
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Divide the y axis into `ticks` equal steps
+    pub fn with_ticks(mut self, ticks: usize) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    /// Set the stroke width of the line in pixels
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    fn lines(&self, axes: &Axes) -> Vec<Line> {
+        let plot = axes.plot();
+        if plot.is_empty() {
+            return Vec::new();
+        }
+        let steps = self.values.len().saturating_sub(1).max(1);
+        let points: Vec<(isize, isize)> = self
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| value.is_finite())
+            .map(|(i, value)| {
+                let x = plot.x + i * (plot.width - 1) / steps;
+                (x as isize, axes.row(*value) as isize)
+            })
+            .collect();
+        match points.as_slice() {
+            [point] => vec![Line::new(*point, *point).with_width(self.width)],
+            _ => points
+                .windows(2)
+                .map(|pair| Line::new(pair[0], pair[1]).with_width(self.width))
+                .collect(),
+        }
+    }
+}
+
+impl Drawable for LineGraph {
+    fn coordinates(&self) -> Coordinates<'_> {
+        let axes = Axes::new(self.area, &self.values, self.range, self.ticks);
+        let lines = self.lines(&axes);
+        let area = self.area;
+        Box::new(
+            axes.parts()
+                .into_iter()
+                .flat_map(Rectangle::rectangle_coordinates)
+                .chain(
+                    lines
+                        .into_iter()
+                        .flat_map(|line| line.thick_line_coordinates())
+                        // Wide lines are kept inside the chart
+                        .filter(move |&(x, y)| area.contains(x, y)),
+                ),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.area
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BarChart, LineGraph};
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::Canvas;
+
+    #[test]
+    fn bar_heights() {
+        let mut canvas = Canvas::new(20, 11);
+        let chart = BarChart::new(Rect::new(0, 0, 7, 11), [10.0, 5.0, 0.0]).with_gap(0);
+        canvas.draw(chart, &Color::Black);
+        // Axes
+        assert_eq!(canvas.get_pixel(0, 0), Color::Black);
+        assert_eq!(canvas.get_pixel(10, 6), Color::Black);
+        // The plot is 6 pixels wide and 10 tall, so each bar is 2 pixels wide
+        assert_eq!(canvas.get_pixel(0, 1), Color::Black);
+        assert_eq!(canvas.get_pixel(0, 3), Color::White);
+        assert_eq!(canvas.get_pixel(4, 3), Color::Black);
+        assert_eq!(canvas.get_pixel(3, 3), Color::White);
+        assert_eq!(canvas.get_pixel(9, 5), Color::Black);
+        assert_eq!(canvas.get_pixel(8, 5), Color::White);
+        // Nothing is drawn outside of the chart
+        assert_eq!(canvas.dirty_region(), Some(Rect::new(0, 0, 7, 11)));
+    }
+
+    #[test]
+    fn ticks() {
+        let mut canvas = Canvas::new(20, 11);
+        let chart = BarChart::new(Rect::new(0, 0, 10, 11), []).with_range(0.0, 10.0);
+        canvas.draw(chart.with_ticks(2), &Color::Black);
+        for y in [0, 4, 9] {
+            assert_eq!(canvas.get_pixel(y, 0), Color::Black);
+        }
+        assert_eq!(canvas.get_pixel(3, 0), Color::White);
+        // The y axis sits past the tick marks
+        assert_eq!(canvas.get_pixel(3, 3), Color::Black);
+    }
+
+    #[test]
+    fn line_graph() {
+        let mut canvas = Canvas::new(11, 11);
+        let graph = LineGraph::new(Rect::new(0, 0, 11, 11), [0.0, 10.0, 0.0]);
+        canvas.draw(graph, &Color::Black);
+        assert_eq!(canvas.get_pixel(9, 1), Color::Black);
+        assert_eq!(canvas.get_pixel(0, 5), Color::Black);
+        assert_eq!(canvas.get_pixel(9, 10), Color::Black);
+        assert_eq!(canvas.get_pixel(0, 1), Color::White);
+    }
+}
@@ -0,0 +1,171 @@
+//! An analogue clock face, which pairs well with partial refreshes for an always-on desk clock
+
+use crate::{
+    core::{geometry::Rect, text::Text},
+    inky::{Coordinates, Drawable, Line},
+};
+
+use chrono::Timelike;
+use std::f32::consts::TAU;
+
+/// A round clock face centred on `center`, with a tick for every minute, hour and minute hands
+/// and optionally a second hand and the hour numerals
+pub struct ClockFace {
+    center: (usize, usize),
+    radius: usize,
+    // Hours, minutes and seconds past twelve o'clock
+    time: (u32, u32, u32),
+    second_hand: bool,
+    numerals: bool,
+}
+
+impl ClockFace {
+    /// Create a clock face showing the time of `time`, such as a [`chrono::NaiveTime`] or
+    /// [`chrono::DateTime`]
+    pub fn new<T: Timelike>(center: (usize, usize), radius: usize, time: &T) -> Self {
+        Self {
+            center,
+            radius,
+            time: (time.hour() % 12, time.minute(), time.second()),
+            second_hand: true,
+            numerals: false,
+        }
+    }
+
+    /// Show or hide the second hand, which is shown by default. Hide it when the display is
+    /// updated less than once a second
+    pub fn with_second_hand(mut self, second_hand: bool) -> Self {
+        self.second_hand = second_hand;
+        self
+    }
+
+    /// Show or hide the numerals 1 to 12 inside the hour ticks
+    pub fn with_numerals(mut self, numerals: bool) -> Self {
+        self.numerals = numerals;
+        self
+    }
+
+    // The point `length` pixels from the centre at `turns` of a full turn clockwise from twelve
+    fn point(&self, turns: f32, length: f32) -> (isize, isize) {
+        let angle = turns * TAU;
+        (
+            self.center.0 as isize + (angle.sin() * length).round() as isize,
+            self.center.1 as isize - (angle.cos() * length).round() as isize,
+        )
+    }
+
+    fn hand(&self, turns: f32, length: f32, width: usize) -> Line {
+        Line::new(
+            (self.center.0 as isize, self.center.1 as isize),
+            self.point(turns, length),
+        )
+        .with_width(width)
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let r = self.radius as f32;
+        let width = (self.radius / 20).max(1);
+        let mut lines: Vec<Line> = (0..60)
+            .map(|minute| {
+                let turns = minute as f32 / 60.0;
+                if minute % 5 == 0 {
+                    Line::new(self.point(turns, r * 0.85), self.point(turns, r)).with_width(width)
+                } else {
+                    Line::new(self.point(turns, r * 0.93), self.point(turns, r))
+                }
+            })
+            .collect();
+
+        let (hour, minute, second) = self.time;
+        let minutes = minute as f32 + second as f32 / 60.0;
+        lines.push(self.hand((hour as f32 + minutes / 60.0) / 12.0, r * 0.5, width * 2));
+        lines.push(self.hand(minutes / 60.0, r * 0.75, width + 1));
+        if self.second_hand {
+            lines.push(self.hand(second as f32 / 60.0, r * 0.85, 1));
+        }
+        lines
+    }
+
+    fn numerals(&self) -> Vec<Text<'static>> {
+        if !self.numerals {
+            return Vec::new();
+        }
+        let scale = (self.radius / 80).max(1);
+        (1..=12)
+            .filter_map(|hour| {
+                let (x, y) = self.point(hour as f32 / 12.0, self.radius as f32 * 0.68);
+                let (width, height) = Text::new((0, 0), &hour.to_string())
+                    .with_scale(scale)
+                    .size();
+                let left = x - width as isize / 2;
+                let top = y - height as isize / 2;
+                (left >= 0 && top >= 0).then(|| {
+                    Text::new((left as usize, top as usize), &hour.to_string()).with_scale(scale)
+                })
+            })
+            .collect()
+    }
+
+    // The rim of the face and the hub the hands turn on
+    fn circles(&self) -> Vec<(usize, usize)> {
+        let r = self.radius as isize;
+        let rim = (self.radius / 40).max(1) as isize;
+        let hub = (self.radius / 15).max(2) as isize;
+        let (cx, cy) = (self.center.0 as isize, self.center.1 as isize);
+        (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy)| {
+                let distance = dx * dx + dy * dy;
+                distance <= hub * hub || (distance <= r * r && distance > (r - rim) * (r - rim))
+            })
+            .map(|(dx, dy)| (cx + dx, cy + dy))
+            .filter(|&(x, y)| x >= 0 && y >= 0)
+            .map(|(x, y)| (x as usize, y as usize))
+            .collect()
+    }
+}
+
+impl Drawable for ClockFace {
+    fn coordinates(&self) -> Coordinates<'_> {
+        let mut coordinates = self.circles();
+        for line in self.lines() {
+            coordinates.extend(line.coordinates());
+        }
+        for text in self.numerals() {
+            coordinates.extend(text.coordinates());
+        }
+        Box::new(coordinates.into_iter())
+    }
+
+    fn bounds(&self) -> Rect {
+        let r = self.radius as isize;
+        let (cx, cy) = (self.center.0 as isize, self.center.1 as isize);
+        Rect::from_signed_corners((cx - r, cy - r), (cx + r, cy + r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClockFace;
+    use crate::core::colors::Color;
+    use crate::inky::Canvas;
+    use chrono::NaiveTime;
+
+    #[test]
+    fn hands_point_at_the_time() {
+        let mut canvas = Canvas::new(101, 101);
+        let three = NaiveTime::from_hms_opt(15, 0, 0).unwrap();
+        canvas.draw(
+            ClockFace::new((50, 50), 50, &three).with_second_hand(false),
+            &Color::Black,
+        );
+        // The hour hand points right and the minute hand up
+        assert_eq!(canvas.get_pixel(50, 70), Color::Black);
+        assert_eq!(canvas.get_pixel(20, 50), Color::Black);
+        assert_eq!(canvas.get_pixel(50, 30), Color::White);
+        assert_eq!(canvas.get_pixel(80, 50), Color::White);
+        // The rim
+        assert_eq!(canvas.get_pixel(50, 100), Color::Black);
+        assert_eq!(canvas.get_pixel(0, 0), Color::White);
+    }
+}
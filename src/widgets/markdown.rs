@@ -0,0 +1,345 @@
+//! Markdown documents laid out within an area of the canvas, for notes and feed readers
+//!
+//! Only block structure is rendered: ATX headings (`#` to `######`), paragraphs, bulleted and
+//! numbered lists, and horizontal rules. Paragraphs and list items are word wrapped to the width
+//! of the area. Emphasis markers and backticks are dropped, links are reduced to their text, and
+//! anything that runs past the bottom of the area is clipped.
+
+use crate::{
+    core::{
+        geometry::Rect,
+        text::{BuiltinFont, Font, Text},
+    },
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+/// Width of the bullet or number column of list items, in characters
+const LIST_INDENT: usize = 3;
+
+/// A block of a Markdown document
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Block {
+    Heading {
+        level: usize,
+        text: String,
+    },
+    Paragraph(String),
+    /// A list item with its marker, such as `-` or `2.`
+    Item {
+        marker: String,
+        text: String,
+    },
+    Rule,
+}
+
+// A laid out line of text or a rule
+enum Piece {
+    Text(Text<'static>),
+    Rule(Rectangle),
+}
+
+pub struct Markdown {
+    area: Rect,
+    blocks: Vec<Block>,
+    scale: usize,
+}
+
+impl Markdown {
+    /// Lay out `source` within `area`
+    pub fn new(area: Rect, source: &str) -> Self {
+        Self {
+            area,
+            blocks: parse(source),
+            scale: 1,
+        }
+    }
+
+    /// Scale the body text. First and second level headings are drawn at twice this scale
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    // Lay the blocks out top to bottom, stopping once the area is full
+    fn pieces(&self) -> Vec<Piece> {
+        let line_height = BuiltinFont.line_height() * self.scale;
+        let gap = line_height / 2;
+        let mut y = self.area.y;
+        let mut pieces = Vec::new();
+        for (i, block) in self.blocks.iter().enumerate() {
+            // Blocks are spaced apart, except for consecutive list items
+            match (i.checked_sub(1).map(|j| &self.blocks[j]), block) {
+                (None, _) | (Some(Block::Item { .. }), Block::Item { .. }) => {}
+                _ => y += gap,
+            }
+            if y >= self.area.bottom() {
+                break;
+            }
+            match block {
+                Block::Heading { level, text } => {
+                    let scale = if *level <= 2 {
+                        self.scale * 2
+                    } else {
+                        self.scale
+                    };
+                    for line in wrap(text, self.area.width, scale) {
+                        pieces.push(Piece::Text(
+                            Text::new((self.area.x, y), &line).with_scale(scale),
+                        ));
+                        y += BuiltinFont.line_height() * scale;
+                    }
+                    // Top level and minor headings are underlined
+                    if *level != 2 && !self.area.is_empty() {
+                        pieces.push(Piece::Rule(Rectangle::new(
+                            (self.area.x, y),
+                            (self.area.right() - 1, y + self.scale - 1),
+                        )));
+                        y += self.scale;
+                    }
+                }
+                Block::Paragraph(text) => {
+                    for line in wrap(text, self.area.width, self.scale) {
+                        pieces.push(Piece::Text(
+                            Text::new((self.area.x, y), &line).with_scale(self.scale),
+                        ));
+                        y += line_height;
+                    }
+                }
+                Block::Item { marker, text } => {
+                    let indent =
+                        BuiltinFont.glyph(' ').map_or(0, |g| g.advance) * LIST_INDENT * self.scale;
+                    pieces.push(Piece::Text(
+                        Text::new((self.area.x, y), marker).with_scale(self.scale),
+                    ));
+                    let width = self.area.width.saturating_sub(indent);
+                    for line in wrap(text, width, self.scale) {
+                        pieces.push(Piece::Text(
+                            Text::new((self.area.x + indent, y), &line).with_scale(self.scale),
+                        ));
+                        y += line_height;
+                    }
+                }
+                Block::Rule => {
+                    if !self.area.is_empty() {
+                        let middle = y + gap / 2;
+                        pieces.push(Piece::Rule(Rectangle::new(
+                            (self.area.x, middle),
+                            (self.area.right() - 1, middle + self.scale - 1),
+                        )));
+                    }
+                    y += gap;
+                }
+            }
+        }
+        pieces
+    }
+}
+
+impl Drawable for Markdown {
+    fn coordinates(&self) -> Coordinates<'_> {
+        let coordinates: Vec<(usize, usize)> = self
+            .pieces()
+            .into_iter()
+            .flat_map(|piece| match piece {
+                Piece::Text(text) => text.coordinates().collect::<Vec<_>>(),
+                Piece::Rule(rule) => rule.rectangle_coordinates().collect(),
+            })
+            .filter(|&(x, y)| self.area.contains(x, y))
+            .collect();
+        Box::new(coordinates.into_iter())
+    }
+
+    fn bounds(&self) -> Rect {
+        self.area
+    }
+}
+
+// Split a document into blocks. Lines indented under a list item continue it
+fn parse(source: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    // The paragraph or list item that following lines are appended to
+    let mut open = false;
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            open = false;
+            continue;
+        }
+        if is_rule(trimmed) {
+            blocks.push(Block::Rule);
+            open = false;
+        } else if let Some((level, text)) = heading(trimmed) {
+            blocks.push(Block::Heading {
+                level,
+                text: inline(text),
+            });
+            open = false;
+        } else if let Some((marker, text)) = list_item(trimmed) {
+            blocks.push(Block::Item {
+                marker,
+                text: inline(text),
+            });
+            open = true;
+        } else if open {
+            if let Some(Block::Paragraph(text) | Block::Item { text, .. }) = blocks.last_mut() {
+                text.push(' ');
+                text.push_str(&inline(trimmed));
+            }
+        } else {
+            blocks.push(Block::Paragraph(inline(trimmed)));
+            open = true;
+        }
+    }
+    blocks
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| marks.chars().all(|c| c == *mark))
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_end_matches('#').trim_end()))
+    } else {
+        None
+    }
+}
+
+fn list_item(line: &str) -> Option<(String, &str)> {
+    if let Some(text) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| line.strip_prefix(bullet))
+    {
+        return Some(("-".to_string(), text.trim_start()));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        Some((format!("{}.", &line[..digits]), rest[2..].trim_start()))
+    } else {
+        None
+    }
+}
+
+// Drop inline formatting, keeping link text
+fn inline(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        result.push_str(&rest[..start]);
+        let link = &rest[start..];
+        match link.find("](").and_then(|middle| {
+            link[middle..]
+                .find(')')
+                .map(|end| (&link[1..middle], middle + end + 1))
+        }) {
+            Some((label, end)) => {
+                result.push_str(label);
+                rest = &link[end..];
+            }
+            None => {
+                result.push('[');
+                rest = &link[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result.replace("**", "").replace("__", "").replace('`', "")
+}
+
+// Greedily wrap words to lines no wider than `width`, breaking words that don't fit on a line
+// of their own
+fn wrap(text: &str, width: usize, scale: usize) -> Vec<String> {
+    let measure = |s: &str| Text::new((0, 0), s).with_scale(scale).size().0;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if measure(&candidate) <= width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if measure(&line) > width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, wrap, Block, Markdown};
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::Canvas;
+
+    #[test]
+    fn parse_blocks() {
+        let blocks = parse(
+            "# Title #\n\nSome **bold**\ntext with a [link](http://x).\n\n- one\n  more\n2. two\n\n***",
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Heading {
+                    level: 1,
+                    text: "Title".to_string()
+                },
+                Block::Paragraph("Some bold text with a link.".to_string()),
+                Block::Item {
+                    marker: "-".to_string(),
+                    text: "one more".to_string()
+                },
+                Block::Item {
+                    marker: "2.".to_string(),
+                    text: "two".to_string()
+                },
+                Block::Rule,
+            ]
+        );
+        assert_eq!(
+            parse("#hashtag"),
+            vec![Block::Paragraph("#hashtag".to_string())]
+        );
+    }
+
+    #[test]
+    fn wrap_words() {
+        assert_eq!(wrap("aa bb cc", 40, 1), vec!["aa bb", "cc"]);
+        assert_eq!(wrap("abcdefg", 24, 1), vec!["abc", "def", "g"]);
+    }
+
+    #[test]
+    fn layout_clipped_to_area() {
+        let mut canvas = Canvas::new(40, 40);
+        let area = Rect::new(0, 0, 32, 30);
+        canvas.draw(
+            Markdown::new(area, "# Hi\n\nsome words that run on\n\n---"),
+            &Color::Black,
+        );
+        // The underline of the heading, 16 pixels below the top
+        assert!((0..32).all(|x| canvas.get_pixel(16, x) == Color::Black));
+        assert!(canvas
+            .pixels()
+            .all(|(x, y, color)| color == Color::White || area.contains(x, y)));
+    }
+}
@@ -0,0 +1,190 @@
+//! Numbers drawn in the segmented style of LED clocks, which stay crisp at any size
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+// Segment bits, in the conventional a-g order: top, top right, bottom right, bottom, bottom left,
+// top left, middle
+const A: u8 = 1 << 0;
+const B: u8 = 1 << 1;
+const C: u8 = 1 << 2;
+const D: u8 = 1 << 3;
+const E: u8 = 1 << 4;
+const F: u8 = 1 << 5;
+const G: u8 = 1 << 6;
+
+/// Space between segments so each one reads as a separate bar
+const SEGMENT_GAP: usize = 1;
+
+fn segments(c: char) -> Option<u8> {
+    Some(match c {
+        '0' => A | B | C | D | E | F,
+        '1' => B | C,
+        '2' => A | B | D | E | G,
+        '3' => A | B | C | D | G,
+        '4' => B | C | F | G,
+        '5' => A | C | D | F | G,
+        '6' => A | C | D | E | F | G,
+        '7' => A | B | C,
+        '8' => A | B | C | D | E | F | G,
+        '9' => A | B | C | D | F | G,
+        '-' => G,
+        ' ' => 0,
+        _ => return None,
+    })
+}
+
+/// A string of digits drawn as seven segment characters with their top left corner at
+/// `position`. Digits, `-`, space, `:` and `.` are supported; other characters are skipped
+pub struct SevenSegment {
+    position: (usize, usize),
+    content: String,
+    height: usize,
+    thickness: usize,
+}
+
+impl SevenSegment {
+    /// Create a display `height` pixels tall. Digits are half as wide as they are tall
+    pub fn new(position: (usize, usize), content: &str, height: usize) -> Self {
+        Self {
+            position,
+            content: content.to_string(),
+            height,
+            thickness: (height / 10).max(1),
+        }
+    }
+
+    /// Set the thickness of each segment, which defaults to a tenth of the height
+    pub fn with_thickness(mut self, thickness: usize) -> Self {
+        self.thickness = thickness.max(1);
+        self
+    }
+
+    fn digit_width(&self) -> usize {
+        self.height / 2
+    }
+
+    // Space left after every character
+    fn spacing(&self) -> usize {
+        self.thickness * 2
+    }
+
+    /// Width and height of the drawn string in pixels
+    pub fn size(&self) -> (usize, usize) {
+        let width: usize = self.content.chars().filter_map(|c| self.advance(c)).sum();
+        (width.saturating_sub(self.spacing()), self.height)
+    }
+
+    fn advance(&self, c: char) -> Option<usize> {
+        match c {
+            ':' | '.' => Some(self.thickness + self.spacing()),
+            c => segments(c).map(|_| self.digit_width() + self.spacing()),
+        }
+    }
+
+    // Rectangles for the lit segments of a digit with its top left corner at (x, y)
+    fn digit(&self, (x, y): (usize, usize), lit: u8) -> Vec<Rectangle> {
+        let (w, h, t, g) = (self.digit_width(), self.height, self.thickness, SEGMENT_GAP);
+        if w < 2 * t + 2 * g + 1 || h < 3 * t + 4 * g + 2 {
+            return Vec::new();
+        }
+        let mid = (h - t) / 2;
+        let (left, right) = (x, x + w - t);
+        let horizontal =
+            |top: usize| Rectangle::new((x + t + g, y + top), (x + w - 1 - t - g, y + top + t - 1));
+        let upper = (y + t + g, y + mid - 1 - g);
+        let lower = (y + mid + t + g, y + h - t - 1 - g);
+        let vertical = |column: usize, (top, bottom): (usize, usize)| {
+            Rectangle::new((column, top), (column + t - 1, bottom))
+        };
+        [
+            (A, horizontal(0)),
+            (B, vertical(right, upper)),
+            (C, vertical(right, lower)),
+            (D, horizontal(h - t)),
+            (E, vertical(left, lower)),
+            (F, vertical(left, upper)),
+            (G, horizontal(mid)),
+        ]
+        .into_iter()
+        .filter(|(bit, _)| lit & bit != 0)
+        .map(|(_, segment)| segment)
+        .collect()
+    }
+
+    fn parts(&self) -> Vec<Rectangle> {
+        let t = self.thickness;
+        let (mut x, y) = self.position;
+        let mut parts = Vec::new();
+        for c in self.content.chars() {
+            let Some(advance) = self.advance(c) else {
+                continue;
+            };
+            let dot = |top: usize| Rectangle::new((x, y + top), (x + t - 1, y + top + t - 1));
+            match c {
+                ':' => {
+                    parts.push(dot(self.height / 3 - t / 2));
+                    parts.push(dot(self.height * 2 / 3 - t / 2));
+                }
+                '.' => parts.push(dot(self.height - t)),
+                c => parts.extend(self.digit((x, y), segments(c).unwrap_or(0))),
+            }
+            x += advance;
+        }
+        parts
+    }
+}
+
+impl Drawable for SevenSegment {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.parts()
+                .into_iter()
+                .flat_map(Rectangle::rectangle_coordinates),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        let (width, height) = self.size();
+        Rect::new(self.position.0, self.position.1, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SevenSegment;
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::{Canvas, Drawable};
+
+    #[test]
+    fn size() {
+        let display = SevenSegment::new((0, 0), "12:34", 40);
+        // Four digits 20 wide, a colon 4 wide and 8 pixels between each character
+        assert_eq!(display.size(), (116, 40));
+        assert_eq!(display.bounds(), Rect::new(0, 0, 116, 40));
+        assert_eq!(SevenSegment::new((0, 0), "", 40).size(), (0, 40));
+    }
+
+    #[test]
+    fn segments() {
+        let mut canvas = Canvas::new(30, 40);
+        canvas.draw(SevenSegment::new((0, 0), "1", 40), &Color::Black);
+        // Only the right hand segments are lit
+        assert_eq!(canvas.get_pixel(10, 18), Color::Black);
+        assert_eq!(canvas.get_pixel(30, 18), Color::Black);
+        assert_eq!(canvas.get_pixel(10, 1), Color::White);
+        assert_eq!(canvas.get_pixel(1, 10), Color::White);
+        assert_eq!(canvas.get_pixel(19, 10), Color::White);
+
+        let mut canvas = Canvas::new(30, 40);
+        canvas.draw(SevenSegment::new((0, 0), "8", 40), &Color::Black);
+        assert_eq!(canvas.get_pixel(1, 10), Color::Black);
+        assert_eq!(canvas.get_pixel(19, 10), Color::Black);
+        assert_eq!(canvas.get_pixel(38, 10), Color::Black);
+        assert_eq!(canvas.get_pixel(10, 1), Color::Black);
+        // Corners are left empty between segments
+        assert_eq!(canvas.get_pixel(0, 0), Color::White);
+    }
+}
@@ -0,0 +1,174 @@
+//! Small battery and Wi-Fi indicators for the status bar of battery powered displays
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+/// A battery outline twice as wide as it is tall, filled in proportion to its charge
+pub struct BatteryIndicator {
+    position: (usize, usize),
+    height: usize,
+    percent: u8,
+}
+
+impl BatteryIndicator {
+    /// Create an indicator with its top left corner at `position`. Percentages over 100 are
+    /// shown as full
+    pub fn new(position: (usize, usize), height: usize, percent: u8) -> Self {
+        Self {
+            position,
+            height,
+            percent: percent.min(100),
+        }
+    }
+
+    fn parts(&self) -> Vec<Rectangle> {
+        let (x, y) = self.position;
+        let h = self.height;
+        let stroke = (h / 8).max(1);
+        // The terminal nub on the right is a quarter of the height wide
+        let nub = (h / 4).max(1);
+        let width = 2 * h;
+        if h < 4 * stroke || width <= nub + 4 * stroke {
+            return Vec::new();
+        }
+        let body_right = x + width - nub - 1;
+        let mut parts = vec![
+            Rectangle::outline((x, y), (body_right, y + h - 1), stroke),
+            Rectangle::new(
+                (body_right + 1, y + h / 3),
+                (x + width - 1, y + h - 1 - h / 3),
+            ),
+        ];
+        // Leave a gap of one stroke inside the outline
+        let inner = body_right + 1 - x - 4 * stroke;
+        let level = inner * self.percent as usize / 100;
+        if level > 0 {
+            let left = x + 2 * stroke;
+            parts.push(Rectangle::new(
+                (left, y + 2 * stroke),
+                (left + level - 1, y + h - 1 - 2 * stroke),
+            ));
+        }
+        parts
+    }
+}
+
+impl Drawable for BatteryIndicator {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.parts()
+                .into_iter()
+                .flat_map(Rectangle::rectangle_coordinates),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(
+            self.position.0,
+            self.position.1,
+            2 * self.height,
+            self.height,
+        )
+    }
+}
+
+/// A Wi-Fi fan of three arcs over a dot, with an arc lit for each third of the signal strength
+pub struct WifiIndicator {
+    position: (usize, usize),
+    size: usize,
+    strength: f32,
+}
+
+impl WifiIndicator {
+    /// Create an indicator `size` pixels square with its top left corner at `position`, for a
+    /// signal strength from 0 to 1
+    pub fn new(position: (usize, usize), size: usize, strength: f32) -> Self {
+        Self {
+            position,
+            size,
+            strength: strength.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Create an indicator for a received signal strength in dBm, treating -90 dBm and below as
+    /// no signal and -50 dBm and above as full strength
+    pub fn from_rssi(position: (usize, usize), size: usize, dbm: i32) -> Self {
+        Self::new(position, size, (dbm + 90) as f32 / 40.0)
+    }
+
+    /// The number of arcs lit
+    pub fn bars(&self) -> usize {
+        (self.strength * 3.0).round() as usize
+    }
+
+    fn covers(&self, x: usize, y: usize) -> bool {
+        // Measured from the dot at the bottom centre
+        let size = self.size as f32;
+        let dx = x as f32 + 0.5 - size / 2.0;
+        let dy = size - (y as f32 + 0.5);
+        let distance = (dx * dx + dy * dy).sqrt() / size;
+        if distance <= 0.12 {
+            return true;
+        }
+        // The arcs span 45 degrees either side of straight up
+        if dy < dx.abs() {
+            return false;
+        }
+        (0..self.bars()).any(|arc| {
+            let inner = 0.25 + arc as f32 * 0.25;
+            distance >= inner && distance <= inner + 0.13
+        })
+    }
+}
+
+impl Drawable for WifiIndicator {
+    fn coordinates(&self) -> Coordinates<'_> {
+        let size = self.size;
+        Box::new(
+            (0..size)
+                .flat_map(move |y| (0..size).map(move |x| (x, y)))
+                .filter(move |&(x, y)| self.covers(x, y))
+                .map(|(x, y)| (self.position.0 + x, self.position.1 + y)),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(self.position.0, self.position.1, self.size, self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatteryIndicator, WifiIndicator};
+    use crate::core::colors::Color;
+    use crate::inky::{Canvas, Drawable};
+
+    #[test]
+    fn battery_level() {
+        let mut canvas = Canvas::new(32, 16);
+        canvas.draw(BatteryIndicator::new((0, 0), 16, 50), &Color::Black);
+        // Outline, nub, and the fill reaching half way across the 20 pixels inside
+        assert_eq!(canvas.get_pixel(0, 0), Color::Black);
+        assert_eq!(canvas.get_pixel(8, 31), Color::Black);
+        assert_eq!(canvas.get_pixel(8, 13), Color::Black);
+        assert_eq!(canvas.get_pixel(8, 14), Color::White);
+        assert_eq!(canvas.get_pixel(2, 8), Color::White);
+
+        let empty = BatteryIndicator::new((0, 0), 16, 0).coordinates().count();
+        let full = BatteryIndicator::new((0, 0), 16, 200).coordinates().count();
+        assert!(full > empty);
+    }
+
+    #[test]
+    fn wifi_bars() {
+        assert_eq!(WifiIndicator::from_rssi((0, 0), 16, -95).bars(), 0);
+        assert_eq!(WifiIndicator::from_rssi((0, 0), 16, -70).bars(), 2);
+        assert_eq!(WifiIndicator::from_rssi((0, 0), 16, -40).bars(), 3);
+
+        let weak = WifiIndicator::new((0, 0), 32, 0.0).coordinates().count();
+        let strong = WifiIndicator::new((0, 0), 32, 1.0).coordinates().count();
+        assert!(weak > 0 && strong > weak);
+    }
+}
@@ -0,0 +1,295 @@
+//! Grids of text cells for calendars, timetables and price boards
+//!
+//! A table is laid out inside an area of the canvas, with a one pixel grid line around and
+//! between every cell. Columns are either a fixed number of pixels wide or share whatever space
+//! the fixed columns leave in proportion to their weights. Rows that don't fit are dropped and
+//! text is clipped to its cell.
+
+use crate::{
+    core::{
+        geometry::Rect,
+        text::{BuiltinFont, Font, Text},
+    },
+    inky::{Coordinates, Drawable, Rectangle},
+};
+
+use std::collections::HashMap;
+
+/// Space between the grid lines and cell text
+const PADDING: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnWidth {
+    /// Exactly this many pixels, not counting grid lines
+    Fixed(usize),
+    /// A share of the width left over by fixed columns, weighted against the other
+    /// proportional columns
+    Proportional(usize),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Alignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+pub struct Table {
+    area: Rect,
+    rows: Vec<Vec<String>>,
+    columns: Vec<ColumnWidth>,
+    column_alignments: Vec<Alignment>,
+    cell_alignments: HashMap<(usize, usize), Alignment>,
+    scale: usize,
+    row_height: Option<usize>,
+}
+
+impl Table {
+    /// A table of `rows` of cell text, with every column sharing the width equally
+    pub fn new<R, C>(area: Rect, rows: R) -> Self
+    where
+        R: IntoIterator<Item = C>,
+        C: IntoIterator,
+        C::Item: Into<String>,
+    {
+        let rows: Vec<Vec<String>> = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(Into::into).collect())
+            .collect();
+        let count = rows.iter().map(Vec::len).max().unwrap_or(0);
+        Self {
+            area,
+            rows,
+            columns: vec![ColumnWidth::Proportional(1); count],
+            column_alignments: Vec::new(),
+            cell_alignments: HashMap::new(),
+            scale: 1,
+            row_height: None,
+        }
+    }
+
+    /// Set the width of every column. Columns past the end of `columns` are not drawn
+    pub fn with_columns(mut self, columns: impl Into<Vec<ColumnWidth>>) -> Self {
+        self.columns = columns.into();
+        self
+    }
+
+    /// Align the text of every cell in a column
+    pub fn with_column_alignment(mut self, column: usize, alignment: Alignment) -> Self {
+        if self.column_alignments.len() <= column {
+            self.column_alignments
+                .resize(column + 1, Alignment::default());
+        }
+        self.column_alignments[column] = alignment;
+        self
+    }
+
+    /// Align the text of a single cell, overriding its column's alignment
+    pub fn with_cell_alignment(mut self, row: usize, column: usize, alignment: Alignment) -> Self {
+        self.cell_alignments.insert((row, column), alignment);
+        self
+    }
+
+    /// Scale the cell text
+    pub fn with_scale(mut self, scale: usize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Set the height of each row, not counting grid lines. Defaults to fit one line of text
+    pub fn with_row_height(mut self, height: usize) -> Self {
+        self.row_height = Some(height);
+        self
+    }
+
+    fn alignment(&self, row: usize, column: usize) -> Alignment {
+        self.cell_alignments
+            .get(&(row, column))
+            .or_else(|| self.column_alignments.get(column))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn row_height(&self) -> usize {
+        self.row_height
+            .unwrap_or(BuiltinFont.line_height() * self.scale + 2 * PADDING)
+    }
+
+    // The x of each vertical grid line, one more than the number of columns
+    fn column_lines(&self) -> Vec<usize> {
+        let lines = self.columns.len() + 1;
+        let fixed: usize = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                ColumnWidth::Fixed(width) => *width,
+                ColumnWidth::Proportional(_) => 0,
+            })
+            .sum();
+        let weights: usize = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                ColumnWidth::Fixed(_) => 0,
+                ColumnWidth::Proportional(weight) => *weight,
+            })
+            .sum();
+        let spare = self.area.width.saturating_sub(fixed + lines);
+
+        let mut x = self.area.x;
+        let mut result = vec![x];
+        let mut shared = 0;
+        let mut weight_so_far = 0;
+        for column in &self.columns {
+            let width = match column {
+                ColumnWidth::Fixed(width) => *width,
+                ColumnWidth::Proportional(weight) => {
+                    // Hand out the rounding remainder so proportional columns fill the table
+                    weight_so_far += weight;
+                    let end = (spare * weight_so_far).checked_div(weights).unwrap_or(0);
+                    let width = end - shared;
+                    shared = end;
+                    width
+                }
+            };
+            x += width + 1;
+            result.push(x);
+        }
+        result
+    }
+
+    // The y of each horizontal grid line for the rows that fit in the area
+    fn row_lines(&self) -> Vec<usize> {
+        let step = self.row_height() + 1;
+        (0..=self.rows.len())
+            .map(|row| self.area.y + row * step)
+            .take_while(|y| *y < self.area.bottom())
+            .collect()
+    }
+
+    fn grid(&self) -> Vec<Rectangle> {
+        let columns = self.column_lines();
+        let rows = self.row_lines();
+        let (Some(&right), Some(&bottom)) = (columns.last(), rows.last()) else {
+            return Vec::new();
+        };
+        let right = right.min(self.area.right() - 1);
+        let horizontal = rows
+            .iter()
+            .map(|&y| Rectangle::new((self.area.x, y), (right, y)));
+        let vertical = columns
+            .iter()
+            .filter(|&&x| x < self.area.right())
+            .map(|&x| Rectangle::new((x, self.area.y), (x, bottom)));
+        horizontal.chain(vertical).collect()
+    }
+
+    fn cell_text(&self) -> Vec<(usize, usize)> {
+        let columns = self.column_lines();
+        let rows = self.row_lines();
+        let mut result = Vec::new();
+        for (row, (top, bottom)) in rows.iter().zip(rows.iter().skip(1)).enumerate() {
+            for (column, (left, right)) in columns.iter().zip(columns.iter().skip(1)).enumerate() {
+                let Some(content) = self.rows[row].get(column) else {
+                    continue;
+                };
+                if *right <= left + 1 || *bottom <= top + 1 {
+                    continue;
+                }
+                let cell = Rect::from_corners((left + 1, top + 1), (right - 1, bottom - 1));
+                let text = Text::new((0, 0), content).with_scale(self.scale);
+                let (width, height) = text.size();
+                let inner = cell.width.saturating_sub(2 * PADDING);
+                let x = cell.x
+                    + PADDING
+                    + match self.alignment(row, column) {
+                        Alignment::Left => 0,
+                        Alignment::Center => inner.saturating_sub(width) / 2,
+                        Alignment::Right => inner.saturating_sub(width),
+                    };
+                let y = cell.y + cell.height.saturating_sub(height) / 2;
+                let clip = cell.intersection(&self.area);
+                let text = Text::new((x, y), content).with_scale(self.scale);
+                result.extend(
+                    text.coordinates()
+                        .filter(|&(x, y)| clip.is_some_and(|clip| clip.contains(x, y))),
+                );
+            }
+        }
+        result
+    }
+}
+
+impl Drawable for Table {
+    fn coordinates(&self) -> Coordinates<'_> {
+        Box::new(
+            self.grid()
+                .into_iter()
+                .flat_map(Rectangle::rectangle_coordinates)
+                .chain(self.cell_text()),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        self.area
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Alignment, ColumnWidth, Table};
+    use crate::core::{colors::Color, geometry::Rect};
+    use crate::inky::Canvas;
+
+    #[test]
+    fn column_widths() {
+        let table = Table::new(Rect::new(0, 0, 100, 40), [["a", "b", "c"]]).with_columns([
+            ColumnWidth::Fixed(20),
+            ColumnWidth::Proportional(1),
+            ColumnWidth::Proportional(2),
+        ]);
+        // 76 pixels are left after the fixed column and four grid lines
+        assert_eq!(table.column_lines(), vec![0, 21, 47, 99]);
+    }
+
+    #[test]
+    fn grid_and_alignment() {
+        let mut canvas = Canvas::new(60, 40);
+        let table = Table::new(Rect::new(0, 0, 60, 40), [vec!["I"], vec!["I"]])
+            .with_cell_alignment(1, 0, Alignment::Right);
+        // Rows are 12 pixels: 8 for the text and 2 of padding either side
+        assert_eq!(table.row_lines(), vec![0, 13, 26]);
+        canvas.draw(table, &Color::Black);
+        assert_eq!(canvas.get_pixel(0, 30), Color::Black);
+        assert_eq!(canvas.get_pixel(26, 59), Color::Black);
+        assert_eq!(canvas.get_pixel(30, 30), Color::White);
+
+        let ink = |y0: usize, y1: usize| {
+            (y0..y1)
+                .flat_map(|y| (1..59).map(move |x| (x, y)))
+                .filter(|&(x, y)| canvas.get_pixel(y, x) == Color::Black)
+                .map(|(x, _)| x)
+                .collect::<Vec<_>>()
+        };
+        assert!(ink(1, 13).iter().all(|x| *x < 12));
+        assert!(ink(14, 26).iter().all(|x| *x > 48));
+    }
+
+    #[test]
+    fn text_clipped_to_cell() {
+        let mut canvas = Canvas::new(40, 20);
+        let table = Table::new(Rect::new(0, 0, 30, 20), [["WWWWWWWW"]]);
+        canvas.draw(table, &Color::Black);
+        for y in 0..20 {
+            for x in 30..40 {
+                assert_eq!(canvas.get_pixel(y, x), Color::White);
+            }
+        }
+    }
+}
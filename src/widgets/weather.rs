@@ -0,0 +1,176 @@
+//! A small monochrome weather icon set for forecast dashboards
+//!
+//! Icons are drawn from shapes defined on a unit square rather than from bitmaps, so they stay
+//! crisp at any size without dithering.
+
+use crate::{
+    core::geometry::Rect,
+    inky::{Coordinates, Drawable},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Weather {
+    Sun,
+    Moon,
+    PartlyCloudy,
+    Cloudy,
+    Rain,
+    Snow,
+    Thunderstorm,
+    Fog,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 8] = [
+        Weather::Sun,
+        Weather::Moon,
+        Weather::PartlyCloudy,
+        Weather::Cloudy,
+        Weather::Rain,
+        Weather::Snow,
+        Weather::Thunderstorm,
+        Weather::Fog,
+    ];
+
+    /// Whether the point (u, v) of the unit square is inked, with (0, 0) at the top left
+    fn covers(&self, u: f32, v: f32) -> bool {
+        match self {
+            Weather::Sun => sun(u, v),
+            Weather::Moon => disc(u, v, (0.5, 0.5), 0.3) && !disc(u, v, (0.63, 0.4), 0.25),
+            Weather::PartlyCloudy => {
+                let (cu, cv) = (u - 0.1, v - 0.05);
+                // Leave a gap between the sun and the cloud in front of it
+                let gap = cloud(0.5 + (cu - 0.5) / 1.15, 0.62 + (cv - 0.62) / 1.15);
+                cloud(cu, cv) || (sun((u - 0.35) / 0.7 + 0.5, (v - 0.35) / 0.7 + 0.5) && !gap)
+            }
+            Weather::Cloudy => cloud(u, v),
+            Weather::Rain => {
+                cloud(u, v + 0.15)
+                    || [0.35, 0.5, 0.65]
+                        .iter()
+                        .any(|&x| segment(u, v, (x, 0.72), (x - 0.05, 0.88), 0.05))
+            }
+            Weather::Snow => {
+                cloud(u, v + 0.15)
+                    || [
+                        (0.32, 0.75),
+                        (0.5, 0.8),
+                        (0.68, 0.75),
+                        (0.41, 0.92),
+                        (0.59, 0.92),
+                    ]
+                    .iter()
+                    .any(|&center| disc(u, v, center, 0.04))
+            }
+            Weather::Thunderstorm => {
+                cloud(u, v + 0.15)
+                    || segment(u, v, (0.56, 0.62), (0.45, 0.78), 0.06)
+                    || segment(u, v, (0.45, 0.78), (0.58, 0.78), 0.06)
+                    || segment(u, v, (0.58, 0.78), (0.47, 0.95), 0.06)
+            }
+            Weather::Fog => [
+                (0.15, 0.75, 0.3),
+                (0.25, 0.85, 0.45),
+                (0.15, 0.75, 0.6),
+                (0.25, 0.85, 0.75),
+            ]
+            .iter()
+            .any(|&(left, right, y)| segment(u, v, (left, y), (right, y), 0.06)),
+        }
+    }
+}
+
+fn disc(u: f32, v: f32, (cu, cv): (f32, f32), radius: f32) -> bool {
+    (u - cu).powi(2) + (v - cv).powi(2) <= radius * radius
+}
+
+// Whether (u, v) is within half of `width` of the segment from `a` to `b`
+fn segment(u: f32, v: f32, a: (f32, f32), b: (f32, f32), width: f32) -> bool {
+    let (du, dv) = (b.0 - a.0, b.1 - a.1);
+    let length = du * du + dv * dv;
+    let t = if length > 0.0 {
+        (((u - a.0) * du + (v - a.1) * dv) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    disc(u, v, (a.0 + t * du, a.1 + t * dv), width / 2.0)
+}
+
+fn sun(u: f32, v: f32) -> bool {
+    disc(u, v, (0.5, 0.5), 0.2)
+        || (0..8).any(|ray| {
+            let angle = ray as f32 * std::f32::consts::FRAC_PI_4;
+            let (sin, cos) = angle.sin_cos();
+            segment(
+                u,
+                v,
+                (0.5 + 0.3 * cos, 0.5 + 0.3 * sin),
+                (0.5 + 0.45 * cos, 0.5 + 0.45 * sin),
+                0.06,
+            )
+        })
+}
+
+// Three puffs over a flat base
+fn cloud(u: f32, v: f32) -> bool {
+    disc(u, v, (0.3, 0.6), 0.17)
+        || disc(u, v, (0.5, 0.47), 0.22)
+        || disc(u, v, (0.72, 0.62), 0.15)
+        || ((0.3..=0.72).contains(&u) && (0.5..=0.77).contains(&v))
+}
+
+/// A weather icon `size` pixels square with its top left corner at `position`
+pub struct WeatherIcon {
+    weather: Weather,
+    position: (usize, usize),
+    size: usize,
+}
+
+impl WeatherIcon {
+    pub fn new(weather: Weather, position: (usize, usize), size: usize) -> Self {
+        Self {
+            weather,
+            position,
+            size,
+        }
+    }
+}
+
+impl Drawable for WeatherIcon {
+    fn coordinates(&self) -> Coordinates<'_> {
+        let size = self.size;
+        let scale = size as f32;
+        Box::new(
+            (0..size)
+                .flat_map(move |y| (0..size).map(move |x| (x, y)))
+                .filter(move |&(x, y)| {
+                    self.weather
+                        .covers((x as f32 + 0.5) / scale, (y as f32 + 0.5) / scale)
+                })
+                .map(|(x, y)| (self.position.0 + x, self.position.1 + y)),
+        )
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(self.position.0, self.position.1, self.size, self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Weather, WeatherIcon};
+    use crate::inky::Drawable;
+
+    #[test]
+    fn icons_fit_their_bounds() {
+        for weather in Weather::ALL {
+            let icon = WeatherIcon::new(weather, (10, 20), 32);
+            let bounds = icon.bounds();
+            assert!(icon.coordinates().count() > 0, "{:?} is empty", weather);
+            assert!(icon.coordinates().all(|(x, y)| bounds.contains(x, y)));
+        }
+        assert!(Weather::Sun.covers(0.5, 0.5));
+        assert!(!Weather::Moon.covers(0.65, 0.4));
+        assert!(!Weather::Rain.covers(0.5, 0.95));
+    }
+}